@@ -0,0 +1,49 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use elf_test::{Struct, Type, TypeKind};
+use std::io;
+
+fn scalar(name: &str, ate: gimli::DwAte, size: usize, offset: usize) -> Type {
+    Type::new(
+        TypeKind::new_from_base_type(ate, name, size),
+        name.into(),
+        vec![],
+        offset,
+    )
+}
+
+/// Something like a control loop would log, `{ time: u32, setpoint: f32, output: i16, ok: bool }`
+fn telemetry() -> Type {
+    let named_children = vec![
+        ("time".into(), scalar("u32", gimli::DW_ATE_unsigned, 4, 0)),
+        ("setpoint".into(), scalar("f32", gimli::DW_ATE_float, 4, 4)),
+        ("output".into(), scalar("i16", gimli::DW_ATE_signed, 2, 8)),
+        ("ok".into(), scalar("bool", gimli::DW_ATE_boolean, 1, 10)),
+    ];
+
+    Type::new(
+        TypeKind::Struct(Struct {
+            named_children,
+            indexed_children: vec![],
+        }),
+        "Telemetry".into(),
+        vec!["app".into()],
+        0,
+    )
+}
+
+fn bench_write(c: &mut Criterion) {
+    let buf = [0x10, 0x27, 0, 0, 0, 0, 0x80, 0x3f, 0xfe, 0xff, 1, 0];
+
+    let typ = scalar("u32", gimli::DW_ATE_unsigned, 4, 0);
+    c.bench_function("Type::write u32", |b| {
+        b.iter(|| typ.write(&mut io::sink(), black_box(&buf[..4])))
+    });
+
+    let typ = telemetry();
+    c.bench_function("Type::write struct", |b| {
+        b.iter(|| typ.write(&mut io::sink(), black_box(&buf)))
+    });
+}
+
+criterion_group!(benches, bench_write);
+criterion_main!(benches);
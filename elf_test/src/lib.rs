@@ -1,10 +1,15 @@
 use gimli::{
     constants, AttributeValue, DebuggingInformationEntry, DwAte, Dwarf, EntriesTreeNode, Reader,
 };
-use object::{Object, ObjectSection};
+use object::{Object, ObjectSection, SectionKind};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::fmt::{Display, LowerExp, UpperExp};
 use std::{borrow, io::Write};
-use std::{collections::HashMap, convert::TryInto};
-use std::{ops::Range, rc::Rc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::{TryFrom, TryInto},
+};
+use std::{ops::Range, path::PathBuf};
 
 /// Extension trait for `Range` to check for overlap
 pub trait ExtRange<T> {
@@ -18,12 +23,168 @@ impl ExtRange<usize> for Range<usize> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BaseEncoding {
     Decimal,
     Hex,
+    UpperHex,
     Octal,
     Binary,
+    Exp,
+    UpperExp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Formatting options from a format string placeholder, e.g. `{:>8}` or `{:#010x}`
+///
+/// They are applied to every scalar in the printed type, the same way `#[derive(Debug)]` passes
+/// the formatter options on to each field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub fill: char,
+    pub align: Option<Align>,
+    pub sign_plus: bool,
+    pub alternate: bool,
+    pub zero_pad: bool,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    pub encoding: BaseEncoding,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            fill: ' ',
+            align: None,
+            sign_plus: false,
+            alternate: false,
+            zero_pad: false,
+            width: None,
+            precision: None,
+            encoding: BaseEncoding::Decimal,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Write an integer, `magnitude` is used for decimal and `bits` (the raw two's complement
+    /// value) for the other radixes, as in `core::fmt`
+    fn write_integer(
+        &self,
+        w: &mut impl Write,
+        negative: bool,
+        magnitude: u128,
+        bits: u128,
+    ) -> std::io::Result<()> {
+        use BaseEncoding::*;
+
+        let (negative, prefix, digits) = match self.encoding {
+            Decimal => (negative, "", magnitude.to_string()),
+            Hex => (false, "0x", format!("{:x}", bits)),
+            UpperHex => (false, "0x", format!("{:X}", bits)),
+            Octal => (false, "0o", format!("{:o}", bits)),
+            Binary => (false, "0b", format!("{:b}", bits)),
+            Exp => (negative, "", self.exp(magnitude, false)),
+            UpperExp => (negative, "", self.exp(magnitude, true)),
+        };
+        let prefix = if self.alternate { prefix } else { "" };
+
+        self.pad_numeric(w, negative, prefix, &digits)
+    }
+
+    fn exp(&self, magnitude: u128, upper: bool) -> String {
+        match (self.precision, upper) {
+            (Some(p), false) => format!("{:.*e}", p, magnitude),
+            (Some(p), true) => format!("{:.*E}", p, magnitude),
+            (None, false) => format!("{:e}", magnitude),
+            (None, true) => format!("{:E}", magnitude),
+        }
+    }
+
+    /// Write a float, formatted with its own type so `f32` is not widened
+    fn write_float<F: Display + LowerExp + UpperExp>(
+        &self,
+        w: &mut impl Write,
+        negative: bool,
+        value: F,
+    ) -> std::io::Result<()> {
+        let digits = match (self.encoding, self.precision) {
+            (BaseEncoding::Exp, Some(p)) => format!("{:.*e}", p, value),
+            (BaseEncoding::Exp, None) => format!("{:e}", value),
+            (BaseEncoding::UpperExp, Some(p)) => format!("{:.*E}", p, value),
+            (BaseEncoding::UpperExp, None) => format!("{:E}", value),
+            (_, Some(p)) => format!("{:.*}", p, value),
+            (_, None) => format!("{}", value),
+        };
+
+        self.pad_numeric(w, negative, "", &digits)
+    }
+
+    /// Numbers are right aligned by default, and `0` pads between the sign and the digits
+    fn pad_numeric(
+        &self,
+        w: &mut impl Write,
+        negative: bool,
+        prefix: &str,
+        digits: &str,
+    ) -> std::io::Result<()> {
+        let sign = if negative {
+            "-"
+        } else if self.sign_plus {
+            "+"
+        } else {
+            ""
+        };
+        let len = sign.len() + prefix.len() + digits.chars().count();
+
+        match self.width {
+            Some(width) if width > len && self.zero_pad => {
+                write!(w, "{}{}{}{}", sign, prefix, "0".repeat(width - len), digits)
+            }
+            Some(width) if width > len => {
+                let body = format!("{}{}{}", sign, prefix, digits);
+                self.pad(w, width - len, Align::Right, &body)
+            }
+            _ => write!(w, "{}{}{}", sign, prefix, digits),
+        }
+    }
+
+    /// Text is left aligned by default, and the precision truncates it
+    pub fn write_str(&self, w: &mut impl Write, s: &str) -> std::io::Result<()> {
+        let s = match self.precision {
+            Some(p) => s.char_indices().nth(p).map_or(s, |(end, _)| &s[..end]),
+            None => s,
+        };
+        let len = s.chars().count();
+
+        match self.width {
+            Some(width) if width > len => self.pad(w, width - len, Align::Left, s),
+            _ => write!(w, "{}", s),
+        }
+    }
+
+    fn pad(
+        &self,
+        w: &mut impl Write,
+        padding: usize,
+        default: Align,
+        s: &str,
+    ) -> std::io::Result<()> {
+        let (pre, post) = match self.align.unwrap_or(default) {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        let fill = self.fill.to_string();
+
+        write!(w, "{}{}{}", fill.repeat(pre), s, fill.repeat(post))
+    }
 }
 
 // Convert DW_ATE + size into the following
@@ -68,8 +229,57 @@ impl BaseType {
         }
     }
 
+    /// The numeric value of the buffer, `None` for non-numeric types
+    pub fn value(&self, buf: &[u8]) -> Option<f64> {
+        use BaseType::*;
+
+        match self {
+            Unsigned(size) if *size == buf.len() => Some(le_bytes_to_u128(buf) as f64),
+            Signed(size) if *size == buf.len() => {
+                Some(sign_extend(le_bytes_to_u128(buf), *size) as f64)
+            }
+            F32 => Some(f32::from_le_bytes(buf.try_into().ok()?).into()),
+            F64 => Some(f64::from_le_bytes(buf.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// The buffer as a `Value`, `Value::Null` if it does not fit the type
+    pub fn decode(&self, buf: &[u8]) -> Value {
+        use BaseType::*;
+
+        match self {
+            Unsigned(size) if *size == buf.len() => Value::Unsigned(le_bytes_to_u128(buf)),
+            Signed(size) if *size == buf.len() => {
+                Value::Signed(sign_extend(le_bytes_to_u128(buf), *size))
+            }
+            F32 | F64 => self.value(buf).map_or(Value::Null, Value::Float),
+            Bool => match buf {
+                [0] => Value::Bool(false),
+                [1] => Value::Bool(true),
+                _ => Value::Null,
+            },
+            Char => match buf {
+                [c] => Value::Char(char::from(*c)),
+                _ => Value::Null,
+            },
+            Zero(name) => Value::String(name.clone()),
+            _ => Value::Null,
+        }
+    }
+
     /// Print buffer as base-type
     pub fn write(&self, w: &mut impl Write, buf: &[u8]) -> std::io::Result<()> {
+        self.write_with(w, buf, &FormatOptions::default())
+    }
+
+    /// Print buffer as base-type, using the formatting options from a format string
+    pub fn write_with(
+        &self,
+        w: &mut impl Write,
+        buf: &[u8],
+        options: &FormatOptions,
+    ) -> std::io::Result<()> {
         use BaseType::*;
 
         match self {
@@ -114,42 +324,45 @@ impl BaseType {
 
         match self {
             Unsigned(size) => match size {
-                1 => write!(w, "{}", buf[0])?,
-                2 => write!(w, "{}", u16::from_le_bytes(buf.try_into().unwrap()))?,
-                4 => write!(w, "{}", u32::from_le_bytes(buf.try_into().unwrap()))?,
-                8 => write!(w, "{}", u64::from_le_bytes(buf.try_into().unwrap()))?,
-                16 => write!(w, "{}", u128::from_le_bytes(buf.try_into().unwrap()))?,
+                1 | 2 | 4 | 8 | 16 => {
+                    let bits = le_bytes_to_u128(buf);
+                    options.write_integer(w, false, bits, bits)?;
+                }
                 _ => panic!("Unsupported size: {:#?}", self),
             },
             Signed(size) => match size {
-                1 => write!(w, "{}", buf[0] as i8)?,
-                2 => write!(w, "{}", i16::from_le_bytes(buf.try_into().unwrap()))?,
-                4 => write!(w, "{}", i32::from_le_bytes(buf.try_into().unwrap()))?,
-                8 => write!(w, "{}", i64::from_le_bytes(buf.try_into().unwrap()))?,
-                16 => write!(w, "{}", i128::from_le_bytes(buf.try_into().unwrap()))?,
+                1 | 2 | 4 | 8 | 16 => {
+                    let bits = le_bytes_to_u128(buf);
+                    let value = sign_extend(bits, *size);
+                    options.write_integer(w, value < 0, value.wrapping_abs() as u128, bits)?;
+                }
                 _ => panic!("Unsupported size: {:#?}", self),
             },
             F32 => {
-                write!(w, "{}", f32::from_le_bytes(buf.try_into().unwrap()))?;
+                let value = f32::from_le_bytes(buf.try_into().unwrap());
+                let negative = value.is_sign_negative() && !value.is_nan();
+                options.write_float(w, negative, value.abs())?;
             }
             F64 => {
-                write!(w, "{}", f64::from_le_bytes(buf.try_into().unwrap()))?;
+                let value = f64::from_le_bytes(buf.try_into().unwrap());
+                let negative = value.is_sign_negative() && !value.is_nan();
+                options.write_float(w, negative, value.abs())?;
             }
             Bool => {
                 assert!(buf.len() == 1);
                 if buf[0] == 0 {
-                    write!(w, "false")?;
+                    options.write_str(w, "false")?;
                 } else if buf[0] == 1 {
-                    write!(w, "true")?;
+                    options.write_str(w, "true")?;
                 } else {
                     panic!("not a bool: {}", buf[0]);
                 }
             }
             Char => {
-                write!(w, "{}", char::from(buf[0]))?;
+                options.write_str(w, char::from(buf[0]).encode_utf8(&mut [0; 4]))?;
             }
             Zero(s) => {
-                write!(w, "{}", &s)?;
+                options.write_str(w, s)?;
             }
             Unimplemented => {
                 write!(w, "Unimplemented type")?;
@@ -160,6 +373,67 @@ impl BaseType {
     }
 }
 
+/// A value decoded from a buffer as data instead of text, see `Type::decode`
+///
+/// Serializes to the natural form in formats like JSON: structs as maps in declaration order,
+/// tuples as sequences, enum variants with fields as a map with the variant name as the only
+/// key, and variants without fields as their name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Unsigned(u128),
+    Signed(i128),
+    Float(f64),
+    Char(char),
+    /// Enum variants without fields and zero sized types, by name
+    String(String),
+    /// Elements of a tuple or tuple struct
+    Seq(Vec<Value>),
+    /// Fields of a struct in declaration order
+    Map(Vec<(String, Value)>),
+    /// Types that cannot be decoded, or a buffer too short for the type
+    Null,
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            // Plenty of formats have no 128-bit integers
+            Value::Unsigned(value) => match u64::try_from(*value) {
+                Ok(value) => serializer.serialize_u64(value),
+                Err(_) => serializer.serialize_u128(*value),
+            },
+            Value::Signed(value) => match i64::try_from(*value) {
+                Ok(value) => serializer.serialize_i64(value),
+                Err(_) => serializer.serialize_i128(*value),
+            },
+            Value::Float(value) => serializer.serialize_f64(*value),
+            Value::Char(value) => serializer.serialize_char(*value),
+            Value::String(value) => serializer.serialize_str(value),
+            Value::Seq(values) => serializer.collect_seq(values),
+            Value::Map(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+            Value::Null => serializer.serialize_unit(),
+        }
+    }
+}
+
+fn le_bytes_to_u128(buf: &[u8]) -> u128 {
+    buf.iter().rev().fold(0, |acc, &b| acc << 8 | u128::from(b))
+}
+
+/// Sign extend a `size` byte two's complement value to i128
+fn sign_extend(bits: u128, size: usize) -> i128 {
+    let shift = 128 - 8 * size;
+    ((bits << shift) as i128) >> shift
+}
+
 // For any DWARF type it needs to become a tree of the following
 #[derive(Debug, Clone)]
 pub struct TypePrinter {
@@ -171,7 +445,17 @@ pub struct TypePrinter {
 
 impl TypePrinter {
     pub fn write(&self, w: &mut impl Write, buf: &[u8]) -> std::io::Result<()> {
-        self.printer.write(w, &buf.get(self.range.clone()).unwrap())
+        self.write_with(w, buf, &FormatOptions::default())
+    }
+
+    pub fn write_with(
+        &self,
+        w: &mut impl Write,
+        buf: &[u8],
+        options: &FormatOptions,
+    ) -> std::io::Result<()> {
+        self.printer
+            .write_with(w, buf.get(self.range.clone()).unwrap(), options)
     }
 }
 
@@ -185,23 +469,268 @@ impl TypePrinters {
             let _ = typ.write(&mut out, buffer);
         }
     }
+
+    /// The type with a name from `core::any::type_name`
+    ///
+    /// Types are found by their name without the path, as in the DWARF, except tuples which are
+    /// named with the paths of their elements. A tuple of one element has no trailing comma in
+    /// the DWARF.
+    pub fn get(&self, type_name: &str) -> Option<&Type> {
+        if type_name.starts_with('(') {
+            let one = type_name
+                .strip_suffix(",)")
+                .map(|name| format!("{})", name));
+            return self.0.get(one.as_deref().unwrap_or(type_name));
+        }
+
+        self.0.get(type_name.rsplit(':').next().unwrap())
+    }
+
+    /// Attach annotations to the fields of all types, see `Type::annotate`
+    pub fn annotate(&mut self, annotations: &Annotations) {
+        for typ in self.0.values_mut() {
+            typ.annotate(annotations);
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Struct {
-    pub named_children: std::collections::HashMap<String, Type>,
+    // In declaration order
+    pub named_children: Vec<(String, Type)>,
     pub indexed_children: Vec<Type>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Enum {
-    pub variants: std::collections::HashMap<String, Type>,
+    // In declaration order
+    pub variants: Vec<(String, Type)>,
     pub discriminant_offset: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct Scalar {
     pub printer: TypePrinter,
+    pub annotation: Option<Annotation>,
+}
+
+/// Host side metadata for a field, e.g. to print `vbat_mv` in volts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Annotation {
+    pub unit: Option<String>,
+    pub scale: Option<f64>,
+    /// Precision of the scaled value, unless the format string sets one
+    pub precision: Option<usize>,
+    /// Number of fractional bits if the integer is a Qm.n fixed-point value
+    pub fractional_bits: Option<u32>,
+    /// Names of values, e.g. error codes, printed as `ERR_TIMEOUT (0x23)`
+    pub constants: BTreeMap<i64, String>,
+    /// Enumeration in the DWARF to take names of values from, see `Annotations::resolve`
+    pub enumeration: Option<String>,
+    /// Names of bits by mask, e.g. of a status register, printed as `RX_DONE | CRC_ERR`
+    pub flags: BTreeMap<u64, String>,
+}
+
+/// Annotations by field (`field` or `Type.field`) and by type name
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    pub fields: HashMap<String, Annotation>,
+    pub types: HashMap<String, Annotation>,
+}
+
+impl Annotations {
+    /// Add the enumerators of the enumerations the annotations name to their constants,
+    /// constants that are already there take precedence
+    pub fn resolve(&mut self, enumerations: &Enumerations) -> Result<(), anyhow::Error> {
+        for (name, annotation) in self.fields.iter_mut().chain(self.types.iter_mut()) {
+            if let Some(enumeration) = &annotation.enumeration {
+                let enumerators = enumerations.get(enumeration).ok_or_else(|| {
+                    anyhow::anyhow!("Unknown enum `{}` for `{}`", enumeration, name)
+                })?;
+                for (value, enumerator) in enumerators {
+                    annotation
+                        .constants
+                        .entry(*value)
+                        .or_insert_with(|| enumerator.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Annotation {
+    /// Apply the fixed-point format and scale factor, `None` if there are neither
+    pub fn apply(&self, value: f64) -> Option<f64> {
+        match (self.fractional_bits, self.scale) {
+            (Some(bits), scale) => Some(value / 2f64.powi(bits as i32) * scale.unwrap_or(1.0)),
+            (None, Some(scale)) => Some(value * scale),
+            (None, None) => None,
+        }
+    }
+}
+
+impl Scalar {
+    fn raw_value(&self, buf: &[u8]) -> Option<f64> {
+        buf.get(self.printer.range.clone())
+            .and_then(|buf| self.printer.printer.value(buf))
+    }
+
+    fn is_integer(&self) -> bool {
+        matches!(
+            self.printer.printer,
+            BaseType::Unsigned(_) | BaseType::Signed(_)
+        )
+    }
+
+    /// The value of an integer as it is in the buffer
+    fn raw_bits(&self, buf: &[u8]) -> Option<u128> {
+        if !self.is_integer() {
+            return None;
+        }
+        buf.get(self.printer.range.clone()).map(le_bytes_to_u128)
+    }
+
+    /// Print the names of the set bits, with the bits without a name in hex, or the bits in
+    /// hex if there are no names
+    fn write_flags(
+        &self,
+        w: &mut impl Write,
+        buf: &[u8],
+        options: &FormatOptions,
+    ) -> std::io::Result<()> {
+        let flags = self
+            .annotation
+            .as_ref()
+            .map(|annotation| &annotation.flags)
+            .filter(|flags| !flags.is_empty());
+        let (flags, bits) = match (flags, self.raw_bits(buf)) {
+            (Some(flags), Some(bits)) => (flags, bits),
+            _ => {
+                let hex = FormatOptions {
+                    alternate: true,
+                    encoding: BaseEncoding::Hex,
+                    ..options.clone()
+                };
+                return self.printer.write_with(w, buf, &hex);
+            }
+        };
+
+        // Flags made of several bits are left out if their bits are already named
+        let mut names = Vec::new();
+        let mut rest = bits;
+        for (mask, name) in flags {
+            let mask = u128::from(*mask);
+            if mask != 0 && bits & mask == mask && rest & mask != 0 {
+                names.push(name.clone());
+                rest &= !mask;
+            }
+        }
+        if rest != 0 {
+            names.push(format!("{:#x}", rest));
+        }
+        if names.is_empty() {
+            names.push("empty".into());
+        }
+
+        options.write_str(w, &names.join(" | "))
+    }
+
+    /// The numeric value, with the fixed-point format and scale factor applied
+    pub fn value(&self, buf: &[u8]) -> Option<f64> {
+        let value = self.raw_value(buf)?;
+
+        Some(
+            self.annotation
+                .as_ref()
+                .and_then(|annotation| annotation.apply(value))
+                .unwrap_or(value),
+        )
+    }
+
+    /// The value as data, scaled if the annotation has a fixed-point format or scale factor
+    ///
+    /// Names of constants and flags and units are left to the text.
+    pub fn decode(&self, buf: &[u8]) -> Value {
+        let scaled = self
+            .annotation
+            .as_ref()
+            .and_then(|annotation| annotation.apply(self.raw_value(buf)?));
+        match (scaled, buf.get(self.printer.range.clone())) {
+            (Some(value), _) => Value::Float(value),
+            (None, Some(buf)) => self.printer.printer.decode(buf),
+            (None, None) => Value::Null,
+        }
+    }
+
+    fn write_with(
+        &self,
+        w: &mut impl Write,
+        buf: &[u8],
+        options: &FormatOptions,
+    ) -> std::io::Result<()> {
+        let annotation = match &self.annotation {
+            Some(annotation) => annotation,
+            None => return self.printer.write_with(w, buf, options),
+        };
+
+        let constant = self
+            .raw_value(buf)
+            .filter(|value| value.fract() == 0.0)
+            .and_then(|value| annotation.constants.get(&(value as i64)));
+        if let Some(constant) = constant {
+            let mut raw = Vec::new();
+            let hex = FormatOptions {
+                alternate: true,
+                encoding: BaseEncoding::Hex,
+                ..FormatOptions::default()
+            };
+            self.printer.write_with(&mut raw, buf, &hex)?;
+            let text = format!("{} ({})", constant, String::from_utf8_lossy(&raw));
+            return options.write_str(w, &text);
+        }
+        if !annotation.flags.is_empty() {
+            return self.write_flags(w, buf, options);
+        }
+
+        let scaled = self
+            .raw_value(buf)
+            .and_then(|value| annotation.apply(value));
+        match scaled {
+            Some(value) => {
+                let negative = value.is_sign_negative() && !value.is_nan();
+                let options = FormatOptions {
+                    precision: options.precision.or(annotation.precision),
+                    ..options.clone()
+                };
+                options.write_float(w, negative, value.abs())?;
+
+                if annotation.fractional_bits.is_some() {
+                    write!(w, " (")?;
+                    self.printer.write(w, buf)?;
+                    write!(w, ")")?;
+                }
+            }
+            None => self.printer.write_with(w, buf, options)?,
+        }
+
+        if let Some(unit) = &annotation.unit {
+            write!(w, " {}", unit)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What a frame of `&str` or `&[u8]` holds, they are sent as what they point to instead of the
+/// pointer and length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contents {
+    /// UTF-8 text, printed as it is
+    Text,
+    /// Bytes, printed in hex
+    Bytes,
 }
 
 #[derive(Debug, Clone)]
@@ -230,6 +759,7 @@ impl TypeKind {
                 range: 0..size,
                 printer: BaseType::from_base_type(ate, name, size),
             },
+            annotation: None,
         })
     }
 }
@@ -245,160 +775,1033 @@ impl Type {
         }
     }
 
-    pub fn name(&self) -> &str {
-        &self.name
-    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// `Some` for the types that are sent as what they point to, see `Contents`
+    ///
+    /// This only applies to the value of a frame, a `&str` in a struct is a pointer like any
+    /// other.
+    pub fn contents(&self) -> Option<Contents> {
+        match self.name.as_str() {
+            "&str" => Some(Contents::Text),
+            "&[u8]" => Some(Contents::Bytes),
+            _ => None,
+        }
+    }
+
+    /// The elements of a tuple in order, e.g. the values of a `log!` with several, empty for other
+    /// types
+    pub fn tuple_elements(&self) -> &[Type] {
+        match &self.kind {
+            TypeKind::Struct(structure) if self.name.starts_with('(') => {
+                &structure.indexed_children
+            }
+            _ => &[],
+        }
+    }
+
+    /// The `bits` of a type like the ones `bitflags!` generates, `struct Flags { bits: u8 }`
+    ///
+    /// Those are printed as `Flags(RX_DONE | CRC_ERR)` when the flags are named by an
+    /// annotation, or with the bits in hex otherwise.
+    fn flag_bits(&self) -> Option<(&Type, &Scalar)> {
+        match &self.kind {
+            TypeKind::Struct(Struct {
+                named_children,
+                indexed_children,
+            }) if indexed_children.is_empty() => match named_children.as_slice() {
+                [(name, bits)] if name == "bits" => match &bits.kind {
+                    TypeKind::Scalar(scalar) if scalar.is_integer() => Some((bits, scalar)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Print the element at `index` of a tuple, see `tuple_elements`
+    pub fn write_element_with(
+        &self,
+        w: &mut impl Write,
+        buf: &[u8],
+        index: usize,
+        options: &FormatOptions,
+    ) -> std::io::Result<()> {
+        match (self.tuple_elements().get(index), buf.get(self.offset..)) {
+            (Some(element), Some(buf)) => element.write_internal(w, true, true, 0, buf, options),
+            _ => Ok(()),
+        }
+    }
+
+    /// Attach annotations to the scalars of this type and all types nested in it
+    ///
+    /// Field annotations are looked up by `Type.field` first, then by the field name alone. A
+    /// type annotation applies to the type itself if it is a scalar, or to the scalars directly
+    /// in it (e.g. `struct Q15(i16)`), unless a field annotation matches.
+    pub fn annotate(&mut self, annotations: &Annotations) {
+        let type_annotation = annotations.types.get(&self.name);
+
+        match &mut self.kind {
+            TypeKind::Scalar(scalar) => {
+                if let Some(annotation) = type_annotation {
+                    scalar.annotation = Some(annotation.clone());
+                }
+            }
+            TypeKind::Struct(structure) => {
+                for (field, typ) in &mut structure.named_children {
+                    typ.annotate(annotations);
+
+                    if let TypeKind::Scalar(scalar) = &mut typ.kind {
+                        let annotation = annotations
+                            .fields
+                            .get(&format!("{}.{}", self.name, field))
+                            .or_else(|| annotations.fields.get(field))
+                            .or(type_annotation);
+                        if let Some(annotation) = annotation {
+                            scalar.annotation = Some(annotation.clone());
+                        }
+                    }
+                }
+                for typ in &mut structure.indexed_children {
+                    typ.annotate(annotations);
+
+                    if let (TypeKind::Scalar(scalar), Some(annotation)) =
+                        (&mut typ.kind, type_annotation)
+                    {
+                        scalar.annotation = Some(annotation.clone());
+                    }
+                }
+            }
+            TypeKind::Enum(enummeration) => {
+                for (_, variant) in &mut enummeration.variants {
+                    variant.annotate(annotations);
+                }
+            }
+            TypeKind::Pointer(typ) => typ.annotate(annotations),
+            TypeKind::PlainVariant | TypeKind::Unknown => (),
+        }
+    }
+
+    /// The numeric scalars in the type by field path, e.g. `accel.x` or `0`, in declaration
+    /// order, with annotations applied
+    ///
+    /// A scalar at the top level is called `value`.
+    pub fn values(&self, buf: &[u8]) -> Vec<(String, f64)> {
+        let mut values = Vec::new();
+        if self.contents().is_some() {
+            return values;
+        }
+        self.collect_values(&mut values, "", buf);
+        values
+    }
+
+    fn collect_values(&self, values: &mut Vec<(String, f64)>, path: &str, buf: &[u8]) {
+        let buf = match buf.get(self.offset..) {
+            Some(buf) => buf,
+            None => return,
+        };
+        let child = |name: &str| {
+            if path.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", path, name)
+            }
+        };
+
+        match &self.kind {
+            TypeKind::Struct(structure) => {
+                for (name, typ) in &structure.named_children {
+                    typ.collect_values(values, &child(name), buf);
+                }
+                for (i, typ) in structure.indexed_children.iter().enumerate() {
+                    typ.collect_values(values, &child(&i.to_string()), buf);
+                }
+            }
+            TypeKind::Enum(enummeration) => {
+                let discriminant = match buf.get(enummeration.discriminant_offset) {
+                    Some(discriminant) => *discriminant as usize,
+                    None => return,
+                };
+                for (_, variant) in &enummeration.variants {
+                    if variant.variant_value == discriminant {
+                        variant.collect_values(values, path, buf);
+                    }
+                }
+            }
+            TypeKind::Scalar(scalar) => {
+                if let Some(value) = scalar.value(buf) {
+                    let name = if path.is_empty() { "value" } else { path };
+                    values.push((name.into(), value));
+                }
+            }
+            TypeKind::Pointer(_) | TypeKind::PlainVariant | TypeKind::Unknown => (),
+        }
+    }
+
+    /// The parts of the type that cannot be decoded from a frame by field path as in `values`,
+    /// with the reason, empty if all of it can
+    ///
+    /// Pointers are listed too, only the address is in the frame and not what it points to.
+    pub fn undecodable(&self) -> Vec<(String, &'static str)> {
+        let mut parts = Vec::new();
+        if self.contents().is_some() {
+            return parts;
+        }
+        self.collect_undecodable(&mut parts, "");
+        parts
+    }
+
+    fn collect_undecodable(&self, parts: &mut Vec<(String, &'static str)>, path: &str) {
+        let child = |name: &str| {
+            if path.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", path, name)
+            }
+        };
+        let name = if path.is_empty() { "value" } else { path };
+
+        match &self.kind {
+            TypeKind::Struct(structure) => {
+                for (name, typ) in &structure.named_children {
+                    typ.collect_undecodable(parts, &child(name));
+                }
+                for (i, typ) in structure.indexed_children.iter().enumerate() {
+                    typ.collect_undecodable(parts, &child(&i.to_string()));
+                }
+            }
+            TypeKind::Enum(enummeration) => {
+                for (name, variant) in &enummeration.variants {
+                    variant.collect_undecodable(parts, &child(name));
+                }
+            }
+            TypeKind::Scalar(Scalar {
+                printer:
+                    TypePrinter {
+                        printer: BaseType::Unimplemented,
+                        ..
+                    },
+                ..
+            }) => parts.push((name.into(), "unsupported base type")),
+            TypeKind::Pointer(_) => parts.push((name.into(), "pointer")),
+            TypeKind::Unknown => parts.push((name.into(), "type not supported by the decoder")),
+            TypeKind::Scalar(_) | TypeKind::PlainVariant => (),
+        }
+    }
+
+    /// The value in the buffer as data, for applications that work with the values instead of
+    /// the text, with annotations applied as in `values`
+    ///
+    /// Text is decoded as a string and bytes as a sequence of them, see `Contents`.
+    pub fn decode(&self, buf: &[u8]) -> Value {
+        match self.contents() {
+            Some(Contents::Text) => Value::String(String::from_utf8_lossy(buf).into_owned()),
+            Some(Contents::Bytes) => Value::Seq(
+                buf.iter()
+                    .map(|byte| Value::Unsigned(u128::from(*byte)))
+                    .collect(),
+            ),
+            None => self.decode_internal(buf),
+        }
+    }
+
+    fn decode_internal(&self, buf: &[u8]) -> Value {
+        let inner = match buf.get(self.offset..) {
+            Some(inner) => inner,
+            None => return Value::Null,
+        };
+
+        match &self.kind {
+            TypeKind::Struct(structure) if !structure.named_children.is_empty() => Value::Map(
+                structure
+                    .named_children
+                    .iter()
+                    .map(|(name, typ)| (name.clone(), typ.decode_internal(inner)))
+                    .collect(),
+            ),
+            TypeKind::Struct(structure) => Value::Seq(
+                structure
+                    .indexed_children
+                    .iter()
+                    .map(|typ| typ.decode_internal(inner))
+                    .collect(),
+            ),
+            TypeKind::Enum(enummeration) => {
+                let discriminant = match inner.get(enummeration.discriminant_offset) {
+                    Some(discriminant) => *discriminant as usize,
+                    None => return Value::Null,
+                };
+                let variant = enummeration
+                    .variants
+                    .iter()
+                    .map(|(_, variant)| variant)
+                    .find(|variant| variant.variant_value == discriminant);
+                match variant {
+                    Some(variant) if matches!(variant.kind, TypeKind::PlainVariant) => {
+                        Value::String(variant.name.clone())
+                    }
+                    Some(variant) => {
+                        Value::Map(vec![(variant.name.clone(), variant.decode_internal(inner))])
+                    }
+                    None => Value::Null,
+                }
+            }
+            TypeKind::Scalar(scalar) => scalar.decode(inner),
+            // As printed, the pointee is read from the same buffer
+            TypeKind::Pointer(typ) => typ.decode_internal(buf),
+            TypeKind::PlainVariant => Value::String(self.name.clone()),
+            TypeKind::Unknown => Value::Null,
+        }
+    }
+
+    pub fn write(&self, w: &mut impl Write, buf: &[u8]) -> std::io::Result<()> {
+        self.write_with(w, buf, &FormatOptions::default())
+    }
+
+    /// Print the type with the formatting options applied to each scalar in it
+    ///
+    /// Text is printed as it is, and bytes as hex pairs separated by spaces, e.g. `de ad be ef`,
+    /// see `Contents`. Both are padded and truncated as strings.
+    pub fn write_with(
+        &self,
+        w: &mut impl Write,
+        buf: &[u8],
+        options: &FormatOptions,
+    ) -> std::io::Result<()> {
+        match self.contents() {
+            Some(Contents::Text) => options.write_str(w, &String::from_utf8_lossy(buf)),
+            Some(Contents::Bytes) => {
+                let hex: Vec<_> = buf
+                    .iter()
+                    .map(|byte| match options.encoding {
+                        BaseEncoding::UpperHex => format!("{:02X}", byte),
+                        _ => format!("{:02x}", byte),
+                    })
+                    .collect();
+                options.write_str(w, &hex.join(" "))
+            }
+            None => self.write_internal(w, true, true, 0, buf, options),
+        }
+    }
+
+    /// `first` is set for the outermost type, which does not get a trailing comma, and `named`
+    /// when the type's own name should be printed (the outermost type and enum variants)
+    fn write_internal(
+        &self,
+        w: &mut impl Write,
+        first: bool,
+        named: bool,
+        depth: usize,
+        buf: &[u8],
+        options: &FormatOptions,
+    ) -> std::io::Result<()> {
+        let pad = " ".repeat(depth * 4);
+        let prefix = if named {
+            format!("{} ", self.name)
+        } else {
+            "".into()
+        };
+        match &self.kind {
+            TypeKind::Struct(_) if self.flag_bits().is_some() => {
+                let (bits, scalar) = self.flag_bits().unwrap();
+                write!(w, "{}(", self.name)?;
+                scalar.write_flags(w, &buf[self.offset + bits.offset..], options)?;
+                write!(w, ")")?;
+
+                if !first {
+                    writeln!(w, ",")?;
+                }
+            }
+            TypeKind::Struct(structure) => {
+                if !structure.named_children.is_empty() {
+                    writeln!(w, "{}{{", prefix)?;
+
+                    for (name, typ) in &structure.named_children {
+                        let pad = " ".repeat((depth + 1) * 4);
+                        write!(w, "{}{}: ", &pad, name)?;
+                        typ.write_internal(
+                            w,
+                            false,
+                            false,
+                            depth + 1,
+                            &buf[self.offset..],
+                            options,
+                        )?;
+                    }
+
+                    writeln!(w, "{}}}{}", &pad, if first { "" } else { "," })?;
+                } else if !structure.indexed_children.is_empty() {
+                    writeln!(w, "{}(", prefix)?;
+
+                    for typ in &structure.indexed_children {
+                        let pad = " ".repeat((depth + 1) * 4);
+                        write!(w, "{}", &pad)?;
+                        typ.write_internal(
+                            w,
+                            false,
+                            false,
+                            depth + 1,
+                            &buf[self.offset..],
+                            options,
+                        )?;
+                    }
+
+                    writeln!(w, "{}){}", &pad, if first { "" } else { "," })?;
+                }
+            }
+            TypeKind::Enum(enummeration) => {
+                write!(w, "{}::", self.name)?;
+                let discriminant = buf[self.offset + enummeration.discriminant_offset] as usize;
+                for (_variant_name, variant) in &enummeration.variants {
+                    if variant.variant_value == discriminant {
+                        variant.write_internal(
+                            w,
+                            first,
+                            true,
+                            depth,
+                            &buf[self.offset..],
+                            options,
+                        )?;
+                    }
+                }
+            }
+            TypeKind::Scalar(scalar) => {
+                scalar.write_with(w, &buf[self.offset..], options)?;
+
+                if first {
+                } else {
+                    writeln!(w, ",")?;
+                }
+            }
+            TypeKind::PlainVariant => {
+                if named {
+                    write!(w, "{}", self.name)?;
+                }
+
+                if first {
+                } else {
+                    writeln!(w, ",")?;
+                }
+            }
+            TypeKind::Pointer(typ) => {
+                write!(w, "*")?;
+                typ.write_internal(w, first, named, depth, buf, options)?;
+            }
+            TypeKind::Unknown => (),
+        }
+
+        Ok(())
+    }
+}
+
+pub fn generate_printers(elf: &[u8]) -> Result<TypePrinters, anyhow::Error> {
+    // Namespace tracker
+    let mut _namespace_tracker: Vec<String> = Vec::new();
+
+    // Where printers are stored
+    let mut printers: HashMap<String, Type> = HashMap::new();
+
+    let decompressed = decompress_sections(elf);
+    let debug_info = DebugInfo::from_raw(elf, &decompressed).unwrap();
+    let mut units = debug_info.get_units();
+    while let Some(unit_info) = debug_info.get_next_unit_info(&mut units) {
+        let types = unit_info.list_types().unwrap();
+        printers.extend(types.into_iter().map(|t| (t.name().to_string(), t)));
+    }
+    log::debug!("Found {} types in the DWARF", printers.len());
+
+    Ok(TypePrinters(printers))
+}
+
+/// A `log!` call site, found through the tag that the macro adds to the names of the format
+/// string static and of the function that has the printed type as generic parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSite {
+    /// Address of the format string in the `.fasthosting` section
+    pub address: u64,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    /// Path of the function with the call site, e.g. `app::sensor::read`
+    pub module: Option<String>,
+    /// Name of the printed type, as found in the DWARF
+    pub type_name: Option<String>,
+    /// Address of the static in the `.fasthosting.types` section that the frames of the call
+    /// site send as their type string, targets from before it send the address of the type
+    /// name in `.rodata` instead
+    pub type_address: Option<u64>,
+    /// `warn` or `error` for the call sites of `warn!` and `error!`
+    pub level: Option<&'static str>,
+    /// Set for the call sites of `log_kv!`, their format string names the fields
+    pub kv: bool,
+    /// Set for the call sites of `log_fmt!`, their message is formatted on the target and has
+    /// no type
+    pub formatted: bool,
+}
+
+/// Find all `log!` call sites in the DWARF, ordered by the address of their format string
+pub fn log_sites(elf: &[u8]) -> Result<Vec<LogSite>, anyhow::Error> {
+    let mut sites = HashMap::new();
+    let mut types = HashMap::new();
+    let mut type_addresses = HashMap::new();
+
+    let decompressed = decompress_sections(elf);
+    let debug_info = DebugInfo::from_raw(elf, &decompressed).unwrap();
+    let mut units = debug_info.get_units();
+    while let Some(unit_info) = debug_info.get_next_unit_info(&mut units) {
+        unit_info.list_log_sites(&mut sites, &mut types, &mut type_addresses)?;
+    }
+
+    // The static and the function can end up in different units, and tags restart for each
+    // crate, so they are paired on namespace and tag
+    let mut sites: Vec<_> = sites
+        .into_iter()
+        .map(|(key, mut site)| {
+            site.type_name = types.remove(&key);
+            site.type_address = type_addresses.remove(&key);
+            site
+        })
+        .collect();
+    sites.sort_by_key(|site| site.address);
+
+    Ok(sites)
+}
+
+/// Tag of the format string static of a `log!` call site, `S_T3` gives `T3`, and the level
+/// for the macros with one, `S_WARN_T3` gives `T3` and `warn`. `log_kv!` and `log_fmt!` have
+/// none, `S_KV_T3` and `S_FMT_T3` give `T3`.
+fn site_tag(name: &str) -> Option<(&str, Option<&'static str>)> {
+    const LEVELS: &[(&str, &str)] = &[
+        ("TRACE_", "trace"),
+        ("DEBUG_", "debug"),
+        ("INFO_", "info"),
+        ("WARN_", "warn"),
+        ("ERROR_", "error"),
+    ];
+
+    let rest = name.strip_prefix("S_")?;
+    let rest = rest
+        .strip_prefix("KV_")
+        .or_else(|| rest.strip_prefix("FMT_"))
+        .unwrap_or(rest);
+    let (tag, level) = LEVELS
+        .iter()
+        .find_map(|(prefix, level)| Some((rest.strip_prefix(prefix)?, Some(*level))))
+        .unwrap_or((rest, None));
+
+    Some((tag, level)).filter(|(tag, _)| is_tag(tag))
+}
+
+/// Tag of the static a `log!` call site sends the address of as its type string, `TYPE_T3`
+/// gives `T3`
+fn type_tag(name: &str) -> Option<&str> {
+    name.strip_prefix("TYPE_").filter(|tag| is_tag(tag))
+}
+
+/// Tag and printed type of the function added by `log!`, e.g.
+/// `__dwarffmt_this_is_for_searching_the_dwarf_T3<app::Foo>` gives `("T3", "app::Foo")`
+fn site_type(name: &str) -> Option<(&str, &str)> {
+    let rest = name.strip_prefix("__dwarffmt_this_is_for_searching_the_dwarf_")?;
+    let open = rest.find('<')?;
+    let typ = rest[open + 1..].strip_suffix('>')?;
+    let tag = &rest[..open];
+
+    if is_tag(tag) {
+        Some((tag, typ))
+    } else {
+        None
+    }
+}
+
+/// Tags are `T` followed by the number of the call site within its crate
+fn is_tag(tag: &str) -> bool {
+    match tag.strip_prefix('T') {
+        Some(n) => !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// The enumerators of the C-like enumerations in the DWARF by value, by the name of the
+/// enumeration without its path
+pub type Enumerations = HashMap<String, BTreeMap<i64, String>>;
+
+/// Find the C-like enumerations in the DWARF, e.g. `#[repr(u8)]` error codes or the enums of
+/// C headers, to print integer fields by the names of their values
+pub fn enumerations(elf: &[u8]) -> Result<Enumerations, anyhow::Error> {
+    let mut enumerations = HashMap::new();
+
+    let decompressed = decompress_sections(elf);
+    let debug_info = DebugInfo::from_raw(elf, &decompressed).unwrap();
+    let mut units = debug_info.get_units();
+    while let Some(unit_info) = debug_info.get_next_unit_info(&mut units) {
+        unit_info.list_enumerations(&mut enumerations)?;
+    }
+
+    Ok(enumerations)
+}
+
+/// A static variable with a fixed address, found in the DWARF
+#[derive(Debug, Clone)]
+pub struct Static {
+    /// Path of the static, e.g. `app::sensor::READINGS`
+    pub name: String,
+    pub address: u64,
+    /// Size of the type, if the DWARF has it
+    pub size: Option<u64>,
+    /// Printer for the value, `None` for types the walker does not handle yet
+    pub typ: Option<Type>,
+}
+
+/// Find all statics in the DWARF, ordered by address
+///
+/// The format strings of `log!` call sites are left out, they are in the catalog.
+pub fn statics(elf: &[u8]) -> Result<Vec<Static>, anyhow::Error> {
+    let mut statics = Vec::new();
+
+    let decompressed = decompress_sections(elf);
+    let debug_info = DebugInfo::from_raw(elf, &decompressed).unwrap();
+    let mut units = debug_info.get_units();
+    while let Some(unit_info) = debug_info.get_next_unit_info(&mut units) {
+        unit_info.list_statics(&mut statics)?;
+    }
+
+    statics.sort_by(|a, b| a.address.cmp(&b.address).then(a.name.cmp(&b.name)));
+    statics.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+
+    Ok(statics)
+}
+
+/// Target memory captured at one point in time, e.g. by a core dump, to decode statics from
+/// after the fact
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Start address and contents of each captured region
+    pub regions: Vec<(u64, Vec<u8>)>,
+}
+
+impl Snapshot {
+    /// The memory as it is before the target runs, from the sections of `elf` that hold
+    /// statics, no target needed
+    ///
+    /// `.data` is at its RAM address with the image it is copied from at startup, and `.bss` is
+    /// all zeros.
+    pub fn from_elf(elf: &[u8]) -> Result<Self, anyhow::Error> {
+        let object = object::File::parse(elf)?;
+
+        let mut regions = Vec::new();
+        for section in object.sections() {
+            let data = match section.kind() {
+                SectionKind::Data | SectionKind::ReadOnlyData | SectionKind::ReadOnlyString => {
+                    section.data()?.to_vec()
+                }
+                SectionKind::UninitializedData => vec![0; section.size() as usize],
+                _ => continue,
+            };
+            regions.push((section.address(), data));
+        }
+
+        Ok(Snapshot { regions })
+    }
+
+    /// `len` bytes at `address`, if they are all in one captured region
+    pub fn read(&self, address: u64, len: usize) -> Option<&[u8]> {
+        self.regions.iter().find_map(|(start, data)| {
+            let offset = address.checked_sub(*start)? as usize;
+            data.get(offset..offset.checked_add(len)?)
+        })
+    }
+
+    /// Print every static in `elf` with its value in the snapshot, one per line, or several
+    /// for structs and enums
+    pub fn write_statics(&self, elf: &[u8], w: &mut impl Write) -> Result<(), anyhow::Error> {
+        for s in statics(elf)? {
+            write!(w, "{} @ {:#010x} = ", s.name, s.address)?;
+
+            let size = s.size.unwrap_or(0) as usize;
+            match (&s.typ, self.read(s.address, size)) {
+                (_, None) => writeln!(w, "<not captured>")?,
+                (Some(typ), Some(buf)) => {
+                    let mut out = Vec::new();
+                    typ.write(&mut out, buf)?;
+                    w.write_all(&out)?;
+                    if !out.ends_with(b"\n") {
+                        writeln!(w)?;
+                    }
+                }
+                (None, Some(buf)) => {
+                    write!(w, "[")?;
+                    for (i, byte) in buf.iter().enumerate() {
+                        write!(w, "{}{:#04x}", if i == 0 { "" } else { ", " }, byte)?;
+                    }
+                    writeln!(w, "]")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Registers of a halted Cortex-M core by DWARF register number, `R0` to `R12`, `SP`, `LR` and
+/// `PC`
+pub type CoreRegisters = [u32; 16];
+
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+
+/// Most frames to unwind, in case the stack is corrupt
+const MAX_FRAMES: usize = 64;
+
+/// A frame of a backtrace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub pc: u32,
+    /// Demangled name of the function, from the symbol table
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    /// The frame was interrupted by an exception, the frames before it are the handler
+    pub exception: bool,
+    /// The code of the function is inlined into the function of the next frame, at the same
+    /// `pc`
+    pub inlined: bool,
+}
+
+/// Unwind the stack of a halted Cortex-M core with the call frame information in `.debug_frame`
+///
+/// `read` reads a word of target memory. Unwinding stops at the first function without unwind
+/// info, usually the reset handler, or when the stack looks corrupt.
+pub fn backtrace(
+    elf: &[u8],
+    mut registers: CoreRegisters,
+    mut read: impl FnMut(u32) -> Option<u32>,
+) -> Result<Vec<Frame>, anyhow::Error> {
+    use gimli::UnwindSection;
+
+    let decompressed = decompress_sections(elf);
+    let debug_info = DebugInfo::from_raw(elf, &decompressed).unwrap();
+    let symbolizer = Symbolizer::new(elf)?;
+
+    let bases = gimli::BaseAddresses::default();
+    let mut ctx = gimli::UninitializedUnwindContext::new();
+    let mut frames = Vec::new();
+    let mut exception = false;
+
+    while frames.len() < MAX_FRAMES {
+        let pc = registers[PC] & !1;
+        // A return address is after the call, which can be the start of the next function
+        let address = if frames.is_empty() || exception {
+            pc
+        } else {
+            pc.saturating_sub(1)
+        };
+
+        let first = frames.len();
+        for function in symbolizer.functions(address as u64) {
+            frames.push(Frame {
+                pc,
+                function: function.name,
+                file: function.file,
+                line: function.line,
+                exception: exception && frames.len() == first,
+                inlined: function.inlined,
+            });
+        }
+
+        let row = match debug_info.frame_section.unwind_info_for_address(
+            &bases,
+            &mut ctx,
+            address as u64,
+            gimli::DebugFrame::cie_from_offset,
+        ) {
+            Ok(row) => row,
+            Err(_) => break,
+        };
+        let cfa = match row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => {
+                match registers.get(register.0 as usize) {
+                    Some(&base) => (base as i64 + offset) as u32,
+                    None => break,
+                }
+            }
+            gimli::CfaRule::Expression(_) => break,
+        };
+
+        // Registers as they were in the caller
+        let mut caller = registers;
+        for (n, value) in caller
+            .iter_mut()
+            .enumerate()
+            .filter(|&(n, _)| n != SP && n != PC)
+        {
+            *value = match row.register(gimli::Register(n as u16)) {
+                gimli::RegisterRule::Undefined | gimli::RegisterRule::SameValue => continue,
+                gimli::RegisterRule::Offset(offset) => match read((cfa as i64 + offset) as u32) {
+                    Some(value) => value,
+                    None => return Ok(frames),
+                },
+                gimli::RegisterRule::ValOffset(offset) => (cfa as i64 + offset) as u32,
+                gimli::RegisterRule::Register(r) => match registers.get(r.0 as usize) {
+                    Some(&value) => value,
+                    None => return Ok(frames),
+                },
+                _ => return Ok(frames),
+            };
+        }
+        caller[SP] = cfa;
+        caller[PC] = caller[LR];
+
+        // Returning to `EXC_RETURN` pops the frame the core pushed on exception entry
+        let exc_return = caller[PC];
+        exception = exc_return >> 8 == 0xff_ffff;
+        if exception {
+            let sp = caller[SP];
+            let stacked = match (0..8)
+                .map(|i| read(sp.wrapping_add(4 * i)))
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(stacked) => stacked,
+                None => break,
+            };
+            caller[..4].copy_from_slice(&stacked[..4]);
+            caller[12] = stacked[4];
+            caller[LR] = stacked[5];
+            caller[PC] = stacked[6];
+
+            // Bit 4 of `EXC_RETURN` is clear if the FPU registers were stacked as well, and
+            // bit 9 of the stacked `xPSR` is set if the stack was realigned
+            let mut size = if exc_return & 0x10 == 0 { 0x68 } else { 0x20 };
+            if stacked[7] & (1 << 9) != 0 {
+                size += 4;
+            }
+            caller[SP] = sp.wrapping_add(size);
+        }
+
+        // The stack grows down, so unwinding has to move up
+        if caller[PC] == 0 || caller[SP] < registers[SP] || caller == registers {
+            break;
+        }
+        registers = caller;
+    }
+
+    Ok(frames)
+}
 
-    pub fn write(&self, w: &mut impl Write, buf: &[u8]) -> std::io::Result<()> {
-        self.write_internal(w, true, 0, buf)
-    }
+/// Function names and source lines of code addresses, from the symbol table, the functions in
+/// the DWARF and the line programs
+#[derive(Debug, Clone, Default)]
+pub struct Symbolizer {
+    /// Address ranges and demangled names of the functions, sorted by address
+    functions: Vec<(Range<u64>, String)>,
+    /// Sorted by address
+    lines: Vec<LineRange>,
+    /// Paths of the source files, by the index in `LineRange`
+    files: Vec<String>,
+    /// Functions and inlined functions in the DWARF, sorted by address
+    scopes: Vec<Scope>,
+}
 
-    fn write_internal(
-        &self,
-        w: &mut impl Write,
-        first: bool,
-        depth: usize,
-        buf: &[u8],
-    ) -> std::io::Result<()> {
-        let pad = " ".repeat(depth * 4);
-        match &self.kind {
-            TypeKind::Struct(structure) => {
-                if !structure.named_children.is_empty() {
-                    println!(
-                        "{}{{",
-                        if first {
-                            format!("{} ", self.name)
-                        } else {
-                            "".into()
-                        }
-                    );
+/// A function with code at an address, see `Symbolizer::functions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    /// Demangled name
+    pub name: Option<String>,
+    /// Where in the function the address is, the call of the inlined function for all but the
+    /// innermost one
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    /// Inlined into the next function
+    pub inlined: bool,
+}
 
-                    for (name, typ) in &structure.named_children {
-                        let pad = " ".repeat((depth + 1) * 4);
-                        print!("{}{}: ", &pad, name);
-                        typ.write_internal(w, false, depth + 1, &buf[self.offset..])?;
-                    }
+/// Addresses with the code of a `DW_TAG_subprogram`, or of a `DW_TAG_inlined_subroutine`
+#[derive(Debug, Clone)]
+struct Scope {
+    range: Range<u64>,
+    name: Option<String>,
+    /// Number of scopes it is in, inlined code is deeper than the function it is inlined into
+    depth: usize,
+    /// Where an inlined function is called from, in the scope it is in
+    call: Option<(Option<String>, Option<u64>)>,
+}
 
-                    println!("{}}}{}", &pad, if first { "" } else { "," });
-                } else if !structure.indexed_children.is_empty() {
-                    println!(
-                        "{}(",
-                        if first {
-                            format!("{} ", self.name)
-                        } else {
-                            "".into()
-                        }
-                    );
+/// Addresses with code from one line of source
+#[derive(Debug, Clone)]
+struct LineRange {
+    range: Range<u64>,
+    file: Option<usize>,
+    line: Option<u64>,
+}
 
-                    for (_i, typ) in structure.indexed_children.iter().enumerate() {
-                        let pad = " ".repeat((depth + 1) * 4);
-                        print!("{}", &pad);
-                        typ.write_internal(w, false, depth + 1, &buf[self.offset..])?;
-                    }
+impl Symbolizer {
+    pub fn new(elf: &[u8]) -> Result<Self, anyhow::Error> {
+        use object::{ObjectSymbol, SymbolKind};
+
+        let object = object::File::parse(elf)?;
+        let mut functions: Vec<_> = object
+            .symbols()
+            .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.size() > 0)
+            .filter_map(|symbol| {
+                // The Thumb bit is set in the addresses of functions
+                let start = symbol.address() & !1;
+                let name = format!("{:#}", rustc_demangle::demangle(symbol.name().ok()?));
+                Some((start..start + symbol.size(), name))
+            })
+            .collect();
+        functions.sort_by_key(|(range, _)| range.start);
+
+        let mut lines = Vec::new();
+        let mut files = Vec::new();
+        let mut scopes = Vec::new();
+        let decompressed = decompress_sections(elf);
+        let debug_info = DebugInfo::from_raw(elf, &decompressed).unwrap();
+        let mut units = debug_info.get_units();
+        while let Some(unit_info) = debug_info.get_next_unit_info(&mut units) {
+            unit_info.list_lines(&mut lines, &mut files)?;
+            unit_info.list_scopes(&mut scopes)?;
+        }
+        lines.sort_by_key(|line| line.range.start);
+        scopes.sort_by_key(|scope| scope.range.start);
+
+        Ok(Symbolizer {
+            functions,
+            lines,
+            files,
+            scopes,
+        })
+    }
 
-                    println!("{}){}", &pad, if first { "" } else { "," });
-                }
-            }
-            TypeKind::Enum(enummeration) => {
-                print!("{}{}::", &pad, self.name);
-                let discriminant = buf[enummeration.discriminant_offset] as usize;
-                for (variant_name, variant) in &enummeration.variants {
-                    if variant.variant_value == discriminant {
-                        print!("{} ", variant_name);
-                        variant.write_internal(w, false, depth, &buf[self.offset..])?;
-                    }
+    /// The functions with code at `address`, from the innermost inlined one out to the one it
+    /// is all compiled into, with the source line in each
+    ///
+    /// Without DWARF for the address it is the function in the symbol table, if any.
+    pub fn functions(&self, address: u64) -> Vec<Function> {
+        let after = self
+            .scopes
+            .partition_point(|scope| scope.range.start <= address);
+        let mut scopes: Vec<_> = self.scopes[..after]
+            .iter()
+            .filter(|scope| scope.range.contains(&address))
+            .collect();
+        scopes.sort_by_key(|scope| std::cmp::Reverse(scope.depth));
+
+        let (file, line) = self.location(address);
+        let (mut file, mut line) = (file.map(String::from), line);
+        let mut functions = Vec::new();
+        for scope in scopes {
+            let name = match (&scope.name, &scope.call) {
+                (None, None) => self.function(address).map(String::from),
+                (name, _) => name.clone(),
+            };
+            functions.push(Function {
+                name,
+                file: file.clone(),
+                line,
+                inlined: scope.call.is_some(),
+            });
+
+            match &scope.call {
+                Some((call_file, call_line)) => {
+                    file = call_file.clone();
+                    line = *call_line;
                 }
+                None => break,
             }
-            TypeKind::Scalar(scalar) => {
-                scalar.printer.write(w, &buf[self.offset..])?;
+        }
 
-                if first {
-                } else {
-                    println!(",");
-                }
-            }
-            TypeKind::PlainVariant => {
-                if first {
-                } else {
-                    println!(",");
-                }
-            }
-            TypeKind::Pointer(typ) => {
-                print!("*");
-                typ.write_internal(w, first, depth, buf)?;
+        if functions.is_empty() {
+            if let Some(name) = self.function(address) {
+                functions.push(Function {
+                    name: Some(name.into()),
+                    file,
+                    line,
+                    inlined: false,
+                });
             }
-            TypeKind::Unknown => (),
         }
 
-        Ok(())
+        functions
     }
-}
-
-pub fn generate_printers(elf: &[u8]) -> Result<TypePrinters, anyhow::Error> {
-    // Namespace tracker
-    let mut _namespace_tracker: Vec<String> = Vec::new();
 
-    // Where printers are stored
-    let mut printers: HashMap<String, Type> = HashMap::new();
+    /// Demangled name of the function with the code at `address`
+    pub fn function(&self, address: u64) -> Option<&str> {
+        let after = self
+            .functions
+            .partition_point(|(range, _)| range.start <= address);
+        let (range, name) = self.functions[..after].last()?;
 
-    let debug_info = DebugInfo::from_raw(elf).unwrap();
-    let mut units = debug_info.get_units();
-    while let Some(unit_info) = debug_info.get_next_unit_info(&mut units) {
-        let types = unit_info.list_types().unwrap();
-        printers.extend(types.into_iter().map(|t| (t.name().to_string(), t)));
+        Some(name.as_str()).filter(|_| range.contains(&address))
     }
 
-    Ok(TypePrinters(printers))
+    /// Source file and line of the code at `address`
+    pub fn location(&self, address: u64) -> (Option<&str>, Option<u64>) {
+        let after = self
+            .lines
+            .partition_point(|line| line.range.start <= address);
+        match self.lines[..after].last() {
+            Some(line) if line.range.contains(&address) => {
+                (line.file.map(|file| self.files[file].as_str()), line.line)
+            }
+            _ => (None, None),
+        }
+    }
 }
 
 /// Helper types to reduce signature bloat.
-type R = gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>;
-type DwarfReader = gimli::read::EndianRcSlice<gimli::LittleEndian>;
-type UnitIter =
-    gimli::DebugInfoUnitHeadersIter<gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>>;
-type NamespaceDie<'abbrev, 'unit> = gimli::DebuggingInformationEntry<
-    'abbrev,
-    'unit,
-    gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>,
-    usize,
->;
-type EntriesCursor<'abbrev, 'unit> = gimli::EntriesCursor<
-    'abbrev,
-    'unit,
-    gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>,
->;
+type R<'data> = gimli::EndianSlice<'data, gimli::LittleEndian>;
+type UnitIter<'data> = gimli::DebugInfoUnitHeadersIter<R<'data>>;
+type NamespaceDie<'abbrev, 'unit, 'data> =
+    gimli::DebuggingInformationEntry<'abbrev, 'unit, R<'data>, usize>;
+type EntriesCursor<'abbrev, 'unit, 'data> = gimli::EntriesCursor<'abbrev, 'unit, R<'data>>;
+
+/// Debug sections that are compressed in the ELF, by name, decompressed up front so the DWARF
+/// readers can borrow every section
+type Decompressed = HashMap<String, Vec<u8>>;
+
+fn decompress_sections(data: &[u8]) -> Decompressed {
+    let object = object::File::parse(data).unwrap();
+
+    object
+        .sections()
+        .filter_map(|section| match section.uncompressed_data() {
+            Ok(borrow::Cow::Owned(uncompressed)) => {
+                Some((section.name().ok()?.to_string(), uncompressed))
+            }
+            _ => None,
+        })
+        .collect()
+}
 
 /// This struct contains all the necessary debug info we might need during our traversal.
-pub struct DebugInfo {
-    dwarf: gimli::Dwarf<DwarfReader>,
-    _frame_section: gimli::DebugFrame<DwarfReader>,
+pub struct DebugInfo<'data> {
+    dwarf: gimli::Dwarf<R<'data>>,
+    frame_section: gimli::DebugFrame<R<'data>>,
 }
 
-impl DebugInfo {
-    /// Parse debug information directly from a buffer containing an ELF file.
-    fn from_raw(data: &[u8]) -> Result<Self, ()> {
+impl<'data> DebugInfo<'data> {
+    /// Parse debug information directly from a buffer containing an ELF file, borrowing the
+    /// sections from it or from `decompressed`
+    fn from_raw(data: &'data [u8], decompressed: &'data Decompressed) -> Result<Self, ()> {
         let object = object::File::parse(data).unwrap();
 
-        // Load a section and return as `Cow<[u8]>`.
-        let load_section = |id: gimli::SectionId| -> Result<DwarfReader, gimli::Error> {
-            let data = object
-                .section_by_name(id.name())
-                .and_then(|section| section.uncompressed_data().ok())
-                .unwrap_or_else(|| borrow::Cow::Borrowed(&[][..]));
+        // Load a section as a slice of the ELF
+        let load_section = |id: gimli::SectionId| -> Result<R<'data>, gimli::Error> {
+            let data = match decompressed.get(id.name()) {
+                Some(data) => &data[..],
+                None => object
+                    .section_by_name(id.name())
+                    .and_then(|section| section.data().ok())
+                    .unwrap_or(&[]),
+            };
 
-            Ok(gimli::read::EndianRcSlice::new(
-                Rc::from(&*data),
-                gimli::LittleEndian,
-            ))
+            Ok(gimli::EndianSlice::new(data, gimli::LittleEndian))
         };
         // Load a supplementary section. We don't have a supplementary object file,
         // so always return an empty slice.
-        let load_section_sup = |_| {
-            Ok(gimli::read::EndianRcSlice::new(
-                Rc::from(&*borrow::Cow::Borrowed(&[][..])),
-                gimli::LittleEndian,
-            ))
-        };
+        let load_section_sup = |_| Ok(gimli::EndianSlice::new(&[][..], gimli::LittleEndian));
 
         // Load all of the sections.
         let dwarf_cow = gimli::Dwarf::load(&load_section, &load_section_sup).unwrap();
@@ -413,17 +1816,17 @@ impl DebugInfo {
         Ok(DebugInfo {
             //object,
             dwarf: dwarf_cow,
-            _frame_section: frame_section,
+            frame_section,
         })
     }
 
     /// Returns an iterator over all the units in the currently open DWARF blob.
-    fn get_units(&self) -> UnitIter {
+    fn get_units(&self) -> UnitIter<'data> {
         self.dwarf.units()
     }
 
     /// Get the next unit in the unit iterator given.
-    fn get_next_unit_info(&self, units: &mut UnitIter) -> Option<UnitInfo> {
+    fn get_next_unit_info(&self, units: &mut UnitIter<'data>) -> Option<UnitInfo<'_, 'data>> {
         while let Ok(Some(header)) = units.next() {
             if let Ok(unit) = self.dwarf.unit(header) {
                 return Some(UnitInfo {
@@ -436,15 +1839,15 @@ impl DebugInfo {
     }
 }
 
-struct UnitInfo<'debuginfo> {
-    debug_info: &'debuginfo DebugInfo,
-    unit: gimli::Unit<gimli::EndianReader<gimli::LittleEndian, std::rc::Rc<[u8]>>, usize>,
+struct UnitInfo<'debuginfo, 'data> {
+    debug_info: &'debuginfo DebugInfo<'data>,
+    unit: gimli::Unit<R<'data>, usize>,
 }
 
-impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
+impl<'debuginfo, 'data> UnitInfo<'debuginfo, 'data> {
     /// Extracts the string representation of any string in the DWARF blob.
     /// This is mostly used to extract names of DIEs.
-    fn extract_string_of(&self, attr: &gimli::Attribute<R>) -> Option<String> {
+    fn extract_string_of(&self, attr: &gimli::Attribute<R<'data>>) -> Option<String> {
         match attr.value() {
             gimli::AttributeValue::DebugStrRef(name_ref) => {
                 let name_raw = self.debug_info.dwarf.string(name_ref).unwrap();
@@ -463,7 +1866,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
 
     fn walk_namespace(
         &self,
-        node: EntriesTreeNode<R>,
+        node: EntriesTreeNode<R<'data>>,
         mut current_namespace: Vec<String>,
     ) -> Result<Vec<Type>, ()> {
         let mut tree = self.unit.entries_tree(Some(node.entry().offset())).unwrap();
@@ -502,10 +1905,405 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
         Ok(types)
     }
 
+    fn list_log_sites(
+        &self,
+        sites: &mut HashMap<(String, String), LogSite>,
+        types: &mut HashMap<(String, String), String>,
+        type_addresses: &mut HashMap<(String, String), u64>,
+    ) -> Result<(), gimli::Error> {
+        let mut tree = self.unit.entries_tree(None)?;
+        self.walk_log_sites(tree.root()?, &mut vec![], sites, types, type_addresses)
+    }
+
+    fn walk_log_sites(
+        &self,
+        node: EntriesTreeNode<R<'data>>,
+        namespace: &mut Vec<String>,
+        sites: &mut HashMap<(String, String), LogSite>,
+        types: &mut HashMap<(String, String), String>,
+        type_addresses: &mut HashMap<(String, String), u64>,
+    ) -> Result<(), gimli::Error> {
+        let entry = node.entry();
+        let tag = entry.tag();
+        let name = match entry.attr(gimli::DW_AT_name)? {
+            Some(attr) => self.extract_string_of(&attr),
+            None => None,
+        };
+
+        match (tag, &name) {
+            (gimli::DW_TAG_variable, Some(name)) => {
+                if let (Some((site_tag, level)), Some(address)) =
+                    (site_tag(name), self.address_of(entry)?)
+                {
+                    let line = entry
+                        .attr_value(gimli::DW_AT_decl_line)?
+                        .and_then(|line| line.udata_value());
+
+                    sites.insert(
+                        (namespace.join("::"), site_tag.to_string()),
+                        LogSite {
+                            address,
+                            file: self.decl_file(entry)?,
+                            line,
+                            module: Some(namespace.join("::")).filter(|m| !m.is_empty()),
+                            type_name: None,
+                            type_address: None,
+                            level,
+                            kv: name.starts_with("S_KV_"),
+                            formatted: name.starts_with("S_FMT_"),
+                        },
+                    );
+                }
+                if let (Some(tag), Some(address)) = (type_tag(name), self.address_of(entry)?) {
+                    type_addresses.insert((namespace.join("::"), tag.to_string()), address);
+                }
+            }
+            (gimli::DW_TAG_subprogram, Some(name)) => {
+                if let Some((site_tag, typ)) = site_type(name) {
+                    types.insert(
+                        (namespace.join("::"), site_tag.to_string()),
+                        typ.to_string(),
+                    );
+                }
+            }
+            _ => (),
+        }
+
+        let is_namespace = tag == gimli::DW_TAG_namespace;
+        if is_namespace {
+            namespace.push(name.unwrap_or_else(|| "<undefined>".to_string()));
+        }
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            self.walk_log_sites(child, namespace, sites, types, type_addresses)?;
+        }
+
+        if is_namespace {
+            namespace.pop();
+        }
+
+        Ok(())
+    }
+
+    fn list_enumerations(&self, enumerations: &mut Enumerations) -> Result<(), gimli::Error> {
+        let mut tree = self.unit.entries_tree(None)?;
+        self.walk_enumerations(tree.root()?, enumerations)
+    }
+
+    fn walk_enumerations(
+        &self,
+        node: EntriesTreeNode<R<'data>>,
+        enumerations: &mut Enumerations,
+    ) -> Result<(), gimli::Error> {
+        let entry = node.entry();
+        let enumeration = match entry.attr(gimli::DW_AT_name)? {
+            Some(attr) if entry.tag() == gimli::DW_TAG_enumeration_type => {
+                self.extract_string_of(&attr)
+            }
+            _ => None,
+        };
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match &enumeration {
+                Some(enumeration) if entry.tag() == gimli::DW_TAG_enumerator => {
+                    let name = match entry.attr(gimli::DW_AT_name)? {
+                        Some(attr) => self.extract_string_of(&attr),
+                        None => None,
+                    };
+                    // Unsigned unless the enumerator has a signed encoding
+                    let value = entry
+                        .attr_value(gimli::DW_AT_const_value)?
+                        .and_then(|value| match value {
+                            AttributeValue::Sdata(value) => Some(value),
+                            value => value.udata_value().map(|value| value as i64),
+                        });
+                    if let (Some(name), Some(value)) = (name, value) {
+                        enumerations
+                            .entry(enumeration.clone())
+                            .or_default()
+                            .insert(value, name);
+                    }
+                }
+                _ => self.walk_enumerations(child, enumerations)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn list_statics(&self, statics: &mut Vec<Static>) -> Result<(), gimli::Error> {
+        let mut tree = self.unit.entries_tree(None)?;
+        self.walk_statics(tree.root()?, &mut vec![], statics)
+    }
+
+    fn walk_statics(
+        &self,
+        node: EntriesTreeNode<R<'data>>,
+        namespace: &mut Vec<String>,
+        statics: &mut Vec<Static>,
+    ) -> Result<(), gimli::Error> {
+        let entry = node.entry();
+        let tag = entry.tag();
+        let name = match entry.attr(gimli::DW_AT_name)? {
+            Some(attr) => self.extract_string_of(&attr),
+            None => None,
+        };
+
+        if let (gimli::DW_TAG_variable, Some(name)) = (tag, &name) {
+            // Locals have no `DW_OP_addr` location, so only statics are found
+            let log_site = site_tag(name).is_some() || type_tag(name).is_some();
+            if let (false, Some(address)) = (log_site, self.address_of(entry)?) {
+                let (typ, size) = self.type_of(entry)?;
+                namespace.push(name.clone());
+                statics.push(Static {
+                    name: namespace.join("::"),
+                    address,
+                    size,
+                    typ,
+                });
+                namespace.pop();
+            }
+        }
+
+        let is_namespace = tag == gimli::DW_TAG_namespace;
+        if is_namespace {
+            namespace.push(name.unwrap_or_else(|| "<undefined>".to_string()));
+        }
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            self.walk_statics(child, namespace, statics)?;
+        }
+
+        if is_namespace {
+            namespace.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Printer and size of the type of a variable
+    fn type_of(
+        &self,
+        entry: &DebuggingInformationEntry<R<'data>>,
+    ) -> Result<(Option<Type>, Option<u64>), gimli::Error> {
+        let offset = match entry.attr_value(gimli::DW_AT_type)? {
+            Some(AttributeValue::UnitRef(offset)) => offset,
+            _ => return Ok((None, None)),
+        };
+        let mut tree = self.unit.entries_tree(Some(offset))?;
+        let root = tree.root()?;
+        let size = root
+            .entry()
+            .attr_value(gimli::DW_AT_byte_size)?
+            .and_then(|size| size.udata_value());
+
+        Ok((self.extract_type_of(root, vec![], 0), size))
+    }
+
+    /// Address of a static, from a `DW_OP_addr` location
+    fn address_of(
+        &self,
+        entry: &DebuggingInformationEntry<R<'data>>,
+    ) -> Result<Option<u64>, gimli::Error> {
+        if let Some(AttributeValue::Exprloc(expr)) = entry.attr_value(gimli::DW_AT_location)? {
+            let mut ops = expr.operations(self.unit.encoding());
+            if let Some(gimli::Operation::Address { address }) = ops.next()? {
+                return Ok(Some(address));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Path of the file a DIE is declared in, from the file table of the line program
+    fn decl_file(
+        &self,
+        entry: &DebuggingInformationEntry<R<'data>>,
+    ) -> Result<Option<String>, gimli::Error> {
+        self.file_attr(entry, gimli::DW_AT_decl_file)
+    }
+
+    /// Path of the file in the `attr` attribute of a DIE, e.g. `DW_AT_call_file`
+    fn file_attr(
+        &self,
+        entry: &DebuggingInformationEntry<R<'data>>,
+        attr: gimli::DwAt,
+    ) -> Result<Option<String>, gimli::Error> {
+        let index = match entry.attr_value(attr)? {
+            Some(AttributeValue::FileIndex(index)) => index,
+            _ => return Ok(None),
+        };
+        match &self.unit.line_program {
+            Some(program) => self.file_path(program.header(), index),
+            None => Ok(None),
+        }
+    }
+
+    /// The address ranges of the functions and inlined functions
+    fn list_scopes(&self, scopes: &mut Vec<Scope>) -> Result<(), gimli::Error> {
+        let mut tree = self.unit.entries_tree(None)?;
+        self.walk_scopes(tree.root()?, 0, scopes)
+    }
+
+    fn walk_scopes(
+        &self,
+        node: EntriesTreeNode<R<'data>>,
+        depth: usize,
+        scopes: &mut Vec<Scope>,
+    ) -> Result<(), gimli::Error> {
+        let entry = node.entry();
+        let tag = entry.tag();
+
+        let is_scope = tag == gimli::DW_TAG_subprogram || tag == gimli::DW_TAG_inlined_subroutine;
+        if is_scope {
+            let name = self.function_name(entry, 0)?;
+            let call = if tag == gimli::DW_TAG_inlined_subroutine {
+                let line = entry
+                    .attr_value(gimli::DW_AT_call_line)?
+                    .and_then(|line| line.udata_value());
+                Some((self.file_attr(entry, gimli::DW_AT_call_file)?, line))
+            } else {
+                None
+            };
+
+            // Declarations and functions that are only ever inlined have no addresses
+            let mut ranges = self.debug_info.dwarf.die_ranges(&self.unit, entry)?;
+            while let Some(range) = ranges.next()? {
+                if range.begin < range.end {
+                    scopes.push(Scope {
+                        range: range.begin..range.end,
+                        name: name.clone(),
+                        depth,
+                        call: call.clone(),
+                    });
+                }
+            }
+        }
+
+        let depth = if is_scope { depth + 1 } else { depth };
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            self.walk_scopes(child, depth, scopes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Demangled linkage name of a function, or its plain name, from the DIE it is an instance
+    /// or the definition of if it has neither
+    fn function_name(
+        &self,
+        entry: &DebuggingInformationEntry<R<'data>>,
+        hops: usize,
+    ) -> Result<Option<String>, gimli::Error> {
+        for attr in &[gimli::DW_AT_linkage_name, gimli::DW_AT_MIPS_linkage_name] {
+            if let Some(name) = entry.attr(*attr)?.and_then(|a| self.extract_string_of(&a)) {
+                return Ok(Some(format!("{:#}", rustc_demangle::demangle(&name))));
+            }
+        }
+        if let Some(name) = entry
+            .attr(gimli::DW_AT_name)?
+            .and_then(|attr| self.extract_string_of(&attr))
+        {
+            return Ok(Some(name));
+        }
+
+        // Only references within the unit are followed, and not in circles
+        for attr in &[gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+            if let Some(AttributeValue::UnitRef(offset)) = entry.attr_value(*attr)? {
+                if hops < 4 {
+                    let origin = self.unit.entry(offset)?;
+                    return self.function_name(&origin, hops + 1);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The addresses covered by each row of the line program, adding new source files to
+    /// `files`
+    fn list_lines(
+        &self,
+        lines: &mut Vec<LineRange>,
+        files: &mut Vec<String>,
+    ) -> Result<(), gimli::Error> {
+        let program = match self.unit.line_program.clone() {
+            Some(program) => program,
+            None => return Ok(()),
+        };
+
+        // Index in `files` by file index of the line program
+        let mut indices = HashMap::new();
+
+        // Rows are in address order within a sequence, a row covers the addresses up to the next
+        let mut rows = program.rows();
+        let mut previous: Option<(u64, u64, Option<u64>)> = None;
+        while let Some((header, row)) = rows.next_row()? {
+            if let Some((start, index, line)) = previous {
+                if start < row.address() {
+                    let file = match indices.get(&index) {
+                        Some(&file) => file,
+                        None => {
+                            let file = self.file_path(header, index)?.map(|path| {
+                                files.push(path);
+                                files.len() - 1
+                            });
+                            indices.insert(index, file);
+                            file
+                        }
+                    };
+                    lines.push(LineRange {
+                        range: start..row.address(),
+                        file,
+                        line,
+                    });
+                }
+            }
+
+            previous = if row.end_sequence() {
+                None
+            } else {
+                Some((row.address(), row.file_index(), row.line()))
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Path of the file with `index` in the file table of a line program
+    fn file_path(
+        &self,
+        header: &gimli::LineProgramHeader<R<'data>>,
+        index: u64,
+    ) -> Result<Option<String>, gimli::Error> {
+        let file = match header.file(index) {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let dwarf = &self.debug_info.dwarf;
+        let mut path = PathBuf::new();
+        if let Some(directory) = file.directory(header) {
+            path.push(&*dwarf.attr_string(&self.unit, directory)?.to_string_lossy());
+        }
+        path.push(
+            &*dwarf
+                .attr_string(&self.unit, file.path_name())?
+                .to_string_lossy(),
+        );
+
+        Ok(Some(path.display().to_string()))
+    }
+
     /// Returns the type that `node` represents.
     fn extract_type_of(
         &self,
-        node: EntriesTreeNode<R>,
+        node: EntriesTreeNode<R<'data>>,
         current_namespace: Vec<String>,
         offset: usize,
     ) -> Option<Type> {
@@ -523,23 +2321,16 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                         offset,
                     ));
                 }
-                let mut named_children = std::collections::HashMap::new();
+                let mut named_children = Vec::new();
                 let mut indexed_children = Vec::new();
-                let mut variants = std::collections::HashMap::new();
+                let mut variants = Vec::new();
                 let mut discriminant_offset: usize = 0;
 
                 let mut children = node.children();
                 while let Ok(Some(child)) = children.next() {
                     let entry = child.entry();
-                    println!("{}", entry.tag());
                     match entry.tag() {
                         gimli::DW_TAG_member => {
-                            let mut attrs = entry.attrs();
-                            while let Ok(Some(attr)) = attrs.next() {
-                                match attr.name() {
-                                    _attr => println!("{}", _attr),
-                                }
-                            }
                             let (name, typ) =
                                 self.extract_member_of(child, current_namespace.clone(), offset);
                             if name.starts_with("__") {
@@ -554,7 +2345,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                                     )),
                                 );
                             } else {
-                                named_children.insert(
+                                named_children.push((
                                     name.clone(),
                                     typ.unwrap_or(Type::new(
                                         TypeKind::Unknown,
@@ -562,7 +2353,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                                         current_namespace.clone(),
                                         offset,
                                     )),
-                                );
+                                ));
                             }
                         }
                         gimli::DW_TAG_variant_part => {
@@ -592,7 +2383,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                                             }
                                         }
                                     }
-                                    _attr => println!("{}", _attr),
+                                    _ => {}
                                 }
                             }
 
@@ -601,16 +2392,13 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                                 let entry = child.entry();
 
                                 if entry.tag() == gimli::DW_TAG_variant {
-                                    println!("VARIANT");
                                     let mut discriminant_value: usize = 0;
                                     let mut attrs = entry.attrs();
                                     while let Ok(Some(attr)) = attrs.next() {
                                         match attr.name() {
                                             gimli::DW_AT_discr_value => {
-                                                println!("XXX");
                                                 if let AttributeValue::Data1(s) = attr.value() {
                                                     discriminant_value = s.try_into().unwrap();
-                                                    println!("{}", discriminant_value);
                                                 }
                                             }
                                             _ => {}
@@ -656,7 +2444,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                                                     }))
                                                     .unwrap();
                                                 let root = tree.root().unwrap();
-                                                variants.insert(
+                                                variants.push((
                                                     name.clone(),
                                                     self.extract_type_of(
                                                         root,
@@ -673,7 +2461,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                                                         current_namespace.clone(),
                                                         offset,
                                                     )),
-                                                );
+                                                ));
                                             }
                                         }
                                     }
@@ -728,7 +2516,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                     ));
                 }
             }
-            t => println!("Unknown type class: {}", t),
+            tag => log::trace!("No printer for {} at {:?}", tag, entry.offset()),
         };
 
         return None;
@@ -737,7 +2525,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
     /// Returns the member that `node` represents.
     fn extract_member_of(
         &self,
-        node: EntriesTreeNode<R>,
+        node: EntriesTreeNode<R<'data>>,
         current_namespace: Vec<String>,
         mut offset: usize,
     ) -> (String, Option<Type>) {
@@ -759,12 +2547,11 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
                         offset = s.try_into().unwrap();
                     }
                 }
-                _attr => println!("{}", _attr),
+                _ => {}
             }
         }
 
         let typ = if let Some(type_attr) = type_attr {
-            dbg!(type_attr.value());
             let mut tree = self
                 .unit
                 .entries_tree(Some(match type_attr.value() {
@@ -817,7 +2604,7 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
     /// Returns all the types in the current DIE.
     fn get_types(
         &self,
-        node: EntriesTreeNode<R>,
+        node: EntriesTreeNode<R<'data>>,
         current_namespace: Vec<String>,
     ) -> Result<Vec<Type>, ()> {
         let mut types = vec![];
@@ -825,7 +2612,8 @@ impl<'debuginfo, 'abbrev, 'unit> UnitInfo<'debuginfo> {
         let mut children = node.children();
 
         while let Ok(Some(current)) = children.next() {
-            if let gimli::DW_TAG_structure_type = current.entry().tag() {
+            // Base types too, for a `log!` of a bare `u32` or `f32`
+            if let gimli::DW_TAG_structure_type | gimli::DW_TAG_base_type = current.entry().tag() {
                 if let Some(typ) = self.extract_type_of(current, current_namespace.clone(), 0) {
                     types.push(typ);
                 }
@@ -846,11 +2634,11 @@ pub struct Variable {
 }
 
 #[derive(Clone)]
-struct DieCursorState<'abbrev, 'unit> {
-    entries_cursor: EntriesCursor<'abbrev, 'unit>,
+struct DieCursorState<'abbrev, 'unit, 'data> {
+    entries_cursor: EntriesCursor<'abbrev, 'unit, 'data>,
     _depth: isize,
     name: String,
-    namespace_die: NamespaceDie<'abbrev, 'unit>,
+    namespace_die: NamespaceDie<'abbrev, 'unit, 'data>,
 }
 
 #[allow(dead_code)]
@@ -936,6 +2724,33 @@ impl DwTagExt for gimli::DwTag {
 mod tests {
     use crate::*;
 
+    #[test]
+    fn log_site_names() {
+        assert_eq!(site_tag("S_T12"), Some(("T12", None)));
+        assert_eq!(site_tag("S_WARN_T3"), Some(("T3", Some("warn"))));
+        assert_eq!(site_tag("S_ERROR_T4"), Some(("T4", Some("error"))));
+        assert_eq!(site_tag("S_TRACE_T5"), Some(("T5", Some("trace"))));
+        assert_eq!(site_tag("S_INFO_T6"), Some(("T6", Some("info"))));
+        assert_eq!(site_tag("S_KV_T7"), Some(("T7", None)));
+        assert_eq!(site_tag("S_FMT_T8"), Some(("T8", None)));
+        assert_eq!(site_tag("S_ABCD"), None);
+        assert_eq!(site_tag("S_T"), None);
+        assert_eq!(site_tag("S_WARN_ABCD"), None);
+        assert_eq!(site_tag("TYPE_T3"), None);
+        assert_eq!(type_tag("TYPE_T3"), Some("T3"));
+        assert_eq!(type_tag("TYPE_ABCD"), None);
+        assert_eq!(type_tag("S_T3"), None);
+        assert_eq!(
+            site_type("__dwarffmt_this_is_for_searching_the_dwarf_T3<app::Foo<u8>>"),
+            Some(("T3", "app::Foo<u8>"))
+        );
+        assert_eq!(
+            site_type("__dwarffmt_this_is_for_searching_the_dwarf_T3"),
+            None
+        );
+        assert_eq!(site_type("main"), None);
+    }
+
     #[test]
     fn range_overlap_1() {
         let r1 = 0..3;
@@ -985,6 +2800,290 @@ mod tests {
         println!();
     }
 
+    #[test]
+    fn annotated_fields() {
+        let scalar = |ate, name: &str, size, offset| {
+            Type::new(
+                TypeKind::new_from_base_type(ate, name, size),
+                name.into(),
+                vec![],
+                offset,
+            )
+        };
+        let mut typ = Type::new(
+            TypeKind::Struct(Struct {
+                named_children: vec![
+                    (
+                        "vbat_mv".into(),
+                        scalar(constants::DW_ATE_unsigned, "u16", 2, 0),
+                    ),
+                    ("temp".into(), scalar(constants::DW_ATE_signed, "i16", 2, 2)),
+                    (
+                        "count".into(),
+                        scalar(constants::DW_ATE_unsigned, "u8", 1, 4),
+                    ),
+                ],
+                indexed_children: vec![],
+            }),
+            "Battery".into(),
+            vec![],
+            0,
+        );
+
+        let mut annotations = Annotations::default();
+        annotations.fields.insert(
+            "vbat_mv".to_string(),
+            Annotation {
+                unit: Some("V".into()),
+                scale: Some(0.001),
+                precision: Some(3),
+                ..Annotation::default()
+            },
+        );
+        annotations.fields.insert(
+            "Battery.temp".to_string(),
+            Annotation {
+                unit: Some("°C".into()),
+                scale: Some(0.5),
+                ..Annotation::default()
+            },
+        );
+        annotations.fields.insert(
+            "Other.count".to_string(),
+            Annotation {
+                unit: Some("ignored".into()),
+                ..Annotation::default()
+            },
+        );
+        typ.annotate(&annotations);
+
+        let mut out = Vec::new();
+        let buf = [0xe4, 0x0c, 0xf6, 0xff, 3];
+        typ.write_with(&mut out, &buf, &FormatOptions::default())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Battery {\n    vbat_mv: 3.300 V,\n    temp: -5 °C,\n    count: 3,\n}\n"
+        );
+    }
+
+    #[test]
+    fn fixed_point() {
+        let i16_at = |offset| {
+            Type::new(
+                TypeKind::new_from_base_type(constants::DW_ATE_signed, "i16", 2),
+                "i16".into(),
+                vec![],
+                offset,
+            )
+        };
+        let q15 = |offset| {
+            Type::new(
+                TypeKind::Struct(Struct {
+                    named_children: vec![],
+                    indexed_children: vec![i16_at(0)],
+                }),
+                "Q15".into(),
+                vec![],
+                offset,
+            )
+        };
+        let mut typ = Type::new(
+            TypeKind::Struct(Struct {
+                named_children: vec![("gain".into(), q15(0)), ("raw".into(), i16_at(2))],
+                indexed_children: vec![],
+            }),
+            "Filter".into(),
+            vec![],
+            0,
+        );
+
+        let mut annotations = Annotations::default();
+        annotations.types.insert(
+            "Q15".into(),
+            Annotation {
+                fractional_bits: Some(15),
+                ..Annotation::default()
+            },
+        );
+        annotations.fields.insert(
+            "raw".into(),
+            Annotation {
+                fractional_bits: Some(8),
+                unit: Some("A".into()),
+                ..Annotation::default()
+            },
+        );
+        typ.annotate(&annotations);
+
+        let mut out = Vec::new();
+        let buf = [0x00, 0xc0, 0x80, 0x01];
+        typ.write(&mut out, &buf).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Filter {\n    gain: (\n        -0.5 (-16384),\n    ),\n    raw: 1.5 (384) A,\n}\n"
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let u8_at = |offset| {
+            Type::new(
+                TypeKind::new_from_base_type(constants::DW_ATE_unsigned, "u8", 1),
+                "u8".into(),
+                vec![],
+                offset,
+            )
+        };
+        let mut typ = Type::new(
+            TypeKind::Struct(Struct {
+                named_children: vec![("code".into(), u8_at(0)), ("last".into(), u8_at(1))],
+                indexed_children: vec![],
+            }),
+            "Status".into(),
+            vec![],
+            0,
+        );
+
+        let mut annotations = Annotations::default();
+        annotations.types.insert(
+            "u8".into(),
+            Annotation {
+                enumeration: Some("ErrorCode".into()),
+                ..Annotation::default()
+            },
+        );
+        annotations.fields.insert(
+            "Status.code".into(),
+            Annotation {
+                constants: vec![(0x24, "ERR_BUSY".to_string())].into_iter().collect(),
+                enumeration: Some("ErrorCode".into()),
+                ..Annotation::default()
+            },
+        );
+
+        let mut enumerations = Enumerations::new();
+        enumerations.insert(
+            "ErrorCode".into(),
+            vec![(0x23, "ERR_TIMEOUT".into()), (0x24, "ERR_NACK".into())]
+                .into_iter()
+                .collect(),
+        );
+        annotations.resolve(&enumerations).unwrap();
+        typ.annotate(&annotations);
+
+        // The table takes precedence over the enumeration, and values without a name are
+        // printed as they are
+        let print = |buf: &[u8], options: &FormatOptions| {
+            let mut out = Vec::new();
+            typ.write_with(&mut out, buf, options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            print(&[0x24, 0x24], &FormatOptions::default()),
+            "Status {\n    code: ERR_BUSY (0x24),\n    last: ERR_NACK (0x24),\n}\n"
+        );
+        let options = FormatOptions {
+            width: Some(20),
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            print(&[0x23, 7], &options),
+            "Status {\n    code: ERR_TIMEOUT (0x23)  ,\n    last:                    7,\n}\n"
+        );
+
+        let mut annotations = Annotations::default();
+        annotations.fields.insert(
+            "code".into(),
+            Annotation {
+                enumeration: Some("Missing".into()),
+                ..Annotation::default()
+            },
+        );
+        assert!(annotations.resolve(&enumerations).is_err());
+    }
+
+    #[test]
+    fn bitflags() {
+        let u8_at = |offset| {
+            Type::new(
+                TypeKind::new_from_base_type(constants::DW_ATE_unsigned, "u8", 1),
+                "u8".into(),
+                vec![],
+                offset,
+            )
+        };
+        // As generated by `bitflags!`
+        let flags = |offset| {
+            Type::new(
+                TypeKind::Struct(Struct {
+                    named_children: vec![("bits".into(), u8_at(0))],
+                    indexed_children: vec![],
+                }),
+                "Flags".into(),
+                vec![],
+                offset,
+            )
+        };
+        let mut typ = Type::new(
+            TypeKind::Struct(Struct {
+                named_children: vec![
+                    ("events".into(), flags(0)),
+                    ("status".into(), u8_at(1)),
+                    ("other".into(), flags(2)),
+                ],
+                indexed_children: vec![],
+            }),
+            "Radio".into(),
+            vec![],
+            0,
+        );
+        let print = |typ: &Type, buf: &[u8]| {
+            let mut out = Vec::new();
+            typ.write(&mut out, buf).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        // Without names the bits are shown in hex
+        assert_eq!(
+            print(&typ, &[0x05, 0x05, 0]),
+            "Radio {\n    events: Flags(0x5),\n    status: 5,\n    other: Flags(0x0),\n}\n"
+        );
+
+        let names: BTreeMap<u64, String> = vec![
+            (0x01, "RX_DONE".to_string()),
+            (0x04, "CRC_ERR".to_string()),
+            (0x05, "ANY".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let mut annotations = Annotations::default();
+        annotations.types.insert(
+            "Flags".into(),
+            Annotation {
+                flags: names.clone(),
+                ..Annotation::default()
+            },
+        );
+        annotations.fields.insert(
+            "status".into(),
+            Annotation {
+                flags: names,
+                ..Annotation::default()
+            },
+        );
+        typ.annotate(&annotations);
+
+        assert_eq!(
+            print(&typ, &[0x45, 0x04, 0]),
+            "Radio {\n    events: Flags(RX_DONE | CRC_ERR | 0x40),\n    status: CRC_ERR,\n    \
+             other: Flags(empty),\n}\n"
+        );
+        let mut typ = flags(0);
+        typ.annotate(&annotations);
+        assert_eq!(print(&typ, &[0x05]), "Flags(RX_DONE | CRC_ERR)");
+    }
+
     #[test]
     fn print_tree() {
         // let mut tree = PrinterTree::new();
@@ -1,4 +1,4 @@
-use elf_test::generate_printers;
+use elf_test::{generate_printers, Snapshot};
 
 use std::fs;
 use std::path::PathBuf;
@@ -8,6 +8,10 @@ use structopt::StructOpt;
 struct Opts {
     #[structopt(name = "FILE", parse(from_os_str))]
     elf: PathBuf,
+
+    /// Print the initial value of every static, as it is in the ELF before the target runs
+    #[structopt(long)]
+    statics: bool,
 }
 
 //
@@ -25,10 +29,16 @@ struct Opts {
 
 fn main() -> Result<(), anyhow::Error> {
     let opts = Opts::from_args();
-    println!("opts: {:#?}", opts.elf);
+    let file = fs::File::open(&opts.elf)?;
+    // Safety: the ELF must not change while it is mapped
+    let bytes = unsafe { memmap2::Mmap::map(&file)? };
 
-    let bytes = fs::read(opts.elf)?;
+    if opts.statics {
+        let stdout = std::io::stdout();
+        return Snapshot::from_elf(&bytes)?.write_statics(&bytes, &mut stdout.lock());
+    }
 
+    log::debug!("ELF: {}", opts.elf.display());
     let _printers = generate_printers(&bytes)?;
 
     Ok(())
@@ -0,0 +1,166 @@
+//! Unwind a simulated Cortex-M stack with the call frame information of the fixture in
+//! `tests/fixtures/backtrace`, halted in an exception handler that interrupted a nested call.
+
+use elf_test::{backtrace, Frame, Function, Symbolizer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const EXC_RETURN: u32 = 0xffff_fff9;
+
+#[test]
+fn unwind_through_exception() {
+    let path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/backtrace/backtrace.elf");
+    let elf = fs::read(path).unwrap();
+
+    // The stack from the handler up, return addresses have the Thumb bit set
+    let sp = 0x2000_0fc0;
+    let stack = [
+        // Handler: r7, lr
+        0x77,
+        EXC_RETURN,
+        // Exception frame: r0-r3, r12, lr, pc, xpsr
+        0,
+        1,
+        2,
+        3,
+        12,
+        0x0002_00c3,
+        0x0002_00c8,
+        0x0100_0000,
+        // inner: locals, r4, lr
+        0,
+        0,
+        0x44,
+        0x0002_00c3,
+        // main: r7, lr
+        0x77,
+        0x0002_00b9,
+    ];
+    let memory: HashMap<u32, u32> = stack
+        .iter()
+        .enumerate()
+        .map(|(i, &word)| (sp + 4 * i as u32, word))
+        .collect();
+
+    let mut registers = [0; 16];
+    registers[13] = sp;
+    registers[14] = EXC_RETURN;
+    registers[15] = 0x0002_00d0;
+
+    let frames = backtrace(&elf, registers, |address| memory.get(&address).copied()).unwrap();
+
+    let frame = |pc, function: &str, line, exception| Frame {
+        pc,
+        function: Some(function.into()),
+        file: Some("backtrace.rs".into()),
+        line: Some(line),
+        exception,
+        inlined: false,
+    };
+    assert_eq!(
+        frames,
+        [
+            frame(0x0002_00d0, "Handler", 17, false),
+            frame(0x0002_00c8, "inner", 12, true),
+            // Return addresses are looked up at the call
+            frame(0x0002_00c2, "main", 7, false),
+            frame(0x0002_00b8, "Reset", 2, false),
+        ]
+    );
+}
+
+#[test]
+fn unwind_stops_at_unreadable_stack() {
+    let path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/backtrace/backtrace.elf");
+    let elf = fs::read(path).unwrap();
+
+    let mut registers = [0; 16];
+    registers[13] = 0x2000_0fc0;
+    registers[15] = 0x0002_00c8;
+
+    let frames = backtrace(&elf, registers, |_| None).unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].function.as_deref(), Some("inner"));
+}
+
+#[test]
+fn symbolize_addresses() {
+    let path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/backtrace/backtrace.elf");
+    let elf = fs::read(path).unwrap();
+    let symbolizer = Symbolizer::new(&elf).unwrap();
+
+    assert_eq!(symbolizer.function(0x0002_00c4), Some("inner"));
+    assert_eq!(symbolizer.function(0x0002_00cd), Some("inner"));
+    assert_eq!(symbolizer.function(0x0002_00d4), None);
+    assert_eq!(
+        symbolizer.location(0x0002_00c9),
+        (Some("backtrace.rs"), Some(12))
+    );
+    assert_eq!(
+        symbolizer.location(0x0002_00d2),
+        (Some("backtrace.rs"), Some(18))
+    );
+    assert_eq!(symbolizer.location(0x0002_00d4), (None, None));
+}
+
+#[test]
+fn symbolize_inlined_functions() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/inline/inline.elf");
+    let elf = fs::read(path).unwrap();
+    let symbolizer = Symbolizer::new(&elf).unwrap();
+
+    let function = |name: &str, line, inlined| Function {
+        name: Some(name.into()),
+        file: Some("inline.rs".into()),
+        line: Some(line),
+        inlined,
+    };
+
+    // `leaf` inlined into `helper` at line 11, inlined into `main` at line 7
+    assert_eq!(
+        symbolizer.functions(0x0002_00c2),
+        [
+            function("app::leaf", 15, true),
+            function("app::helper", 11, true),
+            function("app::main", 7, false),
+        ]
+    );
+    assert_eq!(
+        symbolizer.functions(0x0002_00c4),
+        [
+            function("app::helper", 12, true),
+            function("app::main", 7, false),
+        ]
+    );
+    assert_eq!(
+        symbolizer.functions(0x0002_00be),
+        [function("app::main", 7, false)]
+    );
+    assert_eq!(
+        symbolizer.functions(0x0002_00b4),
+        [function("Reset", 2, false)]
+    );
+    assert_eq!(symbolizer.functions(0x0002_00c8), []);
+
+    // Each inlined function is a frame of its own
+    let mut registers = [0; 16];
+    registers[13] = 0x2000_0fc0;
+    registers[15] = 0x0002_00c2;
+    let frames = backtrace(&elf, registers, |_| None).unwrap();
+    let names: Vec<_> = frames
+        .iter()
+        .map(|frame| (frame.pc, frame.function.as_deref().unwrap(), frame.inlined))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            (0x0002_00c2, "app::leaf", true),
+            (0x0002_00c2, "app::helper", true),
+            (0x0002_00c2, "app::main", false),
+        ]
+    );
+}
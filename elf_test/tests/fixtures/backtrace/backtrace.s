@@ -0,0 +1,104 @@
+@ Fixture for the unwinding test in `tests/backtrace.rs`, `Reset` calls `main`, which calls
+@ `inner`, which is interrupted by `Handler`. Build with:
+@
+@     llvm-mc -triple thumbv7em-none-eabi -filetype obj backtrace.s -o backtrace.o
+@     rust-lld -flavor gnu --entry Reset -o backtrace.elf backtrace.o
+
+    .syntax unified
+    .thumb
+    .cfi_sections .debug_frame
+    .file 1 "backtrace.rs"
+    .text
+.Ltext_start:
+
+    .globl Reset
+    .type Reset, %function
+    .thumb_func
+Reset:
+    .loc 1 2 0
+    bl main
+    .loc 1 3 0
+    b Reset
+    .size Reset, . - Reset
+
+    .globl main
+    .type main, %function
+    .thumb_func
+main:
+    .cfi_startproc
+    .loc 1 6 0
+    push {r7, lr}
+    .cfi_def_cfa_offset 8
+    .cfi_offset lr, -4
+    .cfi_offset r7, -8
+    .loc 1 7 0
+    bl inner
+    .loc 1 8 0
+    pop {r7, pc}
+    .cfi_endproc
+    .size main, . - main
+
+    .globl inner
+    .type inner, %function
+    .thumb_func
+inner:
+    .cfi_startproc
+    .loc 1 11 0
+    push {r4, lr}
+    .cfi_def_cfa_offset 8
+    .cfi_offset lr, -4
+    .cfi_offset r4, -8
+    sub sp, #8
+    .cfi_def_cfa_offset 16
+    .loc 1 12 0
+inner_interrupted:
+    nop
+    .loc 1 13 0
+    add sp, #8
+    pop {r4, pc}
+    .cfi_endproc
+    .size inner, . - inner
+
+    .globl Handler
+    .type Handler, %function
+    .thumb_func
+Handler:
+    .cfi_startproc
+    .loc 1 16 0
+    push {r7, lr}
+    .cfi_def_cfa_offset 8
+    .cfi_offset lr, -4
+    .cfi_offset r7, -8
+    .loc 1 17 0
+handler_halted:
+    bkpt #0
+    .loc 1 18 0
+    pop {r7, pc}
+    .cfi_endproc
+    .size Handler, . - Handler
+.Ltext_end:
+
+@ A compile unit pointing at the line program, which the assembler does not add for `.loc`
+    .section .debug_abbrev, "", %progbits
+.Labbrev:
+    .byte 1             @ Abbreviation code
+    .byte 0x11, 0       @ DW_TAG_compile_unit, no children
+    .byte 0x03, 0x08    @ DW_AT_name, DW_FORM_string
+    .byte 0x10, 0x17    @ DW_AT_stmt_list, DW_FORM_sec_offset
+    .byte 0x11, 0x01    @ DW_AT_low_pc, DW_FORM_addr
+    .byte 0x12, 0x06    @ DW_AT_high_pc, DW_FORM_data4
+    .byte 0, 0
+    .byte 0
+
+    .section .debug_info, "", %progbits
+    .word .Linfo_end - .Linfo_start
+.Linfo_start:
+    .short 4            @ Version
+    .word .Labbrev
+    .byte 4             @ Address size
+    .byte 1
+    .asciz "backtrace.rs"
+    .word 0
+    .word .Ltext_start
+    .word .Ltext_end - .Ltext_start
+.Linfo_end:
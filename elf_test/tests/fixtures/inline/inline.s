@@ -0,0 +1,160 @@
+@ Fixture for the symbolization of inlined code in `tests/backtrace.rs`, `helper` is inlined
+@ into `main`, and `leaf` into `helper`. Build with:
+@
+@     llvm-mc -triple thumbv7em-none-eabi -filetype obj inline.s -o inline.o
+@     rust-lld -flavor gnu --entry Reset -o inline.elf inline.o
+
+    .syntax unified
+    .thumb
+    .file 1 "inline.rs"
+    .text
+.Ltext_start:
+
+    .globl Reset
+    .type Reset, %function
+    .thumb_func
+Reset:
+.LReset_start:
+    .loc 1 2 0
+    bl _ZN3app4main17h0123456789abcdefE
+    b Reset
+.LReset_end:
+    .size Reset, . - Reset
+
+    .globl _ZN3app4main17h0123456789abcdefE
+    .type _ZN3app4main17h0123456789abcdefE, %function
+    .thumb_func
+_ZN3app4main17h0123456789abcdefE:
+.Lmain_start:
+    .loc 1 6 0
+    push {r7, lr}
+    .loc 1 7 0
+    movs r0, #0
+.Lhelper_start:
+    .loc 1 11 0
+    movs r0, #1
+.Lleaf_start:
+    .loc 1 15 0
+    adds r0, #2
+.Lleaf_end:
+    .loc 1 12 0
+    adds r0, #3
+.Lhelper_end:
+    .loc 1 8 0
+    pop {r7, pc}
+.Lmain_end:
+    .size _ZN3app4main17h0123456789abcdefE, . - _ZN3app4main17h0123456789abcdefE
+.Ltext_end:
+
+    .section .debug_abbrev, "", %progbits
+.Labbrev:
+    .byte 1             @ Abbreviation code
+    .byte 0x11, 1       @ DW_TAG_compile_unit, children
+    .byte 0x03, 0x08    @ DW_AT_name, DW_FORM_string
+    .byte 0x10, 0x17    @ DW_AT_stmt_list, DW_FORM_sec_offset
+    .byte 0x11, 0x01    @ DW_AT_low_pc, DW_FORM_addr
+    .byte 0x12, 0x06    @ DW_AT_high_pc, DW_FORM_data4
+    .byte 0, 0
+
+    .byte 2
+    .byte 0x2e, 0       @ DW_TAG_subprogram, no children
+    .byte 0x03, 0x08    @ DW_AT_name, DW_FORM_string
+    .byte 0x11, 0x01    @ DW_AT_low_pc, DW_FORM_addr
+    .byte 0x12, 0x06    @ DW_AT_high_pc, DW_FORM_data4
+    .byte 0, 0
+
+    .byte 3
+    .byte 0x2e, 1       @ DW_TAG_subprogram, children
+    .byte 0x6e, 0x08    @ DW_AT_linkage_name, DW_FORM_string
+    .byte 0x03, 0x08    @ DW_AT_name, DW_FORM_string
+    .byte 0x11, 0x01    @ DW_AT_low_pc, DW_FORM_addr
+    .byte 0x12, 0x06    @ DW_AT_high_pc, DW_FORM_data4
+    .byte 0, 0
+
+    .byte 4
+    .byte 0x1d, 1       @ DW_TAG_inlined_subroutine, children
+    .byte 0x31, 0x13    @ DW_AT_abstract_origin, DW_FORM_ref4
+    .byte 0x11, 0x01    @ DW_AT_low_pc, DW_FORM_addr
+    .byte 0x12, 0x06    @ DW_AT_high_pc, DW_FORM_data4
+    .byte 0x58, 0x0b    @ DW_AT_call_file, DW_FORM_data1
+    .byte 0x59, 0x0b    @ DW_AT_call_line, DW_FORM_data1
+    .byte 0, 0
+
+    .byte 5
+    .byte 0x1d, 0       @ DW_TAG_inlined_subroutine, no children
+    .byte 0x31, 0x13    @ DW_AT_abstract_origin, DW_FORM_ref4
+    .byte 0x55, 0x17    @ DW_AT_ranges, DW_FORM_sec_offset
+    .byte 0x58, 0x0b    @ DW_AT_call_file, DW_FORM_data1
+    .byte 0x59, 0x0b    @ DW_AT_call_line, DW_FORM_data1
+    .byte 0, 0
+
+    .byte 6
+    .byte 0x2e, 0       @ DW_TAG_subprogram, no children
+    .byte 0x6e, 0x08    @ DW_AT_linkage_name, DW_FORM_string
+    .byte 0x03, 0x08    @ DW_AT_name, DW_FORM_string
+    .byte 0x20, 0x0b    @ DW_AT_inline, DW_FORM_data1
+    .byte 0, 0
+    .byte 0
+
+    .section .debug_info, "", %progbits
+.Lunit:
+    .word .Linfo_end - .Linfo_start
+.Linfo_start:
+    .short 4            @ Version
+    .word .Labbrev
+    .byte 4             @ Address size
+
+    .byte 1             @ The compile unit
+    .asciz "inline.rs"
+    .word 0
+    .word .Ltext_start
+    .word .Ltext_end - .Ltext_start
+
+    .byte 2             @ Reset
+    .asciz "Reset"
+    .word .LReset_start
+    .word .LReset_end - .LReset_start
+
+    .byte 3             @ main
+    .asciz "_ZN3app4main17h0123456789abcdefE"
+    .asciz "main"
+    .word .Lmain_start
+    .word .Lmain_end - .Lmain_start
+
+    .byte 4             @ helper, inlined into main
+    .word .Lhelper - .Lunit
+    .word .Lhelper_start
+    .word .Lhelper_end - .Lhelper_start
+    .byte 1
+    .byte 7
+
+    .byte 5             @ leaf, inlined into helper
+    .word .Lleaf - .Lunit
+    .word .Lranges
+    .byte 1
+    .byte 11
+    .byte 0             @ End of helper
+    .byte 0             @ End of main
+
+.Lhelper:
+    .byte 6
+    .asciz "_ZN3app6helper17h0123456789abcdefE"
+    .asciz "helper"
+    .byte 1             @ DW_INL_inlined
+
+.Lleaf:
+    .byte 6
+    .asciz "_ZN3app4leaf17h0123456789abcdefE"
+    .asciz "leaf"
+    .byte 1
+
+    .byte 0             @ End of the compile unit
+.Linfo_end:
+
+@ Offsets from the low PC of the compile unit
+    .section .debug_ranges, "", %progbits
+.Lranges:
+    .word .Lleaf_start - .Ltext_start
+    .word .Lleaf_end - .Ltext_start
+    .word 0
+    .word 0
@@ -0,0 +1,197 @@
+// Fixture for the golden decoding of recorded frames in `log0_host`, each call site is expanded
+// by hand the way `log!` expands it. There is no `core`, so it builds for Cortex-M without the
+// standard library for it installed:
+//
+//     RUSTC_BOOTSTRAP=1 rustc --edition 2018 --target thumbv7em-none-eabi -C debuginfo=2 \
+//         -C panic=abort -C opt-level=0 -C link-arg=-Tlog.x -C link-arg=-zmax-page-size=4 \
+//         log.rs -o log-rustc-$(rustc -V | cut -d' ' -f2).elf
+//
+// `log-rustc-<version>.frames` is the frame stream of the calls in `main`, the payload of each
+// frame is the initial value of its static as it is in that ELF. `log.jsonl` is what the host
+// decodes from it, the same for every compiler version.
+//
+// The lang items are those of rustc 1.95, older compilers need fewer of them. Since 1.97 the
+// one for dropping is `drop_glue`, build with `--cfg drop_glue` there.
+
+#![feature(no_core, lang_items, auto_traits)]
+#![allow(internal_features, non_snake_case)]
+#![no_core]
+#![no_main]
+
+#[lang = "pointee_sized"]
+pub trait PointeeSized {}
+
+#[lang = "meta_sized"]
+pub trait MetaSized: PointeeSized {}
+
+#[lang = "sized"]
+pub trait Sized: MetaSized {}
+
+#[lang = "copy"]
+pub trait Copy {}
+
+impl Copy for u8 {}
+impl Copy for u32 {}
+impl<T: Copy, const N: usize> Copy for [T; N] {}
+
+#[lang = "sync"]
+pub unsafe trait Sync {}
+
+unsafe impl Sync for u8 {}
+unsafe impl Sync for u32 {}
+unsafe impl<const N: usize> Sync for [u8; N] {}
+unsafe impl Sync for (f32, u32) {}
+unsafe impl Sync for &str {}
+
+#[lang = "freeze"]
+pub unsafe auto trait Freeze {}
+
+#[cfg_attr(not(drop_glue), lang = "drop_in_place")]
+#[cfg_attr(drop_glue, lang = "drop_glue")]
+pub unsafe fn drop_in_place<T: PointeeSized>(_: *mut T) {}
+
+#[no_mangle]
+#[used]
+static mut LOG0_CURSORS: [u32; 4] = [0; 4];
+
+#[no_mangle]
+#[used]
+static mut LOG0_BUFFER: [u8; 64] = [0; 64];
+
+pub mod sensor {
+    pub struct Reading {
+        pub channel: u8,
+        pub volts: f32,
+    }
+
+    pub enum State {
+        Idle,
+        Busy { jobs: u16 },
+    }
+
+    unsafe impl super::Sync for Reading {}
+    unsafe impl super::Sync for State {}
+
+    pub static READING: Reading = Reading {
+        channel: 3,
+        volts: 1.25,
+    };
+    pub static COUNT: u32 = 7;
+    pub static STATE: State = State::Busy { jobs: 2 };
+    pub static IDLE: State = State::Idle;
+
+    pub fn read() {
+        // log!("reading {}", READING);
+        #[link_section = ".fasthosting.T0"]
+        static S_T0: [u8; 10] = *b"reading {}";
+        #[link_section = ".fasthosting.types.T0"]
+        static TYPE_T0: u8 = 0;
+        unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_T0<T>(
+            _sym: *const u8,
+            _type_str: *const u8,
+            _t: &T,
+        ) {
+        }
+        unsafe {
+            __dwarffmt_this_is_for_searching_the_dwarf_T0(
+                &S_T0 as *const _ as *const u8,
+                &TYPE_T0 as *const _,
+                &READING,
+            );
+        }
+
+        // log!("count = {}", COUNT);
+        #[link_section = ".fasthosting.T1"]
+        static S_T1: [u8; 10] = *b"count = {}";
+        #[link_section = ".fasthosting.types.T1"]
+        static TYPE_T1: u8 = 0;
+        unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_T1<T>(
+            _sym: *const u8,
+            _type_str: *const u8,
+            _t: &T,
+        ) {
+        }
+        unsafe {
+            __dwarffmt_this_is_for_searching_the_dwarf_T1(
+                &S_T1 as *const _ as *const u8,
+                &TYPE_T1 as *const _,
+                &COUNT,
+            );
+        }
+    }
+
+    pub fn report(state: &State) {
+        // warn!("state {}", state);
+        #[link_section = ".fasthosting.T2"]
+        static S_WARN_T2: [u8; 8] = *b"state {}";
+        #[link_section = ".fasthosting.types.T2"]
+        static TYPE_T2: u8 = 0;
+        unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_T2<T>(
+            _sym: *const u8,
+            _type_str: *const u8,
+            _t: &T,
+        ) {
+        }
+        unsafe {
+            __dwarffmt_this_is_for_searching_the_dwarf_T2(
+                &S_WARN_T2 as *const _ as *const u8,
+                &TYPE_T2 as *const _,
+                state,
+            );
+        }
+    }
+}
+
+pub static PAIR: (f32, u32) = (1.5, 2);
+pub static NAME: &str = "radio";
+
+pub fn main() {
+    // log!("a: {:.2}, b: {:#x}", PAIR.0, PAIR.1);
+    #[link_section = ".fasthosting.T3"]
+    static S_T3: [u8; 18] = *b"a: {:.2}, b: {:#x}";
+    #[link_section = ".fasthosting.types.T3"]
+    static TYPE_T3: u8 = 0;
+    unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_T3<T>(
+        _sym: *const u8,
+        _type_str: *const u8,
+        _t: &T,
+    ) {
+    }
+    unsafe {
+        __dwarffmt_this_is_for_searching_the_dwarf_T3(
+            &S_T3 as *const _ as *const u8,
+            &TYPE_T3 as *const _,
+            &PAIR,
+        );
+    }
+
+    // log!("name {}", NAME);
+    #[link_section = ".fasthosting.T4"]
+    static S_T4: [u8; 7] = *b"name {}";
+    #[link_section = ".fasthosting.types.T4"]
+    static TYPE_T4: u8 = 0;
+    unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_T4<T>(
+        _sym: *const u8,
+        _type_str: *const u8,
+        _t: &T,
+    ) {
+    }
+    unsafe {
+        __dwarffmt_this_is_for_searching_the_dwarf_T4(
+            &S_T4 as *const _ as *const u8,
+            &TYPE_T4 as *const _,
+            &NAME,
+        );
+    }
+
+    sensor::read();
+    sensor::report(&sensor::STATE);
+    sensor::report(&sensor::IDLE);
+}
+
+#[no_mangle]
+pub extern "C" fn Reset() -> ! {
+    main();
+
+    loop {}
+}
@@ -0,0 +1,25 @@
+/* Memory layout of the fixture in `log.rs`, with the `.fasthosting` sections of log0_target */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+  RAM : ORIGIN = 0x20000000, LENGTH = 64K
+}
+
+ENTRY(Reset);
+
+SECTIONS
+{
+  .text : { *(.text .text.*); } > FLASH
+  .rodata : { *(.rodata .rodata.*); } > FLASH
+  .bss (NOLOAD) : { *(.bss .bss.*); } > RAM
+
+  .fasthosting 0 (INFO) :
+  {
+    *(.fasthosting .fasthosting.T*);
+  }
+
+  .fasthosting.types ADDR(.fasthosting) + SIZEOF(.fasthosting) (INFO) :
+  {
+    *(.fasthosting.types .fasthosting.types.*);
+  }
+}
@@ -0,0 +1,84 @@
+// Fixture for the golden decoding tests in `tests/golden.rs`, covering the type shapes the
+// DWARF walker has to handle.
+//
+// To add a fixture for another compiler version, build with that `rustc` and name the output
+// after it, every `*.elf` in this folder is tested:
+//
+//     rustc --edition 2018 -C debuginfo=2 -C panic=abort -C opt-level=0 \
+//         --crate-type cdylib types.rs -o types-rustc-$(rustc -V | cut -d' ' -f2).elf
+
+#![no_std]
+
+pub mod mod1 {
+    pub mod mod2 {
+        pub struct MyStruct {
+            pub b: i32,
+            pub d: MyStruct2,
+        }
+
+        pub struct MyStruct2 {
+            pub a: i32,
+            pub c: u32,
+        }
+
+        pub struct MyStruct3(pub u8);
+    }
+}
+
+pub enum MyEnum {
+    Var1 { x: u8, y: f32 },
+    Var2((u8, f32)),
+    Var3,
+}
+
+pub struct Wrapper {
+    pub flag: bool,
+    pub tag: u16,
+    pub inner: MyEnum,
+    pub big: u64,
+    pub small: i8,
+}
+
+#[repr(u8)]
+pub enum ErrorCode {
+    Timeout = 0x23,
+    Nack = 0x24,
+}
+
+#[no_mangle]
+pub static TEST1: mod1::mod2::MyStruct = mod1::mod2::MyStruct {
+    b: 2,
+    d: mod1::mod2::MyStruct2 { a: -1, c: 3 },
+};
+
+#[no_mangle]
+pub static TEST2: (f32, u32) = (1.5, 2);
+
+#[no_mangle]
+pub static TEST3: MyEnum = MyEnum::Var2((1, 2.0));
+
+#[no_mangle]
+pub static TEST7: mod1::mod2::MyStruct3 = mod1::mod2::MyStruct3(42);
+
+#[no_mangle]
+pub static TEST8: MyEnum = MyEnum::Var1 { x: 42, y: 5.0 };
+
+#[no_mangle]
+pub static TEST9: MyEnum = MyEnum::Var3;
+
+#[no_mangle]
+pub static TEST10: Wrapper = Wrapper {
+    flag: true,
+    tag: 0xbeef,
+    inner: MyEnum::Var1 { x: 7, y: -0.25 },
+    big: 1 << 40,
+    small: -5,
+};
+
+#[no_mangle]
+pub static TEST11: ErrorCode = ErrorCode::Nack;
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
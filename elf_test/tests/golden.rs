@@ -0,0 +1,319 @@
+//! Decode statics from the pre-built fixtures in `tests/fixtures` and compare with the expected
+//! output, the bytes of each static are taken from the ELF so layout differences between
+//! compiler versions are covered as well.
+
+use elf_test::{
+    enumerations, generate_printers, Contents, FormatOptions, Snapshot, Type, TypeKind, Value,
+};
+use object::{Object, ObjectSection, ObjectSymbol};
+use std::fs;
+use std::path::PathBuf;
+
+/// (static, type name, expected output)
+const CASES: &[(&str, &str, &str)] = &[
+    (
+        "TEST1",
+        "MyStruct",
+        "MyStruct {
+    b: 2,
+    d: {
+        a: -1,
+        c: 3,
+    },
+}
+",
+    ),
+    (
+        "TEST2",
+        "(f32, u32)",
+        "(f32, u32) (
+    1.5,
+    2,
+)
+",
+    ),
+    (
+        "TEST3",
+        "MyEnum",
+        "MyEnum::Var2 (
+    (
+        1,
+        2,
+    ),
+)
+",
+    ),
+    (
+        "TEST7",
+        "MyStruct3",
+        "MyStruct3 (
+    42,
+)
+",
+    ),
+    (
+        "TEST8",
+        "MyEnum",
+        "MyEnum::Var1 {
+    x: 42,
+    y: 5,
+}
+",
+    ),
+    ("TEST9", "MyEnum", "MyEnum::Var3"),
+    (
+        "TEST10",
+        "Wrapper",
+        "Wrapper {
+    flag: true,
+    tag: 48879,
+    inner: MyEnum::Var1 {
+        x: 7,
+        y: -0.25,
+    },
+    big: 1099511627776,
+    small: -5,
+}
+",
+    ),
+];
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut fixtures: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "elf"))
+        .collect();
+    fixtures.sort();
+
+    assert!(!fixtures.is_empty(), "no fixtures found");
+
+    fixtures
+}
+
+/// The initial value of a static, as the target would send it
+fn static_bytes(elf: &[u8], name: &str) -> Vec<u8> {
+    let file = object::File::parse(elf).unwrap();
+    let symbol = file
+        .symbols()
+        .find(|symbol| symbol.name() == Ok(name))
+        .unwrap_or_else(|| panic!("missing symbol {}", name));
+    let section = file
+        .section_by_index(symbol.section_index().unwrap())
+        .unwrap();
+    let offset = (symbol.address() - section.address()) as usize;
+
+    section.data().unwrap()[offset..offset + symbol.size() as usize].to_vec()
+}
+
+#[test]
+fn golden_statics() {
+    for fixture in fixtures() {
+        let elf = fs::read(&fixture).unwrap();
+        let printers = generate_printers(&elf).unwrap();
+
+        for (name, type_name, expected) in CASES {
+            let typ = printers
+                .0
+                .get(*type_name)
+                .unwrap_or_else(|| panic!("{}: missing type {}", fixture.display(), type_name));
+
+            let mut out = Vec::new();
+            typ.write(&mut out, &static_bytes(&elf, name)).unwrap();
+
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                *expected,
+                "{}: {}",
+                fixture.display(),
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn snapshot_statics() {
+    for fixture in fixtures() {
+        let elf = fs::read(&fixture).unwrap();
+
+        // Capture only the section with TEST1, as a dump would capture only RAM
+        let file = object::File::parse(&elf[..]).unwrap();
+        let symbol = file
+            .symbols()
+            .find(|symbol| symbol.name() == Ok("TEST1"))
+            .unwrap();
+        let section = file
+            .section_by_index(symbol.section_index().unwrap())
+            .unwrap();
+        let snapshot = Snapshot {
+            regions: vec![(section.address(), section.data().unwrap().to_vec())],
+        };
+
+        let mut out = Vec::new();
+        snapshot.write_statics(&elf, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let expected = format!("TEST1 @ {:#010x} = {}", symbol.address(), CASES[0].2);
+        assert!(
+            out.contains(&expected),
+            "{}: TEST1 missing in\n{}",
+            fixture.display(),
+            out
+        );
+    }
+}
+
+#[test]
+fn initial_statics() {
+    for fixture in fixtures() {
+        let elf = fs::read(&fixture).unwrap();
+
+        let mut out = Vec::new();
+        Snapshot::from_elf(&elf)
+            .unwrap()
+            .write_statics(&elf, &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let file = object::File::parse(&elf[..]).unwrap();
+        for (name, _, value) in CASES {
+            let symbol = file
+                .symbols()
+                .find(|symbol| symbol.name() == Ok(name))
+                .unwrap();
+            let expected = format!("{} @ {:#010x} = {}", name, symbol.address(), value);
+            assert!(
+                out.contains(&expected),
+                "{}: {} missing in\n{}",
+                fixture.display(),
+                name,
+                out
+            );
+        }
+    }
+}
+
+#[test]
+fn decoded_values() {
+    let cases = [
+        (
+            "TEST10",
+            "Wrapper",
+            serde_json::json!({
+                "flag": true,
+                "tag": 48879,
+                "inner": {"Var1": {"x": 7, "y": -0.25}},
+                "big": 1099511627776u64,
+                "small": -5,
+            }),
+        ),
+        ("TEST2", "(f32, u32)", serde_json::json!([1.5, 2])),
+        ("TEST3", "MyEnum", serde_json::json!({"Var2": [[1, 2.0]]})),
+        ("TEST9", "MyEnum", serde_json::json!("Var3")),
+    ];
+
+    for fixture in fixtures() {
+        let elf = fs::read(&fixture).unwrap();
+        let printers = generate_printers(&elf).unwrap();
+
+        for (name, type_name, expected) in &cases {
+            let value = printers
+                .get(type_name)
+                .unwrap()
+                .decode(&static_bytes(&elf, name));
+
+            assert_eq!(
+                serde_json::to_value(&value).unwrap(),
+                *expected,
+                "{}: {}",
+                fixture.display(),
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn tuple_elements() {
+    for fixture in fixtures() {
+        let elf = fs::read(&fixture).unwrap();
+        let printers = generate_printers(&elf).unwrap();
+        let buf = static_bytes(&elf, "TEST2");
+
+        let typ = printers.get("(f32, u32)").unwrap();
+        assert_eq!(typ.tuple_elements().len(), 2);
+
+        let elements: Vec<_> = (0..3)
+            .map(|index| {
+                let mut out = Vec::new();
+                typ.write_element_with(&mut out, &buf, index, &Default::default())
+                    .unwrap();
+                String::from_utf8(out).unwrap()
+            })
+            .collect();
+        assert_eq!(elements, ["1.5", "2", ""], "{}", fixture.display());
+
+        // Other types are found without their path, and are not tuples
+        let typ = printers.get("types::mod1::mod2::MyStruct").unwrap();
+        assert!(typ.tuple_elements().is_empty());
+
+        // Base types have printers of their own, for a `log!` of a bare number
+        let typ = printers.get("u32").unwrap();
+        assert_eq!(typ.decode(&2u32.to_le_bytes()), Value::Unsigned(2));
+    }
+}
+
+#[test]
+fn slice_contents() {
+    let write = |typ: &Type, buf: &[u8], options: &FormatOptions| {
+        let mut out = Vec::new();
+        typ.write_with(&mut out, buf, options).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    for fixture in fixtures() {
+        let elf = fs::read(&fixture).unwrap();
+        let printers = generate_printers(&elf).unwrap();
+
+        // A frame of `&str` holds the text, not the pointer and length
+        let typ = printers.get("&str").unwrap();
+        assert_eq!(typ.contents(), Some(Contents::Text));
+        assert_eq!(write(typ, b"radio up", &Default::default()), "radio up");
+        assert_eq!(typ.decode(b"radio up"), Value::String("radio up".into()));
+        assert!(typ.values(b"radio up").is_empty());
+        assert!(typ.undecodable().is_empty(), "{}", fixture.display());
+    }
+
+    let typ = Type::new(TypeKind::Unknown, "&[u8]".into(), vec![], 0);
+    assert_eq!(typ.contents(), Some(Contents::Bytes));
+    assert_eq!(
+        write(&typ, &[0xde, 0xad, 0x0b], &Default::default()),
+        "de ad 0b"
+    );
+    assert_eq!(
+        typ.decode(&[1, 2]),
+        Value::Seq(vec![Value::Unsigned(1), Value::Unsigned(2)])
+    );
+}
+
+#[test]
+fn enumerators() {
+    for fixture in fixtures() {
+        let elf = fs::read(&fixture).unwrap();
+        let enumerations = enumerations(&elf).unwrap();
+
+        let error_code: Vec<_> = enumerations["ErrorCode"]
+            .iter()
+            .map(|(value, name)| (*value, name.as_str()))
+            .collect();
+        assert_eq!(
+            error_code,
+            [(0x23, "Timeout"), (0x24, "Nack")],
+            "{}",
+            fixture.display()
+        );
+        assert_eq!(static_bytes(&elf, "TEST11"), [0x24]);
+    }
+}
@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use log0_host::{leb128, parser::Parser};
+
+fn bench_leb128(c: &mut Criterion) {
+    let mut group = c.benchmark_group("leb128::decode_u32");
+
+    for &value in &[0x7f, 0x3fff, 0x1f_ffff, 0xfff_ffff, u32::MAX] {
+        let mut encoded = Vec::new();
+        leb128::encode_u32(&mut encoded, value);
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{} bytes", encoded.len())),
+            &encoded,
+            |b, encoded| b.iter(|| leb128::decode_u32(black_box(encoded).iter())),
+        );
+    }
+
+    group.finish();
+}
+
+/// A stream of `frames` frames with `size` byte payloads, addresses typical for a Cortex-M
+fn frame_stream(frames: usize, size: usize) -> Vec<u8> {
+    let mut stream = Vec::new();
+
+    for i in 0..frames {
+        leb128::encode_u32(&mut stream, size as u32);
+        leb128::encode_u32(&mut stream, 0x1000 + i as u32 % 64);
+        leb128::encode_u32(&mut stream, 0x0000_4a00 + i as u32 % 16);
+        stream.extend((0..size).map(|b| b as u8));
+    }
+
+    stream
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Parser::try_parse");
+    let frames = 1000;
+
+    for &size in &[4, 16, 128] {
+        let stream = frame_stream(frames, size);
+        group.throughput(Throughput::Bytes(stream.len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{} byte payloads", size)),
+            &stream,
+            |b, stream| {
+                let mut parser = Parser::new();
+
+                b.iter(|| {
+                    parser.push(black_box(stream));
+                    let mut parsed = 0;
+                    while let Some(packet) = parser.try_parse() {
+                        parsed += packet.buffer.len();
+                    }
+                    assert_eq!(parsed, frames * size);
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_leb128, bench_parser);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use log0_host::leb128;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((val, len)) = leb128::decode_u32(data.iter()) {
+        assert!(len <= leb128::MAX_LEN_U32 && len <= data.len());
+
+        // Valid encodings must survive a round trip, minus any redundant padding bytes
+        let mut encoded = Vec::new();
+        leb128::encode_u32(&mut encoded, val);
+        assert!(encoded.len() <= len);
+        assert_eq!(leb128::decode_u32(encoded.iter()), Ok((val, encoded.len())));
+    }
+});
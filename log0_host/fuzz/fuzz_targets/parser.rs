@@ -0,0 +1,46 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use log0_host::parser::Parser;
+
+// The first byte decides how the rest is chunked, so frames get split at arbitrary points like
+// when the host reads from the ring buffer while the target is writing. Its top bit selects
+// frames with timestamps, the next one frames with task IDs, the one after that frames with
+// only changed bytes, the next one frames with a CRC and the one after that frames with sequence
+// numbers.
+fuzz_target!(|data: &[u8]| {
+    let (first, data) = match data.split_first() {
+        Some((first, data)) => (*first, data),
+        None => return,
+    };
+    let chunk_size = (first & 0x07) as usize + 1;
+
+    let mut parser = if first & 0x80 != 0 {
+        Parser::with_timestamps()
+    } else {
+        Parser::new()
+    };
+    if first & 0x40 != 0 {
+        parser = parser.with_tasks();
+    }
+    let deltas = first & 0x20 != 0;
+    if deltas {
+        parser = parser.with_deltas();
+    }
+    if first & 0x10 != 0 {
+        parser = parser.with_crcs();
+    }
+    if first & 0x08 != 0 {
+        parser = parser.with_sequences();
+    }
+    let mut consumed = 0;
+
+    for chunk in data.chunks(chunk_size) {
+        parser.push(chunk);
+
+        while let Some(packet) = parser.try_parse() {
+            consumed += packet.buffer.len();
+            // Frames with changes are rebuilt to whole values, which can be larger
+            assert!(deltas || consumed <= data.len());
+        }
+    }
+});
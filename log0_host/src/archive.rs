@@ -0,0 +1,270 @@
+use crate::{record::Record, sink::Sink};
+use anyhow::{anyhow, Context, Error, Result};
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the index in the archive directory, one JSON object per closed file
+pub const INDEX: &str = "index.jsonl";
+
+/// How often the archive starts a new file, on UTC hour or day boundaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    Hourly,
+    Daily,
+}
+
+impl FromStr for Split {
+    type Err = Error;
+
+    /// `hourly` or `daily`
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "hourly" => Ok(Split::Hourly),
+            "daily" => Ok(Split::Daily),
+            _ => Err(anyhow!("Unknown split {:?}, expected hourly or daily", s)),
+        }
+    }
+}
+
+impl Split {
+    fn period(self) -> u64 {
+        match self {
+            Split::Hourly => 60 * 60,
+            Split::Daily => 24 * 60 * 60,
+        }
+    }
+
+    /// Start of the period `time` falls in
+    fn start(self, time: SystemTime) -> SystemTime {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        UNIX_EPOCH + Duration::from_secs(secs - secs % self.period())
+    }
+
+    /// Name of the file for the period starting at `start`, e.g. `2021-03-04T05` or
+    /// `2021-03-04`
+    fn name(self, start: SystemTime) -> String {
+        let time = humantime::format_rfc3339_seconds(start).to_string();
+        match self {
+            Split::Hourly => time[..13].into(),
+            Split::Daily => time[..10].into(),
+        }
+    }
+}
+
+/// A line of the index, the time range of the records in an archived file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Name of the file in the archive directory, ending in `.gz` if it is compressed
+    pub file: String,
+    /// When the first and last record arrived, as RFC 3339 UTC
+    pub first: String,
+    pub last: String,
+    pub records: u64,
+}
+
+/// The file of the current period
+struct Open {
+    path: PathBuf,
+    out: BufWriter<File>,
+    start: SystemTime,
+    first: SystemTime,
+    last: SystemTime,
+    records: u64,
+}
+
+/// Writes the records as JSON lines to a new file in `dir` every hour or day, for soak tests
+/// that run for days
+///
+/// A closed file is compressed with gzip if `compress` is set, in the background, and gets a
+/// line in `index.jsonl` with the time range of its records, so the files with a given range
+/// can be found without reading them. The times are when the records arrived on the host.
+pub struct Archive {
+    dir: PathBuf,
+    split: Split,
+    compress: bool,
+    open: Option<Open>,
+    /// The last compression, it waits for the ones before it
+    gzip: Option<JoinHandle<()>>,
+}
+
+impl Archive {
+    pub fn new(dir: &Path, split: Split, compress: bool) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create archive directory {}", dir.display()))?;
+
+        Ok(Archive {
+            dir: dir.into(),
+            split,
+            compress,
+            open: None,
+            gzip: None,
+        })
+    }
+
+    /// Write a record that arrived at `now`
+    pub fn push(&mut self, record: &Record, now: SystemTime) -> Result<()> {
+        let start = self.split.start(now);
+        if self.open.as_ref().map(|open| open.start) != Some(start) {
+            self.close()?;
+            self.start(start, now)?;
+        }
+
+        let open = self.open.as_mut().unwrap();
+        serde_json::to_writer(&mut open.out, record)?;
+        writeln!(open.out)?;
+        open.last = now;
+        open.records += 1;
+
+        Ok(())
+    }
+
+    /// Open the file for the period starting at `start`, a file left by an earlier session
+    /// for the same period is kept
+    fn start(&mut self, start: SystemTime, now: SystemTime) -> Result<()> {
+        let name = self.split.name(start);
+        let path = (0..)
+            .map(|n| match n {
+                0 => self.dir.join(format!("{}.jsonl", name)),
+                n => self.dir.join(format!("{}-{}.jsonl", name, n)),
+            })
+            .find(|path| !path.exists() && !gzipped(path).exists())
+            .unwrap();
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create archive file {}", path.display()))?;
+        log::info!("Archiving to {}", path.display());
+
+        self.open = Some(Open {
+            path,
+            out: BufWriter::new(file),
+            start,
+            first: now,
+            last: now,
+            records: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Close the current file and add it to the index, once it is compressed if `compress` is
+    /// set
+    fn close(&mut self) -> Result<()> {
+        let open = match self.open.take() {
+            Some(open) => open,
+            None => return Ok(()),
+        };
+        open.out
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+
+        let mut entry = IndexEntry {
+            file: String::new(),
+            first: humantime::format_rfc3339_micros(open.first).to_string(),
+            last: humantime::format_rfc3339_micros(open.last).to_string(),
+            records: open.records,
+        };
+        let path = open.path;
+        if !self.compress {
+            entry.file = file_name(&path);
+            return index(&self.dir, &entry);
+        }
+
+        // After the compression before it, so the index stays in order
+        let dir = self.dir.clone();
+        let before = self.gzip.take();
+        self.gzip = Some(thread::spawn(move || {
+            let archived = match gzip(&path) {
+                Ok(gzipped) => gzipped,
+                Err(e) => {
+                    eprintln!("Failed to compress {}: {:#}", path.display(), e);
+                    path
+                }
+            };
+            entry.file = file_name(&archived);
+            if let Some(before) = before {
+                before.join().ok();
+            }
+            if let Err(e) = index(&dir, &entry) {
+                eprintln!("{:#}", e);
+            }
+        }));
+
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Add the line of a closed file to the index in `dir`
+fn index(dir: &Path, entry: &IndexEntry) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(INDEX))
+        .and_then(|mut index| index.write_all(line.as_bytes()))
+        .context("Failed to write the archive index")
+}
+
+/// `path` with `.gz` appended, as `gzip` names it
+fn gzipped(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    name.into()
+}
+
+/// Compress `path` to `path.gz` and remove it, on failure `path` is left as it is
+fn gzip(path: &Path) -> Result<PathBuf> {
+    let gzipped = gzipped(path);
+    let compress = || -> Result<()> {
+        let mut encoder = GzEncoder::new(File::create(&gzipped)?, Compression::default());
+        io::copy(&mut File::open(path)?, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        Ok(())
+    };
+    if let Err(e) = compress() {
+        fs::remove_file(&gzipped).ok();
+        return Err(e);
+    }
+    fs::remove_file(path)?;
+
+    Ok(gzipped)
+}
+
+impl Sink for Archive {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        self.push(record, SystemTime::now())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.open {
+            Some(open) => Ok(open.out.flush()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Close the last file and wait for the compressions to finish
+    fn finish(&mut self) -> Result<()> {
+        self.close()?;
+        if let Some(gzip) = self.gzip.take() {
+            gzip.join().ok();
+        }
+
+        Ok(())
+    }
+}
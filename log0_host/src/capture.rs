@@ -0,0 +1,151 @@
+use crate::{
+    record::Record,
+    sink::{Sink, Terminal},
+    template::Template,
+};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A capture file being written, and when it is closed unless the trigger matches again
+struct Open {
+    path: PathBuf,
+    out: Terminal<BufWriter<File>>,
+    until: Instant,
+}
+
+/// Keeps the messages of the last `before` in memory, and when one matches the trigger, writes
+/// them to a new file in `dir` followed by the messages of the next `after`
+///
+/// Meant for long runs where only what happens around a rare event is of interest. A match
+/// while a file is open extends it instead of starting another one. The files are named
+/// `capture-<n>.log` and laid out like the terminal.
+pub struct Capture {
+    trigger: Regex,
+    before: Duration,
+    after: Duration,
+    dir: PathBuf,
+    template: Option<Template>,
+    window: VecDeque<(Instant, Record)>,
+    open: Option<Open>,
+    /// Paths of the files written so far
+    written: Vec<PathBuf>,
+}
+
+impl Capture {
+    pub fn new(trigger: Regex, before: Duration, after: Duration, dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create capture directory {}", dir.display()))?;
+
+        Ok(Capture {
+            trigger,
+            before,
+            after,
+            dir: dir.into(),
+            template: None,
+            window: VecDeque::new(),
+            open: None,
+            written: Vec::new(),
+        })
+    }
+
+    /// Lay the lines out with `template`, see `Terminal::with_template`
+    pub fn with_template(mut self, template: Option<Template>) -> Self {
+        self.template = template;
+        self
+    }
+
+    pub fn written(&self) -> &[PathBuf] {
+        &self.written
+    }
+
+    /// Handle a record that arrived at `now`
+    pub fn push(&mut self, record: &Record, now: Instant) -> Result<()> {
+        self.close_expired(now)?;
+        while let Some((arrived, _)) = self.window.front() {
+            if now.saturating_duration_since(*arrived) <= self.before {
+                break;
+            }
+            self.window.pop_front();
+        }
+
+        if self.trigger.is_match(&record.message) {
+            match &mut self.open {
+                Some(open) => open.until = now + self.after,
+                None => self.start(now)?,
+            }
+        }
+        if let Some(open) = &mut self.open {
+            open.out.write(record)?;
+        }
+
+        self.window.push_back((now, record.clone()));
+
+        Ok(())
+    }
+
+    /// Close the open file if nothing matched for `after`
+    pub fn close_expired(&mut self, now: Instant) -> Result<()> {
+        match &self.open {
+            Some(open) if now > open.until => self.close(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Open the next capture file, with what is in the window
+    fn start(&mut self, now: Instant) -> Result<()> {
+        let path = (self.written.len() + 1..)
+            .map(|n| self.dir.join(format!("capture-{}.log", n)))
+            .find(|path| !path.exists())
+            .unwrap();
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create capture file {}", path.display()))?;
+        log::info!("Capturing to {}", path.display());
+
+        let mut out =
+            Terminal::new(BufWriter::new(file), false).with_template(self.template.clone());
+        for (_, record) in &self.window {
+            out.write(record)?;
+        }
+
+        self.open = Some(Open {
+            path,
+            out,
+            until: now + self.after,
+        });
+
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut open) = self.open.take() {
+            open.out.finish()?;
+            eprintln!("Captured {}", open.path.display());
+            self.written.push(open.path);
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for Capture {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        self.push(record, Instant::now())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.close_expired(Instant::now())?;
+        match &mut self.open {
+            Some(open) => open.out.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.close()
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    format_string::FormatString,
+    record::Level,
+    symbols::{Intervals, Symbols},
+};
+use anyhow::Result;
+use elf_test::{LogSite, TypePrinters};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+
+/// A format string from the `.fasthosting` section
+#[derive(Debug)]
+pub struct Message {
+    /// Index of the string in the section, stable for a given ELF
+    pub id: u32,
+    pub address: usize,
+    pub text: String,
+    pub format: Result<FormatString>,
+    /// Type of the argument and where the `log!` call is, if found in the DWARF
+    pub type_name: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub module: Option<String>,
+    /// Set for the format strings of `warn!` and `error!`
+    pub level: Option<Level>,
+    /// Names of the fields of a `log_kv!` event in order, empty for other messages
+    pub fields: Vec<String>,
+    /// Set for the format strings of `log_fmt!`, the target sends the formatted message
+    pub formatted: bool,
+}
+
+/// A catalog entry as exported with `catalog --json`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry<'a> {
+    pub id: u32,
+    pub address: usize,
+    pub format: &'a str,
+    #[serde(rename = "type")]
+    pub type_name: Option<&'a str>,
+    pub file: Option<&'a str>,
+    pub line: Option<u64>,
+}
+
+/// Why a message cannot be decoded, see `Catalog::coverage`
+#[derive(Debug, Clone)]
+pub struct Problem<'a> {
+    pub message: &'a Message,
+    pub reason: String,
+}
+
+impl Message {
+    /// `file:line` of the `log!` call, or `<unknown>`
+    pub fn location(&self) -> String {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => format!("{}:{}", file, line),
+            (Some(file), None) => file.clone(),
+            _ => "<unknown>".into(),
+        }
+    }
+
+    /// Name of the `log_kv!` event, `None` for other messages
+    pub fn event(&self) -> Option<&str> {
+        match self.fields.is_empty() {
+            true => None,
+            false => self.text.split(' ').next(),
+        }
+    }
+
+    pub fn entry(&self) -> Entry<'_> {
+        Entry {
+            id: self.id,
+            address: self.address,
+            format: &self.text,
+            type_name: self.type_name.as_deref(),
+            file: self.file.as_deref(),
+            line: self.line,
+        }
+    }
+}
+
+/// All format strings in the ELF, with stable IDs to key on instead of the message text
+#[derive(Debug, Default)]
+pub struct Catalog {
+    messages: Vec<Message>,
+    by_address: HashMap<usize, usize>,
+    ranges: Intervals,
+    /// Printed types by the address of the static in `.fasthosting.types` that their call site
+    /// sends as type string
+    types: HashMap<usize, String>,
+}
+
+impl Catalog {
+    /// Build the catalog from the format strings by address, IDs are assigned in address order
+    pub fn new(strings: &Symbols) -> Self {
+        let messages: Vec<_> = strings
+            .iter()
+            .enumerate()
+            .map(|(id, (address, text))| Message {
+                id: id as u32,
+                address,
+                text: text.into(),
+                format: FormatString::parse(text),
+                type_name: None,
+                file: None,
+                line: None,
+                module: None,
+                level: None,
+                fields: Vec::new(),
+                formatted: false,
+            })
+            .collect();
+        let by_address = messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| (message.address, i))
+            .collect();
+        let ranges = Intervals::new(
+            messages
+                .iter()
+                .map(|message| message.address..message.address + message.text.len())
+                .collect(),
+        );
+
+        Catalog {
+            messages,
+            by_address,
+            ranges,
+            types: HashMap::new(),
+        }
+    }
+
+    /// Add argument types, source locations and levels of the `log!` call sites found in the
+    /// DWARF
+    pub fn with_sites(mut self, sites: &[LogSite]) -> Self {
+        for site in sites {
+            if let (Some(address), Some(type_name)) = (site.type_address, &site.type_name) {
+                self.types.insert(address as usize, type_name.clone());
+            }
+            if let Some(&i) = self.by_address.get(&(site.address as usize)) {
+                let message = &mut self.messages[i];
+                message.type_name = site.type_name.clone();
+                message.file = site.file.clone();
+                message.line = site.line;
+                message.module = site.module.clone();
+                message.level = site.level.and_then(Level::from_site);
+                message.formatted = site.formatted;
+                // The format string of an event is `name key={} key={}`
+                if site.kv {
+                    message.fields = message
+                        .text
+                        .split(' ')
+                        .skip(1)
+                        .filter_map(|field| field.strip_suffix("={}"))
+                        .map(Into::into)
+                        .collect();
+                }
+            }
+        }
+
+        self
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Look up the message for a string address from a frame
+    pub fn get(&self, address: usize) -> Option<&Message> {
+        self.by_address.get(&address).map(|&i| &self.messages[i])
+    }
+
+    /// Look up the printed type for a type string address from a frame, for targets that put
+    /// their types in the `.fasthosting.types` section
+    pub fn type_name(&self, address: usize) -> Option<&str> {
+        self.types.get(&address).map(String::as_str)
+    }
+
+    /// Look up the message a string address points into, and the offset into its text
+    pub fn find(&self, address: usize) -> Option<(&Message, usize)> {
+        self.ranges
+            .find(address)
+            .map(|(i, offset)| (&self.messages[i], offset))
+    }
+
+    /// Write one line per message: ID, source location, argument type and format string
+    pub fn write_text(&self, w: &mut impl Write) -> Result<()> {
+        for message in &self.messages {
+            let type_name = message.type_name.as_deref().unwrap_or("<unknown>");
+
+            writeln!(
+                w,
+                "{:>4}  {}  {}  {:?}",
+                message.id,
+                message.location(),
+                type_name,
+                message.text
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The messages whose payload cannot be decoded with `printers`, in ID order
+    ///
+    /// A message can have more than one problem, e.g. an invalid format string and a type with
+    /// a pointer in it.
+    pub fn coverage(&self, printers: &TypePrinters) -> Vec<Problem<'_>> {
+        let mut problems = Vec::new();
+        for message in &self.messages {
+            // Checked against its arguments by `format_args!` on the target
+            if message.formatted {
+                continue;
+            }
+            let mut problem = |reason: String| problems.push(Problem { message, reason });
+
+            if let Err(e) = &message.format {
+                problem(format!("invalid format string: {}", e));
+            }
+            let type_name = match &message.type_name {
+                Some(type_name) => type_name,
+                None => {
+                    problem("no call site in the debug info, the argument type is unknown".into());
+                    continue;
+                }
+            };
+            let typ = match printers.get(type_name) {
+                Some(typ) => typ,
+                None => {
+                    problem(format!("type {} is not in the debug info", type_name));
+                    continue;
+                }
+            };
+            for (path, reason) in typ.undecodable() {
+                problem(format!("{} of {}: {}", path, type_name, reason));
+            }
+
+            let elements = typ.tuple_elements().len();
+            if let Ok(format) = &message.format {
+                if elements > 0 && format.arguments() > 1 && format.arguments() != elements {
+                    problem(format!(
+                        "{} placeholders for {} values",
+                        format.arguments(),
+                        elements
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Write all messages as a JSON array
+    pub fn write_json(&self, w: &mut impl Write) -> Result<()> {
+        let entries: Vec<_> = self.messages.iter().map(Message::entry).collect();
+        serde_json::to_writer_pretty(&mut *w, &entries)?;
+        writeln!(w)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Context, Error, Result};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+
+/// Turns the data of a raw channel, written with `write_raw` on the target, into the message
+/// shown for it
+///
+/// Register one with `Decoder::with_channel`. Closures taking the data implement it.
+pub trait ChannelDecoder: Send {
+    fn decode(&mut self, data: &[u8]) -> Result<String>;
+}
+
+impl<F: FnMut(&[u8]) -> Result<String> + Send> ChannelDecoder for F {
+    fn decode(&mut self, data: &[u8]) -> Result<String> {
+        self(data)
+    }
+}
+
+/// Shows the data as space separated hex bytes, the decoder of channels without one
+pub struct Hex;
+
+impl ChannelDecoder for Hex {
+    fn decode(&mut self, data: &[u8]) -> Result<String> {
+        Ok(hex(data))
+    }
+}
+
+/// `data` as space separated hex bytes
+pub fn hex(data: &[u8]) -> String {
+    let mut text = String::with_capacity(3 * data.len());
+    for (i, b) in data.iter().enumerate() {
+        if i > 0 {
+            text.push(' ');
+        }
+        write!(text, "{:02x}", b).unwrap();
+    }
+
+    text
+}
+
+/// Passes the data of a channel through an external program, to decode it without rebuilding
+/// the host
+///
+/// The program gets the data of each frame as a line of hex on stdin, without spaces, and
+/// answers each with one line, the message to show.
+///
+/// ```sh
+/// # Show the number of bytes of each frame
+/// while read -r hex; do echo "${#hex} nibbles"; done
+/// ```
+pub struct Program {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Program {
+    /// Start `command` with `sh -c`
+    pub fn spawn(command: &str) -> Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start channel decoder {:?}", command))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        Ok(Program {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+impl ChannelDecoder for Program {
+    fn decode(&mut self, data: &[u8]) -> Result<String> {
+        writeln!(self.stdin, "{}", hex(data).replace(' ', ""))?;
+        self.stdin.flush()?;
+
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Channel decoder exited"));
+        }
+
+        Ok(line.trim_end().into())
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// A decoder for a channel as given on the command line, `<channel>=hex` or
+/// `<channel>=exec:<command>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelArg {
+    pub channel: u8,
+    /// The command of `exec:`, `None` for `hex`
+    pub command: Option<String>,
+}
+
+impl ChannelArg {
+    /// Start the decoder
+    pub fn decoder(&self) -> Result<Box<dyn ChannelDecoder>> {
+        let decoder: Box<dyn ChannelDecoder> = match &self.command {
+            Some(command) => Box::new(Program::spawn(command)?),
+            None => Box::new(Hex),
+        };
+
+        Ok(decoder)
+    }
+}
+
+impl FromStr for ChannelArg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (channel, decoder) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected <channel>=<decoder>, got {:?}", s))?;
+        let channel = channel
+            .parse()
+            .map_err(|_| anyhow!("Channel {:?} is not a number from 0 to 255", channel))?;
+
+        let command = match decoder {
+            "hex" => None,
+            _ => match decoder.strip_prefix("exec:") {
+                Some(command) if !command.is_empty() => Some(command.into()),
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown channel decoder {:?}, expected hex or exec:<command>",
+                        decoder
+                    ))
+                }
+            },
+        };
+
+        Ok(ChannelArg { channel, command })
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{bytes_to_read, leb128};
+use anyhow::{anyhow, Result};
+
+/// Memory access needed to write to the command buffer of the target
+pub trait CommandMemory {
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<()>;
+
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<()>;
+
+    fn write_word_32(&mut self, address: u64, value: u32) -> Result<()>;
+}
+
+/// The `LOG0_COMMAND_CURSORS` and `LOG0_COMMAND_BUFFER` statics of a target built with the
+/// `commands` feature
+///
+/// Commands are LEB128 length-prefixed frames, read on the target with `read_command`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandChannel {
+    pub cursor_address: u64,
+    pub buffer_address: u64,
+    pub buffer_size: usize,
+}
+
+impl CommandChannel {
+    /// Encode a command as a frame for `send`, fails if it can never fit in the buffer
+    pub fn frame(&self, command: &[u8]) -> Result<Vec<u8>> {
+        let mut frame = Vec::with_capacity(command.len() + leb128::MAX_LEN_U32);
+        leb128::encode_u32(&mut frame, command.len() as u32);
+        frame.extend_from_slice(command);
+
+        // One slot is always kept free so a full buffer can be told apart from an empty one
+        if frame.len() > self.buffer_size - 1 {
+            return Err(anyhow!(
+                "Command of {} bytes does not fit in the {} byte command buffer",
+                command.len(),
+                self.buffer_size
+            ));
+        }
+
+        Ok(frame)
+    }
+
+    /// Write a frame made with `frame`, returns `false` if there is no room for it until the target
+    /// has read some of the earlier ones
+    pub fn send(&self, memory: &mut impl CommandMemory, frame: &[u8]) -> Result<bool> {
+        let size = self.buffer_size;
+
+        // [target, host], the target reads and the host writes
+        let mut cursors = [0; 2];
+        memory.read_32(self.cursor_address, &mut cursors)?;
+        let (read, write) = (cursors[0] as usize, cursors[1] as usize);
+        if read >= size || write >= size {
+            return Err(anyhow!("Command cursors out of range"));
+        }
+
+        if frame.len() > size - 1 - bytes_to_read(read, write, size) {
+            return Ok(false);
+        }
+
+        let (first, second) = frame.split_at(frame.len().min(size - write));
+        memory.write_8(self.buffer_address + write as u64, first)?;
+        if !second.is_empty() {
+            memory.write_8(self.buffer_address, second)?;
+        }
+
+        // Only publish the frame once all of it is in place
+        let write = (write + frame.len()) % size;
+        memory.write_word_32(self.cursor_address + 4, write as u32)?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    histogram::HistogramConfig,
+    influx::InfluxConfig,
+    mqtt::MqttConfig,
+    template::Template,
+    trigger::{TriggerConfig, Triggers},
+};
+use anyhow::{anyhow, Context, Result};
+use elf_test::{Annotation, Annotations};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Read from the working directory when no config file is given
+pub const DEFAULT_PATH: &str = "fasthosting.toml";
+
+/// Host side configuration
+///
+/// ```toml
+/// # Layout of the lines in the terminal, see `Template`
+/// format = "[{time}] {module}: {message}"
+///
+/// # Keyed by field name, or by `Type.field` to only match one type
+/// [fields.vbat_mv]
+/// unit = "V"
+/// scale = 0.001
+/// precision = 3
+///
+/// [fields."Telemetry.temp"]
+/// unit = "°C"
+///
+/// # Fixed-point values, for every scalar in the type
+/// [types.Q15]
+/// q = "Q1.15"
+///
+/// # Error codes printed by name, from a table or from an enum in the DWARF
+/// [fields."Status.code"]
+/// enum = "ErrorCode"
+/// symbols = { ERR_TIMEOUT = 0x23, ERR_NACK = 0x24 }
+///
+/// # Set bits printed by name, e.g. `Events(RX_DONE | CRC_ERR)` for a `bitflags!` type
+/// [types.Events]
+/// flags = { RX_DONE = 0x01, TX_DONE = 0x02, CRC_ERR = 0x04 }
+///
+/// [time]
+/// timestamp_hz = 32768
+///
+/// # Also publish every message, see `MqttConfig`
+/// [mqtt]
+/// broker = "localhost:1883"
+///
+/// # Also write telemetry as InfluxDB line protocol, see `InfluxConfig`
+/// [influx]
+/// file = "telemetry.lp"
+///
+/// # Draw a histogram of a field, see `HistogramConfig`
+/// [histograms.latency_us]
+/// bounds = [10, 20, 50, 100]
+///
+/// # Act on matching messages, see `TriggerConfig`
+/// [[triggers]]
+/// on = "FAULT"
+/// action = "halt-and-dump"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub format: Option<String>,
+    #[serde(default)]
+    pub fields: HashMap<String, FieldConfig>,
+    #[serde(default)]
+    pub types: HashMap<String, FieldConfig>,
+    #[serde(default)]
+    pub time: TimeConfig,
+    pub mqtt: Option<MqttConfig>,
+    pub influx: Option<InfluxConfig>,
+    #[serde(default)]
+    pub histograms: HashMap<String, HistogramConfig>,
+    #[serde(default)]
+    pub triggers: Vec<TriggerConfig>,
+}
+
+/// Tick frequency of the target timestamps, overrides the one in the ELF
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeConfig {
+    pub timestamp_hz: Option<u32>,
+    /// For timestamps counting CPU cycles, used if `timestamp_hz` is not set
+    pub cpu_hz: Option<u32>,
+}
+
+/// Unit, scale factor, fixed-point format and names of values or bits for a field or type, applied to
+/// the decoded value before printing
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldConfig {
+    pub unit: Option<String>,
+    pub scale: Option<f64>,
+    pub precision: Option<usize>,
+    /// Qm.n fixed-point format, e.g. `Q1.15` or `Q15`
+    pub q: Option<String>,
+    /// Names of values, e.g. error codes or register values
+    #[serde(default)]
+    pub symbols: HashMap<String, i64>,
+    /// C-like enum in the DWARF to take names of values from, `symbols` take precedence
+    #[serde(rename = "enum")]
+    pub enumeration: Option<String>,
+    /// Names of bits by mask, e.g. of a status register or event mask
+    #[serde(default)]
+    pub flags: HashMap<String, u64>,
+}
+
+impl FieldConfig {
+    fn annotation(&self) -> Result<Annotation> {
+        Ok(Annotation {
+            unit: self.unit.clone(),
+            scale: self.scale,
+            precision: self.precision,
+            fractional_bits: self.q.as_deref().map(fractional_bits).transpose()?,
+            constants: by_value(&self.symbols),
+            enumeration: self.enumeration.clone(),
+            flags: by_value(&self.flags),
+        })
+    }
+}
+
+/// Names keyed by their values, the first name in order wins when values have several
+fn by_value<T: Copy + Ord>(names: &HashMap<String, T>) -> BTreeMap<T, String> {
+    let mut names: Vec<_> = names.iter().collect();
+    names.sort();
+
+    let mut by_value = BTreeMap::new();
+    for (name, value) in names {
+        by_value.entry(*value).or_insert_with(|| name.clone());
+    }
+    by_value
+}
+
+/// Number of fractional bits in a `Qm.n` or `Qn` format
+fn fractional_bits(q: &str) -> Result<u32> {
+    let invalid = || anyhow!("Invalid fixed-point format {:?}, expected Qm.n or Qn", q);
+
+    let q = q.strip_prefix('Q').ok_or_else(invalid)?;
+    let n = match q.find('.') {
+        Some(dot) => {
+            q[..dot].parse::<u32>().map_err(|_| invalid())?;
+            &q[dot + 1..]
+        }
+        None => q,
+    };
+
+    n.parse().map_err(|_| invalid())
+}
+
+impl Config {
+    pub fn parse(s: &str) -> Result<Self> {
+        let config: Config = toml::from_str(s)?;
+        config.annotations()?;
+        config.template()?;
+        for (name, histogram) in &config.histograms {
+            histogram
+                .buckets()
+                .with_context(|| format!("Invalid histogram {:?}", name))?;
+        }
+        Triggers::new(&config.triggers)?;
+
+        Ok(config)
+    }
+
+    /// Load `path`, or `DEFAULT_PATH` if it exists, otherwise use an empty config
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_PATH).exists() => Path::new(DEFAULT_PATH),
+            None => return Ok(Config::default()),
+        };
+
+        let s = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Config::parse(&s).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn template(&self) -> Result<Option<Template>> {
+        self.format.as_deref().map(Template::parse).transpose()
+    }
+
+    /// Field and type annotations for the type printers, without the enumerations from the
+    /// DWARF, see `Annotations::resolve`
+    pub fn annotations(&self) -> Result<Annotations> {
+        let convert = |map: &HashMap<String, FieldConfig>| {
+            map.iter()
+                .map(|(name, field)| Ok((name.clone(), field.annotation()?)))
+                .collect::<Result<HashMap<_, _>>>()
+        };
+
+        Ok(Annotations {
+            fields: convert(&self.fields)?,
+            types: convert(&self.types)?,
+        })
+    }
+}
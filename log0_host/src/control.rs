@@ -0,0 +1,214 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+/// A request on the control socket of the daemon
+///
+/// ```text
+/// {"jsonrpc": "2.0", "id": 1, "method": "filter", "params": {"text": "radio"}}
+/// {"jsonrpc": "2.0", "id": 1, "result": null}
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Method {
+    /// Show the messages again, with the ones held back since `stop`
+    Start,
+    /// Stop showing messages, they are still read and decoded, and held back until `start`
+    Stop,
+    /// Only show messages that contain the `text` param, all of them without one
+    Filter(Option<String>),
+    /// The session summary so far, as JSON
+    Stats,
+    /// Halt the target, save a snapshot to the `path` param and let the target run again
+    Dump(PathBuf),
+    /// End the session
+    Quit,
+}
+
+impl Method {
+    fn parse(name: &str, params: &Value) -> Result<Self, (i64, String)> {
+        let param = |key: &str| match params {
+            Value::Object(params) => params.get(key).filter(|value| !value.is_null()),
+            _ => None,
+        };
+        let string = |key: &str| match param(key) {
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err((INVALID_PARAMS, format!("`{}` has to be a string", key))),
+            None => Ok(None),
+        };
+
+        match name {
+            "start" => Ok(Method::Start),
+            "stop" => Ok(Method::Stop),
+            "filter" => Ok(Method::Filter(string("text")?)),
+            "stats" => Ok(Method::Stats),
+            "dump" => match string("path")? {
+                Some(path) => Ok(Method::Dump(path.into())),
+                None => Err((INVALID_PARAMS, "`dump` needs a `path`".into())),
+            },
+            "quit" => Ok(Method::Quit),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {:?}", name))),
+        }
+    }
+
+    /// Handled by the probe thread, as opposed to on the decode thread
+    pub fn needs_target(&self) -> bool {
+        matches!(self, Method::Dump(_))
+    }
+}
+
+/// A request waiting for the daemon to handle it, the client waits for the reply
+#[derive(Debug)]
+pub struct Call {
+    pub method: Method,
+    reply: Sender<Result<Value, String>>,
+}
+
+impl Call {
+    pub fn new(method: Method) -> (Self, Receiver<Result<Value, String>>) {
+        let (reply, replied) = mpsc::channel();
+        (Call { method, reply }, replied)
+    }
+
+    pub fn reply(self, result: Result<Value>) {
+        self.reply.send(result.map_err(|e| format!("{:#}", e))).ok();
+    }
+}
+
+/// Where the calls of the clients arrive, split by the thread that handles them
+pub struct Calls {
+    pub decode: Receiver<Call>,
+    pub target: Receiver<Call>,
+}
+
+/// Answer one line from a client, handing the call to `decode` or `target` and waiting for the
+/// reply
+///
+/// Notifications, requests without an `id`, get no response.
+fn respond(line: &str, decode: &Sender<Call>, target: &Sender<Call>) -> Option<Value> {
+    let error = |id: &Value, code, message: String| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        })
+    };
+
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return Some(error(&Value::Null, PARSE_ERROR, e.to_string())),
+    };
+    let id = request.get("id").cloned();
+    let response = |result: Result<Value, (i64, String)>| {
+        let id = id.as_ref()?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error(id, code, message),
+        })
+    };
+
+    let name = match request.get("method") {
+        Some(Value::String(name)) => name,
+        _ => {
+            let id = id.unwrap_or(Value::Null);
+            return Some(error(&id, INVALID_REQUEST, "Missing `method`".into()));
+        }
+    };
+    let method = match Method::parse(name, request.get("params").unwrap_or(&Value::Null)) {
+        Ok(method) => method,
+        Err(e) => return response(Err(e)),
+    };
+
+    let to = if method.needs_target() {
+        target
+    } else {
+        decode
+    };
+    let (call, replied) = Call::new(method);
+    let result = match to.send(call).ok().and_then(|_| replied.recv().ok()) {
+        Some(result) => result.map_err(|message| (SERVER_ERROR, message)),
+        None => Err((SERVER_ERROR, "The session has ended".into())),
+    };
+
+    response(result)
+}
+
+/// Removes the socket file when the daemon exits
+pub struct Socket(PathBuf);
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// Listen for clients on the Unix socket at `path`, one JSON-RPC 2.0 request per line
+///
+/// A socket file left by an earlier daemon is replaced. Each client is served on a thread of
+/// its own, and its requests are handled in order.
+#[cfg(unix)]
+pub fn listen(path: &Path) -> Result<(Socket, Calls)> {
+    use anyhow::Context;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    if path.exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    log::info!("Listening for control requests on {}", path.display());
+
+    let (decode, decode_calls) = mpsc::channel();
+    let (target, target_calls) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("Control client failed to connect: {}", e);
+                    continue;
+                }
+            };
+            let (decode, target) = (decode.clone(), target.clone());
+            thread::spawn(move || {
+                let mut out = &stream;
+                for line in BufReader::new(&stream).lines() {
+                    let line = match line {
+                        Ok(line) if line.trim().is_empty() => continue,
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+                    if let Some(response) = respond(&line, &decode, &target) {
+                        if writeln!(out, "{}", response).is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    Ok((
+        Socket(path.into()),
+        Calls {
+            decode: decode_calls,
+            target: target_calls,
+        },
+    ))
+}
+
+/// The control socket is only supported on Unix
+#[cfg(not(unix))]
+pub fn listen(_path: &Path) -> Result<(Socket, Calls)> {
+    Err(anyhow::anyhow!("The daemon is only supported on Unix"))
+}
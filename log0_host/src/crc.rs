@@ -0,0 +1,15 @@
+/// Number of bytes of the CRC after each frame, with the `crc` feature of `log0_target`
+pub const LEN: usize = 2;
+
+/// CRC-16/CCITT-FALSE of `bytes`, the same way the target computes it
+pub fn crc16(bytes: impl IntoIterator<Item = u8>) -> u16 {
+    bytes.into_iter().fold(0xffff, |crc, byte| {
+        (0..8).fold(crc ^ u16::from(byte) << 8, |crc, _| {
+            if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
@@ -0,0 +1,447 @@
+use crate::{
+    catalog::Catalog,
+    channel::{self, ChannelDecoder},
+    fetch::Fetcher,
+    fmt::{self, AddressMap},
+    format_string::FormatString,
+    parser::{Facade, FacadeMessage, Packet, RawFrame, SpanFrame},
+    record::{Event, Record, Span, SpanKind, World},
+    symbols::Symbols,
+    time::{Clock, WallClock},
+};
+use elf_test::{FormatOptions, Symbolizer, TypePrinters};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use xmas_elf::ElfFile;
+
+/// Turns parsed frames into records, using the strings and types from the ELF
+pub struct Decoder<'a> {
+    catalog: Catalog,
+    types: Symbols<'a>,
+    printers: TypePrinters,
+    clock: Clock,
+    wall_clock: Option<WallClock>,
+    addresses: AddressMap,
+    fetcher: Option<Fetcher>,
+    task_names: Vec<String>,
+    world: Option<World>,
+    elf: Option<&'a [u8]>,
+    /// Made from `elf` when first needed, it takes a while
+    symbolizer: Option<Symbolizer>,
+    /// When the spans that were entered and not exited yet were entered, by task and name, the
+    /// innermost last
+    spans: HashMap<(Option<String>, String), Vec<Option<f64>>>,
+    /// Decoders of the raw channels by channel, the others are shown as hex
+    channels: HashMap<u8, Box<dyn ChannelDecoder>>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(catalog: Catalog, types: Symbols<'a>, printers: TypePrinters) -> Self {
+        Decoder {
+            catalog,
+            types,
+            printers,
+            clock: Clock::new(None),
+            wall_clock: None,
+            addresses: AddressMap::default(),
+            fetcher: None,
+            task_names: Vec::new(),
+            world: None,
+            elf: None,
+            symbolizer: None,
+            spans: HashMap::new(),
+            channels: HashMap::new(),
+        }
+    }
+
+    /// Normalize the string and type addresses in frames with `addresses` before looking them up
+    pub fn with_addresses(mut self, addresses: AddressMap) -> Self {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Convert timestamps with `clock`, and to wall-clock time if `wall_clock` is set
+    pub fn with_clock(mut self, clock: Clock, wall_clock: bool) -> Self {
+        self.clock = clock;
+        self.wall_clock = if wall_clock {
+            Some(WallClock::new())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Read strings and type names that are not in the ELF from the target with `fetcher`
+    pub fn with_fetcher(mut self, fetcher: Fetcher) -> Self {
+        self.fetcher = Some(fetcher);
+        self
+    }
+
+    /// Show task IDs by the names in `task_names`, indexed by ID
+    pub fn with_task_names(mut self, task_names: Vec<String>) -> Self {
+        self.task_names = task_names;
+        self
+    }
+
+    /// Tag the records with `world`, for targets with a ring in each security world
+    pub fn with_world(mut self, world: World) -> Self {
+        self.world = Some(world);
+        self
+    }
+
+    /// Look up code addresses, e.g. of a HardFault, in the debug info of `elf`, and read the
+    /// messages of `log` crate records from it
+    pub fn with_elf(mut self, elf: &'a [u8]) -> Self {
+        self.elf = Some(elf);
+        self
+    }
+
+    /// Decode the frames of raw channel `channel` with `decoder`, instead of showing them as hex
+    pub fn with_channel(mut self, channel: u8, decoder: Box<dyn ChannelDecoder>) -> Self {
+        self.channels.insert(channel, decoder);
+        self
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// Where code addresses are, `None` without an ELF or if its debug info could not be read
+    pub fn symbolizer(&mut self) -> Option<&Symbolizer> {
+        if self.symbolizer.is_none() {
+            match Symbolizer::new(self.elf?) {
+                Ok(symbolizer) => self.symbolizer = Some(symbolizer),
+                Err(e) => {
+                    log::warn!("Could not read the debug info of the ELF: {}", e);
+                    self.elf = None;
+                }
+            }
+        }
+
+        self.symbolizer.as_ref()
+    }
+
+    /// Decode a frame that arrived on the host at `arrival`
+    pub fn decode(&mut self, packet: &Packet, arrival: SystemTime) -> Record {
+        if let Some(facade) = packet.facade() {
+            return self.decode_facade(facade, packet, arrival);
+        }
+        if let Some(span) = packet.span() {
+            return self.decode_span(span, packet, arrival);
+        }
+        if let Some(raw) = packet.raw() {
+            return self.decode_raw(raw, packet, arrival);
+        }
+        if let Some(text) = packet.formatted() {
+            return self.decode_formatted(text, packet, arrival);
+        }
+
+        let string_loc = self.addresses.normalize(packet.string_loc);
+        let type_loc = self.addresses.normalize(packet.type_loc);
+
+        if let Some(fetcher) = &mut self.fetcher {
+            if self.catalog.find(string_loc).is_none() {
+                fetcher.fetch(string_loc);
+            }
+            if self.catalog.type_name(type_loc).is_none() && self.types.get(type_loc).is_none() {
+                fetcher.fetch(type_loc);
+            }
+        }
+        let fetcher = self.fetcher.as_ref();
+        let fetched = |address| fetcher.and_then(|fetcher| fetcher.cached(address));
+
+        let type_name = self
+            .catalog
+            .type_name(type_loc)
+            .or_else(|| self.types.get(type_loc))
+            .or_else(|| fetched(type_loc));
+        let printer = type_name.and_then(|type_name| self.printers.get(type_name));
+        // The values of a `log!` with several of them each go in their own placeholder
+        let elements = printer.map_or(0, |printer| printer.tuple_elements().len());
+        let value = |index: Option<usize>, options: &FormatOptions| {
+            let mut value = Vec::new();
+            match (printer, index) {
+                (Some(printer), Some(index)) => {
+                    printer
+                        .write_element_with(&mut value, &packet.buffer, index, options)
+                        .ok();
+                }
+                (Some(printer), None) => {
+                    printer.write_with(&mut value, &packet.buffer, options).ok();
+                }
+                (None, _) => (),
+            }
+            String::from_utf8_lossy(&value).trim_end().to_string()
+        };
+
+        // A pointer into the middle of a format string gets the rest of it, and one that is not
+        // in the ELF the string read from the target. Neither is in the catalog or has an ID.
+        let found = self.catalog.find(string_loc);
+        let tail = match found {
+            Some((message, offset)) if offset > 0 => {
+                message.text.get(offset..).map(FormatString::parse)
+            }
+            Some(_) => None,
+            None => fetched(string_loc).map(FormatString::parse),
+        };
+        let message = match found {
+            Some((message, 0)) => Some(message),
+            _ => None,
+        };
+        let format = match (message, &tail) {
+            (Some(message), _) => message.format.as_ref().ok(),
+            (None, Some(tail)) => tail.as_ref().ok(),
+            (None, None) => None,
+        };
+        let split = match format {
+            Some(format_string) => elements > 0 && format_string.arguments() == elements,
+            None => false,
+        };
+        let text = match format {
+            Some(format_string) if split => {
+                format_string.render(|index, options| value(Some(index), options))
+            }
+            Some(format_string) => format_string.render(|_, options| value(None, options)),
+            None => value(None, &FormatOptions::default()),
+        };
+        let args = match printer {
+            Some(_) if split => (0..elements)
+                .map(|i| value(Some(i), &FormatOptions::default()))
+                .collect(),
+            Some(_) => vec![value(None, &FormatOptions::default())],
+            None => vec![],
+        };
+        let format_text = match (found, fetched(string_loc)) {
+            (Some((message, offset)), _) => message.text.get(offset..).map(Into::into),
+            (None, fetched) => fetched.map(Into::into),
+        };
+
+        let mut values = printer
+            .map(|printer| printer.values(&packet.buffer))
+            .unwrap_or_default();
+
+        // The fields of a `log_kv!` event are the elements of its tuple, the numeric ones are
+        // named by field for the telemetry sinks too
+        let event = match message {
+            Some(message) if !message.fields.is_empty() && message.fields.len() == elements => {
+                let fields = message
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        let text = value(Some(i), &FormatOptions::default());
+                        let scalar = values.iter().any(|(path, _)| *path == i.to_string());
+                        (field.clone(), field_value(text, scalar))
+                    })
+                    .collect();
+                for (path, _) in &mut values {
+                    let (index, rest) = path.split_at(path.find('.').unwrap_or(path.len()));
+                    if let Some(field) = index
+                        .parse()
+                        .ok()
+                        .and_then(|i: usize| message.fields.get(i))
+                    {
+                        *path = format!("{}{}", field, rest);
+                    }
+                }
+
+                message.event().map(|name| Event {
+                    name: name.into(),
+                    fields,
+                })
+            }
+            _ => None,
+        };
+
+        let id = message.map(|message| message.id);
+        let level = message.and_then(|message| message.level);
+        let module = message.and_then(|message| message.module.clone());
+        let type_name = type_name.map(Into::into);
+        let (timestamp, seconds, task) = self.stamp(packet, arrival);
+
+        Record {
+            id,
+            timestamp,
+            seconds,
+            task,
+            world: self.world,
+            level,
+            message: text,
+            module,
+            type_name,
+            event,
+            format: format_text,
+            args,
+            values,
+            payload: packet.buffer.clone(),
+            ..Record::default()
+        }
+    }
+
+    /// A record of the `log` crate, with its target as the module
+    fn decode_facade(&mut self, facade: Facade, packet: &Packet, arrival: SystemTime) -> Record {
+        let message = match facade.message {
+            FacadeMessage::Text(text) => text,
+            FacadeMessage::Static { address, len } => self
+                .elf_str(address, len)
+                .unwrap_or_else(|| format!("<message at {:#010x} not in the ELF>", address)),
+        };
+        let (timestamp, seconds, task) = self.stamp(packet, arrival);
+
+        Record {
+            timestamp,
+            seconds,
+            task,
+            world: self.world,
+            level: facade.level,
+            message,
+            module: Some(facade.target),
+            payload: packet.buffer.clone(),
+            ..Record::default()
+        }
+    }
+
+    /// A span entered or exited, an exit with how long it took since the enter in the same task
+    fn decode_span(&mut self, frame: SpanFrame, packet: &Packet, arrival: SystemTime) -> Record {
+        let name = self
+            .elf_str(frame.address, frame.len)
+            .unwrap_or_else(|| format!("<span at {:#010x}>", frame.address));
+        let (timestamp, seconds, task) = self.stamp(packet, arrival);
+
+        let open = self.spans.entry((task.clone(), name.clone())).or_default();
+        let (kind, duration, message) = if frame.enter {
+            open.push(seconds);
+            (SpanKind::Enter, None, format!("enter {}", name))
+        } else {
+            // An exit without an enter, e.g. after a reset of the host, has no duration
+            let duration = match (open.pop(), seconds) {
+                (Some(Some(entered)), Some(exited)) => Some(exited - entered),
+                _ => None,
+            };
+            let message = match duration {
+                Some(duration) => format!(
+                    "exit {} after {:?}",
+                    name,
+                    Duration::from_secs_f64(duration.max(0.))
+                ),
+                None => format!("exit {}", name),
+            };
+            (SpanKind::Exit, duration, message)
+        };
+
+        Record {
+            timestamp,
+            seconds,
+            task,
+            world: self.world,
+            message,
+            span: Some(Span {
+                name,
+                kind,
+                duration,
+            }),
+            payload: packet.buffer.clone(),
+            ..Record::default()
+        }
+    }
+
+    /// The data of a raw channel, as its decoder shows it
+    fn decode_raw(&mut self, frame: RawFrame, packet: &Packet, arrival: SystemTime) -> Record {
+        let message = match self.channels.get_mut(&frame.channel) {
+            Some(decoder) => decoder.decode(&frame.data).unwrap_or_else(|e| {
+                format!(
+                    "<channel {} could not be decoded: {}> {}",
+                    frame.channel,
+                    e,
+                    channel::hex(&frame.data)
+                )
+            }),
+            None => channel::hex(&frame.data),
+        };
+        let (timestamp, seconds, task) = self.stamp(packet, arrival);
+
+        Record {
+            timestamp,
+            seconds,
+            task,
+            world: self.world,
+            message,
+            channel: Some(frame.channel),
+            payload: frame.data,
+            ..Record::default()
+        }
+    }
+
+    /// A message of a `log_fmt!` call site, formatted on the target, the call site only gives
+    /// its ID, level and module
+    fn decode_formatted(&mut self, text: String, packet: &Packet, arrival: SystemTime) -> Record {
+        let (timestamp, seconds, task) = self.stamp(packet, arrival);
+        let message = self
+            .catalog
+            .get(self.addresses.normalize(packet.string_loc));
+
+        Record {
+            id: message.map(|message| message.id),
+            timestamp,
+            seconds,
+            task,
+            world: self.world,
+            level: message.and_then(|message| message.level),
+            message: text,
+            module: message.and_then(|message| message.module.clone()),
+            format: message.map(|message| message.text.clone()),
+            payload: packet.buffer.clone(),
+            ..Record::default()
+        }
+    }
+
+    /// The `&'static str` of `len` bytes at `address` in the ELF
+    fn elf_str(&self, address: u32, len: u32) -> Option<String> {
+        let elf = ElfFile::new(self.elf?).ok()?;
+        let data = fmt::elf_data(&elf, u64::from(address), len as usize)?;
+        Some(String::from_utf8_lossy(data).into_owned())
+    }
+
+    /// The timestamp of a frame as shown, in seconds if its frequency is known, and its task
+    fn stamp(
+        &mut self,
+        packet: &Packet,
+        arrival: SystemTime,
+    ) -> (Option<String>, Option<f64>, Option<String>) {
+        let clock = &mut self.clock;
+        let wall_clock = &mut self.wall_clock;
+        let mut seconds = None;
+        let timestamp = packet.timestamp.map(|timestamp| {
+            let ticks = clock.extend(timestamp);
+            seconds = clock.seconds(ticks);
+            match (seconds, wall_clock) {
+                (Some(seconds), Some(wall_clock)) => {
+                    let written = wall_clock.map(seconds, arrival);
+                    humantime::format_rfc3339_micros(written).to_string()
+                }
+                _ => clock.format(ticks),
+            }
+        });
+
+        let task = packet.task.map(|task| {
+            self.task_names
+                .get(task as usize)
+                .cloned()
+                .unwrap_or_else(|| task.to_string())
+        });
+
+        (timestamp, seconds, task)
+    }
+}
+
+/// A field of an event as JSON, a number or boolean if the value is one
+fn field_value(text: String, scalar: bool) -> serde_json::Value {
+    match serde_json::from_str(&text) {
+        Ok(value @ serde_json::Value::Number(_)) | Ok(value @ serde_json::Value::Bool(_))
+            if scalar =>
+        {
+            value
+        }
+        _ => serde_json::Value::String(text),
+    }
+}
@@ -0,0 +1,323 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Expected messages for `fasthosting test`
+///
+/// ```toml
+/// # Expectations have to be met in the order they are listed, defaults to true
+/// ordered = true
+/// timeout = "30s"
+///
+/// [[expect]]
+/// name = "boot"
+/// message = "booted"
+///
+/// # `value` checks the number captured by the `value` group
+/// [[expect]]
+/// name = "battery"
+/// message = "vbat: (?P<value>[0-9.]+)"
+/// value = ">= 3.3"
+/// ```
+#[derive(Debug)]
+pub struct Script {
+    pub ordered: bool,
+    pub timeout: Option<Duration>,
+    pub expectations: Vec<Expectation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptConfig {
+    #[serde(default = "ordered_default")]
+    ordered: bool,
+    timeout: Option<String>,
+    #[serde(default)]
+    expect: Vec<ExpectationConfig>,
+}
+
+fn ordered_default() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExpectationConfig {
+    name: Option<String>,
+    message: String,
+    value: Option<String>,
+}
+
+/// A message that has to show up, matched with a regex on the decoded text
+#[derive(Debug)]
+pub struct Expectation {
+    pub name: String,
+    pub message: Regex,
+    pub value: Option<Predicate>,
+}
+
+impl Expectation {
+    /// Check a message, `Err` has the reason when the pattern matches but the value does not
+    fn check(&self, message: &str) -> Option<Result<(), String>> {
+        let captures = self.message.captures(message)?;
+
+        let predicate = match &self.value {
+            Some(predicate) => predicate,
+            None => return Some(Ok(())),
+        };
+        let text = captures.name("value").map(|m| m.as_str()).unwrap_or("");
+
+        Some(match text.trim().parse::<f64>() {
+            Ok(value) if predicate.holds(value) => Ok(()),
+            Ok(value) => Err(format!("value {} is not {}", value, predicate)),
+            Err(_) => Err(format!("value {:?} is not a number", text)),
+        })
+    }
+}
+
+impl Script {
+    pub fn parse(s: &str) -> Result<Self> {
+        let config: ScriptConfig = toml::from_str(s)?;
+
+        let timeout = config
+            .timeout
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()
+            .context("Invalid timeout")?;
+
+        let expectations = config
+            .expect
+            .into_iter()
+            .enumerate()
+            .map(|(i, expect)| {
+                let message = Regex::new(&expect.message)?;
+                let value = match &expect.value {
+                    Some(value) if message.capture_names().flatten().any(|n| n == "value") => {
+                        Some(value.parse()?)
+                    }
+                    Some(_) => {
+                        return Err(anyhow!(
+                            "Expectation {:?} has a value check but no `value` group",
+                            expect.message
+                        ))
+                    }
+                    None => None,
+                };
+
+                Ok(Expectation {
+                    name: expect.name.unwrap_or_else(|| format!("expect {}", i + 1)),
+                    message,
+                    value,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Script {
+            ordered: config.ordered,
+            timeout,
+            expectations,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let s = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read test script {}", path.display()))?;
+        Script::parse(&s).with_context(|| format!("Invalid test script {}", path.display()))
+    }
+}
+
+/// Comparison of a captured value against a constant, e.g. `>= 3.3`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Predicate {
+    op: &'static str,
+    rhs: f64,
+}
+
+impl Predicate {
+    pub fn holds(&self, value: f64) -> bool {
+        match self.op {
+            "<" => value < self.rhs,
+            "<=" => value <= self.rhs,
+            ">" => value > self.rhs,
+            ">=" => value >= self.rhs,
+            "==" => value == self.rhs,
+            _ => value != self.rhs,
+        }
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let op = ["<=", ">=", "==", "!=", "<", ">"]
+            .iter()
+            .find(|op| s.starts_with(*op))
+            .ok_or_else(|| anyhow!("Invalid value check {:?}, expected e.g. `>= 3.3`", s))?;
+        let rhs = s[op.len()..]
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid number in value check {:?}", s))?;
+
+        Ok(Predicate { op, rhs })
+    }
+}
+
+impl std::fmt::Display for Predicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.op, self.rhs)
+    }
+}
+
+/// Result of one expectation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Pending {
+        /// Why the last message matching the pattern did not count
+        mismatch: Option<String>,
+    },
+    /// Met after this long
+    Met(Duration),
+}
+
+/// Checks decoded messages against a script
+#[derive(Debug)]
+pub struct Runner {
+    script: Script,
+    status: Vec<Status>,
+}
+
+impl Runner {
+    pub fn new(script: Script) -> Self {
+        let status = vec![Status::Pending { mismatch: None }; script.expectations.len()];
+
+        Runner { script, status }
+    }
+
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+
+    /// Check a message that arrived `elapsed` into the run
+    pub fn observe(&mut self, message: &str, elapsed: Duration) {
+        for (expectation, status) in self.script.expectations.iter().zip(&mut self.status) {
+            if let Status::Pending { mismatch } = status {
+                match expectation.check(message) {
+                    Some(Ok(())) => {
+                        *status = Status::Met(elapsed);
+                        return;
+                    }
+                    Some(Err(reason)) => *mismatch = Some(reason),
+                    None => (),
+                }
+
+                // Only the first pending expectation can be met when ordered
+                if self.script.ordered {
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn status(&self) -> &[Status] {
+        &self.status
+    }
+
+    /// All expectations are met
+    pub fn passed(&self) -> bool {
+        self.status.iter().all(|s| matches!(s, Status::Met(_)))
+    }
+
+    /// Write one line per expectation and the overall result
+    pub fn write_summary(&self, w: &mut impl Write) -> Result<()> {
+        for (expectation, status) in self.script.expectations.iter().zip(&self.status) {
+            match status {
+                Status::Met(at) => writeln!(
+                    w,
+                    "  ok    {} ({:.3} s)",
+                    expectation.name,
+                    at.as_secs_f64()
+                )?,
+                Status::Pending {
+                    mismatch: Some(reason),
+                } => writeln!(w, "  FAIL  {}: {}", expectation.name, reason)?,
+                Status::Pending { mismatch: None } => {
+                    writeln!(w, "  FAIL  {}: not seen", expectation.name)?
+                }
+            }
+        }
+
+        let met = self
+            .status
+            .iter()
+            .filter(|s| matches!(s, Status::Met(_)))
+            .count();
+        writeln!(
+            w,
+            "test result: {}. {} of {} expectations met",
+            if self.passed() { "ok" } else { "FAILED" },
+            met,
+            self.status.len()
+        )?;
+
+        Ok(())
+    }
+
+    /// Write the results as a JUnit XML test suite
+    pub fn write_junit(&self, w: &mut impl Write, suite: &str, elapsed: Duration) -> Result<()> {
+        let failures = self
+            .status
+            .iter()
+            .filter(|s| !matches!(s, Status::Met(_)))
+            .count();
+
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<testsuite name="{}" tests="{}" failures="{}" time="{:.3}">"#,
+            escape(suite),
+            self.status.len(),
+            failures,
+            elapsed.as_secs_f64()
+        )?;
+
+        for (expectation, status) in self.script.expectations.iter().zip(&self.status) {
+            let name = escape(&expectation.name);
+            match status {
+                Status::Met(at) => writeln!(
+                    w,
+                    r#"  <testcase name="{}" time="{:.3}"/>"#,
+                    name,
+                    at.as_secs_f64()
+                )?,
+                Status::Pending { mismatch } => {
+                    let reason = match mismatch {
+                        Some(reason) => format!("matching message seen, but {}", reason),
+                        None => format!("no message matching {:?}", expectation.message.as_str()),
+                    };
+                    writeln!(w, r#"  <testcase name="{}">"#, name)?;
+                    writeln!(w, r#"    <failure message="{}"/>"#, escape(&reason))?;
+                    writeln!(w, "  </testcase>")?;
+                }
+            }
+        }
+
+        writeln!(w, "</testsuite>")?;
+
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
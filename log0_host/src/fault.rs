@@ -0,0 +1,183 @@
+use elf_test::Symbolizer;
+use std::convert::TryInto;
+
+/// Bits of the Configurable Fault Status Register, by bit number
+const CFSR: &[(u32, &str, &str)] = &[
+    (0, "IACCVIOL", "instruction access violation"),
+    (1, "DACCVIOL", "data access violation"),
+    (3, "MUNSTKERR", "MemManage fault on unstacking"),
+    (4, "MSTKERR", "MemManage fault on stacking"),
+    (
+        5,
+        "MLSPERR",
+        "MemManage fault during lazy FP state preservation",
+    ),
+    (8, "IBUSERR", "instruction bus error"),
+    (9, "PRECISERR", "precise data bus error"),
+    (10, "IMPRECISERR", "imprecise data bus error"),
+    (11, "UNSTKERR", "bus fault on unstacking"),
+    (12, "STKERR", "bus fault on stacking"),
+    (13, "LSPERR", "bus fault during lazy FP state preservation"),
+    (16, "UNDEFINSTR", "undefined instruction"),
+    (17, "INVSTATE", "invalid state"),
+    (18, "INVPC", "invalid EXC_RETURN"),
+    (19, "NOCP", "no coprocessor"),
+    (20, "STKOF", "stack overflow"),
+    (24, "UNALIGNED", "unaligned access"),
+    (25, "DIVBYZERO", "divide by zero"),
+];
+
+/// Bits of the HardFault Status Register, by bit number
+const HFSR: &[(u32, &str, &str)] = &[
+    (1, "VECTTBL", "bus fault on a vector table read"),
+    (30, "FORCED", "escalated from a configurable fault"),
+    (31, "DEBUGEVT", "debug event"),
+];
+
+/// MMFAR holds the faulting address
+const MMARVALID: u32 = 1 << 7;
+
+/// BFAR holds the faulting address
+const BFARVALID: u32 = 1 << 15;
+
+/// The registers the target stacked on a HardFault and its fault status registers, from the
+/// fault frame
+///
+/// Armv6-M has no fault status registers, the target sends them as 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+}
+
+impl Fault {
+    /// Parse the data of a fault frame, 12 little endian `u32`s
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() != 48 {
+            return None;
+        }
+        let word = |i: usize| u32::from_le_bytes(data[4 * i..4 * i + 4].try_into().unwrap());
+
+        Some(Fault {
+            r0: word(0),
+            r1: word(1),
+            r2: word(2),
+            r3: word(3),
+            r12: word(4),
+            lr: word(5),
+            pc: word(6),
+            xpsr: word(7),
+            cfsr: word(8),
+            hfsr: word(9),
+            mmfar: word(10),
+            bfar: word(11),
+        })
+    }
+
+    /// What caused the fault, from the CFSR and HFSR
+    pub fn causes(&self) -> Vec<&'static str> {
+        let set = |register: u32| move |(bit, ..): &&(u32, &str, &str)| register & (1 << bit) != 0;
+        CFSR.iter()
+            .filter(set(self.cfsr))
+            .chain(HFSR.iter().filter(set(self.hfsr)))
+            .map(|(_, _, cause)| *cause)
+            .collect()
+    }
+
+    /// The faulting data address, if MMFAR or BFAR holds one
+    pub fn address(&self) -> Option<u32> {
+        if self.cfsr & MMARVALID != 0 {
+            Some(self.mmfar)
+        } else if self.cfsr & BFARVALID != 0 {
+            Some(self.bfar)
+        } else {
+            None
+        }
+    }
+
+    /// One line about where the fault happened and why, e.g. `HardFault at 0x00000abc in
+    /// app::main (src/main.rs:12): precise data bus error at 0x20010000`
+    pub fn summary(&self, symbolizer: Option<&Symbolizer>) -> String {
+        let mut summary = format!("HardFault at {}", located(self.pc, symbolizer));
+        let causes = self.causes();
+        if !causes.is_empty() {
+            summary.push_str(": ");
+            summary.push_str(&causes.join(", "));
+        }
+        if let Some(address) = self.address() {
+            summary.push_str(&format!(" at {:#010x}", address));
+        }
+        summary
+    }
+
+    /// The summary followed by the registers and the decoded status registers, a line each
+    pub fn report(&self, symbolizer: Option<&Symbolizer>) -> Vec<String> {
+        let mut report = vec![
+            self.summary(symbolizer),
+            format!("pc   {}", located(self.pc, symbolizer)),
+            // The Thumb bit is set in return addresses
+            format!("lr   {}", located(self.lr & !1, symbolizer)),
+            format!(
+                "r0   {:#010x}  r1   {:#010x}  r2   {:#010x}  r3   {:#010x}",
+                self.r0, self.r1, self.r2, self.r3
+            ),
+            format!("r12  {:#010x}  xpsr {:#010x}", self.r12, self.xpsr),
+        ];
+        if self.cfsr != 0 || self.hfsr != 0 {
+            report.push(format!(
+                "cfsr {:#010x}  {}",
+                self.cfsr,
+                bits(self.cfsr, CFSR)
+            ));
+            report.push(format!(
+                "hfsr {:#010x}  {}",
+                self.hfsr,
+                bits(self.hfsr, HFSR)
+            ));
+        }
+        if self.cfsr & MMARVALID != 0 {
+            report.push(format!("mmfar {:#010x}", self.mmfar));
+        }
+        if self.cfsr & BFARVALID != 0 {
+            report.push(format!("bfar {:#010x}", self.bfar));
+        }
+        report
+    }
+}
+
+/// `address`, and the function and line it is in if the ELF knows
+fn located(address: u32, symbolizer: Option<&Symbolizer>) -> String {
+    let mut located = format!("{:#010x}", address);
+    let symbolizer = match symbolizer {
+        Some(symbolizer) => symbolizer,
+        None => return located,
+    };
+
+    if let Some(function) = symbolizer.function(address as u64) {
+        located.push_str(&format!(" in {}", function));
+    }
+    if let (Some(file), Some(line)) = symbolizer.location(address as u64) {
+        located.push_str(&format!(" ({}:{})", file, line));
+    }
+    located
+}
+
+/// The names of the bits set in `register`
+fn bits(register: u32, names: &[(u32, &str, &str)]) -> String {
+    names
+        .iter()
+        .filter(|(bit, ..)| register & (1 << bit) != 0)
+        .map(|(_, name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
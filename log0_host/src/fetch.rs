@@ -0,0 +1,98 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::time::Duration;
+
+/// Longest string read from the target
+pub const MAX_LEN: usize = 256;
+
+/// Bytes read per transfer, most strings end in the first one
+const BLOCK: usize = 64;
+
+/// How long the decode thread waits for the probe thread to read a string
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Memory access needed to read strings from the target
+pub trait ReadMemory {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<()>;
+}
+
+/// Read a NUL terminated string from target memory, at most `MAX_LEN` bytes of it
+pub fn read_string(memory: &mut impl ReadMemory, address: u64) -> Result<String> {
+    let mut bytes = Vec::new();
+
+    while bytes.len() < MAX_LEN {
+        let mut block = [0; BLOCK];
+        memory.read_8(address + bytes.len() as u64, &mut block)?;
+
+        match block.iter().position(|&b| b == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&block[..end]);
+                break;
+            }
+            None => bytes.extend_from_slice(&block),
+        }
+    }
+    bytes.truncate(MAX_LEN);
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// A string the decode thread wants read from the target
+pub struct Request {
+    pub address: u64,
+    reply: Sender<Option<String>>,
+}
+
+impl Request {
+    /// Read the string and answer, `None` if it could not be read
+    pub fn serve(self, memory: &mut impl ReadMemory) {
+        let string = read_string(memory, self.address).ok();
+        self.reply.send(string).ok();
+    }
+}
+
+/// Reads format strings and type names that are not in the ELF from the target, e.g. ones built
+/// at runtime or in stripped `.rodata`
+///
+/// The decode thread has no access to the probe, so the reads are requests served by the probe
+/// thread. Answers are cached, so each address is only read once.
+pub struct Fetcher {
+    requests: SyncSender<Request>,
+    cache: HashMap<usize, Option<String>>,
+}
+
+impl Fetcher {
+    pub fn new(requests: SyncSender<Request>) -> Self {
+        Fetcher {
+            requests,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Read the string at `address` unless it already has been, see `cached`
+    ///
+    /// Nothing is cached if the probe thread does not answer in time, e.g. while reconnecting.
+    pub fn fetch(&mut self, address: usize) {
+        if self.cache.contains_key(&address) {
+            return;
+        }
+
+        let (reply, answer) = mpsc::channel();
+        let request = Request {
+            address: address as u64,
+            reply,
+        };
+        if self.requests.try_send(request).is_err() {
+            return;
+        }
+        if let Ok(string) = answer.recv_timeout(TIMEOUT) {
+            self.cache.insert(address, string);
+        }
+    }
+
+    /// The string read from `address`
+    pub fn cached(&self, address: usize) -> Option<&str> {
+        self.cache.get(&address)?.as_deref()
+    }
+}
@@ -1,66 +1,216 @@
+use crate::{command::CommandChannel, rtt, symbols::Symbols};
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
 use std::fmt;
+use std::ops::Range;
 use xmas_elf::{
-    sections::{SectionData, SHN_LORESERVE},
+    program,
+    sections::{SectionData, ShType, SHF_ALLOC, SHF_EXECINSTR, SHN_LORESERVE},
     symbol_table::Entry,
     ElfFile,
 };
 
 pub struct Res<'a> {
-    pub map_strings: HashMap<usize, &'a str>,
-    pub map_types: HashMap<usize, &'a str>,
-    pub cursor_address: u32,
-    pub buffer_address: u32,
+    /// Format strings in `.fasthosting`, by symbol
+    pub map_strings: Symbols<'a>,
+    /// Type names in `.rodata`, by symbol, for targets from before the types of the `log!` call
+    /// sites had the `.fasthosting.types` section, see `Catalog::type_name`
+    pub map_types: Symbols<'a>,
+    pub cursor_address: u64,
+    pub buffer_address: u64,
     pub buffer_size: usize,
+    /// The target adds a timestamp to each frame
+    pub timestamps: bool,
+    /// Tick frequency of the timestamps, as given to `log0_target::timestamp!`
+    pub timestamp_hz: Option<u32>,
+    /// The target adds the ID of the running task to each frame
+    pub tasks: bool,
+    /// Task names by ID, as given to `log0_target::task!`
+    pub task_names: Vec<String>,
+    /// The target is built with the `delta` feature, and marks frames with only changed bytes
+    pub deltas: bool,
+    /// The target is built with the `crc` feature, and writes a CRC after each frame
+    pub crcs: bool,
+    /// The target is built with the `sequence` feature, and numbers the frames
+    pub sequences: bool,
+    /// `LOG0_CURSORS` has the count of dropped frames, older targets do not
+    pub dropped_count: bool,
+    /// The target is built with the `overwrite` feature, and overwrites the oldest frames when
+    /// the buffer is full
+    pub overwrite: bool,
+    /// The target is built with the `double-buffer` feature, and hands the buffer over a bank
+    /// at a time
+    pub double_buffer: bool,
+    /// The target is built with the `high-water` feature, the offset from the target cursor of
+    /// the most bytes that were in the buffer at once
+    pub high_water: Option<u64>,
+    /// The target is built with the `commands` feature
+    pub commands: Option<CommandChannel>,
+    /// The target is built with the `priority` feature
+    pub priority: Option<ExtraRing>,
+    /// The rings of the other cores of a target built with the `dual-core` feature, the ring
+    /// of core `n` at `n - 1`
+    pub cores: Vec<ExtraRing>,
+    /// `log0_target::WIRE_VERSION` of the target, older targets do not have it
+    pub wire_version: Option<u8>,
+    /// Address of the `_SEGGER_RTT` control block of a target built with the `rtt` feature, the
+    /// cursors are those of its first up-channel until it is found on the target
+    pub rtt: Option<u64>,
+    /// The ITM stimulus port of a target built with the `itm` feature, the frames are read from
+    /// its SWO output instead of the ring
+    pub itm_port: Option<u8>,
+    /// The target links `log0_panic`, and marks its ring when it panics
+    pub panics: bool,
+    pub addresses: AddressMap,
+}
+
+/// A ring next to the main one, e.g. the one for `warn!` and `error!` that is drained before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtraRing {
+    pub cursor_address: u64,
+    pub buffer_address: u64,
+    pub buffer_size: usize,
+}
+
+/// A loadable segment whose load address differs from its link address, e.g. `.data` that is
+/// copied from flash to RAM at startup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub virtual_address: usize,
+    pub physical_address: usize,
+    pub size: usize,
+}
+
+/// Turns addresses captured on the target into the link addresses the symbols are at
+#[derive(Debug, Clone, Default)]
+pub struct AddressMap {
+    /// Executable sections, where pointers carry the Thumb bit
+    pub code: Vec<Range<usize>>,
+    pub segments: Vec<Segment>,
+}
+
+impl AddressMap {
+    pub fn new(elf: &ElfFile) -> Self {
+        let code = elf
+            .section_iter()
+            .filter(|sect| sect.flags() & SHF_EXECINSTR != 0)
+            .map(|sect| sect.address() as usize..(sect.address() + sect.size()) as usize)
+            .collect();
+
+        let segments = elf
+            .program_iter()
+            .filter(|header| header.get_type() == Ok(program::Type::Load))
+            .filter(|header| header.virtual_addr() != header.physical_addr())
+            .map(|header| Segment {
+                virtual_address: header.virtual_addr() as usize,
+                physical_address: header.physical_addr() as usize,
+                size: header.file_size() as usize,
+            })
+            .collect();
+
+        AddressMap { code, segments }
+    }
+
+    /// The link address for `address`
+    ///
+    /// Addresses in the load image of a relocated segment are moved to where it is linked, and
+    /// the Thumb bit is cleared for addresses in code. Data can be at odd addresses, so the bit
+    /// is left alone elsewhere.
+    pub fn normalize(&self, address: usize) -> usize {
+        let linked = self.segments.iter().any(|segment| {
+            (segment.virtual_address..segment.virtual_address + segment.size).contains(&address)
+        });
+        let address = match self.segments.iter().find(|segment| {
+            (segment.physical_address..segment.physical_address + segment.size).contains(&address)
+        }) {
+            Some(segment) if !linked => {
+                address - segment.physical_address + segment.virtual_address
+            }
+            _ => address,
+        };
+
+        if address & 1 == 1 && self.code.iter().any(|code| code.contains(&address)) {
+            address & !1
+        } else {
+            address
+        }
+    }
 }
 
 pub fn extract_format_and_type_strings<'a>(elf: &'a ElfFile) -> Result<Res<'a>> {
     let mut cursor_address = None;
     let mut buf_address = None;
+    let mut timestamps = false;
+    let mut timestamp_hz = None;
+    let mut tasks = false;
+    let mut task_names = Vec::new();
+    let mut deltas = false;
+    let mut crcs = false;
+    let mut sequences = false;
+    let mut dropped_count = false;
+    let mut overwrite = false;
+    let mut double_buffer = false;
+    let mut high_water = None;
+    let mut command_cursor_address = None;
+    let mut command_buffer = None;
+    let mut priority_cursor_address = None;
+    let mut priority_buffer = None;
+    // By core, `LOG0_CURSORS_<n>` and `LOG0_BUFFER_<n>`
+    let mut core_cursors = BTreeMap::new();
+    let mut core_buffers = BTreeMap::new();
+    let mut wire_version = None;
+    let mut rtt = None;
+    let mut itm_port = None;
+    let mut panics = false;
 
     let sections = get_sections(elf);
+    log::trace!("Sections: {:#?}", sections);
 
-    // println!("sections: {:#?}", sections);
-
-    let mut map_strings: HashMap<usize, &str> = HashMap::new();
-    let mut map_types: HashMap<usize, &str> = HashMap::new();
+    let mut map_strings = Vec::new();
+    let mut map_types = Vec::new();
 
     for sect in elf.section_iter() {
-        // if sect.flags() & SHF_ALLOC != 0 {
-        //     println!(
-        //         "alloc section: {:?}, address: {:x}, size: {}",
-        //         sect.get_name(elf),
-        //         sect.address(),
-        //         sect.size()
-        //     );
-        // } else {
-        //     println!(
-        //         "not alloc section: {:?}, address: {:x}, size: {}",
-        //         sect.get_name(elf),
-        //         sect.address(),
-        //         sect.size()
-        //     );
-        // }
+        log::trace!(
+            "{} section: {:?}, address: {:x}, size: {}",
+            if sect.flags() & SHF_ALLOC != 0 {
+                "Alloc"
+            } else {
+                "Not alloc"
+            },
+            sect.get_name(elf),
+            sect.address(),
+            sect.size()
+        );
 
         if sect.get_name(elf) == Ok(".symtab") {
             if let Ok(symtab) = sect.get_data(elf) {
                 if let SectionData::SymbolTable32(entries) = symtab {
                     for entry in entries {
                         if let Ok(name) = entry.get_name(elf) {
-                            // println!(
-                            //     "names: {}, addr: {:x}, size: {}, shndx: {}",
-                            //     rustc_demangle::demangle(name).to_string(),
-                            //     entry.value(),
-                            //     entry.size(),
-                            //     entry.shndx(),
-                            // );
+                            log::trace!(
+                                "Symbol: {}, addr: {:x}, size: {}, shndx: {}",
+                                rustc_demangle::demangle(name),
+                                entry.value(),
+                                entry.size(),
+                                entry.shndx(),
+                            );
 
                             if entry.shndx() < SHN_LORESERVE {
                                 if let Ok(s) = elf.section_header(entry.shndx()) {
                                     let ev = entry.value() as usize;
                                     let es = entry.size() as usize;
-                                    if let Ok(".fasthosting") = s.get_name(elf) {
+                                    // The type markers of the call sites end up here with a
+                                    // linker script from before `.fasthosting.types`
+                                    let demangled = format!("{:#}", rustc_demangle::demangle(name));
+                                    let type_marker = demangled
+                                        .rsplit("::")
+                                        .next()
+                                        .unwrap()
+                                        .starts_with("TYPE_T");
+                                    if let (Ok(".fasthosting"), false) =
+                                        (s.get_name(elf), type_marker)
+                                    {
                                         let cs = sections
                                             .iter()
                                             .find(|v| &v.name == &".fasthosting")
@@ -72,7 +222,7 @@ pub fn extract_format_and_type_strings<'a>(elf: &'a ElfFile) -> Result<Res<'a>>
                                         if let Ok(s) =
                                             std::str::from_utf8(&cs.bytes[ev_off..ev_off + es])
                                         {
-                                            map_strings.insert(ev, s);
+                                            map_strings.push((ev, s));
                                         }
                                     }
 
@@ -88,32 +238,132 @@ pub fn extract_format_and_type_strings<'a>(elf: &'a ElfFile) -> Result<Res<'a>>
                                         if let Ok(s) =
                                             std::str::from_utf8(&cs.bytes[ev_off..ev_off + es])
                                         {
-                                            map_types.insert(ev, s);
+                                            map_types.push((ev, s));
                                         }
                                     }
                                 }
                             }
 
                             if name == "LOG0_CURSORS" {
-                                // println!(
-                                //     "        Found '{}', address = 0x{:8x}, size = {}b",
-                                //     name,
-                                //     entry.value(),
-                                //     entry.size()
-                                // );
+                                log::debug!(
+                                    "Found '{}', address = 0x{:8x}, size = {}b",
+                                    name,
+                                    entry.value(),
+                                    entry.size()
+                                );
 
-                                cursor_address = Some(entry.value() as u32);
+                                cursor_address = Some(entry.value());
+                                // The target and host cursors, the buffer pointer and the count
+                                dropped_count = entry.size() >= 16;
+                            }
+
+                            if name == "_log0_timestamp" {
+                                timestamps = true;
+                            }
+
+                            if name == "LOG0_TIMESTAMP_HZ" {
+                                if let Some(bytes) = symbol_data(elf, entry, 4) {
+                                    timestamp_hz =
+                                        Some(u32::from_le_bytes(bytes.try_into().unwrap()));
+                                }
+                            }
+
+                            if name == "_log0_task" {
+                                tasks = true;
+                            }
+
+                            if name == "LOG0_TASK_NAMES" {
+                                if let Some(bytes) = symbol_data(elf, entry, entry.size() as usize)
+                                {
+                                    task_names = bytes
+                                        .split(|&b| b == 0)
+                                        .map(|name| String::from_utf8_lossy(name).into_owned())
+                                        .collect();
+                                    // The last name is terminated too
+                                    task_names.pop();
+                                }
+                            }
+
+                            if name == "LOG0_DELTA" {
+                                deltas = true;
+                            }
+
+                            if name == "LOG0_CRC" {
+                                crcs = true;
+                            }
+
+                            if name == "LOG0_SEQUENCE" {
+                                sequences = true;
+                            }
+
+                            if name == "LOG0_OVERWRITE" {
+                                overwrite = true;
+                            }
+
+                            if name == "LOG0_DOUBLE_BUFFER" {
+                                double_buffer = true;
+                            }
+
+                            if name == "LOG0_HIGH_WATER" {
+                                high_water =
+                                    symbol_data(elf, entry, 1).map(|bytes| u64::from(bytes[0]));
+                            }
+
+                            if name == "LOG0_COMMAND_CURSORS" {
+                                command_cursor_address = Some(entry.value());
+                            }
+
+                            if name == "LOG0_COMMAND_BUFFER" {
+                                command_buffer = Some((entry.value(), entry.size() as usize));
+                            }
+
+                            if name == "LOG0_PRIORITY_CURSORS" {
+                                priority_cursor_address = Some(entry.value());
+                            }
+
+                            if name == "LOG0_PRIORITY_BUFFER" {
+                                priority_buffer = Some((entry.value(), entry.size() as usize));
+                            }
+
+                            if let Some(core) = name
+                                .strip_prefix("LOG0_CURSORS_")
+                                .and_then(|core| core.parse::<usize>().ok())
+                            {
+                                core_cursors.insert(core, entry.value());
+                            }
+
+                            if let Some(core) = name
+                                .strip_prefix("LOG0_BUFFER_")
+                                .and_then(|core| core.parse::<usize>().ok())
+                            {
+                                core_buffers.insert(core, (entry.value(), entry.size() as usize));
+                            }
+
+                            if name == "LOG0_WIRE_VERSION" {
+                                wire_version = symbol_data(elf, entry, 1).map(|bytes| bytes[0]);
+                            }
+
+                            if name == "LOG0_ITM_PORT" {
+                                itm_port = symbol_data(elf, entry, 1).map(|bytes| bytes[0]);
+                            }
+
+                            if name == "LOG0_PANIC" {
+                                panics = true;
+                            }
+
+                            if name == "_SEGGER_RTT" {
+                                rtt = Some(entry.value());
                             }
 
                             if name == "LOG0_BUFFER" {
-                                // println!(
-                                //     "        Found '{}', address = 0x{:8x}, size = {}b",
-                                //     name,
-                                //     entry.value(),
-                                //     entry.size()
-                                // );
+                                log::debug!(
+                                    "Found '{}', address = 0x{:8x}, size = {}b",
+                                    name,
+                                    entry.value(),
+                                    entry.size()
+                                );
 
-                                buf_address = Some((entry.value() as u32, entry.size() as usize));
+                                buf_address = Some((entry.value(), entry.size() as usize));
                             }
                         }
                     }
@@ -122,6 +372,12 @@ pub fn extract_format_and_type_strings<'a>(elf: &'a ElfFile) -> Result<Res<'a>>
         }
     }
 
+    // The cursors of an RTT up-channel are followed by the count of dropped frames
+    if let (None, Some(address)) = (cursor_address, rtt) {
+        cursor_address = Some(rtt::cursor_address(address, 0));
+        dropped_count = true;
+    }
+
     if cursor_address.is_none() {
         return Err(anyhow!("Missing cursor address"));
     }
@@ -131,14 +387,82 @@ pub fn extract_format_and_type_strings<'a>(elf: &'a ElfFile) -> Result<Res<'a>>
     }
 
     Ok(Res {
-        map_strings,
-        map_types,
+        map_strings: map_strings.into_iter().collect(),
+        map_types: map_types.into_iter().collect(),
         cursor_address: cursor_address.unwrap(),
         buffer_address: buf_address.unwrap().0,
         buffer_size: buf_address.unwrap().1,
+        timestamps,
+        timestamp_hz,
+        tasks,
+        task_names,
+        deltas,
+        crcs,
+        sequences,
+        dropped_count,
+        overwrite,
+        double_buffer,
+        high_water,
+        commands: match (command_cursor_address, command_buffer) {
+            (Some(cursor_address), Some((buffer_address, buffer_size))) => Some(CommandChannel {
+                cursor_address,
+                buffer_address,
+                buffer_size,
+            }),
+            _ => None,
+        },
+        priority: match (priority_cursor_address, priority_buffer) {
+            (Some(cursor_address), Some((buffer_address, buffer_size))) => Some(ExtraRing {
+                cursor_address,
+                buffer_address,
+                buffer_size,
+            }),
+            _ => None,
+        },
+        // Only the cores from 1 up without a gap
+        cores: (1..)
+            .map_while(|core| {
+                let cursor_address = *core_cursors.get(&core)?;
+                let (buffer_address, buffer_size) = *core_buffers.get(&core)?;
+                Some(ExtraRing {
+                    cursor_address,
+                    buffer_address,
+                    buffer_size,
+                })
+            })
+            .collect(),
+        wire_version,
+        rtt,
+        itm_port,
+        panics,
+        addresses: AddressMap::new(elf),
     })
 }
 
+/// The `len` bytes of the static `entry` in the ELF
+fn symbol_data<'a>(elf: &ElfFile<'a>, entry: &impl Entry, len: usize) -> Option<&'a [u8]> {
+    if entry.shndx() >= SHN_LORESERVE {
+        return None;
+    }
+    let section = elf.section_header(entry.shndx()).ok()?;
+    let off = entry.value().checked_sub(section.address())? as usize;
+
+    section.raw_data(elf).get(off..off + len)
+}
+
+/// The `len` bytes at `address` in the loaded sections of the ELF, e.g. a `&'static str` in
+/// `.rodata`
+pub fn elf_data<'a>(elf: &ElfFile<'a>, address: u64, len: usize) -> Option<&'a [u8]> {
+    elf.section_iter()
+        .filter(|section| section.flags() & SHF_ALLOC != 0)
+        .filter(|section| section.get_type() != Ok(ShType::NoBits))
+        .find(|section| (section.address()..section.address() + section.size()).contains(&address))
+        .and_then(|section| {
+            let off = (address - section.address()) as usize;
+            section.raw_data(elf).get(off..off + len)
+        })
+}
+
 struct Section<'a> {
     address: u32,
     bytes: &'a [u8],
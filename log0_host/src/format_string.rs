@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Result};
+use elf_test::{Align, BaseEncoding, FormatOptions};
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// A piece of a parsed format string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Literal text, with `{{` and `}}` unescaped
+    Literal(String),
+    /// A placeholder for the argument at `index`, with the options parsed from the text after
+    /// the `:` (e.g. `08x` for `{:08x}`)
+    Argument {
+        index: usize,
+        options: FormatOptions,
+    },
+}
+
+/// A format string from the `.fasthosting` section, following the `core::fmt` syntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatString {
+    segments: Vec<Segment>,
+}
+
+impl FormatString {
+    /// Parse a format string, fails on unbalanced braces
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut next_index = 0;
+        let mut chars = s.char_indices().peekable();
+
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '{' | '}' if chars.peek().map(|&(_, next)| next) == Some(c) => {
+                    chars.next();
+                    literal.push(c);
+                }
+                '}' => return Err(anyhow!("Unmatched `}}` at {} in {:?}", pos, s)),
+                '{' => {
+                    let placeholder = placeholder(&mut chars)
+                        .ok_or_else(|| anyhow!("Unterminated `{{` at {} in {:?}", pos, s))?;
+
+                    let (position, spec) = match placeholder.find(':') {
+                        Some(colon) => (&placeholder[..colon], &placeholder[colon + 1..]),
+                        None => (placeholder.as_str(), ""),
+                    };
+
+                    let index = if position.is_empty() {
+                        next_index += 1;
+                        next_index - 1
+                    } else {
+                        position.trim().parse().map_err(|_| {
+                            anyhow!("Unsupported argument {:?} at {} in {:?}", position, pos, s)
+                        })?
+                    };
+
+                    let options =
+                        parse_spec(spec).map_err(|e| anyhow!("{} at {} in {:?}", e, pos, s))?;
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Argument { index, options });
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(FormatString { segments })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Number of arguments the format string refers to
+    pub fn arguments(&self) -> usize {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Argument { index, .. } => Some(index + 1),
+                Segment::Literal(_) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Render the format string, `argument` is called with the index and options of each
+    /// placeholder and returns the text to put in its place
+    pub fn render(&self, mut argument: impl FnMut(usize, &FormatOptions) -> String) -> String {
+        let mut out = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Argument { index, options } => out.push_str(&argument(*index, options)),
+            }
+        }
+
+        out
+    }
+}
+
+/// Consume a placeholder up to and including the closing `}`, returns `None` if it is not
+/// terminated
+fn placeholder(chars: &mut Peekable<CharIndices>) -> Option<String> {
+    let mut placeholder = String::new();
+
+    for (_, c) in chars {
+        match c {
+            '}' => return Some(placeholder),
+            '{' => return None,
+            c => placeholder.push(c),
+        }
+    }
+
+    None
+}
+
+/// Parse the part of a placeholder after the `:`, following the `core::fmt` syntax
+///
+/// `[[fill]align][sign]['#']['0'][width]['.' precision][type]`
+///
+/// Width and precision taken from arguments (`1$`, `.*`) are not supported, as the target only
+/// sends the value being printed.
+pub fn parse_spec(spec: &str) -> Result<FormatOptions> {
+    let mut options = FormatOptions::default();
+    let mut rest = spec;
+
+    let align = |c| match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    };
+
+    let mut chars = rest.chars();
+    let first = chars.next();
+    let second = chars.next();
+    if let (Some(fill), Some(a)) = (first, second.and_then(align)) {
+        options.fill = fill;
+        options.align = Some(a);
+        rest = &rest[fill.len_utf8() + 1..];
+    } else if let Some(a) = first.and_then(align) {
+        options.align = Some(a);
+        rest = &rest[1..];
+    }
+
+    if let Some(r) = rest.strip_prefix('+') {
+        options.sign_plus = true;
+        rest = r;
+    } else if let Some(r) = rest.strip_prefix('-') {
+        rest = r;
+    }
+
+    if let Some(r) = rest.strip_prefix('#') {
+        options.alternate = true;
+        rest = r;
+    }
+
+    if let Some(r) = rest.strip_prefix('0') {
+        options.zero_pad = true;
+        rest = r;
+    }
+
+    let (width, r) = number(rest);
+    options.width = width;
+    rest = r;
+    if rest.starts_with('$') {
+        return Err(anyhow!("Width from arguments is not supported"));
+    }
+
+    if let Some(r) = rest.strip_prefix('.') {
+        let (precision, r) = number(r);
+        if precision.is_none() || r.starts_with('$') {
+            return Err(anyhow!("Precision from arguments is not supported"));
+        }
+        options.precision = precision;
+        rest = r;
+    }
+
+    options.encoding = match rest {
+        "" | "?" => BaseEncoding::Decimal,
+        "x" | "x?" => BaseEncoding::Hex,
+        "X" | "X?" => BaseEncoding::UpperHex,
+        "o" => BaseEncoding::Octal,
+        "b" => BaseEncoding::Binary,
+        "e" => BaseEncoding::Exp,
+        "E" => BaseEncoding::UpperExp,
+        _ => return Err(anyhow!("Unsupported format spec {:?}", spec)),
+    };
+
+    Ok(options)
+}
+
+/// Split off a leading decimal number
+fn number(s: &str) -> (Option<usize>, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+    (s[..end].parse().ok(), &s[end..])
+}
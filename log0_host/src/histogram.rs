@@ -0,0 +1,266 @@
+use crate::{record::Record, sink::Sink};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Length of the bar of the fullest bucket
+const BAR_WIDTH: u64 = 40;
+
+/// Buckets of a histogram, the `[histograms]` section of the config keyed by field as in
+/// `--histogram`, every field in it gets a histogram
+///
+/// ```toml
+/// # Upper bounds of the buckets, values from the last bound up are counted in one more
+/// [histograms.latency_us]
+/// bounds = [10, 20, 50, 100, 200]
+///
+/// # Or `count` buckets of the same width between `min` and `max`
+/// [histograms."Timing.jitter"]
+/// min = -50
+/// max = 50
+/// count = 10
+/// ```
+///
+/// Without either, the buckets are powers of two, which suits latencies.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistogramConfig {
+    pub bounds: Option<Vec<f64>>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub count: Option<usize>,
+}
+
+impl HistogramConfig {
+    pub fn buckets(&self) -> Result<Buckets> {
+        match (&self.bounds, self.min, self.max, self.count) {
+            (Some(bounds), None, None, None) => {
+                if bounds.is_empty() || bounds.windows(2).any(|w| w[0] >= w[1]) {
+                    return Err(anyhow!("Histogram bounds have to be increasing"));
+                }
+                Ok(Buckets::Bounds(bounds.clone()))
+            }
+            (None, Some(min), Some(max), Some(count)) => {
+                if count == 0 || min >= max {
+                    return Err(anyhow!("Histogram needs min < max and at least one bucket"));
+                }
+                let width = (max - min) / count as f64;
+                let bounds = (0..=count).map(|i| min + width * i as f64).collect();
+                Ok(Buckets::Bounds(bounds))
+            }
+            (None, None, None, None) => Ok(Buckets::PowersOfTwo),
+            _ => Err(anyhow!(
+                "Set either `bounds`, or `min`, `max` and `count` for a histogram"
+            )),
+        }
+    }
+}
+
+/// How values are sorted into buckets
+#[derive(Debug, Clone, PartialEq)]
+pub enum Buckets {
+    /// Upper bounds in increasing order, with one more bucket for values from the last bound up
+    Bounds(Vec<f64>),
+    /// `[1, 2)`, `[2, 4)` and so on, with one bucket for everything below 1
+    PowersOfTwo,
+}
+
+impl Buckets {
+    fn index(&self, value: f64) -> i64 {
+        match self {
+            Buckets::Bounds(bounds) => bounds.iter().take_while(|b| **b <= value).count() as i64,
+            Buckets::PowersOfTwo if value < 1.0 => -1,
+            Buckets::PowersOfTwo => value.log2().floor() as i64,
+        }
+    }
+
+    fn label(&self, index: i64) -> String {
+        match self {
+            Buckets::Bounds(bounds) => {
+                let i = index as usize;
+                match (i.checked_sub(1).map(|i| bounds[i]), bounds.get(i)) {
+                    (None, Some(upper)) => format!("< {}", upper),
+                    (Some(lower), Some(upper)) => format!("[{}, {})", lower, upper),
+                    (Some(lower), None) => format!(">= {}", lower),
+                    (None, None) => unreachable!(),
+                }
+            }
+            Buckets::PowersOfTwo if index < 0 => "< 1".into(),
+            Buckets::PowersOfTwo => {
+                let lower = 2f64.powi(index as i32);
+                format!("[{}, {})", lower, 2.0 * lower)
+            }
+        }
+    }
+}
+
+/// Counts of the values of one field
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    buckets: Buckets,
+    counts: BTreeMap<i64, u64>,
+    total: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Histogram {
+    pub fn new(buckets: Buckets) -> Self {
+        Histogram {
+            buckets,
+            counts: BTreeMap::new(),
+            total: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        *self.counts.entry(self.buckets.index(value)).or_default() += 1;
+        self.total += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Write a line with the count, minimum, mean and maximum under `name`, then one bar per
+    /// bucket from the lowest to the highest one with values in it
+    pub fn write(&self, w: &mut impl Write, name: &str) -> Result<()> {
+        let (first, last) = match (self.counts.keys().next(), self.counts.keys().last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Ok(()),
+        };
+
+        writeln!(
+            w,
+            "{}: {} samples, min {}, mean {:.2}, max {}",
+            name,
+            self.total,
+            self.min,
+            self.sum / self.total as f64,
+            self.max
+        )?;
+
+        let labels: Vec<_> = (first..=last).map(|i| self.buckets.label(i)).collect();
+        let width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+        let fullest = self.counts.values().copied().max().unwrap_or(1);
+        for (i, label) in (first..=last).zip(&labels) {
+            let count = self.counts.get(&i).copied().unwrap_or(0);
+            // Any count shows, however small
+            let bar = "#".repeat((count * BAR_WIDTH).div_ceil(fullest) as usize);
+            let line = format!("  {:>width$}  {:>8}  {}", label, count, bar, width = width);
+            writeln!(w, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sorts the selected numeric fields of the records into histograms, and draws them every
+/// `interval` and at the end of the session
+///
+/// A field is selected by its path as in `Type::values`, or by the short type name and path
+/// joined with a dot to only match one type, e.g. `Timing.latency_us`. Values that are not
+/// wrapped in a struct have the path `value`, and are also matched by the type name alone.
+pub struct Histograms<W: Write> {
+    w: W,
+    histograms: Vec<(String, Histogram)>,
+    interval: Duration,
+    last: Instant,
+}
+
+impl<W: Write> Histograms<W> {
+    /// A histogram for each of `fields` and each field in `config`, with the buckets from the
+    /// config
+    pub fn new(
+        w: W,
+        fields: &[String],
+        config: &HashMap<String, HistogramConfig>,
+        interval: Duration,
+    ) -> Result<Self> {
+        let mut names: Vec<_> = fields.iter().chain(config.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let histograms = names
+            .into_iter()
+            .map(|name| {
+                let buckets = config.get(name).cloned().unwrap_or_default().buckets()?;
+                Ok((name.clone(), Histogram::new(buckets)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Histograms {
+            w,
+            histograms,
+            interval,
+            last: Instant::now(),
+        })
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        self.last = Instant::now();
+        for (name, histogram) in &self.histograms {
+            if histogram.total() > 0 {
+                writeln!(self.w)?;
+                histogram.write(&mut self.w, name)?;
+            }
+        }
+
+        Ok(self.w.flush()?)
+    }
+}
+
+/// Whether `name` selects the field at `path` of a value of `type_name`
+fn selects(name: &str, type_name: Option<&str>, path: &str) -> bool {
+    if name == path {
+        return true;
+    }
+
+    let short = match type_name {
+        Some(type_name) => type_name.rsplit(':').next().unwrap(),
+        None => return false,
+    };
+    match name.strip_prefix(short) {
+        Some(rest) => rest.strip_prefix('.') == Some(path) || (rest.is_empty() && path == "value"),
+        None => false,
+    }
+}
+
+impl<W: Write> Sink for Histograms<W> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let type_name = record.type_name.as_deref();
+        for (path, value) in &record.values {
+            if !value.is_finite() {
+                continue;
+            }
+            for (name, histogram) in &mut self.histograms {
+                if selects(name, type_name, path) {
+                    histogram.add(*value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.last.elapsed() >= self.interval {
+            self.draw()?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.draw()
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{record::Record, sink::Sink};
+use anyhow::{anyhow, Context, Result};
+use rhai::serde::{from_dynamic, to_dynamic};
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::fs;
+use std::path::Path;
+
+/// Function of the script that every record is passed through
+const HOOK: &str = "hook";
+
+/// Passes every record through a Rhai script before the sink, to filter, transform or
+/// aggregate messages without rebuilding the host
+///
+/// The script defines `fn hook(record)`, which gets each record as a map and returns the record
+/// to pass on, possibly changed, or `()` to drop it. `this` is a map that is kept between calls,
+/// e.g. for running totals. `print` writes to stderr, so it does not mix with the messages.
+///
+/// ```text
+/// // Drop everything from the radio module
+/// fn hook(record) {
+///     if record.module == "app::radio" { () } else { record }
+/// }
+/// ```
+pub struct Hook<S: Sink> {
+    inner: S,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// `this` of every call
+    state: Dynamic,
+}
+
+impl<S: Sink> Hook<S> {
+    /// Load the script at `path`
+    pub fn open(path: &Path, inner: S) -> Result<Self> {
+        let script = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read hook {}", path.display()))?;
+        Hook::new(&script, inner).with_context(|| format!("Invalid hook {}", path.display()))
+    }
+
+    /// Compile `script` and run its statements outside of functions once
+    pub fn new(script: &str, inner: S) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.on_print(|text| eprintln!("{}", text));
+
+        let ast = engine.compile(script)?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == HOOK && f.params.len() == 1)
+        {
+            return Err(anyhow!("No `fn {}(record)` in the script", HOOK));
+        }
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast)?;
+
+        Ok(Hook {
+            inner,
+            engine,
+            ast,
+            scope,
+            state: Map::new().into(),
+        })
+    }
+}
+
+impl<S: Sink> Sink for Hook<S> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let answer: Dynamic = self
+            .engine
+            .call_fn_with_options(
+                options,
+                &mut self.scope,
+                &self.ast,
+                HOOK,
+                (to_dynamic(record)?,),
+            )
+            .context("Hook failed")?;
+        if answer.is_unit() {
+            return Ok(());
+        }
+
+        let answer: Record = from_dynamic(&answer)
+            .with_context(|| format!("Invalid answer from hook: {}", answer))?;
+        self.inner.write(&Record {
+            format: None,
+            args: vec![],
+            values: record.values.clone(),
+            payload: record.payload.clone(),
+            ..answer
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{record::Record, sink::Sink};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Where and how to write telemetry as InfluxDB line protocol, the `[influx]` section of the
+/// config
+///
+/// ```toml
+/// [influx]
+/// # Either an http:// URL of the write endpoint, or a file to append to
+/// url = "http://localhost:8086/api/v2/write?org=lab&bucket=firmware&precision=ns"
+/// token = "..."
+/// # Added to every point, along with `module`
+/// tags = { board = "rev-b" }
+///
+/// # Keyed by type name, by default the measurement is named after the type and has all of its
+/// # numeric fields
+/// [influx.measurements.Telemetry]
+/// name = "power"
+/// tags = { rail = "main" }
+/// fields = ["vbat_mv", "temp"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InfluxConfig {
+    pub url: Option<String>,
+    pub file: Option<PathBuf>,
+    pub token: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub measurements: HashMap<String, MeasurementConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeasurementConfig {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Only write these fields, by path as in `Type::values`
+    pub fields: Option<Vec<String>>,
+}
+
+/// Lines are posted at most this often, and on exit
+const POST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest wait to connect to InfluxDB, and for each read or write of a request
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Lines that could not be posted are kept for the next post up to this many bytes, then
+/// dropped
+const MAX_UNPOSTED: usize = 16 << 20;
+
+enum Output {
+    File(File),
+    Http {
+        host: String,
+        path: String,
+        token: Option<String>,
+        last: Instant,
+    },
+}
+
+/// Writes the numeric fields of each record as a point in InfluxDB line protocol, stamped with
+/// the host time it was written at
+///
+/// Records without numeric fields are skipped. Points InfluxDB cannot be reached for are kept
+/// and posted along with the next ones.
+pub struct Influx {
+    config: InfluxConfig,
+    output: Output,
+    lines: String,
+}
+
+impl Influx {
+    pub fn open(config: &InfluxConfig) -> Result<Self> {
+        let output = match (&config.url, &config.file) {
+            (Some(url), None) => {
+                let rest = url
+                    .strip_prefix("http://")
+                    .ok_or_else(|| anyhow!("Only http:// InfluxDB URLs are supported"))?;
+                let (host, path) = match rest.find('/') {
+                    Some(slash) => rest.split_at(slash),
+                    None => (rest, "/"),
+                };
+
+                Output::Http {
+                    host: host.into(),
+                    path: path.into(),
+                    token: config.token.clone(),
+                    last: Instant::now(),
+                }
+            }
+            (None, Some(file)) => Output::File(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(file)
+                    .with_context(|| format!("Failed to open {}", file.display()))?,
+            ),
+            _ => return Err(anyhow!("Set one of `url` or `file` in [influx]")),
+        };
+
+        Ok(Influx {
+            config: config.clone(),
+            output,
+            lines: String::new(),
+        })
+    }
+
+    /// The point for a record, `None` if it has no fields to write
+    pub fn line(config: &InfluxConfig, record: &Record, time: SystemTime) -> Option<String> {
+        let type_name = record.type_name.as_deref()?;
+        let short = type_name.rsplit(':').next().unwrap();
+        let measurement = config
+            .measurements
+            .get(type_name)
+            .or_else(|| config.measurements.get(short));
+
+        let fields: Vec<_> = record
+            .values
+            .iter()
+            .filter(|(_, value)| value.is_finite())
+            .filter(
+                |(name, _)| match measurement.and_then(|m| m.fields.as_ref()) {
+                    Some(fields) => fields.contains(name),
+                    None => true,
+                },
+            )
+            .collect();
+        if fields.is_empty() {
+            return None;
+        }
+
+        let mut tags = config.tags.clone();
+        if let Some(module) = &record.module {
+            tags.insert("module".into(), module.clone());
+        }
+        if let Some(measurement) = measurement {
+            tags.extend(measurement.tags.clone());
+        }
+
+        let name = measurement.and_then(|m| m.name.as_deref()).unwrap_or(short);
+        let mut line = escape(name, &[',', ' ']);
+        for (key, value) in &tags {
+            let _ = write!(line, ",{}={}", escape_key(key), escape_key(value));
+        }
+        for (i, (name, value)) in fields.iter().enumerate() {
+            let separator = if i == 0 { ' ' } else { ',' };
+            let _ = write!(line, "{}{}={}", separator, escape_key(name), value);
+        }
+        let nanos = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let _ = writeln!(line, " {}", nanos);
+
+        Some(line)
+    }
+
+    fn post(&mut self) -> Result<()> {
+        if let Output::Http {
+            host,
+            path,
+            token,
+            last,
+        } = &mut self.output
+        {
+            *last = Instant::now();
+            if self.lines.is_empty() {
+                return Ok(());
+            }
+
+            match post(host, path, token.as_deref(), &self.lines) {
+                Ok(Posted::Written) => self.lines.clear(),
+                Ok(Posted::Rejected(status)) => {
+                    log::warn!("InfluxDB rejected the points, dropping them: {}", status);
+                    self.lines.clear();
+                }
+                Err(e) if self.lines.len() >= MAX_UNPOSTED => {
+                    log::warn!("{:#}, dropping {} bytes of points", e, self.lines.len());
+                    self.lines.clear();
+                }
+                Err(e) => log::warn!("{:#}, trying again with the next points", e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for Influx {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        if let Some(line) = Influx::line(&self.config, record, SystemTime::now()) {
+            self.lines.push_str(&line);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.output {
+            Output::File(file) => {
+                file.write_all(self.lines.as_bytes())?;
+                self.lines.clear();
+                Ok(())
+            }
+            Output::Http { last, .. } if last.elapsed() >= POST_INTERVAL => self.post(),
+            Output::Http { .. } => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        match self.output {
+            Output::File(_) => self.flush(),
+            Output::Http { .. } => self.post(),
+        }
+    }
+}
+
+/// What InfluxDB made of a write request it answered
+enum Posted {
+    Written,
+    /// The request itself is wrong, sending it again does not help
+    Rejected(String),
+}
+
+/// Send one write request and check the status, errors are worth trying again
+fn post(host: &str, path: &str, token: Option<&str>, body: &str) -> Result<Posted> {
+    let host_port = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let connect = || -> std::io::Result<TcpStream> {
+        let mut last = None;
+        for addr in host_port.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last = Some(e),
+            }
+        }
+        Err(last.unwrap_or_else(|| ErrorKind::NotFound.into()))
+    };
+    let mut stream =
+        connect().with_context(|| format!("Failed to connect to InfluxDB at {}", host))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n",
+        path,
+        host,
+        body.len()
+    );
+    if let Some(token) = token {
+        let _ = write!(request, "Authorization: Token {}\r\n", token);
+    }
+    request.push_str("\r\n");
+    let mut response = String::new();
+    stream
+        .write_all(request.as_bytes())
+        .and_then(|()| stream.write_all(body.as_bytes()))
+        .and_then(|()| stream.read_to_string(&mut response))
+        .context("InfluxDB write failed")?;
+
+    let status = response.lines().next().unwrap_or("");
+    match status.split(' ').nth(1) {
+        Some(code) if code.starts_with('2') => Ok(Posted::Written),
+        Some(code) if code.starts_with('4') && code != "429" => Ok(Posted::Rejected(status.into())),
+        _ => Err(anyhow!("InfluxDB write failed: {}", status)),
+    }
+}
+
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Tag keys and values, and field keys
+fn escape_key(s: &str) -> String {
+    escape(s, &[',', '=', ' '])
+}
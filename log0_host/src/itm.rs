@@ -0,0 +1,366 @@
+use crate::{record::Record, time::Clock};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// Hardware source packet IDs of the DWT
+const EXCEPTION_TRACE: u8 = 1;
+const PC_SAMPLE: u8 = 2;
+
+/// Most continuation bytes of a packet, a global timestamp has up to 7
+const MAX_CONTINUATION: usize = 7;
+
+/// How long to wait for more data at the end of a capture file that is still being written
+const POLL: Duration = Duration::from_millis(10);
+
+/// An ITM or DWT packet, as sent over SWO
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Written by the firmware to a stimulus port
+    Stimulus { port: u8, payload: Vec<u8> },
+    /// Exception trace from the DWT, exception 0 is thread mode
+    Exception {
+        number: u16,
+        action: ExceptionAction,
+    },
+    /// Periodic PC sample from the DWT, `None` while the core sleeps
+    PcSample(Option<u32>),
+    /// Other DWT packets, e.g. data trace
+    Hardware { id: u8, payload: Vec<u8> },
+    /// The ITM could not keep up and packets were lost
+    Overflow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionAction {
+    Entered,
+    Exited,
+    Returned,
+}
+
+/// An event, with the timestamp counter when it happened if the ITM sends timestamps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timed {
+    pub cycles: Option<u64>,
+    pub event: Event,
+}
+
+enum Packet {
+    Event(Event),
+    /// Local timestamp, the counter value since the previous one
+    Timestamp(u64),
+    /// Synchronization, global timestamps, extensions and reserved headers
+    Other,
+}
+
+/// Splits the SWO byte stream into packets
+///
+/// Local timestamps are added up from the start of the stream. They come after the packets
+/// they are for, so once one has been seen the events are held until their timestamp arrives.
+#[derive(Debug, Default)]
+pub struct PacketParser {
+    buf: Vec<u8>,
+    cycles: u64,
+    timestamps: bool,
+    pending: Vec<Event>,
+}
+
+impl PacketParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the packets completed by `data`, and return the events that are timed
+    pub fn push(&mut self, data: &[u8]) -> Vec<Timed> {
+        self.buf.extend_from_slice(data);
+
+        let mut timed = Vec::new();
+        let mut pos = 0;
+        while let Some((len, parsed)) = packet(&self.buf[pos..]) {
+            pos += len;
+
+            match parsed {
+                Packet::Event(event) if self.timestamps => self.pending.push(event),
+                Packet::Event(event) => timed.push(Timed {
+                    cycles: None,
+                    event,
+                }),
+                Packet::Timestamp(delta) => {
+                    self.timestamps = true;
+                    self.cycles += delta;
+                    let cycles = self.cycles;
+                    timed.extend(self.pending.drain(..).map(|event| Timed {
+                        cycles: Some(cycles),
+                        event,
+                    }));
+                }
+                Packet::Other => (),
+            }
+        }
+        self.buf.drain(..pos);
+
+        timed
+    }
+}
+
+/// What a `Stimulus` takes out of the SWO stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Written {
+    Bytes(Vec<u8>),
+    /// The ITM could not keep up and bytes were lost, any frame they were part of with them
+    Overflow,
+}
+
+/// Takes the bytes written to one stimulus port out of the SWO byte stream, in order, e.g. the
+/// frames of a target built with the `itm` feature of `log0_target`
+///
+/// The other packets are skipped. With ITM timestamps, the bytes come out once their timestamp
+/// has arrived.
+#[derive(Debug)]
+pub struct Stimulus {
+    parser: PacketParser,
+    port: u8,
+}
+
+impl Stimulus {
+    pub fn new(port: u8) -> Self {
+        Stimulus {
+            parser: PacketParser::new(),
+            port,
+        }
+    }
+
+    /// The bytes of the port in the packets completed by `data`, split where the ITM overflowed
+    pub fn push(&mut self, data: &[u8]) -> Vec<Written> {
+        let mut written = Vec::new();
+        for timed in self.parser.push(data) {
+            match (timed.event, written.last_mut()) {
+                (Event::Stimulus { port, payload }, last) if port == self.port => match last {
+                    Some(Written::Bytes(bytes)) => bytes.extend_from_slice(&payload),
+                    _ => written.push(Written::Bytes(payload)),
+                },
+                (Event::Overflow, _) => written.push(Written::Overflow),
+                _ => (),
+            }
+        }
+
+        written
+    }
+}
+
+/// The packet at the start of `bytes` and its length, `None` if it is not complete
+fn packet(bytes: &[u8]) -> Option<(usize, Packet)> {
+    let header = *bytes.first()?;
+
+    // Source packets, with a payload of 1, 2 or 4 bytes
+    if header & 0x03 != 0 {
+        let size = [0, 1, 2, 4][(header & 0x03) as usize];
+        let payload = bytes.get(1..1 + size)?.to_vec();
+        let id = header >> 3;
+
+        let event = if header & 0x04 == 0 {
+            Event::Stimulus { port: id, payload }
+        } else {
+            hardware(id, payload)
+        };
+        return Some((1 + size, Packet::Event(event)));
+    }
+
+    match header {
+        // Synchronization, a run of zeros ended by 0x80
+        0x00 | 0x80 => Some((1, Packet::Other)),
+        0x70 => Some((1, Packet::Event(Event::Overflow))),
+        // Local timestamp with the value in the header
+        _ if header & 0x8f == 0 => Some((1, Packet::Timestamp(u64::from(header >> 4)))),
+        // Local timestamp with the value in continuation bytes
+        _ if header & 0xcf == 0xc0 => {
+            let (len, value) = continuation(bytes)?;
+            Some((len, Packet::Timestamp(value)))
+        }
+        // Global timestamps and extensions, which are not used
+        _ if header & 0x80 != 0 => {
+            let (len, _) = continuation(bytes)?;
+            Some((len, Packet::Other))
+        }
+        _ => Some((1, Packet::Other)),
+    }
+}
+
+/// Length of a packet with continuation bytes after the header, and their value
+fn continuation(bytes: &[u8]) -> Option<(usize, u64)> {
+    let mut value = 0;
+    for (i, byte) in bytes.iter().skip(1).enumerate() {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 || i + 1 == MAX_CONTINUATION {
+            return Some((i + 2, value));
+        }
+    }
+
+    None
+}
+
+fn hardware(id: u8, payload: Vec<u8>) -> Event {
+    match (id, payload.as_slice()) {
+        (EXCEPTION_TRACE, &[low, high]) => {
+            let number = u16::from(low) | u16::from(high & 1) << 8;
+            let action = match (high >> 4) & 0x3 {
+                1 => ExceptionAction::Entered,
+                2 => ExceptionAction::Exited,
+                3 => ExceptionAction::Returned,
+                _ => return Event::Hardware { id, payload },
+            };
+            Event::Exception { number, action }
+        }
+        (PC_SAMPLE, &[a, b, c, d]) => Event::PcSample(Some(u32::from_le_bytes([a, b, c, d]))),
+        (PC_SAMPLE, &[_]) => Event::PcSample(None),
+        _ => Event::Hardware { id, payload },
+    }
+}
+
+/// Name of an exception by its number, as in the vector table
+pub fn exception_name(number: u16) -> String {
+    match number {
+        0 => "thread mode".into(),
+        1 => "Reset".into(),
+        2 => "NMI".into(),
+        3 => "HardFault".into(),
+        4 => "MemManage".into(),
+        5 => "BusFault".into(),
+        6 => "UsageFault".into(),
+        11 => "SVCall".into(),
+        12 => "DebugMonitor".into(),
+        14 => "PendSV".into(),
+        15 => "SysTick".into(),
+        16..=511 => format!("IRQ{}", number - 16),
+        _ => format!("exception {}", number),
+    }
+}
+
+/// Turns the SWO byte stream into records, to show them between the messages from the ring
+/// buffer
+///
+/// Text written to a stimulus port is collected into lines, other values are shown one by one.
+pub struct ItmDecoder {
+    parser: PacketParser,
+    clock: Clock,
+    /// Text of the unfinished line on each port, with when it was started
+    lines: HashMap<u8, (Option<u64>, Vec<u8>)>,
+}
+
+impl ItmDecoder {
+    /// `hz` is the frequency of the ITM timestamps, the CPU clock divided by the prescaler
+    pub fn new(hz: Option<u32>) -> Self {
+        ItmDecoder {
+            parser: PacketParser::new(),
+            clock: Clock::new(hz),
+            lines: HashMap::new(),
+        }
+    }
+
+    /// Decode the events completed by `data`
+    pub fn push(&mut self, data: &[u8]) -> Vec<Record> {
+        let mut records = Vec::new();
+
+        for Timed { cycles, event } in self.parser.push(data) {
+            let message = match event {
+                Event::Stimulus { port, payload } if is_text(&payload) => {
+                    let (started, line) = self.lines.entry(port).or_default();
+                    if line.is_empty() {
+                        *started = cycles;
+                    }
+                    line.extend_from_slice(&payload);
+
+                    // A write can end one line and start the next
+                    while let Some(end) = line.iter().position(|&b| b == b'\n') {
+                        let text: Vec<u8> = line.drain(..=end).collect();
+                        let text = String::from_utf8_lossy(&text);
+                        records.push(record(
+                            &self.clock,
+                            *started,
+                            format!("ITM port {}: {}", port, text.trim_end()),
+                        ));
+                        *started = cycles;
+                    }
+                    continue;
+                }
+                Event::Stimulus { port, payload } => {
+                    let mut value = [0; 4];
+                    value[..payload.len()].copy_from_slice(&payload);
+                    format!(
+                        "ITM port {}: {:#0width$x}",
+                        port,
+                        u32::from_le_bytes(value),
+                        width = payload.len() * 2 + 2
+                    )
+                }
+                Event::Exception { number, action } => {
+                    let action = match action {
+                        ExceptionAction::Entered => "entered",
+                        ExceptionAction::Exited => "exited",
+                        ExceptionAction::Returned => "returned to",
+                    };
+                    format!("ITM exception: {} {}", action, exception_name(number))
+                }
+                Event::PcSample(Some(pc)) => format!("ITM PC sample: {:#010x}", pc),
+                Event::PcSample(None) => "ITM PC sample: sleeping".into(),
+                Event::Hardware { id, payload } => {
+                    format!("ITM DWT packet {}: {:02x?}", id, payload)
+                }
+                Event::Overflow => "ITM overflow, events were lost".into(),
+            };
+            records.push(record(&self.clock, cycles, message));
+        }
+
+        records
+    }
+}
+
+/// A record for an ITM event, in the `itm` module
+fn record(clock: &Clock, cycles: Option<u64>, message: String) -> Record {
+    Record {
+        timestamp: cycles.map(|cycles| clock.format(cycles)),
+        seconds: cycles.and_then(|cycles| clock.seconds(cycles)),
+        message,
+        module: Some("itm".into()),
+        ..Record::default()
+    }
+}
+
+fn is_text(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .all(|&b| b.is_ascii_graphic() || b == b' ' || b == b'\t' || b == b'\r' || b == b'\n')
+}
+
+/// Read the SWO stream captured to `path` on a background thread, following the file as it
+/// grows, so it also works for a FIFO or a file another tool is still writing
+pub fn spawn_reader(path: &Path) -> Result<Receiver<Vec<u8>>> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => thread::sleep(POLL),
+                Ok(len) => {
+                    if tx.send(buf[..len].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to read the ITM capture: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(rx)
+}
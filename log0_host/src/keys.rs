@@ -0,0 +1,104 @@
+use std::io::{BufRead, Read};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Keeps the terminal in non-canonical mode without echo while alive, so single key presses can
+/// be read without waiting for Enter. Ctrl-C still stops the host.
+pub struct RawMode {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Returns `None` if stdin is not a terminal
+    #[cfg(unix)]
+    pub fn enable() -> Option<Self> {
+        unsafe {
+            if libc::isatty(libc::STDIN_FILENO) == 0 {
+                return None;
+            }
+
+            let mut original = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return None;
+            }
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+
+            Some(RawMode { original })
+        }
+    }
+
+    /// Key presses are only supported on Unix terminals
+    #[cfg(not(unix))]
+    pub fn enable() -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Is stdin a terminal someone can answer a prompt on
+#[cfg(unix)]
+pub fn interactive() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) != 0 }
+}
+
+/// Prompts are only shown on Unix terminals
+#[cfg(not(unix))]
+pub fn interactive() -> bool {
+    false
+}
+
+/// Read key presses on a background thread, so polling the target is never blocked on stdin
+pub fn spawn() -> Receiver<u8> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let stdin = std::io::stdin();
+        let mut stdin = stdin.lock();
+        let mut key = [0];
+
+        while let Ok(1) = stdin.read(&mut key) {
+            if tx.send(key[0]).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Read lines from stdin on a background thread, without the line ending
+pub fn spawn_lines() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let stdin = std::io::stdin();
+
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
@@ -1,10 +1,16 @@
 pub(crate) const CONTINUE: u8 = 1 << 7;
 
+/// Maximum number of bytes in a LEB128 encoded u32
+pub const MAX_LEN_U32: usize = 5;
+
 /// Try to decode a LEB128 encoded u32, returns `(value, bytes used)` if successful
+///
+/// Fails if the data ends before the last byte, or if the encoding is longer than
+/// `MAX_LEN_U32` bytes.
 pub fn decode_u32<'a, T: Iterator<Item = &'a u8>>(bytes: T) -> Result<(u32, usize), ()> {
     let mut val = 0;
 
-    for (i, byte) in bytes.enumerate() {
+    for (i, byte) in bytes.take(MAX_LEN_U32).enumerate() {
         val |= u32::from(*byte & !CONTINUE) << (7 * i);
 
         if *byte & CONTINUE == 0 {
@@ -14,3 +20,20 @@ pub fn decode_u32<'a, T: Iterator<Item = &'a u8>>(bytes: T) -> Result<(u32, usiz
 
     Err(())
 }
+
+/// Encode a u32 as LEB128 and append it to `v`, the same way the target does
+pub fn encode_u32(v: &mut Vec<u8>, mut word: u32) {
+    loop {
+        let mut byte = (word & 0x7f) as u8;
+        word >>= 7;
+
+        if word != 0 {
+            byte |= CONTINUE;
+        }
+        v.push(byte);
+
+        if word == 0 {
+            return;
+        }
+    }
+}
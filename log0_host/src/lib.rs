@@ -1,11 +1,116 @@
 #[cfg(test)]
+mod sim;
+#[cfg(test)]
 mod tests;
 
+pub mod archive;
+pub mod capture;
+pub mod catalog;
+pub mod channel;
+pub mod command;
+pub mod config;
+pub mod control;
+pub mod crc;
+pub mod decoder;
+pub mod expect;
+pub mod fault;
+pub mod fetch;
 pub mod fmt;
+pub mod format_string;
+pub mod histogram;
+pub mod hook;
+pub mod influx;
+pub mod itm;
+pub mod keys;
 pub mod leb128;
+pub mod live;
+pub mod mqtt;
 pub mod parser;
+pub mod pipeline;
+pub mod probe;
+pub mod profile;
+pub mod reader;
+pub mod reconnect;
+pub mod record;
+pub mod render;
+pub mod replay;
+pub mod rtt;
+pub mod sink;
+pub mod snapshot;
+pub mod sqlite;
+pub mod stats;
+pub mod svd;
+pub mod symbols;
+pub mod template;
+pub mod time;
+pub mod timeline;
+pub mod transport;
+pub mod trigger;
+pub mod tune;
+pub mod until;
+pub mod watchdog;
+pub mod xml;
+
+use std::ops::Range;
 
 pub fn bytes_to_read(host_idx: usize, target_idx: usize, buffer_size: usize) -> usize {
     // (head_idx_ - tail_idx_ + mask_ + 1) & mask_;
     target_idx.wrapping_sub(host_idx).wrapping_add(buffer_size) % buffer_size
 }
+
+/// The parts of the ring buffer that hold unread data, the second range is only non-empty when
+/// the data wraps around the end of the buffer
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReadPlan {
+    pub first: Range<usize>,
+    pub second: Range<usize>,
+    pub new_host_idx: usize,
+}
+
+impl ReadPlan {
+    /// Total number of bytes to read
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read no more than `max` bytes, leaving the rest for the next read
+    pub fn limit(&mut self, max: usize, buffer_size: usize) {
+        if self.len() <= max {
+            return;
+        }
+
+        if max <= self.first.len() {
+            self.first.end = self.first.start + max;
+            self.second = 0..0;
+            self.new_host_idx = self.first.end % buffer_size;
+        } else {
+            self.second.end = max - self.first.len();
+            self.new_host_idx = self.second.end;
+        }
+    }
+}
+
+/// Work out which parts of the ring buffer to read, and where the host cursor ends up after
+pub fn plan_read(host_idx: usize, target_idx: usize, buffer_size: usize) -> ReadPlan {
+    let br = bytes_to_read(host_idx, target_idx, buffer_size);
+
+    if host_idx + br > buffer_size {
+        // cursor will overflow
+        let pivot = buffer_size - host_idx;
+        ReadPlan {
+            first: host_idx..buffer_size,
+            second: 0..br - pivot,
+            new_host_idx: br - pivot,
+        }
+    } else {
+        ReadPlan {
+            first: host_idx..host_idx + br,
+            second: 0..0,
+            new_host_idx: (host_idx + br) % buffer_size,
+        }
+    }
+}
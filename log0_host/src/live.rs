@@ -0,0 +1,191 @@
+use crate::{
+    record::{Level, Record},
+    sink::Sink,
+};
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::io::Write;
+
+/// What a key press does in the live mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Stop showing messages, they are still read and shown on resume
+    TogglePause,
+    /// Prompt for a text that messages have to contain to be shown
+    Filter,
+    Clear,
+    /// Print the session summary so far
+    Summary,
+    /// Print the stack and heap usage the target last reported, see `Stats::write_resources`
+    Resources,
+    /// Print where the target is right now, see `Pipeline::backtrace`
+    Backtrace,
+    Quit,
+}
+
+impl Action {
+    pub fn from_key(key: u8) -> Option<Self> {
+        match key {
+            b' ' => Some(Action::TogglePause),
+            b'f' => Some(Action::Filter),
+            b'c' => Some(Action::Clear),
+            b's' => Some(Action::Summary),
+            b'r' => Some(Action::Resources),
+            b'b' => Some(Action::Backtrace),
+            b'q' => Some(Action::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Display state of the live mode
+///
+/// Decoding keeps going while the display is paused or a filter is being typed, the records are
+/// held back and shown once the display is resumed.
+#[derive(Debug, Default)]
+pub struct Live {
+    paused: bool,
+    held: VecDeque<Record>,
+    filter: Option<String>,
+    prompt: Option<String>,
+    /// Lowest level shown, messages without a level count as `info`
+    level: Option<Level>,
+}
+
+impl Live {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only show messages of `level` and above
+    pub fn with_level(mut self, level: Option<Level>) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn filter(&self) -> Option<&str> {
+        self.filter.as_deref()
+    }
+
+    /// Pause or resume the display as space does, for the control socket of the daemon
+    pub fn set_paused(&mut self, paused: bool, sink: &mut dyn Sink) -> Result<()> {
+        self.paused = paused;
+        self.release(sink)
+    }
+
+    /// Set the filter as `f` does, an empty one shows all messages
+    pub fn set_filter(&mut self, filter: Option<String>, sink: &mut dyn Sink) -> Result<()> {
+        self.filter = filter.filter(|f| !f.is_empty());
+        self.release(sink)
+    }
+
+    /// Show a record, or hold it back while the display is paused
+    pub fn show(&mut self, record: Record, sink: &mut dyn Sink) -> Result<()> {
+        if self.holding() {
+            self.held.push_back(record);
+        } else if self.matches(&record) {
+            sink.write(&record)?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle a key press, `out` is where the prompt and status lines go
+    ///
+    /// Pausing, filtering and clearing are handled here, the action is returned so the caller can
+    /// handle the rest. Keys typed at the filter prompt return `None`.
+    pub fn key(
+        &mut self,
+        key: u8,
+        sink: &mut dyn Sink,
+        out: &mut impl Write,
+    ) -> Result<Option<Action>> {
+        if let Some(prompt) = &mut self.prompt {
+            match key {
+                b'\n' | b'\r' => {
+                    self.filter = Some(std::mem::take(prompt)).filter(|f| !f.is_empty());
+                    self.prompt = None;
+                    writeln!(out)?;
+                    self.release(sink)?;
+                }
+                // Escape
+                0x1b => {
+                    self.prompt = None;
+                    writeln!(out, " (cancelled)")?;
+                    self.release(sink)?;
+                }
+                // Backspace or delete
+                0x08 | 0x7f if prompt.pop().is_some() => write!(out, "\x08 \x08")?,
+                c if c.is_ascii_graphic() || c == b' ' => {
+                    prompt.push(c as char);
+                    write!(out, "{}", c as char)?;
+                }
+                _ => (),
+            }
+            out.flush()?;
+
+            return Ok(None);
+        }
+
+        let action = Action::from_key(key);
+        match action {
+            Some(Action::TogglePause) => {
+                self.paused = !self.paused;
+                if self.paused {
+                    writeln!(out, "-- paused, press space to resume --")?;
+                } else {
+                    self.release(sink)?;
+                }
+            }
+            Some(Action::Filter) => {
+                self.prompt = Some(String::new());
+                write!(out, "filter (empty to clear): ")?;
+            }
+            Some(Action::Clear) => write!(out, "\x1b[2J\x1b[H")?,
+            Some(Action::Summary)
+            | Some(Action::Resources)
+            | Some(Action::Backtrace)
+            | Some(Action::Quit)
+            | None => (),
+        }
+        out.flush()?;
+
+        Ok(action)
+    }
+
+    fn holding(&self) -> bool {
+        self.paused || self.prompt.is_some()
+    }
+
+    fn matches(&self, record: &Record) -> bool {
+        if let Some(level) = self.level {
+            if record.level.unwrap_or(Level::Info) < level {
+                return false;
+            }
+        }
+
+        match &self.filter {
+            Some(filter) => record.message.contains(filter.as_str()),
+            None => true,
+        }
+    }
+
+    /// Show the records held back, if the display is no longer paused
+    fn release(&mut self, sink: &mut dyn Sink) -> Result<()> {
+        if self.holding() {
+            return Ok(());
+        }
+
+        while let Some(record) = self.held.pop_front() {
+            if self.matches(&record) {
+                sink.write(&record)?;
+            }
+        }
+
+        sink.flush()
+    }
+}
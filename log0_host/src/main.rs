@@ -1,56 +1,878 @@
-use anyhow::Result;
-use elf_test::generate_printers;
+use anyhow::{Context, Result};
+use elf_test::{
+    backtrace, enumerations, generate_printers, log_sites, CoreRegisters, Frame, Function,
+    Symbolizer, TypePrinters,
+};
 use gimli as _;
-use log0_host::{bytes_to_read, fmt, parser::Parser};
+use log0_host::{
+    archive::{Archive, Split},
+    capture::Capture,
+    catalog::Catalog,
+    channel::{self, ChannelArg},
+    config::Config,
+    control::{self, Method as ControlMethod},
+    decoder::Decoder,
+    expect::{Runner, Script},
+    fetch::Fetcher,
+    fmt,
+    histogram::Histograms,
+    hook::Hook,
+    influx::Influx,
+    itm::{self, ItmDecoder, Stimulus, Written},
+    keys::{self, RawMode},
+    live::Live,
+    mqtt::Mqtt,
+    parser::{check_wire_version, Parser},
+    pipeline::{self, Chunk, Itm, Pipeline, Secure},
+    probe,
+    profile::{Profile, Sampler},
+    reader::{Poll, Reader},
+    reconnect::{Backoff, Policy},
+    record::{Level, World},
+    replay::{Recorder, Replay},
+    rtt,
+    sink::{Collapse, Fanout, Json, Output, Sink, Terminal},
+    snapshot::Snapshot,
+    sqlite::Sqlite,
+    stats::Stats,
+    svd::Device,
+    template::Template,
+    time::Clock,
+    timeline::Timeline,
+    transport::{ProbeTransport, Security, Transport},
+    trigger::{Action as TriggerAction, Triggers},
+    tune,
+    until::{Outcome, Until},
+    watchdog::{StallAction, Watchdog},
+};
+use memmap2::Mmap;
 use probe_rs::{
+    architecture::arm::{ArmChipInfo, ArmCommunicationInterface, ArmCommunicationInterfaceState},
+    config::{registry, MemoryRegion},
     flashing::{download_file_with_options, DownloadOptions, FlashProgress, Format},
-    MemoryInterface, Probe, WireProtocol,
+    Core, DebugProbeInfo, DebugProbeSelector, MemoryInterface, Probe, Session, WireProtocol,
 };
+use regex::Regex;
+use std::collections::VecDeque;
 use std::fs;
+use std::io::{Read, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::{
+    mpsc::{self, SyncSender},
+    Arc,
+};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use structopt::StructOpt;
 use xmas_elf::ElfFile;
 
 #[derive(StructOpt)]
 struct Opts {
+    /// ELF to flash and run, required unless a subcommand is given
     #[structopt(name = "FILE", parse(from_os_str))]
-    elf: PathBuf,
+    elf: Option<PathBuf>,
+
+    /// ELF of the secure image on a TrustZone target, whose ring is drained next to the one of
+    /// FILE, with the messages tagged by their security world
+    #[structopt(long, parse(from_os_str))]
+    secure_elf: Option<PathBuf>,
+
+    /// Config file with field units and scale factors, defaults to `fasthosting.toml` if it
+    /// exists
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Print the raw payload bytes next to the decoded value
+    #[structopt(long)]
+    show_raw: bool,
+
+    /// Tick frequency of the target timestamps, overrides the one in the ELF
+    #[structopt(long)]
+    timestamp_hz: Option<u32>,
+
+    /// CPU clock, for timestamps counting CPU cycles
+    #[structopt(long)]
+    cpu_hz: Option<u32>,
+
+    /// Show timestamps as wall-clock time (UTC), based on when the frames arrive
+    #[structopt(long)]
+    wall_clock: bool,
+
+    /// Layout of the lines, e.g. `[{time}] {task:>8} {module}: {message}`, with the fields
+    /// time, level, task, world, core, module, id, type and message, overrides `format` in the
+    /// config
+    #[structopt(long)]
+    format: Option<Template>,
+
+    /// Do not color the task names
+    #[structopt(long)]
+    no_color: bool,
+
+    /// Only show messages of this level and above, `trace`, `debug`, `info`, `warn` or
+    /// `error`, messages of `log!` count as `info`
+    #[structopt(long)]
+    level: Option<Level>,
+
+    /// Print diagnostics of the tool itself to stderr, `-v` for progress and `-vv` for
+    /// debugging, `RUST_LOG` takes precedence
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Same as `--output json`
+    #[structopt(long)]
+    json: bool,
+
+    /// How to print the messages, `text` or `json` for one JSON object per frame with its
+    /// timestamp, level, format string, decoded values and raw bytes
+    #[structopt(long, default_value = "text")]
+    output: Output,
+
+    /// Number of times to reconnect after a probe or USB error, 0 exits on the first error,
+    /// shorthand for `--on-probe-error retry:<n>`
+    #[structopt(long, default_value = "0")]
+    reconnect: u32,
+
+    /// What to do when reading from the target or the connection fails: `abort`, `warn` (and
+    /// reconnect for as long as it takes) or `retry:<n>` to reconnect up to n times in a row
+    #[structopt(long)]
+    on_probe_error: Option<Policy>,
+
+    /// What to do when flashing fails: `abort`, `warn` (and run what is already on the
+    /// target) or `retry:<n>`
+    #[structopt(long, default_value = "abort")]
+    on_flash_error: Policy,
+
+    /// What to do when unread data is lost and the frames have to be resynchronized: `abort`,
+    /// `warn` or `retry:<n>` to resynchronize up to n times in total
+    #[structopt(long, default_value = "warn")]
+    on_parse_error: Policy,
+
+    /// Delay before reconnecting or flashing again, doubled after each failed attempt
+    #[structopt(long, default_value = "500ms", parse(try_from_str = humantime::parse_duration))]
+    reconnect_delay: Duration,
+
+    /// Do not flash and reset the target again when reconnecting
+    #[structopt(long)]
+    no_reflash: bool,
+
+    /// Attach to the firmware running on the target without flashing or resetting it, e.g. to
+    /// look at a device already in a failed state, it is left running on exit
+    #[structopt(long, alias = "attach")]
+    no_flash: bool,
+
+    /// Which alias of memory to access on TrustZone targets: `auto`, `flat` (the addresses in
+    /// the ELF as they are), `secure` or `non-secure`
+    #[structopt(long, default_value = "auto")]
+    security: Security,
+
+    /// Most bytes read from the ring buffer before the host cursor is written, measured for the
+    /// probe at startup if not given
+    #[structopt(long)]
+    read_chunk: Option<usize>,
+
+    /// Warn when no frames arrive for this long, e.g. `5s`
+    #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+    stall_timeout: Option<Duration>,
+
+    /// What to do when the target stalls: `warn`, `halt` (and print registers), `reset` or
+    /// `exec:<command>` to run a shell command
+    #[structopt(long, default_value = "warn")]
+    on_stall: StallAction,
+
+    /// Stop when a message matches this pattern, and exit with status 0
+    #[structopt(long)]
+    until_regex: Option<Regex>,
+
+    /// Stop when a message matches this pattern, and exit with status 1
+    #[structopt(long)]
+    fail_regex: Option<Regex>,
+
+    /// Stop after this long, e.g. `60s`, and exit with status 124
+    #[structopt(long, parse(try_from_str = humantime::parse_duration))]
+    timeout: Option<Duration>,
+
+    /// Send a command to the target after starting it, can be given more than once
+    #[structopt(long = "cmd", number_of_values = 1)]
+    commands: Vec<String>,
+
+    /// Send lines from stdin to the target as commands, instead of reading keys
+    #[structopt(long)]
+    stdin: bool,
+
+    /// Pass every message through `fn hook(record)` of this Rhai script, it returns the message
+    /// to show, possibly changed, or `()` to drop it
+    #[structopt(long, parse(from_os_str))]
+    hook: Option<PathBuf>,
+
+    /// Also store every message in this SQLite file
+    #[structopt(long, parse(from_os_str))]
+    sqlite: Option<PathBuf>,
+
+    /// Also write every message as JSON lines to a new file in this directory every day or
+    /// hour, with an `index.jsonl` of the time range in each file
+    #[structopt(long, parse(from_os_str))]
+    archive_dir: Option<PathBuf>,
+
+    /// When the archive starts a new file: `hourly` or `daily`
+    #[structopt(long, default_value = "daily")]
+    archive_split: Split,
+
+    /// Compress the archive files with gzip once they are closed
+    #[structopt(long)]
+    archive_compress: bool,
+
+    /// SWO capture to show the ITM events from, between the messages by target time
+    ///
+    /// A file or FIFO written by another tool, e.g. a UART on the SWO pin, as the probe is not
+    /// read for SWO. The ITM timestamps are taken to count from when the target boots.
+    #[structopt(long, parse(from_os_str))]
+    itm: Option<PathBuf>,
+
+    /// Frequency of the ITM timestamps, the CPU clock divided by the ITM prescaler, defaults to
+    /// the CPU clock
+    #[structopt(long)]
+    itm_hz: Option<u32>,
+
+    /// Draw a histogram of this numeric field, e.g. `latency_us` or `Timing.latency_us`, on
+    /// stderr, can be given more than once, see `[histograms]` in the config for the buckets
+    #[structopt(long = "histogram", number_of_values = 1)]
+    histograms: Vec<String>,
+
+    /// How often to draw the histograms, they are drawn once more at the end
+    #[structopt(long, default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+    histogram_interval: Duration,
+
+    /// Keep the messages of the last `--capture-before` in memory, and when one matches this
+    /// pattern, write them and those of the next `--capture-after` to a file
+    #[structopt(long)]
+    capture_on: Option<Regex>,
+
+    /// How far back a capture starts before the first match
+    #[structopt(long, default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+    capture_before: Duration,
+
+    /// How long a capture goes on after the last match
+    #[structopt(long, default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+    capture_after: Duration,
+
+    /// Where to write the `capture-<n>.log` files
+    #[structopt(long, default_value = ".", parse(from_os_str))]
+    capture_dir: PathBuf,
+
+    /// Write the raw bytes read from the target, with when they were read and from which ring,
+    /// to this file, to decode them again with `replay`, `--capture` as well
+    #[structopt(long, alias = "capture", parse(from_os_str))]
+    record: Option<PathBuf>,
+
+    /// Collapse bursts of identical messages into one record with a repeat count
+    #[structopt(long)]
+    collapse: bool,
+
+    /// Decode the frames of a raw channel of `write_raw`, `<n>=hex` or `<n>=exec:<command>` to
+    /// pass each one through a command as a line of hex that it answers with the line to show,
+    /// can be given more than once. Channels without a decoder are shown as hex.
+    #[structopt(long = "channel", number_of_values = 1)]
+    channels: Vec<ChannelArg>,
+
+    #[structopt(flatten)]
+    probe: ProbeOpts,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() -> Result<()> {
-    let opts = Opts::from_args();
-    // println!("opts: {:#?}", opts.elf);
+/// How to connect to the target
+#[derive(Clone, StructOpt)]
+struct ProbeOpts {
+    /// Chip to attach to, by its probe-rs target name, e.g. `nrf52840` or `STM32L412CBUx`, or
+    /// `auto` to have probe-rs detect it, required for everything that connects to the target
+    #[structopt(long)]
+    chip: Option<String>,
 
-    // Get address of cursors
-    let bytes = fs::read(&opts.elf)?;
+    /// Probe to open when more than one is connected, by `VID:PID` in hex, optionally followed
+    /// by `:<serial>`
+    #[structopt(long)]
+    probe: Option<DebugProbeSelector>,
+
+    /// Probe to open by its index among the connected ones, or the ones matching `--probe`
+    #[structopt(long)]
+    probe_index: Option<usize>,
+
+    /// Wire protocol to the target, `swd` or `jtag`
+    #[structopt(long, default_value = "swd")]
+    protocol: WireProtocol,
+
+    /// Clock of the probe in kHz, lower ones are tried in turn when the probe rejects it
+    #[structopt(long, default_value = "24000")]
+    speed: u32,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Print the format strings in the ELF with their IDs, argument types and source locations
+    Catalog {
+        /// ELF to read the format strings and debug info from
+        #[structopt(long, parse(from_os_str))]
+        elf: PathBuf,
+
+        /// Print the catalog as JSON
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Report the messages in the ELF that cannot be decoded, without a target, and fail if
+    /// there are any
+    Check {
+        /// ELF to read the format strings and debug info from
+        #[structopt(long, parse(from_os_str))]
+        elf: PathBuf,
+    },
+    /// Flash and run the ELF, and check the messages against the expectations in a script
+    Test {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        elf: PathBuf,
+
+        /// TOML file with the expected messages
+        #[structopt(long, parse(from_os_str))]
+        script: PathBuf,
+
+        /// Write the result as JUnit XML to this file
+        #[structopt(long, parse(from_os_str))]
+        junit: Option<PathBuf>,
+    },
+    /// Decode raw frames from a file or stdin instead of the target, e.g. from a custom
+    /// transport, with the same options and output as when running it
+    Decode {
+        /// ELF the frames were written by
+        #[structopt(long, parse(from_os_str))]
+        elf: PathBuf,
+
+        /// File with the frames, `-` for stdin
+        #[structopt(name = "INPUT", parse(from_os_str))]
+        input: PathBuf,
+
+        /// The input is an SWO capture of a target built with the `itm` feature, the frames are
+        /// taken from the writes to its stimulus port
+        #[structopt(long)]
+        swo: bool,
+    },
+    /// Decode a recording of `--record` again, with the same options and output as when running
+    /// it and the host times of the original session
+    Replay {
+        /// ELF the target was running
+        #[structopt(long, parse(from_os_str))]
+        elf: PathBuf,
+
+        /// File written by `--record`
+        #[structopt(name = "RECORDING", parse(from_os_str))]
+        recording: PathBuf,
+    },
+    /// Flash and run the ELF like without a subcommand, and keep the probe session alive for
+    /// tools that control it through a Unix socket, with one JSON-RPC 2.0 request per line
+    ///
+    /// The methods are `start` and `stop` to show messages or hold them back, `filter` with a
+    /// `text` param, `stats`, `dump` with a `path` param to save a snapshot, and `quit`.
+    Daemon {
+        /// ELF to flash and decode the messages of
+        #[structopt(name = "FILE", parse(from_os_str))]
+        elf: PathBuf,
+
+        /// Path of the control socket
+        #[structopt(long, default_value = "fasthosting.sock", parse(from_os_str))]
+        socket: PathBuf,
+    },
+    /// Print where the running target is right now, without flashing it
+    Backtrace {
+        /// ELF the target is running
+        #[structopt(name = "FILE", parse(from_os_str))]
+        elf: PathBuf,
+    },
+    /// Sample the PC of the running target and print where it spends its time
+    Profile {
+        /// ELF the target is running
+        #[structopt(name = "FILE", parse(from_os_str))]
+        elf: PathBuf,
+
+        /// How long to sample for
+        #[structopt(long, default_value = "10s", parse(try_from_str = humantime::parse_duration))]
+        duration: Duration,
+
+        /// Number of functions to show
+        #[structopt(long, default_value = "20")]
+        top: usize,
+
+        /// Break the functions down by source line
+        #[structopt(long)]
+        lines: bool,
+
+        /// Write the samples as folded stacks to this file, for flamegraph tools
+        #[structopt(long, parse(from_os_str))]
+        folded: Option<PathBuf>,
+    },
+    /// Read registers of the running target and decode them with the SVD file of the chip
+    Inspect {
+        /// CMSIS-SVD file of the chip
+        #[structopt(long, parse(from_os_str))]
+        svd: PathBuf,
+
+        #[structopt(subcommand)]
+        what: Inspect,
+    },
+    /// Halt the core and save its registers and RAM to a snapshot, for postmortem debugging
+    Dump {
+        /// File to save the snapshot to
+        #[structopt(long, default_value = "snapshot.bin", parse(from_os_str))]
+        out: PathBuf,
+
+        /// ELF the target is running, recorded in the snapshot for `dump decode`
+        #[structopt(long, parse(from_os_str))]
+        elf: Option<PathBuf>,
+
+        #[structopt(subcommand)]
+        decode: Option<Dump>,
+    },
+}
+
+#[derive(StructOpt)]
+enum Inspect {
+    /// Print the registers of a peripheral with the value of each field
+    Periph {
+        /// Name of the peripheral in the SVD, e.g. `UARTE0`
+        name: String,
+
+        /// Only print the registers whose name contains this
+        #[structopt(long)]
+        register: Option<String>,
+    },
+}
+
+#[derive(StructOpt)]
+enum Dump {
+    /// Print the registers and the value of every static in a snapshot
+    Decode {
+        #[structopt(name = "SNAPSHOT", default_value = "snapshot.bin", parse(from_os_str))]
+        snapshot: PathBuf,
+
+        /// ELF to read the statics from, defaults to the one recorded in the snapshot
+        #[structopt(long, parse(from_os_str))]
+        elf: Option<PathBuf>,
+    },
+}
+
+/// Map the ELF instead of reading it, with debug info it is often tens of MB
+fn map_elf(path: &Path) -> Result<Mmap> {
+    let file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    // Safety: the ELF must not change while it is mapped, which holds for a build output that
+    // is not being rebuilt
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+fn catalog(path: &Path, json: bool) -> Result<()> {
+    let bytes = map_elf(path)?;
     let elf = &ElfFile::new(&bytes).map_err(anyhow::Error::msg)?;
 
-    // -------------------------------------------------------------------
-    //
-    // Setup debug probe
-    //
-    // -------------------------------------------------------------------
+    let fmt::Res { map_strings, .. } = fmt::extract_format_and_type_strings(elf)?;
+    let catalog = Catalog::new(&map_strings).with_sites(&log_sites(&bytes)?);
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    if json {
+        catalog.write_json(&mut out)
+    } else {
+        catalog.write_text(&mut out)
+    }
+}
+
+/// Print each message that cannot be decoded with the reason, as `file:line: reason` lines
+fn check(path: &Path) -> Result<()> {
+    let bytes = map_elf(path)?;
+    let elf = &ElfFile::new(&bytes).map_err(anyhow::Error::msg)?;
+
+    let fmt::Res { map_strings, .. } = fmt::extract_format_and_type_strings(elf)?;
+    let catalog = Catalog::new(&map_strings).with_sites(&log_sites(&bytes)?);
+    let printers = generate_printers(&bytes)?;
+
+    let problems = catalog.coverage(&printers);
+    for problem in &problems {
+        println!(
+            "{}: {:?} (ID {}): {}",
+            problem.message.location(),
+            problem.message.text,
+            problem.message.id,
+            problem.reason
+        );
+    }
+
+    let mut undecodable: Vec<_> = problems.iter().map(|p| p.message.id).collect();
+    undecodable.dedup();
+    if !undecodable.is_empty() {
+        anyhow::bail!(
+            "{} of {} messages cannot be decoded",
+            undecodable.len(),
+            catalog.messages().len()
+        );
+    }
+    println!("All {} messages can be decoded", catalog.messages().len());
+
+    Ok(())
+}
+
+/// Halt the target and save its registers and RAM to `out`
+fn dump(probe: &mut ProbeOpts, out: &Path, elf: Option<&Path>) -> Result<()> {
+    let mut session = connect(probe)?;
+    // `connect` failed without one
+    let chip = probe.chip.clone().unwrap_or_default();
+    let ram = ram(&session);
+    let snapshot = save_snapshot(&mut session.core(0)?, &chip, &ram, elf, out)?;
+
+    let stdout = std::io::stdout();
+    snapshot.write_summary(&mut stdout.lock())?;
+    println!("Target halted, snapshot saved to {}", out.display());
+
+    Ok(())
+}
+
+/// The RAM regions of the target
+fn ram(session: &Session) -> Vec<Range<u32>> {
+    session
+        .memory_map()
+        .iter()
+        .filter_map(|region| match region {
+            MemoryRegion::Ram(ram) => Some(ram.range.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Halt the core and save its registers and `ram` to `out`, the core is left halted
+fn save_snapshot(
+    core: &mut Core,
+    chip: &str,
+    ram: &[Range<u32>],
+    elf: Option<&Path>,
+    out: &Path,
+) -> Result<Snapshot> {
+    let elf = elf.map(|elf| fs::canonicalize(elf).unwrap_or_else(|_| elf.into()));
+    let snapshot = Snapshot::capture(core, chip, ram, elf.map(|elf| elf.display().to_string()))?;
+
+    let mut file = std::io::BufWriter::new(
+        fs::File::create(out).with_context(|| format!("Failed to create {}", out.display()))?,
+    );
+    snapshot.write_to(&mut file)?;
+    file.into_inner().map_err(|e| e.into_error())?;
+
+    Ok(snapshot)
+}
+
+/// Print the registers and statics in the snapshot at `path`
+fn decode_dump(path: &Path, elf: Option<&Path>) -> Result<()> {
+    let mut file = std::io::BufReader::new(
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+    );
+    let snapshot = Snapshot::read_from(&mut file)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let elf = match (elf, &snapshot.elf) {
+        (Some(elf), _) => elf.to_path_buf(),
+        (None, Some(elf)) => PathBuf::from(elf),
+        (None, None) => anyhow::bail!("The snapshot does not name the ELF, pass it with --elf"),
+    };
+    let bytes = map_elf(&elf)?;
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    snapshot.write_summary(&mut out)?;
+    writeln!(out, "Statics:")?;
+    snapshot.memory.write_statics(&bytes, &mut out)
+}
+
+/// Halt the core for as long as it takes to unwind its stack, print the backtrace on stderr, and
+/// resume the core if it was running
+fn print_backtrace(core: &mut Core, elf: &[u8]) -> Result<()> {
+    let halted = core.core_halted()?;
+    core.halt(Duration::from_millis(10))?;
+    let frames = unwind(core, elf);
+    if !halted {
+        core.run()?;
+    }
+
+    eprintln!("Backtrace:");
+    for (i, frame) in frames?.iter().enumerate() {
+        if frame.exception {
+            eprintln!("      <exception entry>");
+        }
+        eprintln!(
+            "  {:>2}: {:#010x} {}{}",
+            i,
+            frame.pc,
+            frame.function.as_deref().unwrap_or("<unknown>"),
+            if frame.inlined { " (inlined)" } else { "" }
+        );
+        if let Frame {
+            file: Some(file),
+            line: Some(line),
+            ..
+        } = frame
+        {
+            eprintln!("          at {}:{}", file, line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Unwind the stack of the halted core
+fn unwind(core: &mut Core, elf: &[u8]) -> Result<Vec<Frame>> {
+    let mut registers: CoreRegisters = [0; 16];
+    for (number, register) in registers.iter_mut().enumerate() {
+        *register = core.read_core_reg(number as u16)?;
+    }
+
+    backtrace(elf, registers, |address| core.read_word_32(address).ok())
+}
+
+/// Sample the PC for `duration`, or until Ctrl-C, and print the functions it was in most
+fn profile(
+    probe: &mut ProbeOpts,
+    elf: &Path,
+    duration: Duration,
+    top: usize,
+    lines: bool,
+    folded: Option<&Path>,
+) -> Result<()> {
+    let bytes = map_elf(elf)?;
+    let symbolizer = Symbolizer::new(&bytes)?;
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))?;
+
+    let mut session = connect(probe)?;
+    let mut core = session.core(0)?;
+    let mut sampler = Sampler::new(&mut core)?;
+    if !sampler.uses_pcsr() {
+        log::warn!("No DWT PC sampling on this core, halting it for each sample instead");
+    }
+
+    let mut profile = Profile::new();
+    let started = Instant::now();
+    while started.elapsed() < duration && running.load(Ordering::SeqCst) {
+        profile.add(sampler.sample()?);
+    }
+    let elapsed = started.elapsed();
+
+    // Where `pc` is attributed to, the innermost inlined function first
+    let location = |function: &Function| match (&function.file, function.line) {
+        (Some(file), Some(line)) if lines => Some(format!("{}:{}", file, line)),
+        _ => None,
+    };
+    let name = |function: &Function| function.name.clone().unwrap_or_else(|| "<unknown>".into());
+
+    // `outer;inlined;...` optionally followed by `;file:line`, the frames of the folded stacks
+    let folded_key = |pc: u32| {
+        let functions = symbolizer.functions(pc as u64);
+        let innermost = functions.first()?;
+        let mut frames: Vec<_> = functions.iter().rev().map(name).collect();
+        frames.extend(location(innermost));
+        Some(frames.join(";"))
+    };
+    // `inlined (inlined into outer) at file:line`
+    let flat_key = |pc: u32| {
+        let functions = symbolizer.functions(pc as u64);
+        let (innermost, outermost) = (functions.first()?, functions.last()?);
+        let mut key = name(innermost);
+        if innermost.inlined {
+            key = format!("{} (inlined into {})", key, name(outermost));
+        }
+        if let Some(location) = location(innermost) {
+            key = format!("{} at {}", key, location);
+        }
+        Some(key)
+    };
+    let groups = profile.group(folded_key);
+
+    println!(
+        "{} samples in {} ({:.0}/s)",
+        profile.total(),
+        humantime::format_duration(Duration::from_millis(elapsed.as_millis() as u64)),
+        profile.total() as f64 / elapsed.as_secs_f64()
+    );
+    let flat = profile.group(flat_key);
+    let stdout = std::io::stdout();
+    profile.write_flat(&mut stdout.lock(), &flat, top)?;
+
+    if let Some(path) = folded {
+        let mut file = fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Profile::write_folded(&mut file, &groups)?;
+    }
+
+    Ok(())
+}
+
+/// Read the registers of the peripheral called `name` and print them decoded with the SVD
+fn inspect_peripheral(
+    probe: &mut ProbeOpts,
+    svd: &Path,
+    name: &str,
+    register: Option<&str>,
+) -> Result<()> {
+    let device = Device::load(svd)?;
+    let peripheral = device.peripheral(name).ok_or_else(|| {
+        let names: Vec<_> = device.peripherals.iter().map(|p| p.name.as_str()).collect();
+        anyhow::anyhow!(
+            "No peripheral {} in the SVD, it has {}",
+            name,
+            names.join(", ")
+        )
+    })?;
+
+    let mut session = connect(probe)?;
+    let mut core = session.core(0)?;
+
+    let stdout = std::io::stdout();
+    peripheral.write_registers(&mut core, register, &mut stdout.lock())
+}
+
+/// Open the selected probe and attach to the target
+///
+/// A probe picked at the prompt is kept in `opts`, so reconnecting opens the same one.
+fn connect(opts: &mut ProbeOpts) -> Result<Session> {
+    let chip = opts
+        .chip
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("No chip given, name it with e.g. `--chip nrf52840`"))?;
+    probe::check_chip(&chip, &probe::chip_names()?)?;
 
     // Get a list of all available debug probes.
     let probes = Probe::list_all();
-    println!("Probes: {:#?}", probes);
+    log::debug!("Probes: {:#?}", probes);
 
-    // Use the first probe found.
-    let mut probe = probes[0].open()?;
-    probe.select_protocol(WireProtocol::Swd)?;
-    let speed_khz = probe.set_speed(24_000)?;
-    println!("Probe speed: {} kHz", speed_khz);
+    let info = match probe::select(&probes, opts.probe.as_ref(), opts.probe_index)? {
+        probe::Selection::One(info) => info,
+        probe::Selection::Ambiguous(matching) => {
+            let info = pick_probe(&matching)?;
+            opts.probe = Some(info.into());
+            opts.probe_index = None;
+            info
+        }
+    };
+    log::info!("Probe: {}", probe::describe(info));
+    let mut probe = info.open()?;
+    probe
+        .select_protocol(opts.protocol)
+        .with_context(|| format!("The probe does not support {}", opts.protocol))?;
+    let speed_khz = set_speed(&mut probe, opts.speed)?;
+    eprintln!("Probe: {} at {} kHz", opts.protocol, speed_khz);
 
     // Attach to a chip.
-    let mut session = probe.attach("nrf52840")?;
+    if chip.eq_ignore_ascii_case(probe::AUTO) {
+        let target =
+            detect(&mut probe).context("Failed to detect the chip, name it with --chip")?;
+        let name = target.identifier.chip_name.clone();
+        let mut session = probe.attach(target)?;
+        match read_unique_id(&mut session, &name) {
+            Some(id) => eprintln!("Detected chip: {} (ID {})", name, id),
+            None => eprintln!("Detected chip: {}", name),
+        }
+        // Reconnecting attaches to the same chip without detecting it again
+        opts.chip = Some(name);
+        return Ok(session);
+    }
+
+    let mut session = probe.attach(chip.as_str())?;
+    if let Some(id) = read_unique_id(&mut session, &chip) {
+        log::info!("Chip ID: {}", id);
+    }
+
+    Ok(session)
+}
+
+/// The target of the chip behind `probe`, from its ROM table as `TargetSelector::Auto` reads it
+///
+/// A session does not tell which target it picked, so the chip is detected here first.
+fn detect(probe: &mut Probe) -> Result<probe_rs::Target> {
+    probe.attach_to_unspecified()?;
+    let mut state = ArmCommunicationInterfaceState::new();
+    let info = ArmCommunicationInterface::new(probe, &mut state)?.and_then(|mut interface| {
+        ArmChipInfo::read_from_rom_table(&mut interface)
+            .ok()
+            .flatten()
+    });
+    probe.detach()?;
+
+    let info = info.ok_or_else(|| anyhow::anyhow!("No ARM chip found in the ROM table"))?;
+    Ok(registry::get_target_by_chip_info(info.into())?)
+}
+
+/// The unique ID of the chip as hex, for the families that have one at a known place
+fn read_unique_id(session: &mut Session, chip: &str) -> Option<String> {
+    let (address, len) = probe::unique_id(chip)?;
+    let mut id = vec![0; len];
+    session.core(0).ok()?.read_8(address, &mut id).ok()?;
+
+    Some(channel::hex(&id).replace(' ', ""))
+}
 
-    print!("Spinning up the binary ...");
+/// Set the clock of the probe to `speed` kHz, or the fastest fallback it takes, and return the
+/// one it runs at
+fn set_speed(probe: &mut Probe, speed: u32) -> Result<u32> {
+    let mut error = None;
+    for khz in probe::speeds(speed) {
+        match probe.set_speed(khz) {
+            Ok(actual) => {
+                if khz != speed {
+                    log::warn!(
+                        "The probe rejected {} kHz: {}",
+                        speed,
+                        error.as_ref().unwrap()
+                    );
+                }
+                return Ok(actual);
+            }
+            Err(e) => error = error.or(Some(e)),
+        }
+    }
+
+    Err(error.unwrap()).with_context(|| format!("The probe rejected {} kHz and slower", speed))
+}
+
+/// Ask which of `probes` to open, when the user can answer
+fn pick_probe<'a>(probes: &[&'a DebugProbeInfo]) -> Result<&'a DebugProbeInfo> {
+    let list = probe::list(probes.iter().copied());
+    if !keys::interactive() {
+        return Err(anyhow::anyhow!(
+            "Found {} probes, pick one with --probe <VID:PID[:serial]> or --probe-index\n{}",
+            probes.len(),
+            list
+        ));
+    }
+
+    eprintln!("Found {} probes\n{}", probes.len(), list);
+    loop {
+        eprint!("Probe to open [0-{}]: ", probes.len() - 1);
+        std::io::stderr().flush()?;
+
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Err(anyhow::anyhow!("No probe picked"));
+        }
+        let picked = line.trim().parse::<usize>().ok();
+        match picked.and_then(|i| probes.get(i)) {
+            Some(probe) => return Ok(*probe),
+            None => eprintln!("Not one of the probes: {:?}", line.trim()),
+        }
+    }
+}
+
+/// Write `elf` to the flash and halt the core at reset
+fn download(session: &mut Session, elf: &Path) -> Result<()> {
     download_file_with_options(
-        &mut session,
-        Path::new(&opts.elf),
+        session,
+        elf,
         Format::Elf,
         DownloadOptions {
             progress: Some(&FlashProgress::new(|_event| {
@@ -60,17 +882,436 @@ fn main() -> Result<()> {
         },
     )?;
     let mut core = session.core(0)?;
-    core.reset_and_halt(std::time::Duration::from_millis(10))?;
+    core.reset_and_halt(Duration::from_millis(10))?;
+
+    Ok(())
+}
+
+/// Flash `elf` and halt the core at reset, handling errors as `policy` says
+fn flash(session: &mut Session, elf: &Path, policy: Policy, delay: Duration) -> Result<()> {
+    let mut backoff = policy.backoff(delay);
+
+    loop {
+        print!("Spinning up the binary ...");
+        let e = match download(session, elf) {
+            Ok(()) => break,
+            Err(e) => e,
+        };
+        println!(" Failed!");
+
+        if policy == Policy::Warn {
+            log::warn!("Flashing failed, running what is on the target: {:#}", e);
+            return Ok(());
+        }
+        let delay = match backoff.next_delay() {
+            Some(delay) => delay,
+            None => return Err(e),
+        };
+        log::warn!(
+            "Flashing failed: {:#}, retrying in {} (attempt {}) ...",
+            e,
+            humantime::format_duration(delay),
+            attempts(&backoff)
+        );
+        std::thread::sleep(delay);
+    }
 
     println!(" Done!");
 
-    std::thread::sleep(std::time::Duration::from_millis(500));
+    std::thread::sleep(Duration::from_millis(500));
 
-    // -------------------------------------------------------------------
-    //
-    // Read from MCU
-    //
-    // -------------------------------------------------------------------
+    Ok(())
+}
+
+/// Wait before reconnecting after `error`, or give up with it once out of retries
+fn retry(backoff: &mut Backoff, error: anyhow::Error) -> Result<()> {
+    let delay = backoff.next_delay().ok_or(error)?;
+    log::warn!(
+        "Connection lost, reconnecting in {} (attempt {}) ...",
+        humantime::format_duration(delay),
+        attempts(backoff)
+    );
+    std::thread::sleep(delay);
+
+    Ok(())
+}
+
+/// The attempt being made, and how many there are if limited, e.g. `2 of 5`
+fn attempts(backoff: &Backoff) -> String {
+    match backoff.retries() {
+        Some(retries) => format!("{} of {}", backoff.attempt(), retries),
+        None => backoff.attempt().to_string(),
+    }
+}
+
+/// Warn that the target stopped sending frames, and take the configured action
+fn stalled(core: &mut Core, silent: Duration, action: &StallAction) -> Result<()> {
+    let silent = Duration::from_millis(silent.as_millis() as u64);
+    eprintln!(
+        "\x1b[1;31m!!! No frames from the target for {} !!!\x1b[0m",
+        humantime::format_duration(silent)
+    );
+
+    match action {
+        StallAction::Warn => {}
+        StallAction::Halt => {
+            core.halt(Duration::from_millis(10))?;
+            print_registers(core)?;
+            eprintln!("Target halted");
+        }
+        StallAction::Reset => {
+            eprintln!("Resetting the target ...");
+            core.reset()?;
+        }
+        StallAction::Exec(command) => {
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .status()?;
+            eprintln!("Stall hook exited with {}", status);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_registers(core: &mut Core) -> Result<()> {
+    let registers = core.registers();
+    for (name, register) in &[
+        ("PC", registers.program_counter()),
+        ("SP", registers.stack_pointer()),
+        ("LR", registers.return_address()),
+    ] {
+        eprintln!("  {}: {:#010x}", name, core.read_core_reg(*register)?);
+    }
+
+    Ok(())
+}
+
+/// Do what a trigger asked for, see `Pipeline::target_actions`
+fn triggered(core: &mut Core, elf: &[u8], action: &TriggerAction) -> Result<()> {
+    match action {
+        TriggerAction::HaltAndDump => {
+            core.halt(Duration::from_millis(10))?;
+            print_registers(core)?;
+            print_backtrace(core, elf)?;
+            eprintln!("Target halted");
+        }
+        TriggerAction::Backtrace => print_backtrace(core, elf)?,
+        TriggerAction::Reset => {
+            eprintln!("Resetting the target ...");
+            core.reset()?;
+        }
+        // Run by the decode thread
+        TriggerAction::Run(_) => (),
+    }
+
+    Ok(())
+}
+
+/// Read what is new in a ring buffer, as a chunk for the decode thread
+fn drain(reader: &mut Reader, transport: &mut impl Transport) -> Result<Option<Chunk>> {
+    Ok(match reader.poll(transport)? {
+        Poll::Idle => None,
+        Poll::Resync => Some(Chunk::Resync),
+        Poll::Dropped(frames) => Some(Chunk::Dropped(frames)),
+        Poll::Overrun { frames, skipped } => Some(Chunk::Overrun { frames, skipped }),
+        Poll::Data(read) => Some(Chunk::Data(read.to_vec(), SystemTime::now())),
+    })
+}
+
+/// Drain `ring` over the connection of `transport` if there is one, the chunk is wrapped with
+/// `wrap` to tell it from the ones of the main ring
+fn drain_ring(
+    ring: Option<&mut Ring>,
+    transport: &mut ProbeTransport,
+    wrap: impl FnOnce(Box<Chunk>) -> Chunk,
+) -> Result<Option<Chunk>> {
+    let ring = match ring {
+        Some(ring) => ring,
+        None => return Ok(None),
+    };
+    let chunk = drain(
+        &mut ring.reader,
+        &mut transport.ring(ring.cursor_address, ring.buffer_address),
+    )?;
+
+    Ok(chunk.map(|chunk| wrap(Box::new(chunk))))
+}
+
+/// Type printers for the DWARF in `bytes`, with the field and type settings of `config`
+fn type_printers(bytes: &[u8], config: &Config) -> Result<TypePrinters> {
+    let mut type_printers = generate_printers(bytes)?;
+    let mut annotations = config.annotations()?;
+    annotations
+        .resolve(&enumerations(bytes)?)
+        .context("Invalid config file")?;
+    type_printers.annotate(&annotations);
+
+    Ok(type_printers)
+}
+
+/// A parser for the frames of a target with the given features
+fn parser(timestamps: bool, tasks: bool, deltas: bool, crcs: bool, sequences: bool) -> Parser {
+    let mut parser = if timestamps {
+        Parser::with_timestamps()
+    } else {
+        Parser::new()
+    };
+    if tasks {
+        parser = parser.with_tasks();
+    }
+    if deltas {
+        parser = parser.with_deltas();
+    }
+    if crcs {
+        parser = parser.with_crcs();
+    }
+    if sequences {
+        parser = parser.with_sequences();
+    }
+
+    parser
+}
+
+/// A reader for a ring of `buffer_size` bytes, following the count of dropped frames if the
+/// target has it, and reading it a bank at a time if it is double-buffered
+fn reader(buffer_size: usize, dropped_count: bool, overwrite: bool, banks: bool) -> Reader {
+    let mut reader = Reader::new(buffer_size);
+    if dropped_count {
+        reader = reader.with_dropped_count(overwrite);
+    }
+    if banks {
+        reader = reader.with_banks();
+    }
+    reader
+}
+
+/// The `log0` up-channel of a target built with the `rtt` feature, the control block is looked
+/// for at `hint` first and then in `ram`
+fn rtt_channel(
+    core: &mut Core,
+    hint: u64,
+    ram: &[Range<u32>],
+    buffer_size: usize,
+) -> Result<rtt::UpChannel> {
+    let channel = rtt::channel(core, Some(hint), ram)?;
+    // A different image than the ELF is running
+    if channel.buffer_size != buffer_size {
+        return Err(anyhow::anyhow!(
+            "The RTT channel has {} bytes, the ELF a {} byte buffer",
+            channel.buffer_size,
+            buffer_size
+        ));
+    }
+    log::info!(
+        "Reading RTT channel {:?} at {:#010x}",
+        channel.name,
+        channel.buffer_address
+    );
+
+    Ok(channel)
+}
+
+/// Where a ring other than the main one is, e.g. the one of the secure image, and the reader
+/// draining it
+struct Ring {
+    reader: Reader,
+    cursor_address: u64,
+    buffer_address: u64,
+}
+
+/// The parser and decoder for the ring of the secure image in `elf`, and where that ring is
+///
+/// Timestamps are converted at `hz` if given, or at the rate the secure image declares.
+fn secure_ring<'a>(
+    elf: &'a ElfFile,
+    bytes: &'a [u8],
+    config: &Config,
+    hz: Option<u32>,
+    wall_clock: bool,
+) -> Result<(Secure<'a>, Ring)> {
+    let res = fmt::extract_format_and_type_strings(elf).context("In the secure image")?;
+    if let Some(version) = res.wire_version {
+        check_wire_version(version).context("In the secure image")?;
+    }
+
+    let catalog = Catalog::new(&res.map_strings).with_sites(&log_sites(bytes)?);
+    let decoder = Decoder::new(catalog, res.map_types, type_printers(bytes, config)?)
+        .with_clock(Clock::new(hz.or(res.timestamp_hz)), wall_clock)
+        .with_addresses(res.addresses)
+        .with_task_names(res.task_names)
+        .with_world(World::Secure)
+        .with_elf(bytes);
+
+    Ok((
+        Secure {
+            parser: parser(
+                res.timestamps,
+                res.tasks,
+                res.deltas,
+                res.crcs,
+                res.sequences,
+            ),
+            decoder,
+        },
+        Ring {
+            reader: reader(
+                res.buffer_size,
+                res.dropped_count,
+                res.overwrite,
+                res.double_buffer,
+            ),
+            cursor_address: res.cursor_address,
+            buffer_address: res.buffer_address,
+        },
+    ))
+}
+
+/// Hand the frames in `input`, or stdin if it is `-`, to the decode thread until the end or
+/// until stopped
+///
+/// With `swo`, the input is an SWO capture and the frames are the writes to that stimulus port.
+fn read_frames(
+    input: &Path,
+    mut swo: Option<Stimulus>,
+    chunks: &SyncSender<Chunk>,
+    running: &AtomicBool,
+) -> Result<()> {
+    let mut reader: Box<dyn Read> = if input == Path::new("-") {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(
+            fs::File::open(input).with_context(|| format!("Failed to open {}", input.display()))?,
+        )
+    };
+
+    let mut buf = vec![0; 4096];
+    while running.load(Ordering::SeqCst) {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let read = match &mut swo {
+            Some(swo) => swo
+                .push(&buf[..len])
+                .into_iter()
+                .map(|written| match written {
+                    Written::Bytes(bytes) => Chunk::Data(bytes, SystemTime::now()),
+                    Written::Overflow => {
+                        log::warn!("ITM overflow, frames were lost");
+                        Chunk::Reset
+                    }
+                })
+                .collect(),
+            None => vec![Chunk::Data(buf[..len].to_vec(), SystemTime::now())],
+        };
+        if read.into_iter().any(|chunk| chunks.send(chunk).is_err()) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Hand the chunks of the recording at `path` to the decode thread until the end or Ctrl-C
+fn replay_frames(path: &Path, chunks: &SyncSender<Chunk>, running: &AtomicBool) -> Result<()> {
+    let mut replay = Replay::open(path)?;
+    while running.load(Ordering::SeqCst) {
+        match replay.next_chunk()? {
+            Some(chunk) => {
+                if chunks.send(chunk).is_err() {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+
+    Ok(())
+}
+
+/// Show the diagnostics of the tool itself, warnings by default and more for each `-v`, the
+/// ones of the libraries only with `RUST_LOG`
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .filter_module("log0_host", level)
+        .filter_module("elf_test", level)
+        .format_timestamp(None)
+        .parse_default_env()
+        .init();
+}
+
+fn main() -> Result<()> {
+    let mut opts = Opts::from_args();
+    init_logging(opts.verbose);
+
+    let (elf_path, runner, junit) = match &opts.command {
+        Some(Command::Catalog { elf, json }) => return catalog(elf, *json),
+        Some(Command::Check { elf }) => return check(elf),
+        Some(Command::Backtrace { elf }) => {
+            let bytes = map_elf(elf)?;
+            let mut session = connect(&mut opts.probe)?;
+            return print_backtrace(&mut session.core(0)?, &bytes);
+        }
+        Some(Command::Profile {
+            elf,
+            duration,
+            top,
+            lines,
+            folded,
+        }) => {
+            return profile(
+                &mut opts.probe,
+                elf,
+                *duration,
+                *top,
+                *lines,
+                folded.as_deref(),
+            )
+        }
+        Some(Command::Inspect {
+            svd,
+            what: Inspect::Periph { name, register },
+        }) => return inspect_peripheral(&mut opts.probe, svd, name, register.as_deref()),
+        Some(Command::Dump { out, elf, decode }) => {
+            return match decode {
+                Some(Dump::Decode { snapshot, elf }) => decode_dump(snapshot, elf.as_deref()),
+                None => dump(&mut opts.probe, out, elf.as_deref()),
+            }
+        }
+        Some(Command::Decode { elf, .. })
+        | Some(Command::Replay { elf, .. })
+        | Some(Command::Daemon { elf, .. }) => (elf.as_path(), None, None),
+        Some(Command::Test { elf, script, junit }) => (
+            elf.as_path(),
+            Some(Runner::new(Script::load(script)?)),
+            junit.as_deref(),
+        ),
+        None => (
+            opts.elf
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("No ELF file given"))?,
+            None,
+            None,
+        ),
+    };
+
+    let config = Config::load(opts.config.as_deref())?;
+
+    // Get address of cursors
+    let bytes = map_elf(elf_path)?;
+    let elf = &ElfFile::new(&bytes).map_err(anyhow::Error::msg)?;
 
     let fmt::Res {
         map_strings,
@@ -78,11 +1319,96 @@ fn main() -> Result<()> {
         cursor_address,
         buffer_address,
         buffer_size,
-    } = fmt::extract_format_and_type_strings(&elf)?;
+        timestamps,
+        timestamp_hz,
+        tasks,
+        task_names,
+        deltas,
+        crcs,
+        sequences,
+        dropped_count,
+        overwrite,
+        double_buffer,
+        high_water,
+        commands,
+        priority,
+        cores,
+        wire_version,
+        rtt,
+        itm_port,
+        panics,
+        addresses,
+    } = fmt::extract_format_and_type_strings(elf)?;
+    // Caught here, before the target is flashed with an image this host cannot decode
+    if let Some(version) = wire_version {
+        check_wire_version(version)?;
+    }
 
-    let type_printers = generate_printers(&bytes).unwrap();
+    // Frames are read from `input` instead of the target when decoding, and from `replay` when
+    // replaying a recording
+    let input = match &opts.command {
+        Some(Command::Decode { input, .. }) => Some(input.as_path()),
+        _ => None,
+    };
+    let replay = match &opts.command {
+        Some(Command::Replay { recording, .. }) => Some(recording.as_path()),
+        _ => None,
+    };
+    let offline = input.is_some() || replay.is_some();
+    let mut recorder = match &opts.record {
+        Some(_) if offline => return Err(anyhow::anyhow!("--record needs a target to read from")),
+        Some(path) => Some(Recorder::create(path)?),
+        None => None,
+    };
+    let swo = match (&opts.command, itm_port) {
+        (Some(Command::Decode { swo: true, .. }), Some(port)) => Some(Stimulus::new(port)),
+        (Some(Command::Decode { swo: true, .. }), None) => {
+            return Err(anyhow::anyhow!(
+                "--swo needs a target built with the `itm` feature"
+            ))
+        }
+        (_, Some(port)) if !offline => {
+            return Err(anyhow::anyhow!(
+                "The target sends its frames on ITM port {}, decode a capture of its SWO \
+                 output with `decode --swo`",
+                port
+            ))
+        }
+        _ => None,
+    };
+    let mut session = if offline {
+        None
+    } else {
+        let mut session = connect(&mut opts.probe)?;
+        if opts.no_flash {
+            println!("Attached to the running binary");
+        } else {
+            flash(
+                &mut session,
+                elf_path,
+                opts.on_flash_error,
+                opts.reconnect_delay,
+            )?;
+        }
+        Some(session)
+    };
+
+    // -------------------------------------------------------------------
+    //
+    // Read from MCU
+    //
+    // -------------------------------------------------------------------
+
+    let type_printers = type_printers(&bytes, &config)?;
+
+    let catalog = Catalog::new(&map_strings).with_sites(&log_sites(&bytes)?);
+    for message in catalog.messages() {
+        if let Err(e) = &message.format {
+            log::warn!("Invalid format string, printing the value only: {}", e);
+        }
+    }
 
-    dbg!(&type_printers);
+    log::trace!("Type printers: {:#?}", type_printers);
 
     // Ctrl-C handling
     let running = Arc::new(AtomicBool::new(true));
@@ -92,78 +1418,516 @@ fn main() -> Result<()> {
     })
     .expect("Error setting Ctrl-C handler");
 
-    let mut old_target = 0;
-    let mut read_buff = vec![0; buffer_size];
-    let mut parser = Parser::new();
+    // Warnings and errors have a ring of their own on targets with the `priority` feature
+    let mut priority_ring = priority.map(|ring| Ring {
+        reader: reader(ring.buffer_size, dropped_count, overwrite, double_buffer),
+        cursor_address: ring.cursor_address,
+        buffer_address: ring.buffer_address,
+    });
+    let priority_parser = priority_ring
+        .as_ref()
+        .map(|_| parser(timestamps, tasks, deltas, crcs, sequences));
+    // Each core has a ring of its own on targets with the `dual-core` feature, all of them are
+    // in RAM both cores share and read through the core the host is attached to
+    let mut core_rings: Vec<_> = cores
+        .iter()
+        .map(|ring| Ring {
+            reader: reader(ring.buffer_size, dropped_count, overwrite, double_buffer),
+            cursor_address: ring.cursor_address,
+            buffer_address: ring.buffer_address,
+        })
+        .collect();
+    let core_parsers = core_rings
+        .iter()
+        .map(|_| parser(timestamps, tasks, deltas, crcs, sequences))
+        .collect();
+    let mut reader = reader(buffer_size, dropped_count, overwrite, double_buffer);
+    if let Some(chunk) = opts.read_chunk {
+        reader.set_chunk(chunk);
+    }
+    // Banks are read whole, there is no chunk to tune
+    let mut tune = opts.read_chunk.is_none() && !double_buffer;
+    let parser = parser(timestamps, tasks, deltas, crcs, sequences);
+    let hz = opts
+        .timestamp_hz
+        .or(opts.cpu_hz)
+        .or(config.time.timestamp_hz)
+        .or(config.time.cpu_hz);
+    let clock = Clock::new(hz.or(timestamp_hz));
+    // Strings missing from the ELF are read from the target by the probe thread
+    let (fetch_requests, fetch_requested) = mpsc::sync_channel(16);
+    let mut decoder = Decoder::new(catalog, map_types, type_printers)
+        .with_clock(clock, opts.wall_clock)
+        .with_addresses(addresses)
+        .with_task_names(task_names)
+        .with_elf(&bytes);
+    if !offline {
+        decoder = decoder.with_fetcher(Fetcher::new(fetch_requests));
+    }
+    for channel in &opts.channels {
+        decoder = decoder.with_channel(channel.channel, channel.decoder()?);
+    }
 
-    core.run()?;
+    // TrustZone targets can have a ring in the secure image too
+    let secure_bytes = match &opts.secure_elf {
+        Some(_) if offline => {
+            return Err(anyhow::anyhow!("--secure-elf needs a target to read from"))
+        }
+        Some(path) => Some(map_elf(path)?),
+        None => None,
+    };
+    let secure_elf = match &secure_bytes {
+        Some(bytes) => Some(ElfFile::new(bytes).map_err(anyhow::Error::msg)?),
+        None => None,
+    };
+    let (secure, mut secure_ring) = match (&secure_elf, &secure_bytes) {
+        (Some(elf), Some(bytes)) => {
+            decoder = decoder.with_world(World::NonSecure);
+            let (secure, ring) = secure_ring(elf, bytes, &config, hz, opts.wall_clock)?;
+            (Some(secure), Some(ring))
+        }
+        _ => (None, None),
+    };
 
-    while running.load(Ordering::SeqCst) {
-        let mut buff = [0u32; 2];
-
-        let now = Instant::now();
-
-        core.read_32(cursor_address, &mut buff)?;
-
-        let target = buff[0];
-        let host = buff[1];
-
-        if target != old_target {
-            old_target = target;
-
-            let br = bytes_to_read(host as usize, target as usize, buffer_size);
-            // println!("bytes to read: {}", br);
-
-            let mut read = &mut read_buff[0..br];
-
-            if host + br as u32 > buffer_size as u32 {
-                // cursor will overflow
-                let pivot = buffer_size - host as usize;
-                // println!(
-                //     "pivot: {}, reading from {} to {}, 0 to {}",
-                //     pivot,
-                //     host,
-                //     host + pivot as u32,
-                //     br - pivot
-                // );
-                core.read_8(buffer_address + host, &mut read[0..pivot])?;
-                core.read_8(buffer_address, &mut read[pivot..br])?;
-                core.write_word_32(cursor_address + 4, (br - pivot) as u32)?;
-            } else {
-                // println!("reading from {} to {}", host, host + br as u32);
-                core.read_8(buffer_address + host, &mut read)?;
-                core.write_word_32(cursor_address + 4, (host + br as u32) % buffer_size as u32)?;
+    let template = match &opts.format {
+        Some(template) => Some(template.clone()),
+        None => config.template()?,
+    };
+    let output: Box<dyn Sink + Send> = if opts.output == Output::Json || opts.json {
+        Box::new(Json::new(std::io::stdout()).with_details(true))
+    } else {
+        Box::new(
+            Terminal::new(std::io::stdout(), opts.show_raw)
+                .with_colors(!opts.no_color)
+                .with_template(template.clone()),
+        )
+    };
+    let mut sinks = vec![output];
+    if let Some(mqtt) = &config.mqtt {
+        sinks.push(Box::new(Mqtt::connect(mqtt)?));
+    }
+    if let Some(influx) = &config.influx {
+        sinks.push(Box::new(Influx::open(influx)?));
+    }
+    if let Some(path) = &opts.sqlite {
+        sinks.push(Box::new(Sqlite::open(path, elf_path)?));
+    }
+    if let Some(dir) = &opts.archive_dir {
+        sinks.push(Box::new(Archive::new(
+            dir,
+            opts.archive_split,
+            opts.archive_compress,
+        )?));
+    }
+    if let Some(trigger) = &opts.capture_on {
+        sinks.push(Box::new(
+            Capture::new(
+                trigger.clone(),
+                opts.capture_before,
+                opts.capture_after,
+                &opts.capture_dir,
+            )?
+            .with_template(template),
+        ));
+    }
+    if !opts.histograms.is_empty() || !config.histograms.is_empty() {
+        sinks.push(Box::new(Histograms::new(
+            std::io::stderr(),
+            &opts.histograms,
+            &config.histograms,
+            opts.histogram_interval,
+        )?));
+    }
+    let mut sink: Box<dyn Sink + Send> = Box::new(Fanout::new(sinks));
+    if opts.collapse {
+        sink = Box::new(Collapse::new(sink));
+    }
+    if let Some(path) = &opts.hook {
+        sink = Box::new(Hook::open(path, sink)?);
+    }
+
+    let itm = match &opts.itm {
+        Some(path) => Some(Itm {
+            data: itm::spawn_reader(path)?,
+            decoder: ItmDecoder::new(opts.itm_hz.or(opts.cpu_hz).or(config.time.cpu_hz)),
+            timeline: Timeline::new(),
+        }),
+        None => None,
+    };
+
+    let started = Instant::now();
+    let until = Until::new(
+        opts.until_regex.clone(),
+        opts.fail_regex.clone(),
+        opts.timeout
+            .or_else(|| runner.as_ref().and_then(|r| r.script().timeout)),
+        started,
+    );
+
+    // Commands waiting for room in the command buffer
+    let mut pending = VecDeque::new();
+    if !opts.commands.is_empty() || opts.stdin {
+        let channel = commands.as_ref().ok_or_else(|| {
+            anyhow::anyhow!("The target has no command channel, enable the `commands` feature")
+        })?;
+        for command in &opts.commands {
+            pending.push_back(channel.frame(command.as_bytes())?);
+        }
+    }
+    let lines = if opts.stdin {
+        Some(keys::spawn_lines())
+    } else {
+        None
+    };
+
+    // The daemon is driven through its control socket instead of the keyboard
+    let (socket, control_calls, target_calls) = match &opts.command {
+        Some(Command::Daemon { socket, .. }) => {
+            let (socket, calls) = control::listen(socket)?;
+            (Some(socket), Some(calls.decode), Some(calls.target))
+        }
+        _ => (None, None, None),
+    };
+
+    // Space pauses, `f` filters, `c` clears, `s` prints the summary, `r` the stack and heap usage,
+    // `b` prints a backtrace and `q` quits
+    let raw_mode = if opts.stdin || input == Some(Path::new("-")) || socket.is_some() {
+        None
+    } else {
+        RawMode::enable()
+    };
+    let keys = raw_mode.as_ref().map(|_| keys::spawn());
+    let wants_backtrace = Arc::new(AtomicBool::new(false));
+
+    let triggers = Triggers::new(&config.triggers)?;
+    let (target_actions, target_triggered) = mpsc::channel();
+    let target_actions = if offline {
+        if triggers.needs_target() {
+            log::warn!("Triggers that act on the target are ignored when decoding a file");
+        }
+        None
+    } else {
+        Some(target_actions)
+    };
+
+    let pipeline = Pipeline {
+        parser,
+        decoder,
+        sink,
+        stats: Stats::new(),
+        live: Live::new().with_level(opts.level),
+        until,
+        runner,
+        outcome: None,
+        resyncs: 0,
+        on_parse_error: opts.on_parse_error,
+        started,
+        running: running.clone(),
+        backtrace: wants_backtrace.clone(),
+        itm,
+        secure,
+        priority: priority_parser,
+        cores: core_parsers,
+        triggers,
+        target_actions,
+    };
+
+    let mut backoff = opts
+        .on_probe_error
+        .unwrap_or(Policy::Retry(opts.reconnect))
+        .backoff(opts.reconnect_delay);
+    // The core is halted after flashing, and has to be started, a core attached to is left as
+    // it is
+    let mut start_core = !opts.no_flash;
+    let reflash = !(opts.no_reflash || opts.no_flash);
+    // A copy, the closure below would otherwise borrow all of `opts` mutably
+    let mut probe_opts = opts.probe.clone();
+
+    // This thread drains the ring buffer, decoding and showing the messages happens on another
+    let pipeline = thread::scope(|s| {
+        let (chunks, received) = mpsc::sync_channel(pipeline::CAPACITY);
+        let decoding = s.spawn(move || pipeline.run(received, keys, control_calls));
+
+        if let Some(input) = input {
+            read_frames(input, swo, &chunks, &running)?;
+            drop(chunks);
+            return decoding.join().expect("Decode thread panicked");
+        }
+        if let Some(replay) = replay {
+            replay_frames(replay, &chunks, &running)?;
+            drop(chunks);
+            return decoding.join().expect("Decode thread panicked");
+        }
+
+        while running.load(Ordering::SeqCst) {
+            let mut session = match session.take() {
+                Some(session) => session,
+                None => {
+                    let mut session = match connect(&mut probe_opts) {
+                        Ok(session) => session,
+                        Err(e) => {
+                            retry(&mut backoff, e)?;
+                            continue;
+                        }
+                    };
+                    if reflash {
+                        flash(
+                            &mut session,
+                            elf_path,
+                            opts.on_flash_error,
+                            opts.reconnect_delay,
+                        )?;
+                    }
+                    start_core = reflash;
+                    session
+                }
+            };
+
+            let chip = probe_opts.chip.clone().unwrap_or_default();
+            let ram = ram(&session);
+            let mut core = match session.core(0) {
+                Ok(core) => core,
+                Err(e) => {
+                    retry(&mut backoff, e.into())?;
+                    continue;
+                }
+            };
+            if start_core {
+                if let Err(e) = core.run() {
+                    retry(&mut backoff, e.into())?;
+                    continue;
+                }
+            }
+            // The ring of an RTT target is wherever its control block says it is
+            let (cursor_address, buffer_address) = match rtt {
+                Some(hint) => match rtt_channel(&mut core, hint, &ram, buffer_size) {
+                    Ok(channel) => (channel.cursor_address, channel.buffer_address),
+                    Err(e) => {
+                        retry(&mut backoff, e)?;
+                        continue;
+                    }
+                },
+                None => (cursor_address, buffer_address),
+            };
+            let mut transport =
+                ProbeTransport::new(core, cursor_address, buffer_address, opts.security);
+            if transport.security() != Security::Flat {
+                log::info!(
+                    "Accessing memory through the {:?} alias",
+                    transport.security()
+                );
+            }
+            if tune {
+                match tune::measure(&mut transport, buffer_size) {
+                    Ok(samples) => {
+                        let (overhead, per_byte) = tune::fit(&samples);
+                        reader.set_chunk(tune::pick_chunk(overhead, per_byte, buffer_size));
+                        for ring in secure_ring.iter_mut().chain(&mut core_rings) {
+                            ring.reader.set_chunk(reader.chunk());
+                        }
+                        log::info!(
+                            "Read chunk: {} bytes ({:?} per transfer, {:.0} kB/s)",
+                            reader.chunk(),
+                            overhead,
+                            1e-3 / per_byte.as_secs_f64().max(1e-12)
+                        );
+                        tune = false;
+                    }
+                    Err(e) => {
+                        retry(&mut backoff, e)?;
+                        continue;
+                    }
+                }
             }
+            let mut lost = None;
+            let mut watchdog = opts
+                .stall_timeout
+                .map(|timeout| Watchdog::new(timeout, Instant::now()));
+
+            while running.load(Ordering::SeqCst) {
+                if let Some(channel) = &commands {
+                    for line in lines.iter().flat_map(|lines| lines.try_iter()) {
+                        match channel.frame(line.as_bytes()) {
+                            Ok(frame) => pending.push_back(frame),
+                            Err(e) => eprintln!("Command not sent: {}", e),
+                        }
+                    }
+
+                    while let Some(frame) = pending.front() {
+                        match channel.send(&mut transport, frame) {
+                            Ok(true) => {
+                                pending.pop_front();
+                            }
+                            Ok(false) => break,
+                            Err(e) => {
+                                lost = Some(e);
+                                break;
+                            }
+                        }
+                    }
+                    if lost.is_some() {
+                        break;
+                    }
+                }
+
+                for request in fetch_requested.try_iter() {
+                    request.serve(&mut transport);
+                }
+
+                for action in target_triggered.try_iter() {
+                    if let Err(e) = triggered(transport.core(), &bytes, &action) {
+                        eprintln!("Trigger failed: {}", e);
+                    }
+                }
+
+                for call in target_calls.iter().flat_map(|calls| calls.try_iter()) {
+                    let result = match &call.method {
+                        ControlMethod::Dump(out) => {
+                            let core = transport.core();
+                            save_snapshot(core, &chip, &ram, Some(elf_path), out)
+                                .and_then(|_| Ok(core.run()?))
+                                .map(|_| serde_json::json!({ "path": out }))
+                        }
+                        method => Err(anyhow::anyhow!("{:?} does not need the target", method)),
+                    };
+                    call.reply(result);
+                }
+
+                if wants_backtrace.swap(false, Ordering::SeqCst) {
+                    if let Err(e) = print_backtrace(transport.core(), &bytes) {
+                        eprintln!("Backtrace failed: {}", e);
+                    }
+                }
+
+                // Warnings and errors first, so they get through while the main ring is busy
+                let polled = drain_ring(priority_ring.as_mut(), &mut transport, Chunk::Priority)
+                    .and_then(|priority| {
+                        let chunk = drain(&mut reader, &mut transport)?;
+                        let secure =
+                            drain_ring(secure_ring.as_mut(), &mut transport, Chunk::Secure)?;
+                        let mut polled = vec![priority, chunk, secure];
+                        for (i, ring) in core_rings.iter_mut().enumerate() {
+                            let wrap = |chunk| Chunk::Core(i + 1, chunk);
+                            polled.push(drain_ring(Some(ring), &mut transport, wrap)?);
+                        }
+
+                        Ok(polled)
+                    });
+                let mut polled = match polled {
+                    Ok(polled) => polled,
+                    Err(e) => {
+                        lost = Some(e);
+                        break;
+                    }
+                };
+                backoff.reset();
 
-            let _dur = now.elapsed();
+                // A panic frame that did not fit still leaves the mark, looked for once the
+                // rings are drained
+                if panics && polled.iter().all(Option::is_none) {
+                    match transport.read_panicked() {
+                        Ok(true) => polled.push(Some(Chunk::Panicked)),
+                        Ok(false) => (),
+                        Err(e) => {
+                            lost = Some(e);
+                            break;
+                        }
+                    }
+                }
 
-            parser.push(&read);
+                for chunk in polled.into_iter().flatten() {
+                    if chunk.has_data() {
+                        if let Some(watchdog) = &mut watchdog {
+                            watchdog.feed(Instant::now());
+                        }
+                    }
+                    if let Some(recorder) = &mut recorder {
+                        recorder.write(&chunk)?;
+                    }
 
-            while let Some(packet) = parser.try_parse() {
-                // let string = map_strings
-                //     .get(&packet.string_loc)
-                //     .unwrap_or(&"String not found in hashmap?!?!?!");
-                let typ = map_types
-                    .get(&packet.type_loc)
-                    .unwrap_or(&"String not found in hashmap?!?!?!");
-                // println!(
-                //     "String: '{}', Type string: '{}', Buffer: {:x?}",
-                //     string, typ, packet.buffer
-                // );
-                type_printers.print(typ.split(':').last().unwrap(), &packet.buffer);
+                    // Blocks if the decode thread is far behind, and fails if it stopped on an
+                    // error
+                    if chunks.send(chunk).is_err() {
+                        running.store(false, Ordering::SeqCst);
+                    }
+                }
 
-                // println!("packet: {:x?}", p);
+                if let Some(silent) = watchdog.as_mut().and_then(|w| w.check(Instant::now())) {
+                    stalled(transport.core(), silent, &opts.on_stall)?;
+                }
             }
 
-            // println!("target: {}, host: {}, len to read: {}", target, host, br,);
-            // println!("read buf: {:x?}", read);
-            // println!("");
+            match lost {
+                Some(e) => {
+                    if let Some(recorder) = &mut recorder {
+                        recorder.write(&Chunk::Reset)?;
+                    }
+                    chunks.send(Chunk::Reset).ok();
+                    retry(&mut backoff, e)?;
+                }
+                None => {
+                    // Before halting, so the peak of the whole session is in the summary
+                    if let Some(offset) = high_water {
+                        match transport.read_high_water(offset) {
+                            Ok(bytes) => {
+                                let bytes = bytes as usize;
+                                let capacity = buffer_size;
+                                chunks.send(Chunk::HighWater { bytes, capacity }).ok();
+                            }
+                            Err(e) => log::warn!("Could not read the high-water mark: {}", e),
+                        }
+                    }
+                    // A core attached to is left running, as it was found
+                    if !opts.no_flash {
+                        transport
+                            .core()
+                            .halt(std::time::Duration::from_millis(10))?;
+                    }
+                }
+            }
         }
-    }
 
-    core.halt(std::time::Duration::from_millis(10))?;
+        if let Some(recorder) = &mut recorder {
+            recorder.flush()?;
+        }
+        drop(chunks);
+        decoding.join().expect("Decode thread panicked")
+    })?;
+
+    drop(raw_mode);
+    drop(socket);
+    log::info!("Exiting ...");
+
+    let Pipeline {
+        stats,
+        runner,
+        outcome,
+        resyncs,
+        ..
+    } = pipeline;
+
+    // On stderr so it does not end up in the JSON output
+    stats.write_summary(&mut std::io::stderr(), started.elapsed(), resyncs)?;
 
-    println!("Exiting ...");
+    if let Some(runner) = runner {
+        runner.write_summary(&mut std::io::stderr())?;
+        if let Some(junit) = junit {
+            let mut file = fs::File::create(junit)?;
+            runner.write_junit(
+                &mut file,
+                &elf_path.display().to_string(),
+                started.elapsed(),
+            )?;
+        }
+        std::process::exit(if runner.passed() { 0 } else { 1 });
+    }
+
+    if let Some(outcome) = outcome {
+        if outcome == Outcome::TimedOut {
+            eprintln!("Timed out");
+        }
+        std::process::exit(outcome.exit_code());
+    }
 
     Ok(())
 }
@@ -0,0 +1,246 @@
+use crate::{leb128, record::Record, sink::Sink};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// MQTT broker to publish to, the `[mqtt]` section of the config
+///
+/// ```toml
+/// [mqtt]
+/// broker = "localhost:1883"
+/// client_id = "bench-3"
+/// # `{module}`, `{type}` and `{id}` are replaced per message, `::` in module paths becomes `/`
+/// topic = "lab/bench-3/{module}"
+/// retain = false
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    pub broker: String,
+    #[serde(default = "client_id_default")]
+    pub client_id: String,
+    #[serde(default = "topic_default")]
+    pub topic: String,
+    #[serde(default)]
+    pub retain: bool,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+fn client_id_default() -> String {
+    "fasthosting".into()
+}
+
+fn topic_default() -> String {
+    "fasthosting/{module}".into()
+}
+
+/// How long to wait before connecting again after losing the broker, the records in between
+/// are dropped
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Publishes each record as a JSON object with MQTT 3.1.1, QoS 0
+///
+/// Losing the broker does not end the session, the records are dropped until it can connect
+/// again.
+pub struct Mqtt<T: Read + Write> {
+    stream: Option<T>,
+    config: MqttConfig,
+    /// Opens a new stream to the broker
+    open: fn(&MqttConfig) -> Result<T>,
+    /// When the last stream was lost, or the last attempt to connect again failed
+    lost: Instant,
+}
+
+impl Mqtt<TcpStream> {
+    pub fn connect(config: &MqttConfig) -> Result<Self> {
+        Ok(Mqtt::handshake(tcp(config)?, config)?.with_open(tcp))
+    }
+}
+
+fn tcp(config: &MqttConfig) -> Result<TcpStream> {
+    let stream = TcpStream::connect(&config.broker)
+        .with_context(|| format!("Failed to connect to MQTT broker {}", config.broker))?;
+    stream.set_nodelay(true)?;
+
+    Ok(stream)
+}
+
+fn no_reconnect<T>(_: &MqttConfig) -> Result<T> {
+    Err(anyhow!("Cannot connect to the MQTT broker again"))
+}
+
+impl<T: Read + Write> Mqtt<T> {
+    /// Send CONNECT over an open stream and wait for the CONNACK
+    pub fn handshake(mut stream: T, config: &MqttConfig) -> Result<Self> {
+        connect(&mut stream, config)?;
+
+        Ok(Mqtt {
+            stream: Some(stream),
+            config: config.clone(),
+            open: no_reconnect,
+            lost: Instant::now(),
+        })
+    }
+
+    /// Open streams with `open` to connect again after losing the broker
+    pub fn with_open(self, open: fn(&MqttConfig) -> Result<T>) -> Self {
+        Mqtt { open, ..self }
+    }
+
+    /// The stream to the broker, connecting again if it was lost a while ago
+    fn stream(&mut self) -> Option<&mut T> {
+        if self.stream.is_none() && self.lost.elapsed() >= RECONNECT_INTERVAL {
+            let config = &self.config;
+            match (self.open)(config).and_then(|mut stream| {
+                connect(&mut stream, config)?;
+                Ok(stream)
+            }) {
+                Ok(stream) => {
+                    log::info!("Connected to MQTT broker {} again", config.broker);
+                    self.stream = Some(stream);
+                }
+                Err(e) => {
+                    log::warn!("{:#}", e);
+                    self.lost = Instant::now();
+                }
+            }
+        }
+
+        self.stream.as_mut()
+    }
+
+    /// Drop the stream after it failed with `e`
+    fn lose(&mut self, e: anyhow::Error) {
+        log::warn!(
+            "Lost MQTT broker {}, dropping records until it is back: {:#}",
+            self.config.broker,
+            e
+        );
+        self.stream = None;
+        self.lost = Instant::now();
+    }
+
+    pub fn stream_mut(&mut self) -> &mut T {
+        self.stream.as_mut().expect("MQTT broker lost")
+    }
+
+    /// The topic for a record, with the placeholders filled in
+    pub fn topic(&self, record: &Record) -> String {
+        let id = record.id.map(|id| id.to_string());
+
+        self.config
+            .topic
+            .replace(
+                "{module}",
+                &record
+                    .module
+                    .as_deref()
+                    .unwrap_or("unknown")
+                    .replace("::", "/"),
+            )
+            .replace("{type}", record.type_name.as_deref().unwrap_or("unknown"))
+            .replace("{id}", id.as_deref().unwrap_or("unknown"))
+    }
+}
+
+/// Send CONNECT and wait for the CONNACK
+fn connect(stream: &mut (impl Read + Write), config: &MqttConfig) -> Result<()> {
+    let mut flags = 0x02; // Clean session
+    let mut payload = Vec::new();
+    string(&mut payload, &config.client_id)?;
+    // A password without a username is not allowed in 3.1.1
+    if let Some(username) = &config.username {
+        flags |= 0x80;
+        string(&mut payload, username)?;
+        if let Some(password) = &config.password {
+            flags |= 0x40;
+            string(&mut payload, password)?;
+        }
+    }
+
+    let mut body = Vec::new();
+    string(&mut body, "MQTT")?;
+    body.push(4); // Protocol level 3.1.1
+    body.push(flags);
+    // No keep alive, QoS 0 publishing does not need the broker to notice a dead client
+    body.extend_from_slice(&0u16.to_be_bytes());
+    body.extend_from_slice(&payload);
+    packet(stream, 0x10, &body)?;
+
+    let mut connack = [0; 4];
+    stream
+        .read_exact(&mut connack)
+        .context("No CONNACK from MQTT broker")?;
+    match connack {
+        [0x20, 2, _, 0] => Ok(()),
+        [0x20, 2, _, code] => Err(anyhow!("MQTT broker refused the connection ({})", code)),
+        _ => Err(anyhow!("Invalid CONNACK from MQTT broker")),
+    }
+}
+
+impl<T: Read + Write> Sink for Mqtt<T> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let mut body = Vec::new();
+        if let Err(e) = string(&mut body, &self.topic(record)) {
+            log::warn!("Not publishing a record to MQTT: {:#}", e);
+            return Ok(());
+        }
+        serde_json::to_writer(&mut body, record)?;
+
+        let header = 0x30 | self.config.retain as u8;
+        if let Some(Err(e)) = self.stream().map(|stream| packet(stream, header, &body)) {
+            self.lose(e);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(Err(e)) = self.stream.as_mut().map(|stream| stream.flush()) {
+            self.lose(e.into());
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let disconnect = |stream: &mut T| -> Result<()> {
+            packet(stream, 0xe0, &[])?;
+            Ok(stream.flush()?)
+        };
+        if let Some(Err(e)) = self.stream.as_mut().map(disconnect) {
+            self.lose(e);
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a packet, the remaining length uses the same encoding as LEB128
+fn packet(w: &mut impl Write, header: u8, body: &[u8]) -> Result<()> {
+    // At most 4 bytes of length
+    if body.len() >= 1 << 28 {
+        return Err(anyhow!("MQTT packet too large"));
+    }
+
+    let mut bytes = vec![header];
+    leb128::encode_u32(&mut bytes, body.len() as u32);
+    bytes.extend_from_slice(body);
+
+    Ok(w.write_all(&bytes)?)
+}
+
+/// Append a UTF-8 string with its 16-bit length
+fn string(v: &mut Vec<u8>, s: &str) -> Result<()> {
+    if s.len() > u16::MAX as usize {
+        return Err(anyhow!("MQTT string too long"));
+    }
+
+    v.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    v.extend_from_slice(s.as_bytes());
+
+    Ok(())
+}
@@ -1,5 +1,68 @@
-use crate::leb128;
-use std::collections::VecDeque;
+use crate::{crc, fault::Fault, leb128, record::Level};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::fmt;
+use std::mem;
+
+/// Version of the frame format this host knows, see `Packet::boot_version`
+pub const WIRE_VERSION: u8 = 1;
+
+/// String address of the boot frame, that a target writes to each ring before its first frame
+pub const BOOT_FRAME: usize = u32::MAX as usize;
+
+/// String address of the panic frame, written by the panic handler of `log0_panic`
+pub const PANIC_FRAME: usize = u32::MAX as usize - 1;
+
+/// String address of the frame with the registers of a HardFault, see `report_fault` on the
+/// target
+pub const FAULT_FRAME: usize = u32::MAX as usize - 2;
+
+/// String address of the frames of the `log` crate records, written by `facade::Logger` on the
+/// target
+pub const LOG_FRAME: usize = u32::MAX as usize - 3;
+
+/// String address of the frames of `span_enter!` and `span_exit!` on the target
+pub const SPAN_FRAME: usize = u32::MAX as usize - 4;
+
+/// String address of the frames of `heartbeat` on the target
+pub const HEARTBEAT_FRAME: usize = u32::MAX as usize - 5;
+
+/// String address of the frames of `write_raw` on the target
+pub const RAW_FRAME: usize = u32::MAX as usize - 6;
+
+/// Type string address of the frames of `log_fmt!` on the target, whose data is the message
+/// formatted on the target instead of a value
+pub const FORMATTED_TYPE: usize = u32::MAX as usize;
+
+/// String address of the frames of `report_resources` on the target
+pub const RESOURCES_FRAME: usize = u32::MAX as usize - 7;
+
+/// Set in the level byte of a `log` crate record when its message is sent as the address and
+/// length of a `&'static str`, to read from the ELF
+const DEFERRED: u8 = 0x80;
+
+/// A frame never holds more than the largest ring buffer, a longer one is taken to be corrupted
+/// when the frames have a CRC
+const MAX_FRAME: usize = 64 * 1024;
+
+/// Refuse a target with another wire version, as its frames would be decoded wrong
+///
+/// The version is in the boot frame, and in `LOG0_WIRE_VERSION` in the ELF of targets that
+/// have it.
+pub fn check_wire_version(version: u8) -> Result<()> {
+    if version != WIRE_VERSION {
+        bail!(
+            "The target writes frames in wire format version {}, but this host only knows \
+             version {}, build it with a log0_target that matches this log0_host",
+            version,
+            WIRE_VERSION
+        );
+    }
+
+    Ok(())
+}
 
 /// A parsed packet containing the addresses of the formating and type strings, as well as the
 /// transmitted buffer
@@ -7,9 +70,241 @@ use std::collections::VecDeque;
 pub struct Packet {
     pub string_loc: usize,
     pub type_loc: usize,
+    /// Target ticks when the frame was written, if the target sends timestamps
+    pub timestamp: Option<u32>,
+    /// ID of the task that wrote the frame, if the target sends task IDs
+    pub task: Option<u32>,
     pub buffer: Vec<u8>,
 }
 
+impl Packet {
+    /// The wire version the target writes, if this is the boot frame
+    ///
+    /// Targets from before the version was sent never write one, and are taken to write the
+    /// current version.
+    pub fn boot_version(&self) -> Option<u8> {
+        match self.buffer.as_slice() {
+            [version] if self.string_loc == BOOT_FRAME && self.type_loc == 0 => Some(*version),
+            _ => None,
+        }
+    }
+
+    /// The panic, if this is the panic frame
+    ///
+    /// It holds the line as a little endian `u32`, then the file and the message separated by
+    /// a NUL.
+    pub fn panic(&self) -> Option<Panic> {
+        if self.string_loc != PANIC_FRAME || self.type_loc != 0 || self.buffer.len() < 4 {
+            return None;
+        }
+
+        let (line, rest) = self.buffer.split_at(4);
+        let mut parts = rest.splitn(2, |&b| b == 0);
+        let file = parts.next().unwrap_or_default();
+        let message = parts.next().unwrap_or_default();
+
+        Some(Panic {
+            file: String::from_utf8_lossy(file).into_owned(),
+            line: u32::from_le_bytes([line[0], line[1], line[2], line[3]]),
+            message: String::from_utf8_lossy(message).into_owned(),
+        })
+    }
+
+    /// The `log` crate record, if this is the frame of one
+    ///
+    /// It holds the level as in `log::Level` and `DEFERRED`, then the target and the message
+    /// separated by a NUL. A deferred message is its address and length as little endian `u32`s.
+    pub fn facade(&self) -> Option<Facade> {
+        if self.string_loc != LOG_FRAME || self.type_loc != 0 {
+            return None;
+        }
+
+        let (&level, rest) = self.buffer.split_first()?;
+        let mut parts = rest.splitn(2, |&b| b == 0);
+        let target = parts.next().unwrap_or_default();
+        let message = parts.next().unwrap_or_default();
+
+        let message = if level & DEFERRED != 0 {
+            match message {
+                [a0, a1, a2, a3, l0, l1, l2, l3] => FacadeMessage::Static {
+                    address: u32::from_le_bytes([*a0, *a1, *a2, *a3]),
+                    len: u32::from_le_bytes([*l0, *l1, *l2, *l3]),
+                },
+                _ => return None,
+            }
+        } else {
+            FacadeMessage::Text(String::from_utf8_lossy(message).into_owned())
+        };
+
+        Some(Facade {
+            level: match level & !DEFERRED {
+                1 => Some(Level::Error),
+                2 => Some(Level::Warn),
+                3 => Some(Level::Info),
+                4 => Some(Level::Debug),
+                5 => Some(Level::Trace),
+                _ => None,
+            },
+            target: String::from_utf8_lossy(target).into_owned(),
+            message,
+        })
+    }
+
+    /// The message formatted on the target, if this is the frame of a `log_fmt!`
+    pub fn formatted(&self) -> Option<String> {
+        if self.type_loc != FORMATTED_TYPE {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&self.buffer).into_owned())
+    }
+
+    /// A heartbeat of the target, sent periodically to show it is alive
+    pub fn is_heartbeat(&self) -> bool {
+        self.string_loc == HEARTBEAT_FRAME && self.type_loc == 0 && self.buffer.is_empty()
+    }
+
+    /// The span entered or exited, if this is the frame of one
+    ///
+    /// It holds 1 for enter and 0 for exit, then the address and length of the name as little
+    /// endian `u32`s.
+    pub fn span(&self) -> Option<SpanFrame> {
+        if self.string_loc != SPAN_FRAME || self.type_loc != 0 {
+            return None;
+        }
+
+        match self.buffer.as_slice() {
+            [enter @ (0 | 1), a0, a1, a2, a3, l0, l1, l2, l3] => Some(SpanFrame {
+                enter: *enter == 1,
+                address: u32::from_le_bytes([*a0, *a1, *a2, *a3]),
+                len: u32::from_le_bytes([*l0, *l1, *l2, *l3]),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The channel and data, if this is the frame of a raw channel
+    ///
+    /// It holds the channel, then the data as the application wrote it.
+    pub fn raw(&self) -> Option<RawFrame> {
+        if self.string_loc != RAW_FRAME || self.type_loc != 0 {
+            return None;
+        }
+
+        let (&channel, data) = self.buffer.split_first()?;
+        Some(RawFrame {
+            channel,
+            data: data.to_vec(),
+        })
+    }
+
+    /// The stack and heap usage, if this is a resources frame
+    ///
+    /// It holds the bytes of stack used and the size of the stack, then the used and free bytes
+    /// of the heap if the target has one, as little endian `u32`s.
+    pub fn resources(&self) -> Option<Resources> {
+        if self.string_loc != RESOURCES_FRAME || self.type_loc != 0 {
+            return None;
+        }
+
+        let data = &self.buffer;
+        let word = |i: usize| u32::from_le_bytes(data[4 * i..4 * i + 4].try_into().unwrap());
+        let heap = match data.len() {
+            8 => None,
+            16 => Some(HeapUsage {
+                used: word(2),
+                free: word(3),
+            }),
+            _ => return None,
+        };
+
+        Some(Resources {
+            stack_used: word(0),
+            stack_size: word(1),
+            heap,
+        })
+    }
+
+    /// The stacked and fault status registers, if this is the fault frame
+    pub fn fault(&self) -> Option<Fault> {
+        if self.string_loc != FAULT_FRAME || self.type_loc != 0 {
+            return None;
+        }
+
+        Fault::parse(&self.buffer)
+    }
+}
+
+/// Where the target panicked and why, from the panic frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Panic {
+    pub file: String,
+    pub line: u32,
+    /// Cut off by the target if it is long
+    pub message: String,
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "panicked at {}:{}: {}",
+            self.file, self.line, self.message
+        )
+    }
+}
+
+/// A record of the `log` crate on the target, from its frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Facade {
+    /// `None` if the target sent one this host does not know
+    pub level: Option<Level>,
+    /// Usually the module path of the `log::info!` call
+    pub target: String,
+    pub message: FacadeMessage,
+}
+
+/// The message of a `log` crate record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FacadeMessage {
+    /// Formatted on the target, cut off if it is long
+    Text(String),
+    /// A message without arguments, the `&'static str` at `address` in the ELF
+    Static { address: u32, len: u32 },
+}
+
+/// A span entered or exited on the target, from its frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanFrame {
+    pub enter: bool,
+    /// The name is the `&'static str` at `address` in the ELF
+    pub address: u32,
+    pub len: u32,
+}
+
+/// Data written to a raw channel on the target, from its frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    pub channel: u8,
+    pub data: Vec<u8>,
+}
+
+/// Stack and heap usage reported by the target, from its frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Resources {
+    /// Deepest the stack has been since it was painted
+    pub stack_used: u32,
+    pub stack_size: u32,
+    pub heap: Option<HeapUsage>,
+}
+
+/// Heap usage as the allocator on the target reported it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HeapUsage {
+    pub used: u32,
+    pub free: u32,
+}
+
 /// Parser worker, this handles the parsing of the binary format
 #[derive(Debug)]
 pub struct Parser {
@@ -17,6 +312,28 @@ pub struct Parser {
     data_size: Option<usize>,
     sym: Option<u32>,
     typ: Option<u32>,
+    timestamps: bool,
+    timestamp: Option<u32>,
+    tasks: bool,
+    task: Option<u32>,
+    deltas: bool,
+    /// The frame being parsed holds only the changes since the previous one of its call site
+    delta: bool,
+    /// The last value of each call site that sends changes, by format string address
+    images: HashMap<u32, Vec<u8>>,
+    crcs: bool,
+    /// The bytes of the frame being parsed so far, to check its CRC
+    frame: Vec<u8>,
+    /// A frame did not match its CRC, and no frame has matched since
+    searching: bool,
+    /// Frames dropped because they did not match their CRC, since `take_corrupted`
+    corrupted: usize,
+    sequences: bool,
+    sequence: Option<u32>,
+    /// The sequence number the next frame should have, `None` until a frame arrived
+    next_sequence: Option<u8>,
+    /// Frames missing from the sequence numbers, since `take_missed`
+    missed: usize,
 }
 
 impl Parser {
@@ -27,26 +344,126 @@ impl Parser {
             data_size: None,
             sym: None,
             typ: None,
+            timestamps: false,
+            timestamp: None,
+            tasks: false,
+            task: None,
+            deltas: false,
+            delta: false,
+            images: HashMap::new(),
+            crcs: false,
+            frame: Vec::new(),
+            searching: false,
+            corrupted: 0,
+            sequences: false,
+            sequence: None,
+            next_sequence: None,
+            missed: 0,
         }
     }
 
+    /// Create a parser for frames with a timestamp after the type string address, as written
+    /// with the `timestamp` feature of `log0_target`
+    pub fn with_timestamps() -> Self {
+        Parser {
+            timestamps: true,
+            ..Parser::new()
+        }
+    }
+
+    /// Also expect a task ID after the timestamp, as written with the `task` feature of
+    /// `log0_target`
+    pub fn with_tasks(self) -> Self {
+        Parser {
+            tasks: true,
+            ..self
+        }
+    }
+
+    /// Expect the lowest bit of the frame size to mark frames with only the changed bytes, as
+    /// written with the `delta` feature of `log0_target`, and rebuild the whole values
+    pub fn with_deltas(self) -> Self {
+        Parser {
+            deltas: true,
+            ..self
+        }
+    }
+
+    /// Expect a CRC-16 after each frame, as written with the `crc` feature of `log0_target`, and
+    /// drop the frames that do not match it
+    ///
+    /// After a corrupted frame the parser looks for the next frame that matches from the byte
+    /// after the start of the dropped one, as its size may have been corrupted too. Until one
+    /// matches, frames that are not all in the pushed data are dropped too instead of waited
+    /// for.
+    pub fn with_crcs(self) -> Self {
+        Parser { crcs: true, ..self }
+    }
+
+    /// Expect a sequence number after the task ID, as written with the `sequence` feature of
+    /// `log0_target`, and count the frames missing from it
+    pub fn with_sequences(self) -> Self {
+        Parser {
+            sequences: true,
+            ..self
+        }
+    }
+
+    /// The number of frames missing from the sequence numbers since the last call, corrupted
+    /// ones included
+    ///
+    /// The numbers are 8 bits, a gap of 256 frames or more is counted modulo 256. After `reset`
+    /// the next frame starts over, as the data skipped there is reported by whoever skipped it.
+    pub fn take_missed(&mut self) -> usize {
+        mem::take(&mut self.missed)
+    }
+
+    /// The number of corrupted frames dropped since the last call, a run of them while looking
+    /// for the next good frame counts as one
+    pub fn take_corrupted(&mut self) -> usize {
+        mem::take(&mut self.corrupted)
+    }
+
     /// Push a slice of data into the parser
     pub fn push(&mut self, data: &[u8]) {
         self.buf.extend(data.iter());
     }
 
+    /// Drop all buffered data, including any partially parsed frame
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.data_size = None;
+        self.sym = None;
+        self.typ = None;
+        self.timestamp = None;
+        self.task = None;
+        self.sequence = None;
+        self.next_sequence = None;
+        // The next changes may be relative to frames that were dropped
+        self.images.clear();
+        self.frame.clear();
+        self.searching = false;
+    }
+
     /// Try to decode a LEB128 encoded u32 from the queue
     fn try_leb128(&mut self) -> Option<u32> {
         let slices = self.buf.as_slices();
         let data_iter = slices.0.iter().chain(slices.1.iter());
 
         if let Ok((val, len_used)) = leb128::decode_u32(data_iter) {
-            for _ in 0..len_used {
-                self.buf.pop_front();
+            for byte in self.buf.drain(..len_used) {
+                if self.crcs {
+                    self.frame.push(byte);
+                }
             }
 
             Some(val)
         } else {
+            if self.buf.len() >= leb128::MAX_LEN_U32 {
+                // Not a valid encoding, drop it so the parser does not get stuck here
+                self.buf.drain(..leb128::MAX_LEN_U32);
+            }
+
             None
         }
     }
@@ -54,30 +471,90 @@ impl Parser {
     /// Try to parse the existing buffer
     pub fn try_parse(&mut self) -> Option<Packet> {
         loop {
-            match (self.data_size, self.sym, self.typ) {
-                (None, _, _) => {
-                    self.data_size = Some(self.try_leb128()? as usize);
+            match (
+                self.data_size,
+                self.sym,
+                self.typ,
+                self.timestamp,
+                self.task,
+                self.sequence,
+            ) {
+                (None, _, _, _, _, _) => {
+                    let size = self.try_leb128()?;
+                    let data_size = if self.deltas {
+                        self.delta = size & 1 != 0;
+                        (size >> 1) as usize
+                    } else {
+                        size as usize
+                    };
+                    if self.crcs && data_size > MAX_FRAME {
+                        let frame = mem::take(&mut self.frame);
+                        self.reject(frame);
+                        continue;
+                    }
+                    self.data_size = Some(data_size);
                 }
-                (Some(_), None, _) => {
+                (Some(_), None, _, _, _, _) => {
                     self.sym = Some(self.try_leb128()?);
                 }
-                (Some(_), Some(_), None) => {
+                (Some(_), Some(_), None, _, _, _) => {
                     self.typ = Some(self.try_leb128()?);
                 }
-                (Some(data_size), Some(sym), Some(typ)) => {
-                    // Wait for the data payload
-                    if self.buf.len() >= data_size {
+                (Some(_), Some(_), Some(_), None, _, _) if self.timestamps => {
+                    self.timestamp = Some(self.try_leb128()?);
+                }
+                (Some(_), Some(_), Some(_), _, None, _) if self.tasks => {
+                    self.task = Some(self.try_leb128()?);
+                }
+                (Some(_), Some(_), Some(_), _, _, None) if self.sequences => {
+                    self.sequence = Some(self.try_leb128()?);
+                }
+                (Some(data_size), Some(sym), Some(typ), timestamp, task, sequence) => {
+                    // Wait for the data payload, and the CRC after it
+                    let crc_len = if self.crcs { crc::LEN } else { 0 };
+                    if self.buf.len() >= data_size + crc_len {
                         let buf = self.buf.drain(..data_size).collect::<Vec<_>>();
 
                         self.data_size = None;
                         self.sym = None;
                         self.typ = None;
+                        self.timestamp = None;
+                        self.task = None;
+                        self.sequence = None;
+
+                        if self.crcs && !self.check_crc(&buf) {
+                            continue;
+                        }
+                        if let Some(sequence) = sequence {
+                            self.follow_sequence(sym, sequence as u8);
+                        }
+
+                        let buf = if self.delta {
+                            match self.apply_delta(sym, &buf) {
+                                Some(buf) => buf,
+                                // Changes to a value that was not received, wait for the next
+                                // whole one
+                                None => continue,
+                            }
+                        } else {
+                            if self.deltas {
+                                self.images.insert(sym, buf.clone());
+                            }
+                            buf
+                        };
 
                         return Some(Packet {
                             string_loc: sym as usize,
                             type_loc: typ as usize,
+                            timestamp,
+                            task,
                             buffer: buf,
                         });
+                    } else if self.searching {
+                        // Looking for the next frame, one that is not all there yet is not
+                        // waited for, as its size may be anything
+                        let frame = mem::take(&mut self.frame);
+                        self.reject(frame);
                     } else {
                         return None;
                     }
@@ -85,4 +562,87 @@ impl Parser {
             }
         }
     }
+
+    /// Count the frames missing before the one with `sequence`, the boot frame starts over at 0
+    fn follow_sequence(&mut self, sym: u32, sequence: u8) {
+        if let Some(next) = self.next_sequence {
+            if sym as usize != BOOT_FRAME {
+                self.missed += sequence.wrapping_sub(next) as usize;
+            }
+        }
+        self.next_sequence = Some(sequence.wrapping_add(1));
+    }
+
+    /// Check the CRC after `data` against the frame, drop the frame if it does not match
+    fn check_crc(&mut self, data: &[u8]) -> bool {
+        let received: Vec<_> = self.buf.drain(..crc::LEN).collect();
+        let mut frame = mem::take(&mut self.frame);
+        frame.extend_from_slice(data);
+
+        if crc::crc16(frame.iter().copied()).to_le_bytes() == received.as_slice() {
+            self.searching = false;
+            return true;
+        }
+
+        frame.extend(received);
+        self.reject(frame);
+        false
+    }
+
+    /// Count a corrupted frame, and parse again from the byte after its start
+    fn reject(&mut self, frame: Vec<u8>) {
+        self.data_size = None;
+        self.sym = None;
+        self.typ = None;
+        self.timestamp = None;
+        self.task = None;
+        self.sequence = None;
+        if !self.searching {
+            self.corrupted += 1;
+            self.searching = true;
+        }
+
+        for byte in frame.into_iter().skip(1).rev() {
+            self.buf.push_front(byte);
+        }
+    }
+
+    /// Apply the changes in `delta` to the last value of the call site `sym`, and return the
+    /// new value
+    fn apply_delta(&mut self, sym: u32, delta: &[u8]) -> Option<Vec<u8>> {
+        let image = self.images.get_mut(&sym)?;
+
+        match apply_delta(image, delta) {
+            Some(()) => Some(image.clone()),
+            None => {
+                // Does not fit the value, it cannot be trusted anymore
+                self.images.remove(&sym);
+                None
+            }
+        }
+    }
+}
+
+/// Apply changes to `image`, as runs of the LEB128 encoded count of unchanged bytes to skip,
+/// the LEB128 encoded length and the new bytes
+fn apply_delta(image: &mut [u8], delta: &[u8]) -> Option<()> {
+    let mut rest = delta;
+    let mut pos = 0usize;
+
+    while !rest.is_empty() {
+        let (skip, used) = leb128::decode_u32(rest.iter()).ok()?;
+        rest = &rest[used..];
+        let (len, used) = leb128::decode_u32(rest.iter()).ok()?;
+        rest = &rest[used..];
+
+        pos = pos.checked_add(skip as usize)?;
+        let end = pos.checked_add(len as usize)?;
+        image
+            .get_mut(pos..end)?
+            .copy_from_slice(rest.get(..len as usize)?);
+        rest = &rest[len as usize..];
+        pos = end;
+    }
+
+    Some(())
 }
@@ -0,0 +1,568 @@
+use crate::{
+    control::{Call, Method},
+    decoder::Decoder,
+    expect::Runner,
+    itm::ItmDecoder,
+    live::{Action, Live},
+    parser::{check_wire_version, Packet, Panic, Parser},
+    reconnect::Policy,
+    record::{Level, Record, World},
+    sink::Sink,
+    stats::Stats,
+    timeline::{Source, Timeline},
+    trigger::{self, Action as TriggerAction, Triggers},
+    until::{Outcome, Until},
+};
+use anyhow::{anyhow, bail, Result};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Number of reads that can be waiting for the decode thread, the probe thread blocks once it
+/// is this far ahead
+pub const CAPACITY: usize = 1024;
+
+/// How often the decode thread checks for key presses and the timeout when no data arrives
+const TICK: Duration = Duration::from_millis(10);
+
+/// What the probe thread hands to the decode thread
+#[derive(Debug, PartialEq, Eq)]
+pub enum Chunk {
+    /// Bytes read from the ring buffer, and when they were read
+    Data(Vec<u8>, SystemTime),
+    /// The cursors were out of range and unread data was skipped, see `Poll::Resync`
+    Resync,
+    /// The connection was lost, any partially parsed frame is gone with it
+    Reset,
+    /// The target dropped new frames, see `Poll::Dropped`
+    Dropped(u32),
+    /// The target overwrote old frames and unread data was skipped, see `Poll::Overrun`
+    Overrun { frames: u32, skipped: usize },
+    /// A chunk from the ring of the secure image, see `Secure`
+    Secure(Box<Chunk>),
+    /// A chunk from the ring for `warn!` and `error!`, see `Pipeline::priority`
+    Priority(Box<Chunk>),
+    /// A chunk from the ring of the core with this index, see `Pipeline::cores`
+    Core(usize, Box<Chunk>),
+    /// The target marked its ring as panicked, the panic frame may not have fit
+    Panicked,
+    /// The most bytes that were in the main ring at once, of the `capacity` of its buffer
+    HighWater { bytes: usize, capacity: usize },
+}
+
+impl Chunk {
+    /// New bytes arrived, from either ring
+    pub fn has_data(&self) -> bool {
+        match self {
+            Chunk::Data(..) => true,
+            Chunk::Secure(chunk) | Chunk::Priority(chunk) | Chunk::Core(_, chunk) => {
+                chunk.has_data()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The ring of the secure image on a TrustZone target, drained next to the one of the
+/// non-secure image, with its own strings and types
+pub struct Secure<'a> {
+    pub parser: Parser,
+    pub decoder: Decoder<'a>,
+}
+
+/// ITM events from a SWO capture, shown between the messages by target time
+pub struct Itm {
+    /// Bytes from `itm::spawn_reader`
+    pub data: Receiver<Vec<u8>>,
+    pub decoder: ItmDecoder,
+    pub timeline: Timeline,
+}
+
+/// The decode side of the host, parses and decodes what the probe thread read, and shows it
+///
+/// Running it on its own thread means slow rendering or sinks never hold up draining the ring
+/// buffer, which is what causes drops on the target.
+pub struct Pipeline<'a, S: Sink> {
+    pub parser: Parser,
+    pub decoder: Decoder<'a>,
+    pub sink: S,
+    pub stats: Stats,
+    pub live: Live,
+    pub until: Until,
+    pub runner: Option<Runner>,
+    pub outcome: Option<Outcome>,
+    pub resyncs: usize,
+    /// What to do on a resync, `Policy::Retry` allows that many in total
+    pub on_parse_error: Policy,
+    pub started: Instant,
+    /// Cleared to stop the probe thread, when a run ends or `q` is pressed
+    pub running: Arc<AtomicBool>,
+    /// Set to have the probe thread print a backtrace, when `b` is pressed
+    pub backtrace: Arc<AtomicBool>,
+    pub itm: Option<Itm>,
+    pub secure: Option<Secure<'a>>,
+    /// Parser for the ring of warnings and errors, on targets built with the `priority`
+    /// feature, its frames are decoded like the ones of the main ring
+    pub priority: Option<Parser>,
+    /// Parsers for the rings of the other cores, on targets built with the `dual-core` feature,
+    /// the one of core `n` at `n - 1`. Their frames are decoded like the ones of the main ring,
+    /// which is the one of core 0.
+    pub cores: Vec<Parser>,
+    pub triggers: Triggers,
+    /// Where the probe thread picks up triggered actions that need the target, `None` when
+    /// decoding frames from a file
+    pub target_actions: Option<Sender<TriggerAction>>,
+}
+
+/// A ring the target writes frames to, each has a parser of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ring {
+    Main,
+    Secure,
+    Priority,
+    Core(usize),
+}
+
+/// A frame of a ring, parsed and decoded but not handled yet
+enum Frame {
+    Record(Box<Record>),
+    Panic(Panic),
+    Fault(Vec<String>),
+}
+
+/// Check the wire version of the boot frame, returns `false` for other frames
+fn boot(packet: &Packet) -> Result<bool> {
+    match packet.boot_version() {
+        Some(version) => {
+            check_wire_version(version)?;
+            log::debug!("Target booted, wire format version {}", version);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Count the frames `parser` dropped because they did not match their CRC, and the ones missing
+/// from the sequence numbers
+fn corrupted(parser: &mut Parser, stats: &mut Stats) {
+    let frames = parser.take_corrupted();
+    if frames > 0 {
+        stats.corrupted(frames);
+        log::warn!(
+            "Dropped {} corrupted frames, they did not match their CRC",
+            frames
+        );
+    }
+
+    let frames = parser.take_missed();
+    if frames > 0 {
+        stats.missed(frames);
+        log::warn!(
+            "Missed {} frames, their sequence numbers were skipped",
+            frames
+        );
+    }
+}
+
+impl<'a, S: Sink> Pipeline<'a, S> {
+    /// A pipeline for the main ring only, that shows everything until the probe thread hangs up
+    pub fn new(parser: Parser, decoder: Decoder<'a>, sink: S) -> Self {
+        Pipeline {
+            parser,
+            decoder,
+            sink,
+            stats: Stats::new(),
+            live: Live::new(),
+            until: Until::new(None, None, None, Instant::now()),
+            runner: None,
+            outcome: None,
+            resyncs: 0,
+            on_parse_error: Policy::Warn,
+            started: Instant::now(),
+            running: Arc::new(AtomicBool::new(true)),
+            backtrace: Arc::new(AtomicBool::new(false)),
+            itm: None,
+            secure: None,
+            priority: None,
+            cores: vec![],
+            triggers: Triggers::default(),
+            target_actions: None,
+        }
+    }
+
+    /// Handle chunks, key presses and requests from the control socket until the probe thread
+    /// hangs up, then finish the sink
+    pub fn run(
+        mut self,
+        chunks: Receiver<Chunk>,
+        keys: Option<Receiver<u8>>,
+        control: Option<Receiver<Call>>,
+    ) -> Result<Self> {
+        loop {
+            match chunks.recv_timeout(TICK) {
+                Ok(chunk) => {
+                    self.chunk(chunk)?;
+                    // Render everything that is already there before flushing
+                    for chunk in chunks.try_iter() {
+                        self.chunk(chunk)?;
+                    }
+                    self.sink.flush()?;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            self.itm()?;
+
+            for key in keys.iter().flat_map(|keys| keys.try_iter()) {
+                self.key(key)?;
+            }
+
+            for call in control.iter().flat_map(|control| control.try_iter()) {
+                self.call(call);
+            }
+
+            if self.outcome.is_none() {
+                self.outcome = self.until.expired(Instant::now());
+            }
+            if self.outcome.is_some() {
+                self.running.store(false, Ordering::SeqCst);
+            }
+        }
+
+        if let Some(itm) = &mut self.itm {
+            for record in itm.timeline.drain() {
+                self.live.show(record, &mut self.sink)?;
+            }
+        }
+        self.sink.finish()?;
+
+        Ok(self)
+    }
+
+    pub fn chunk(&mut self, chunk: Chunk) -> Result<()> {
+        match chunk {
+            Chunk::Data(..) | Chunk::Resync | Chunk::Dropped(_) | Chunk::Overrun { .. } => {
+                self.ring(Ring::Main, chunk)?
+            }
+            Chunk::Reset => {
+                self.parser.reset();
+                if let Some(secure) = &mut self.secure {
+                    secure.parser.reset();
+                }
+                if let Some(parser) = &mut self.priority {
+                    parser.reset();
+                }
+                for parser in &mut self.cores {
+                    parser.reset();
+                }
+            }
+            Chunk::Secure(chunk) => self.ring(Ring::Secure, *chunk)?,
+            Chunk::Priority(chunk) => self.ring(Ring::Priority, *chunk)?,
+            Chunk::Core(core, chunk) => self.ring(Ring::Core(core), *chunk)?,
+            Chunk::Panicked => self.panicked(None, None)?,
+            Chunk::HighWater { bytes, capacity } => self.stats.high_water(bytes, capacity),
+        }
+
+        Ok(())
+    }
+
+    /// Handle a chunk from `ring`, its frames go through the parser and decoder of the ring
+    fn ring(&mut self, ring: Ring, chunk: Chunk) -> Result<()> {
+        let Pipeline {
+            parser,
+            decoder,
+            secure,
+            priority,
+            cores,
+            stats,
+            ..
+        } = self;
+        let (parser, decoder) = match ring {
+            Ring::Main => (parser, decoder),
+            Ring::Secure => match secure {
+                Some(secure) => (&mut secure.parser, &mut secure.decoder),
+                None => return Ok(()),
+            },
+            // `report_fault` writes to the priority ring when there is one
+            Ring::Priority => match priority {
+                Some(parser) => (parser, decoder),
+                None => return Ok(()),
+            },
+            Ring::Core(core) => match core.checked_sub(1).and_then(|i| cores.get_mut(i)) {
+                Some(parser) => (parser, decoder),
+                None => return Ok(()),
+            },
+        };
+
+        match chunk {
+            Chunk::Data(read, arrival) => {
+                stats.received(read.len());
+                parser.push(&read);
+
+                // Handled once the parser is released, in the order they arrived
+                let mut frames = Vec::new();
+                while let Some(packet) = parser.try_parse() {
+                    if boot(&packet)? {
+                        continue;
+                    }
+                    if packet.is_heartbeat() {
+                        stats.heartbeat(Instant::now());
+                    } else if let Some(resources) = packet.resources() {
+                        stats.resources(resources);
+                    } else if let Some(panic) = packet.panic() {
+                        frames.push(Frame::Panic(panic));
+                    } else if let Some(fault) = packet.fault() {
+                        frames.push(Frame::Fault(fault.report(decoder.symbolizer())));
+                    } else {
+                        frames.push(Frame::Record(Box::new(decoder.decode(&packet, arrival))));
+                    }
+                }
+                corrupted(parser, stats);
+
+                let world = match ring {
+                    Ring::Secure => Some(World::Secure),
+                    _ => None,
+                };
+                // The main ring is the one of core 0
+                let core = match ring {
+                    Ring::Main if !self.cores.is_empty() => Some(0),
+                    Ring::Core(core) => Some(core),
+                    _ => None,
+                };
+                for frame in frames {
+                    match frame {
+                        Frame::Record(mut record) => {
+                            record.core = core;
+                            self.record(*record)?;
+                        }
+                        Frame::Panic(panic) => self.panicked(Some(panic), world)?,
+                        Frame::Fault(report) => self.faulted(report, world)?,
+                    }
+                }
+            }
+            Chunk::Resync => {
+                parser.reset();
+                self.resync()?;
+            }
+            Chunk::Dropped(frames) => self.lost(frames, 0),
+            Chunk::Overrun { frames, skipped } => {
+                parser.reset();
+                self.lost(frames, skipped);
+            }
+            _ => parser.reset(),
+        }
+
+        Ok(())
+    }
+
+    /// Show a panic of the target prominently and end the session, `None` if only the mark on
+    /// the ring was seen
+    ///
+    /// The mark is seen after the panic frame when it did fit, and is then ignored.
+    fn panicked(&mut self, panic: Option<Panic>, world: Option<World>) -> Result<()> {
+        if self.outcome == Some(Outcome::Panicked) {
+            return Ok(());
+        }
+
+        let message = match panic {
+            Some(panic) => format!("Target {}", panic),
+            None => "Target panicked, the panic frame did not fit in the ring buffer".into(),
+        };
+        eprintln!();
+        eprintln!("!!! {}", message);
+        eprintln!();
+        self.crashed(message, "panic", world, Outcome::Panicked)
+    }
+
+    /// Show a HardFault of the target prominently, the summary line of `report` first, and end
+    /// the session
+    fn faulted(&mut self, report: Vec<String>, world: Option<World>) -> Result<()> {
+        let mut lines = report.into_iter();
+        let message = format!("Target {}", lines.next().unwrap_or_default());
+        eprintln!();
+        eprintln!("!!! {}", message);
+        for line in lines {
+            eprintln!("    {}", line);
+        }
+        eprintln!();
+        self.crashed(message, "fault", world, Outcome::Faulted)
+    }
+
+    /// Record why the target stopped as an error and end the session with `outcome`
+    fn crashed(
+        &mut self,
+        message: String,
+        module: &str,
+        world: Option<World>,
+        outcome: Outcome,
+    ) -> Result<()> {
+        self.record(Record {
+            world,
+            level: Some(Level::Error),
+            message,
+            module: Some(module.into()),
+            ..Record::default()
+        })?;
+
+        self.outcome = Some(outcome);
+        self.running.store(false, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Report frames the target lost because the ring buffer was full
+    fn lost(&mut self, frames: u32, skipped: usize) {
+        self.stats.lost(frames);
+        if skipped > 0 {
+            log::warn!(
+                "The target overwrote {} frames, skipped {} unread bytes to resynchronize",
+                frames,
+                skipped
+            );
+        } else {
+            log::warn!(
+                "The target lost {} frames, the ring buffer was full",
+                frames
+            );
+        }
+    }
+
+    /// Count a resync, and end the session if that is the policy
+    fn resync(&mut self) -> Result<()> {
+        self.resyncs += 1;
+        match self.on_parse_error {
+            Policy::Abort => bail!("Cursors out of range, unread data was lost"),
+            Policy::Retry(retries) if self.resyncs > retries as usize => {
+                bail!("Cursors out of range {} times, giving up", self.resyncs)
+            }
+            _ => log::warn!("Cursors out of range, resynchronizing ..."),
+        }
+
+        Ok(())
+    }
+
+    /// Check a decoded record against the run conditions and show it
+    fn record(&mut self, record: Record) -> Result<()> {
+        self.stats.record(&record);
+        if self.outcome.is_none() {
+            self.outcome = self.until.check(&record.message);
+        }
+        if let Some(runner) = &mut self.runner {
+            runner.observe(&record.message, self.started.elapsed());
+            if runner.passed() {
+                self.running.store(false, Ordering::SeqCst);
+            }
+        }
+        for action in self.triggers.check(&record.message) {
+            log::info!("Triggered {:?} by {:?}", action, record.message);
+            match (action, &self.target_actions) {
+                (TriggerAction::Run(command), _) => trigger::run(command, &record)?,
+                (action, Some(target_actions)) => {
+                    target_actions.send(action.clone()).ok();
+                }
+                (action, None) => log::warn!("Not connected to a target for {:?}", action),
+            }
+        }
+
+        self.show(Source::Log, record)
+    }
+
+    /// Decode what arrived from the ITM, and show the records that are due
+    fn itm(&mut self) -> Result<()> {
+        let itm = match &mut self.itm {
+            Some(itm) => itm,
+            None => return Ok(()),
+        };
+
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        for data in itm.data.try_iter() {
+            for record in itm.decoder.push(&data) {
+                ready.extend(itm.timeline.push(Source::Itm, record, now));
+            }
+        }
+        ready.extend(itm.timeline.release(now));
+
+        if !ready.is_empty() {
+            for record in ready {
+                self.live.show(record, &mut self.sink)?;
+            }
+            self.sink.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Show a record, after the earlier ITM events if they are merged
+    fn show(&mut self, source: Source, record: Record) -> Result<()> {
+        let ready = match &mut self.itm {
+            Some(itm) => itm.timeline.push(source, record, Instant::now()),
+            None => vec![record],
+        };
+        for record in ready {
+            self.live.show(record, &mut self.sink)?;
+        }
+
+        Ok(())
+    }
+
+    fn key(&mut self, key: u8) -> Result<()> {
+        match self.live.key(key, &mut self.sink, &mut std::io::stderr())? {
+            Some(Action::Summary) => {
+                self.stats.write_summary(
+                    &mut std::io::stderr(),
+                    self.started.elapsed(),
+                    self.resyncs,
+                )?;
+            }
+            Some(Action::Resources) => self.stats.write_resources(&mut std::io::stderr())?,
+            Some(Action::Backtrace) => self.backtrace.store(true, Ordering::SeqCst),
+            Some(Action::Quit) => self.running.store(false, Ordering::SeqCst),
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Handle a request from the control socket, the ones that need the target go to the probe
+    /// thread instead
+    fn call(&mut self, call: Call) {
+        let result = match &call.method {
+            Method::Start => self
+                .live
+                .set_paused(false, &mut self.sink)
+                .map(|_| Value::Null),
+            Method::Stop => self
+                .live
+                .set_paused(true, &mut self.sink)
+                .map(|_| Value::Null),
+            Method::Filter(filter) => self
+                .live
+                .set_filter(filter.clone(), &mut self.sink)
+                .map(|_| Value::Null),
+            Method::Stats => self.stats_json(),
+            Method::Quit => {
+                self.running.store(false, Ordering::SeqCst);
+                Ok(Value::Null)
+            }
+            Method::Dump(_) => Err(anyhow!("{:?} needs the target", call.method)),
+        };
+
+        call.reply(result);
+    }
+
+    /// The session summary so far, as `s` prints it
+    fn stats_json(&self) -> Result<Value> {
+        let mut stats = serde_json::to_value(&self.stats)?;
+        stats["duration"] = json!(self.started.elapsed().as_secs_f64());
+        stats["resyncs"] = json!(self.resyncs);
+        if let Some(health) = self.stats.health(Instant::now()) {
+            stats["health"] = json!(health);
+        }
+
+        Ok(stats)
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Result};
+use probe_rs::{DebugProbeInfo, DebugProbeSelector};
+
+/// Most names suggested for an unknown chip
+const SUGGESTIONS: usize = 5;
+
+/// `--chip` value that has probe-rs detect the chip
+pub const AUTO: &str = "auto";
+
+/// Where the unique ID is in the memory of a chip family, by the start of the probe-rs names,
+/// and its length in bytes
+const UNIQUE_IDS: &[(&str, u32, usize)] = &[
+    ("nrf51", 0x1000_0060, 8),
+    ("nrf52", 0x1000_0060, 8),
+    ("stm32f0", 0x1fff_f7ac, 12),
+    ("stm32f1", 0x1fff_f7e8, 12),
+    ("stm32f3", 0x1fff_f7ac, 12),
+    ("stm32f4", 0x1fff_7a10, 12),
+    ("stm32f7", 0x1ff0_f420, 12),
+    ("stm32g0", 0x1fff_7590, 12),
+    ("stm32g4", 0x1fff_7590, 12),
+    ("stm32h7", 0x1ff1_e800, 12),
+    ("stm32l4", 0x1fff_7590, 12),
+    ("stm32wb", 0x1fff_7590, 12),
+];
+
+/// Clocks to try in turn when the probe rejects the one asked for, in kHz
+pub const FALLBACK_SPEEDS: &[u32] = &[12_000, 4_000, 1_000, 100];
+
+/// Names of the chips probe-rs has targets for
+pub fn chip_names() -> Result<Vec<String>> {
+    let families = probe_rs::config::registry::families()
+        .map_err(|e| anyhow!("Failed to read the probe-rs targets: {}", e))?;
+
+    Ok(families
+        .iter()
+        .flat_map(|family| family.variants.iter().map(|chip| chip.name.to_string()))
+        .collect())
+}
+
+/// Check that `chip` names one of `names`, or is the start of one as probe-rs takes it, or is
+/// `auto`, the error lists the closest names
+pub fn check_chip(chip: &str, names: &[String]) -> Result<()> {
+    let lower = chip.to_ascii_lowercase();
+    if lower == AUTO
+        || names
+            .iter()
+            .any(|name| name.to_ascii_lowercase().starts_with(&lower))
+    {
+        return Ok(());
+    }
+
+    let matches = close_matches(chip, names);
+    if matches.is_empty() {
+        Err(anyhow!(
+            "Unknown chip {:?}, probe-rs has targets for {} chips",
+            chip,
+            names.len()
+        ))
+    } else {
+        Err(anyhow!(
+            "Unknown chip {:?}, did you mean {}?",
+            chip,
+            matches.join(", ")
+        ))
+    }
+}
+
+/// Address and length of the unique ID of the chip called `chip`, for the families that have
+/// one at a known place
+pub fn unique_id(chip: &str) -> Option<(u32, usize)> {
+    let chip = chip.to_ascii_lowercase();
+    UNIQUE_IDS
+        .iter()
+        .find(|(family, ..)| chip.starts_with(family))
+        .map(|&(_, address, len)| (address, len))
+}
+
+/// The names in `names` closest to `chip`, the closest first
+///
+/// Names are compared without case, and only as far as `chip` goes, so a name that starts with
+/// a near miss of `chip` matches as well as one that is a near miss of all of it.
+pub fn close_matches<'a>(chip: &str, names: &'a [String]) -> Vec<&'a str> {
+    let chip: Vec<_> = chip.to_ascii_lowercase().chars().collect();
+    // A typo or two, more for longer names
+    let max = (chip.len() / 4).max(1);
+
+    let mut scored: Vec<_> = names
+        .iter()
+        .filter_map(|name| {
+            let prefix: Vec<_> = name.to_ascii_lowercase().chars().take(chip.len()).collect();
+            let distance = edit_distance(&chip, &prefix);
+            Some((distance, name.as_str())).filter(|_| distance <= max)
+        })
+        .collect();
+    scored.sort_unstable();
+    scored.dedup_by_key(|(_, name)| *name);
+
+    scored
+        .into_iter()
+        .take(SUGGESTIONS)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// Levenshtein distance, the insertions, deletions and substitutions that turn `a` into `b`
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<_> = (0..=b.len()).collect();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// The probe to open out of the connected ones
+#[derive(Debug)]
+pub enum Selection<'a> {
+    One(&'a DebugProbeInfo),
+    /// More than one probe matches, the user has to pick one
+    Ambiguous(Vec<&'a DebugProbeInfo>),
+}
+
+/// Pick the probe to open, out of the ones matching `selector`, or all of them without one, by
+/// its `index` among those
+pub fn select<'a>(
+    probes: &'a [DebugProbeInfo],
+    selector: Option<&DebugProbeSelector>,
+    index: Option<usize>,
+) -> Result<Selection<'a>> {
+    if probes.is_empty() {
+        return Err(anyhow!("No debug probe found"));
+    }
+
+    let mut matching: Vec<_> = probes
+        .iter()
+        .filter(|probe| selector.is_none_or(|selector| matches(selector, probe)))
+        .collect();
+    if let Some(selector) = selector.filter(|_| matching.is_empty()) {
+        return Err(anyhow!(
+            "No probe matches {:04x}:{:04x}{}, found\n{}",
+            selector.vendor_id,
+            selector.product_id,
+            selector
+                .serial_number
+                .as_ref()
+                .map_or(String::new(), |serial| format!(":{}", serial)),
+            list(probes.iter())
+        ));
+    }
+
+    match index {
+        Some(index) if index < matching.len() => Ok(Selection::One(matching.swap_remove(index))),
+        Some(index) => Err(anyhow!(
+            "No probe {}, the {} found are\n{}",
+            index,
+            matching.len(),
+            list(matching.into_iter())
+        )),
+        None if matching.len() == 1 => Ok(Selection::One(matching[0])),
+        None => Ok(Selection::Ambiguous(matching)),
+    }
+}
+
+/// Does `probe` have the VID and PID of `selector`, and its serial number if it has one
+pub fn matches(selector: &DebugProbeSelector, probe: &DebugProbeInfo) -> bool {
+    selector.vendor_id == probe.vendor_id
+        && selector.product_id == probe.product_id
+        && selector
+            .serial_number
+            .as_ref()
+            .is_none_or(|serial| probe.serial_number.as_ref() == Some(serial))
+}
+
+/// `VID:PID[:serial]` as `--probe` takes it, followed by the name of the probe
+pub fn describe(probe: &DebugProbeInfo) -> String {
+    let mut text = format!("{:04x}:{:04x}", probe.vendor_id, probe.product_id);
+    if let Some(serial) = &probe.serial_number {
+        text.push(':');
+        text.push_str(serial);
+    }
+    text.push(' ');
+    text.push_str(&probe.identifier);
+
+    text
+}
+
+/// One numbered line per probe
+pub fn list<'a>(probes: impl Iterator<Item = &'a DebugProbeInfo>) -> String {
+    probes
+        .enumerate()
+        .map(|(i, probe)| format!("  [{}] {}", i, describe(probe)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The clocks to try for a requested `speed`, first the one itself, then the fallbacks below it
+pub fn speeds(speed: u32) -> impl Iterator<Item = u32> {
+    std::iter::once(speed).chain(
+        FALLBACK_SPEEDS
+            .iter()
+            .copied()
+            .filter(move |&fallback| fallback < speed),
+    )
+}
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use probe_rs::{Core, MemoryInterface};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
+/// `DWT_PCSR`, samples the PC without halting the core
+const PCSR: u32 = 0xe000_101c;
+/// `DEMCR`, with the `TRCENA` bit that enables the DWT
+const DEMCR: u32 = 0xe000_edfc;
+const TRCENA: u32 = 1 << 24;
+
+/// What `PCSR` reads while the core is halted or in reset
+const NO_SAMPLE: u32 = 0xffff_ffff;
+
+/// Reads the PC of a running core
+///
+/// The DWT PC sample register is used if the core has one, otherwise the core is halted for
+/// each sample, which slows it down.
+pub struct Sampler<'a, 'probe> {
+    core: &'a mut Core<'probe>,
+    pcsr: bool,
+}
+
+impl<'a, 'probe> Sampler<'a, 'probe> {
+    pub fn new(core: &'a mut Core<'probe>) -> Result<Self> {
+        let demcr = core.read_word_32(DEMCR)?;
+        core.write_word_32(DEMCR, demcr | TRCENA)?;
+
+        // Reads as zero if it is not implemented, e.g. on a Cortex-M0
+        let pcsr = core.read_word_32(PCSR)? != 0;
+
+        Ok(Sampler { core, pcsr })
+    }
+
+    /// The PC is sampled without halting the core
+    pub fn uses_pcsr(&self) -> bool {
+        self.pcsr
+    }
+
+    /// The PC, `None` if the core is halted or in reset
+    pub fn sample(&mut self) -> Result<Option<u32>> {
+        if self.pcsr {
+            let pc = self.core.read_word_32(PCSR)?;
+            return Ok(Some(pc).filter(|&pc| pc != NO_SAMPLE));
+        }
+
+        self.core.halt(Duration::from_millis(10))?;
+        let pc = self.core.read_core_reg(15u16);
+        self.core.run()?;
+
+        Ok(Some(pc?))
+    }
+}
+
+/// PC samples by address
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    samples: HashMap<u32, u64>,
+    /// Samples taken while the core was halted or in reset
+    missed: u64,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, pc: Option<u32>) {
+        match pc {
+            // The Thumb bit is not part of the address
+            Some(pc) => *self.samples.entry(pc & !1).or_default() += 1,
+            None => self.missed += 1,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.samples.values().sum::<u64>() + self.missed
+    }
+
+    /// Samples by the key of their address, e.g. the function, most first
+    ///
+    /// Addresses without a key are counted as `<unknown>`, and missed samples as `<halted>`.
+    pub fn group(&self, key: impl Fn(u32) -> Option<String>) -> Vec<(String, u64)> {
+        let mut groups: HashMap<String, u64> = HashMap::new();
+        for (&pc, &count) in &self.samples {
+            let key = key(pc).unwrap_or_else(|| "<unknown>".into());
+            *groups.entry(key).or_default() += count;
+        }
+        if self.missed > 0 {
+            groups.insert("<halted>".into(), self.missed);
+        }
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        groups
+    }
+
+    /// The `top` groups with their share of the samples
+    pub fn write_flat(
+        &self,
+        w: &mut impl Write,
+        groups: &[(String, u64)],
+        top: usize,
+    ) -> Result<()> {
+        let total = self.total().max(1);
+
+        writeln!(w, "{:>7} {:>9}  function", "%", "samples")?;
+        for (key, count) in groups.iter().take(top) {
+            writeln!(
+                w,
+                "{:>6.2}% {:>9}  {}",
+                *count as f64 * 100.0 / total as f64,
+                count,
+                key
+            )?;
+        }
+        if groups.len() > top {
+            let rest: u64 = groups[top..].iter().map(|(_, count)| count).sum();
+            writeln!(
+                w,
+                "{:>6.2}% {:>9}  ({} more)",
+                rest as f64 * 100.0 / total as f64,
+                rest,
+                groups.len() - top
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// One line per group with its sample count, the folded stacks read by flamegraph tools,
+    /// where `;` separates the frames of a key
+    pub fn write_folded(w: &mut impl Write, groups: &[(String, u64)]) -> Result<()> {
+        for (key, count) in groups {
+            writeln!(w, "{} {}", key, count)?;
+        }
+
+        Ok(())
+    }
+}
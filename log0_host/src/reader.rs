@@ -0,0 +1,232 @@
+use crate::{bytes_to_read, plan_read, transport::Transport};
+use anyhow::Result;
+
+/// Outcome of polling the ring buffer
+#[derive(Debug, PartialEq, Eq)]
+pub enum Poll<'a> {
+    /// No new data since the last poll
+    Idle,
+    /// Newly read bytes, in the order the target wrote them
+    Data(&'a [u8]),
+    /// The cursors were out of range and the unread data was skipped, any partially parsed
+    /// frame is lost
+    Resync,
+    /// The target dropped this many new frames because the buffer was full, what was read
+    /// before and after is intact
+    Dropped(u32),
+    /// The target overwrote this many of the oldest frames, possibly while they were being read,
+    /// so the `skipped` unread bytes were skipped and any partially parsed frame is lost
+    Overrun { frames: u32, skipped: usize },
+}
+
+/// The target's count of lost frames, see `Reader::with_dropped_count`
+#[derive(Debug)]
+struct Dropped {
+    /// Last value read, `None` before the first poll
+    seen: Option<u32>,
+    overwrite: bool,
+}
+
+/// Host side of the ring buffer, drains new data from the target through a `Transport`
+#[derive(Debug)]
+pub struct Reader {
+    buffer_size: usize,
+    read_buff: Vec<u8>,
+    resyncs: usize,
+    chunk: usize,
+    dropped: Option<Dropped>,
+    banks: bool,
+}
+
+impl Reader {
+    /// Create a reader for a ring buffer of `buffer_size` bytes
+    pub fn new(buffer_size: usize) -> Self {
+        Reader {
+            buffer_size,
+            read_buff: vec![0; buffer_size],
+            resyncs: 0,
+            chunk: buffer_size,
+            dropped: None,
+            banks: false,
+        }
+    }
+
+    /// Follow the count of frames the target lost because the buffer was full, and report
+    /// changes as `Poll::Dropped`, or as `Poll::Overrun` if the target `overwrite`s the oldest
+    /// frames
+    pub fn with_dropped_count(self, overwrite: bool) -> Self {
+        Reader {
+            dropped: Some(Dropped {
+                seen: None,
+                overwrite,
+            }),
+            ..self
+        }
+    }
+
+    /// Drain the buffer a bank at a time, for targets with the `double-buffer` feature
+    ///
+    /// The target cursor then tells which bank the target handed over and how much of it is
+    /// used, with a sequence number in its top 7 bits. The host cursor is the sequence number of
+    /// the last bank drained. Banks are read whole, the chunk size is not used.
+    pub fn with_banks(self) -> Self {
+        Reader {
+            banks: true,
+            ..self
+        }
+    }
+
+    /// Read at most `chunk` bytes per poll, writing the host cursor after each chunk frees the
+    /// space for the target sooner, at the cost of more transfers
+    ///
+    /// Not used for targets that overwrite the oldest frames, see `with_dropped_count`.
+    pub fn set_chunk(&mut self, chunk: usize) {
+        self.chunk = chunk.clamp(1, self.buffer_size);
+    }
+
+    /// Most bytes read per poll, the whole buffer unless set with `set_chunk`
+    pub fn chunk(&self) -> usize {
+        self.chunk
+    }
+
+    /// Number of times the reader had to skip data to get back in sync with the target
+    pub fn resyncs(&self) -> usize {
+        self.resyncs
+    }
+
+    /// Read all data the target has written since the last poll
+    ///
+    /// The host cursor is only advanced after the data has been read, so a failed transfer can
+    /// simply be retried by polling again.
+    pub fn poll<T: Transport>(&mut self, transport: &mut T) -> Result<Poll<'_>> {
+        if self.banks {
+            return self.poll_banks(transport);
+        }
+
+        let dropped = self.dropped(transport)?;
+        let [target, host] = transport.read_cursors()?;
+        let (target, host) = (target as usize, host as usize);
+
+        if target >= self.buffer_size || host >= self.buffer_size {
+            // Corrupted cursors, or the target is being reset. Skip whatever is in the buffer
+            // once the target cursor makes sense again.
+            self.resyncs += 1;
+            if target < self.buffer_size {
+                transport.write_host_cursor(target as u32)?;
+            }
+
+            return Ok(Poll::Resync);
+        }
+
+        if let Some(frames) = dropped {
+            return match self.overwrite() {
+                true => self.overrun(transport, frames, target, host),
+                false => Ok(Poll::Dropped(frames)),
+            };
+        }
+
+        if target == host {
+            return Ok(Poll::Idle);
+        }
+
+        let mut plan = plan_read(host, target, self.buffer_size);
+        // A target that overwrites frames starts from the host cursor, which has to stay at
+        // the start of a frame
+        if !self.overwrite() {
+            plan.limit(self.chunk, self.buffer_size);
+        }
+        let pivot = plan.first.len();
+        let read = &mut self.read_buff[0..plan.len()];
+
+        transport.read_buffer(plan.first.start as u32, &mut read[0..pivot])?;
+        if !plan.second.is_empty() {
+            // cursor will overflow
+            transport.read_buffer(0, &mut read[pivot..])?;
+        }
+        if self.overwrite() {
+            // What was read may have been overwritten while reading it
+            if let Some(frames) = self.dropped(transport)? {
+                return self.overrun(transport, frames, target, host);
+            }
+        }
+        transport.write_host_cursor(plan.new_host_idx as u32)?;
+
+        Ok(Poll::Data(&self.read_buff[0..plan.len()]))
+    }
+
+    /// Read the bank the target handed over, if it is not the one read last
+    fn poll_banks<T: Transport>(&mut self, transport: &mut T) -> Result<Poll<'_>> {
+        let dropped = self.dropped(transport)?;
+        let [ready, drained] = transport.read_cursors()?;
+        let seq = ready >> 25;
+        let bank = (ready >> 24) & 1;
+        let len = (ready & 0xff_ffff) as usize;
+        let half = self.buffer_size / 2;
+
+        if let Some(frames) = dropped {
+            return Ok(Poll::Dropped(frames));
+        }
+
+        if seq == drained {
+            return Ok(Poll::Idle);
+        }
+
+        if len == 0 || len > half {
+            // The target never hands over an empty bank, it is being reset or the cursor is
+            // corrupted. Taking the sequence number lets it swap again.
+            self.resyncs += 1;
+            transport.write_host_cursor(seq)?;
+
+            return Ok(Poll::Resync);
+        }
+
+        transport.read_buffer(bank * half as u32, &mut self.read_buff[0..len])?;
+        transport.write_host_cursor(seq)?;
+
+        Ok(Poll::Data(&self.read_buff[0..len]))
+    }
+
+    fn overwrite(&self) -> bool {
+        matches!(
+            self.dropped,
+            Some(Dropped {
+                overwrite: true,
+                ..
+            })
+        )
+    }
+
+    /// Frames lost since the last call, if the count is followed and changed
+    fn dropped<T: Transport>(&mut self, transport: &mut T) -> Result<Option<u32>> {
+        let dropped = match &mut self.dropped {
+            Some(dropped) => dropped,
+            None => return Ok(None),
+        };
+
+        let count = transport.read_dropped()?;
+        let frames = match dropped.seen.replace(count) {
+            // Goes back to 0 when the target is reset
+            Some(seen) if count > seen => count - seen,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(frames))
+    }
+
+    /// Skip to the target cursor, the only place known to be the start of a frame once the
+    /// target moved the host cursor on its own
+    fn overrun<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        frames: u32,
+        target: usize,
+        host: usize,
+    ) -> Result<Poll<'_>> {
+        transport.write_host_cursor(target as u32)?;
+
+        Ok(Poll::Overrun {
+            frames,
+            skipped: bytes_to_read(host, target, self.buffer_size),
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Error};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Longest delay between reconnect attempts
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// What to do about an error of one class: probe errors, flashing errors or parse errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// End the session with the error
+    Abort,
+    /// Print the error and carry on, retrying for as long as it takes
+    Warn,
+    /// Retry this many times in a row, then end the session with the error
+    Retry(u32),
+}
+
+impl Policy {
+    /// Delays between the retries this policy allows, starting with `initial`
+    pub fn backoff(self, initial: Duration) -> Backoff {
+        match self {
+            Policy::Abort => Backoff::new(0, initial),
+            Policy::Warn => Backoff::unlimited(initial),
+            Policy::Retry(retries) => Backoff::new(retries, initial),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = Error;
+
+    /// `abort`, `warn` or `retry:<n>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(Policy::Abort),
+            "warn" => Ok(Policy::Warn),
+            _ => match s.strip_prefix("retry:").map(str::parse) {
+                Some(Ok(retries)) => Ok(Policy::Retry(retries)),
+                _ => Err(anyhow!(
+                    "Unknown error policy {:?}, expected abort, warn or retry:<n>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// Delays between attempts to reconnect after the probe or target was lost, doubling after each
+/// failed attempt
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    retries: Option<u32>,
+    initial: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    /// Allow `retries` attempts in a row, starting with a delay of `initial`
+    pub fn new(retries: u32, initial: Duration) -> Self {
+        Backoff {
+            retries: Some(retries),
+            initial,
+            attempt: 0,
+        }
+    }
+
+    /// Never run out of attempts
+    pub fn unlimited(initial: Duration) -> Self {
+        Backoff {
+            retries: None,
+            initial,
+            attempt: 0,
+        }
+    }
+
+    /// Delay before the next attempt, `None` once all retries are used up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if matches!(self.retries, Some(retries) if self.attempt >= retries) {
+            return None;
+        }
+
+        let delay = self
+            .initial
+            .checked_mul(1 << self.attempt.min(16))
+            .map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY));
+        self.attempt = self.attempt.saturating_add(1);
+
+        Some(delay)
+    }
+
+    /// Number of attempts made since the connection was last working
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Number of attempts allowed in a row, `None` if there is no limit
+    pub fn retries(&self) -> Option<u32> {
+        self.retries
+    }
+
+    /// The connection works again, the next loss starts over with the initial delay
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
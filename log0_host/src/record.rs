@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A decoded frame, as handed to the sinks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Stable ID of the format string, see `Catalog`
+    pub id: Option<u32>,
+    /// When the frame was written, if the target sends timestamps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    /// Target seconds since boot, if the target sends timestamps and their frequency is known
+    #[serde(skip)]
+    pub seconds: Option<f64>,
+    /// Name of the task that wrote the frame, or its ID if it has no name, if the target sends
+    /// task IDs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    /// Security world of the image that wrote the frame, if both have a ring, see `World`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub world: Option<World>,
+    /// Index of the core that wrote the frame, on targets with a ring for each core
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub core: Option<usize>,
+    /// Set on messages of `warn!` and `error!`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub level: Option<Level>,
+    pub message: String,
+    /// Path of the function with the `log!` call, if found in the DWARF
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    /// Set on the records of `log_kv!`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub event: Option<Event>,
+    /// Set on the records of `span_enter!` and `span_exit!`
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub span: Option<Span>,
+    /// Set on the frames of `write_raw`, the raw channel they were written to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub channel: Option<u8>,
+    /// Set on the marker that closes a burst of duplicates, see `sink::Collapse`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeated: Option<Repeated>,
+    /// The format string of the message, if it has one
+    #[serde(skip)]
+    pub format: Option<String>,
+    /// The decoded values, one for each placeholder of the format string, or the one value of
+    /// a message without placeholders
+    #[serde(skip)]
+    pub args: Vec<String>,
+    /// Numeric fields of the value by path, for the telemetry sinks, see `Type::values`
+    #[serde(skip)]
+    pub values: Vec<(String, f64)>,
+    #[serde(skip)]
+    pub payload: Vec<u8>,
+}
+
+/// An event of `log_kv!`, with its fields by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub name: String,
+    /// Numbers and booleans as such, other values as they are shown in the message
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A span entered or exited on the target, e.g. a task or interrupt handler
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub name: String,
+    pub kind: SpanKind,
+    /// Seconds since the span was entered, on exits of targets that send timestamps
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpanKind {
+    Enter,
+    Exit,
+}
+
+/// Duplicates of a record that were collapsed into one marker
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Repeated {
+    /// Number of duplicates after the record that was shown
+    pub count: u64,
+    /// Timestamps of the first and last duplicate, if the target sends timestamps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last: Option<String>,
+}
+
+/// Security world of an image on a TrustZone target, where the secure and non-secure images
+/// each have their own ring buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum World {
+    Secure,
+    NonSecure,
+}
+
+impl World {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            World::Secure => "secure",
+            World::NonSecure => "non-secure",
+        }
+    }
+
+    /// Short form shown in front of the messages, `S` or `NS`
+    pub fn tag(self) -> &'static str {
+        match self {
+            World::Secure => "S",
+            World::NonSecure => "NS",
+        }
+    }
+}
+
+/// Level of a message logged with `trace!` up to `error!`, messages of `log!` have none
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl FromStr for Level {
+    type Err = Error;
+
+    /// `trace`, `debug`, `info`, `warn` or `error`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Level::from_site(s).ok_or_else(|| {
+            anyhow!(
+                "Unknown level {:?}, expected trace, debug, info, warn or error",
+                s
+            )
+        })
+    }
+}
+
+impl Level {
+    /// The level of a call site, as in `LogSite::level`
+    pub fn from_site(level: &str) -> Option<Self> {
+        match level {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+
+    /// Form shown in front of the messages, e.g. `WARN`
+    pub fn tag(self) -> &'static str {
+        match self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
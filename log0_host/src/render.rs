@@ -0,0 +1,40 @@
+use std::fmt::Write;
+
+/// Bytes per line in the raw column
+const RAW_BYTES_PER_LINE: usize = 16;
+
+/// Put a hex dump of `raw` next to the decoded text, line by line
+///
+/// ```text
+/// 02 00 00 00 ff ff ff ff 03 00 00 00             | MyStruct {
+///                                                 |     b: 2,
+/// ```
+pub fn side_by_side(raw: &[u8], decoded: &str) -> String {
+    let width = RAW_BYTES_PER_LINE * 3 - 1;
+    let mut raw_lines = raw.chunks(RAW_BYTES_PER_LINE).map(|chunk| {
+        chunk
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    let mut decoded_lines = decoded.lines();
+
+    let mut out = String::new();
+    loop {
+        match (raw_lines.next(), decoded_lines.next()) {
+            (None, None) => break,
+            (raw, decoded) => {
+                let line = format!(
+                    "{:width$} | {}",
+                    raw.unwrap_or_default(),
+                    decoded.unwrap_or_default(),
+                    width = width
+                );
+                writeln!(out, "{}", line.trim_end()).ok();
+            }
+        }
+    }
+
+    out
+}
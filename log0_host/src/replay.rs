@@ -0,0 +1,159 @@
+use crate::pipeline::Chunk;
+use anyhow::{anyhow, Context, Result};
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Start of every recording, the last byte is the version of the layout
+pub const MAGIC: &[u8; 8] = b"LOG0RAW1";
+
+/// Length of an entry that marks a reset of the parser instead of holding data
+const RESET: u32 = u32::MAX;
+
+/// Ring of an entry, the main one, the one of the secure image, the one for `warn!` and
+/// `error!`, and from `CORE` on the ones of the other cores by index
+const MAIN: u8 = 0;
+const SECURE: u8 = 1;
+const PRIORITY: u8 = 2;
+const CORE: u8 = 3;
+
+/// Writes the bytes read from the rings to a file, with when they were read, so a session can
+/// be decoded again with `replay`
+///
+/// Each entry is the host time in microseconds since the Unix epoch as a `u64`, the ring it was
+/// read from as a `u8`, and the length of the data as a `u32` followed by the data, all
+/// little-endian. A length of `u32::MAX` without data marks where the parser of the ring was
+/// reset, after a lost connection or skipped data.
+pub struct Recorder<W: Write> {
+    w: W,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+        Recorder::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut w: W) -> Result<Self> {
+        w.write_all(MAGIC)?;
+        Ok(Recorder { w })
+    }
+
+    /// Write the data of `chunk`, or a reset for the chunks after which the parser starts over
+    pub fn write(&mut self, chunk: &Chunk) -> Result<()> {
+        self.write_ring(MAIN, chunk)
+    }
+
+    fn write_ring(&mut self, ring: u8, chunk: &Chunk) -> Result<()> {
+        match chunk {
+            Chunk::Data(data, read) => self.entry(ring, *read, Some(data.as_slice())),
+            Chunk::Resync | Chunk::Reset | Chunk::Overrun { .. } => {
+                self.entry(ring, SystemTime::now(), None)
+            }
+            Chunk::Secure(chunk) => self.write_ring(SECURE, chunk),
+            Chunk::Priority(chunk) => self.write_ring(PRIORITY, chunk),
+            Chunk::Core(core, chunk) => {
+                let ring = (*core)
+                    .checked_add(CORE.into())
+                    .and_then(|ring| u8::try_from(ring).ok())
+                    .ok_or_else(|| anyhow!("Core {} is too many to record", core))?;
+                self.write_ring(ring, chunk)
+            }
+            // Reported, they do not change how the frames decode
+            Chunk::Dropped(_) | Chunk::Panicked | Chunk::HighWater { .. } => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.w.flush()?)
+    }
+
+    fn entry(&mut self, ring: u8, time: SystemTime, data: Option<&[u8]>) -> Result<()> {
+        let micros = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        self.w.write_all(&micros.to_le_bytes())?;
+        self.w.write_all(&[ring])?;
+        match data {
+            Some(data) => {
+                self.w.write_all(&(data.len() as u32).to_le_bytes())?;
+                self.w.write_all(data)?;
+            }
+            None => self.w.write_all(&RESET.to_le_bytes())?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads back the chunks written by `Recorder`, for the ring they were read from
+pub struct Replay<R: Read> {
+    r: R,
+}
+
+impl Replay<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open recording {}", path.display()))?;
+        Replay::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> Replay<R> {
+    pub fn new(mut r: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)
+            .ok()
+            .filter(|_| magic == *MAGIC)
+            .ok_or_else(|| anyhow!("Not a recording of `--record`"))?;
+
+        Ok(Replay { r })
+    }
+
+    /// The next chunk, `Chunk::Data` with the time it was read or `Chunk::Reset`, wrapped in
+    /// the chunk of its ring, `None` at the end of the recording
+    ///
+    /// A recording cut off in the middle of an entry, e.g. when the host was killed, ends with
+    /// the last whole one.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        let mut header = [0; 13];
+        if !self.read(&mut header)? {
+            return Ok(None);
+        }
+        let micros = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let ring = header[8];
+        let len = u32::from_le_bytes(header[9..].try_into().unwrap());
+
+        let chunk = if len == RESET {
+            Chunk::Reset
+        } else {
+            let mut data = vec![0; len as usize];
+            if !self.read(&mut data)? {
+                return Ok(None);
+            }
+            Chunk::Data(data, UNIX_EPOCH + Duration::from_micros(micros))
+        };
+
+        Ok(Some(match ring {
+            MAIN => chunk,
+            SECURE => Chunk::Secure(Box::new(chunk)),
+            PRIORITY => Chunk::Priority(Box::new(chunk)),
+            core => Chunk::Core((core - CORE).into(), Box::new(chunk)),
+        }))
+    }
+
+    /// Fill `buf`, `false` at the end of the recording
+    fn read(&mut self, buf: &mut [u8]) -> Result<bool> {
+        match self.r.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::fetch::{read_string, ReadMemory};
+use anyhow::{anyhow, Result};
+use std::convert::TryInto;
+use std::ops::Range;
+
+/// Starts the SEGGER RTT control block, the host looks for it in RAM
+pub const ID: &[u8; 16] = b"SEGGER RTT\0\0\0\0\0\0";
+
+/// Name of the up-channel a target built with the `rtt` feature writes its frames to
+pub const CHANNEL: &str = "log0";
+
+/// Bytes of an up or down channel descriptor: name, buffer, size, write and read offsets and
+/// flags
+const DESCRIPTOR: u64 = 24;
+
+/// Offset of the first descriptor in the control block, after the ID and the channel counts
+const CHANNELS: u64 = 24;
+
+/// More channels than this means the control block is not initialized, or not one at all
+const MAX_CHANNELS: u32 = 64;
+
+/// Bytes of RAM read per transfer while looking for the control block
+const BLOCK: usize = 1024;
+
+/// An up-channel of the control block, for a `Reader` and a `Transport` like the `LOG0_CURSORS`
+/// ring
+///
+/// The write and read offsets of the channel are the target and host cursors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpChannel {
+    pub name: String,
+    pub cursor_address: u64,
+    pub buffer_address: u64,
+    pub buffer_size: usize,
+}
+
+/// Address of the write offset of up-channel `n` of the control block at `address`, the read
+/// offset follows it
+pub fn cursor_address(address: u64, n: u64) -> u64 {
+    address + CHANNELS + n * DESCRIPTOR + 12
+}
+
+/// Where the control block is, checked at `hint`, e.g. the address of `_SEGGER_RTT` in the ELF,
+/// before the `ram` regions are searched for its ID
+pub fn find(
+    memory: &mut impl ReadMemory,
+    hint: Option<u64>,
+    ram: &[Range<u32>],
+) -> Result<Option<u64>> {
+    if let Some(hint) = hint {
+        let mut id = [0; 16];
+        if memory.read_8(hint, &mut id).is_ok() && &id == ID {
+            return Ok(Some(hint));
+        }
+    }
+
+    let mut block = vec![0; BLOCK];
+    for region in ram {
+        let mut address = u64::from(region.start);
+        let end = u64::from(region.end);
+        while address + ID.len() as u64 <= end {
+            let len = BLOCK.min((end - address) as usize);
+            memory.read_8(address, &mut block[..len])?;
+            if let Some(at) = block[..len].windows(ID.len()).position(|w| w == ID) {
+                return Ok(Some(address + at as u64));
+            }
+
+            // The next block overlaps this one, in case the ID straddles them
+            address += (len - ID.len() + 1) as u64;
+        }
+    }
+
+    Ok(None)
+}
+
+/// The up-channels of the control block at `address`
+pub fn up_channels(memory: &mut impl ReadMemory, address: u64) -> Result<Vec<UpChannel>> {
+    let mut header = [0; 24];
+    memory.read_8(address, &mut header)?;
+    if &header[..16] != ID {
+        return Err(anyhow!("No RTT control block at {:#010x}", address));
+    }
+    let max_up = u32::from_le_bytes(header[16..20].try_into().unwrap());
+    if max_up > MAX_CHANNELS {
+        return Err(anyhow!(
+            "RTT control block at {:#010x} has {} up-channels, is it initialized?",
+            address,
+            max_up
+        ));
+    }
+
+    (0..u64::from(max_up))
+        .map(|n| {
+            let cursor_address = cursor_address(address, n);
+            let mut words = [0; DESCRIPTOR as usize];
+            memory.read_8(cursor_address - 12, &mut words)?;
+            let word = |i: usize| u32::from_le_bytes(words[4 * i..4 * i + 4].try_into().unwrap());
+
+            let name = match word(0) {
+                0 => String::new(),
+                name => read_string(memory, u64::from(name))?,
+            };
+
+            Ok(UpChannel {
+                name,
+                cursor_address,
+                buffer_address: u64::from(word(1)),
+                buffer_size: word(2) as usize,
+            })
+        })
+        .collect()
+}
+
+/// The `log0` up-channel of the target, see `find` for where the control block is looked for
+pub fn channel(
+    memory: &mut impl ReadMemory,
+    hint: Option<u64>,
+    ram: &[Range<u32>],
+) -> Result<UpChannel> {
+    let address =
+        find(memory, hint, ram)?.ok_or_else(|| anyhow!("No RTT control block found in RAM"))?;
+    log::debug!("Found the RTT control block at {:#010x}", address);
+
+    up_channels(memory, address)?
+        .into_iter()
+        .find(|channel| channel.name == CHANNEL)
+        .ok_or_else(|| anyhow!("The RTT control block has no {:?} up-channel", CHANNEL))
+}
@@ -0,0 +1,296 @@
+use crate::{bytes_to_read, leb128, transport::Transport};
+use anyhow::{anyhow, Result};
+
+/// Simulated target memory holding the `LOG0_CURSORS` and `LOG0_BUFFER` statics
+#[derive(Debug)]
+pub struct SimTarget {
+    cursor_address: u32,
+    buffer_address: u32,
+    // [target, host, buffer pointer, dropped], same layout as `LOG0_CURSORS`
+    cursors: [u32; 4],
+    buffer: Vec<u8>,
+    dropped: usize,
+    overwrite: bool,
+    /// The bank being filled and the bytes in it, with `with_banks`
+    banks: Option<(usize, usize)>,
+}
+
+impl SimTarget {
+    /// Create a simulated target with an empty ring buffer of `buffer_size` bytes
+    pub fn new(cursor_address: u32, buffer_address: u32, buffer_size: usize) -> Self {
+        SimTarget {
+            cursor_address,
+            buffer_address,
+            cursors: [0, 0, buffer_address, 0],
+            buffer: vec![0; buffer_size],
+            dropped: 0,
+            overwrite: false,
+            banks: None,
+        }
+    }
+
+    /// Overwrite the oldest frames when the buffer is full, like the `overwrite` feature of
+    /// `log0_target`
+    pub fn with_overwrite(self) -> Self {
+        SimTarget {
+            overwrite: true,
+            ..self
+        }
+    }
+
+    /// Split the buffer in two banks, like the `double-buffer` feature of `log0_target`
+    pub fn with_banks(self) -> Self {
+        SimTarget {
+            banks: Some((0, 0)),
+            ..self
+        }
+    }
+
+    pub fn cursor_address(&self) -> u32 {
+        self.cursor_address
+    }
+
+    pub fn buffer_address(&self) -> u32 {
+        self.buffer_address
+    }
+
+    /// Number of frames that did not fit in the ring buffer, or were overwritten
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Encode a frame and write it into the ring buffer like `log!` does on the target, returns
+    /// `false` if the frame did not fit and was dropped
+    pub fn log(&mut self, string_loc: u32, type_loc: u32, data: &[u8]) -> bool {
+        let mut frame = Vec::with_capacity(data.len() + 15);
+        leb128::encode_u32(&mut frame, data.len() as u32);
+        leb128::encode_u32(&mut frame, string_loc);
+        leb128::encode_u32(&mut frame, type_loc);
+        frame.extend_from_slice(data);
+
+        if self.banks.is_some() {
+            return self.log_banked(&frame);
+        }
+
+        let size = self.buffer.len();
+        let mut target = self.cursors[0] as usize;
+        let mut host = self.cursors[1] as usize;
+
+        // One slot is always kept free so a full buffer can be told apart from an empty one
+        let free = |host| size - 1 - bytes_to_read(host, target, size);
+        if self.overwrite && frame.len() < size {
+            while frame.len() > free(host) {
+                host = self.frame_end(host);
+                self.dropped += 1;
+            }
+            self.cursors[1] = host as u32;
+        }
+        if frame.len() > free(host) {
+            self.dropped += 1;
+            self.cursors[3] = self.dropped as u32;
+            return false;
+        }
+        self.cursors[3] = self.dropped as u32;
+
+        for byte in frame {
+            self.buffer[target] = byte;
+            target = (target + 1) % size;
+        }
+
+        // The cursor is only published once the whole frame is in place
+        self.cursors[0] = target as u32;
+
+        true
+    }
+
+    /// Write `frame` into the bank being filled, after a swap if it does not fit
+    fn log_banked(&mut self, frame: &[u8]) -> bool {
+        let half = self.buffer.len() / 2;
+        let (_, fill) = self.banks.unwrap();
+        if fill + frame.len() > half && (frame.len() > half || !self.swap()) {
+            self.dropped += 1;
+            self.cursors[3] = self.dropped as u32;
+            return false;
+        }
+
+        let (active, fill) = self.banks.unwrap();
+        let start = active * half + fill;
+        self.buffer[start..start + frame.len()].copy_from_slice(frame);
+        self.banks = Some((active, fill + frame.len()));
+        self.swap();
+
+        true
+    }
+
+    /// Hand the bank being filled to the host if it drained the other one, with its sequence
+    /// number, bank and length in the target cursor
+    fn swap(&mut self) -> bool {
+        let seq = self.cursors[0] >> 25;
+        if self.cursors[1] != seq {
+            return false;
+        }
+
+        if let Some((active, fill)) = self.banks.filter(|&(_, fill)| fill > 0) {
+            self.cursors[0] = ((seq + 1) % 128) << 25 | (active as u32) << 24 | fill as u32;
+            self.banks = Some((active ^ 1, 0));
+        }
+
+        true
+    }
+
+    /// Where the frame starting at `idx` ends
+    fn frame_end(&self, mut idx: usize) -> usize {
+        let mut fields = [0; 3];
+        for field in &mut fields {
+            let bytes =
+                (0..leb128::MAX_LEN_U32).map(|i| &self.buffer[(idx + i) % self.buffer.len()]);
+            let (value, len) = leb128::decode_u32(bytes).unwrap();
+            *field = value;
+            idx += len;
+        }
+
+        (idx + fields[0] as usize) % self.buffer.len()
+    }
+
+    /// Read words from the cursor structure
+    pub fn read_32(&self, address: u32, data: &mut [u32]) {
+        let start = self.cursor_offset(address, data.len() * 4) / 4;
+        data.copy_from_slice(&self.cursors[start..start + data.len()]);
+    }
+
+    /// Read bytes from the ring buffer
+    pub fn read_8(&self, address: u32, data: &mut [u8]) {
+        let start = self.buffer_offset(address, data.len());
+        data.copy_from_slice(&self.buffer[start..start + data.len()]);
+    }
+
+    /// Write a word in the cursor structure
+    pub fn write_word_32(&mut self, address: u32, value: u32) {
+        let idx = self.cursor_offset(address, 4) / 4;
+        self.cursors[idx] = value;
+    }
+
+    fn cursor_offset(&self, address: u32, len: usize) -> usize {
+        let offset = address.wrapping_sub(self.cursor_address) as usize;
+        assert!(
+            offset.is_multiple_of(4) && offset + len <= 16,
+            "Access outside of simulated cursors: 0x{:08x} ({} bytes)",
+            address,
+            len
+        );
+        offset
+    }
+
+    fn buffer_offset(&self, address: u32, len: usize) -> usize {
+        let offset = address.wrapping_sub(self.buffer_address) as usize;
+        assert!(
+            offset + len <= self.buffer.len(),
+            "Access outside of simulated buffer: 0x{:08x} ({} bytes)",
+            address,
+            len
+        );
+        offset
+    }
+}
+
+/// In-memory `Transport` backed by a `SimTarget`, with support for injecting transfer errors
+#[derive(Debug)]
+pub struct MockTransport {
+    pub target: SimTarget,
+    fail_after: Option<usize>,
+}
+
+impl MockTransport {
+    pub fn new(target: SimTarget) -> Self {
+        MockTransport {
+            target,
+            fail_after: None,
+        }
+    }
+
+    /// Let `n` more transfers succeed and fail the one after, like a flaky probe connection
+    pub fn fail_after(&mut self, n: usize) {
+        self.fail_after = Some(n);
+    }
+
+    fn transfer(&mut self) -> Result<()> {
+        match self.fail_after {
+            Some(0) => {
+                self.fail_after = None;
+                Err(anyhow!("Simulated transfer error"))
+            }
+            Some(n) => {
+                self.fail_after = Some(n - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn read_cursors(&mut self) -> Result<[u32; 2]> {
+        self.transfer()?;
+
+        let mut buff = [0u32; 2];
+        self.target.read_32(self.target.cursor_address(), &mut buff);
+
+        Ok(buff)
+    }
+
+    fn read_buffer(&mut self, offset: u32, data: &mut [u8]) -> Result<()> {
+        self.transfer()?;
+
+        self.target
+            .read_8(self.target.buffer_address() + offset, data);
+
+        Ok(())
+    }
+
+    fn write_host_cursor(&mut self, idx: u32) -> Result<()> {
+        self.transfer()?;
+
+        self.target
+            .write_word_32(self.target.cursor_address() + 4, idx);
+
+        Ok(())
+    }
+
+    fn read_dropped(&mut self) -> Result<u32> {
+        self.transfer()?;
+
+        let mut buff = [0u32; 1];
+        self.target
+            .read_32(self.target.cursor_address() + 12, &mut buff);
+
+        Ok(buff[0])
+    }
+}
+
+/// `Transport` over the ring of `log0_target` itself, built for the host, so the frames are
+/// written by the same code as on a target
+pub struct TargetTransport(pub &'static log0_target::Cursors);
+
+impl Transport for TargetTransport {
+    fn read_cursors(&mut self) -> Result<[u32; 2]> {
+        let [target, host] = self.0.indices();
+
+        Ok([target as u32, host as u32])
+    }
+
+    fn read_buffer(&mut self, offset: u32, data: &mut [u8]) -> Result<()> {
+        self.0.read(offset as usize, data);
+
+        Ok(())
+    }
+
+    fn write_host_cursor(&mut self, idx: u32) -> Result<()> {
+        self.0.set_host(idx as usize);
+
+        Ok(())
+    }
+
+    fn read_dropped(&mut self) -> Result<u32> {
+        Ok(self.0.dropped() as u32)
+    }
+}
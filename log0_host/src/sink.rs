@@ -0,0 +1,325 @@
+use crate::{
+    channel,
+    record::{Level, Record, Repeated},
+    render,
+    template::Template,
+};
+use anyhow::{anyhow, Error, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Destination for decoded records
+pub trait Sink {
+    fn write(&mut self, record: &Record) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once at the end of the session, before exiting
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        (**self).write(record)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
+}
+
+/// Colors for the task names, picked by a hash of the name so a task keeps its color
+const TASK_COLORS: &[u8] = &[32, 33, 34, 35, 36, 92, 93, 94, 95, 96];
+
+/// Human readable output, one message per line prefixed with the timestamp, task and level
+pub struct Terminal<W: Write> {
+    w: W,
+    show_raw: bool,
+    colors: bool,
+    template: Option<Template>,
+}
+
+impl<W: Write> Terminal<W> {
+    /// `show_raw` puts a hex dump of the payload next to each message
+    pub fn new(w: W, show_raw: bool) -> Self {
+        Terminal {
+            w,
+            show_raw,
+            colors: false,
+            template: None,
+        }
+    }
+
+    /// Show each task name in its own color, to tell interleaved tasks apart
+    pub fn with_colors(mut self, colors: bool) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    /// Lay the lines out with `template` instead of the timestamp, task and message
+    pub fn with_template(mut self, template: Option<Template>) -> Self {
+        self.template = template;
+        self
+    }
+
+    fn task_tag(&self, task: &str) -> String {
+        if !self.colors {
+            return format!("[{}] ", task);
+        }
+
+        let hash = task.bytes().fold(0usize, |hash, b| {
+            hash.wrapping_mul(31).wrapping_add(b as usize)
+        });
+        let color = TASK_COLORS[hash % TASK_COLORS.len()];
+        format!("\x1b[{}m[{}]\x1b[0m ", color, task)
+    }
+
+    fn level_tag(&self, level: Level) -> String {
+        if !self.colors {
+            return format!("{} ", level.tag());
+        }
+
+        let color = match level {
+            Level::Trace => 90,
+            Level::Debug => 36,
+            Level::Info => 32,
+            Level::Warn => 33,
+            Level::Error => 31,
+        };
+        format!("\x1b[{}m{}\x1b[0m ", color, level.tag())
+    }
+}
+
+impl<W: Write> Sink for Terminal<W> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let mut task = match &record.task {
+            Some(task) => self.task_tag(task),
+            None => String::new(),
+        };
+        if let Some(world) = record.world {
+            task.insert_str(0, &format!("[{}] ", world.tag()));
+        }
+        if let Some(core) = record.core {
+            task.insert_str(0, &format!("[core {}] ", core));
+        }
+        if let Some(level) = record.level {
+            task.push_str(&self.level_tag(level));
+        }
+        if let Some(channel) = record.channel {
+            task.push_str(&format!("[channel {}] ", channel));
+        }
+        let line = match (&self.template, &record.timestamp) {
+            (Some(template), _) => template.render(record),
+            (None, Some(timestamp)) => format!("[{}] {}{}", timestamp, task, record.message),
+            (None, None) => format!("{}{}", task, record.message),
+        };
+
+        if let Some(repeated) = &record.repeated {
+            write!(self.w, "... repeated {} more times", repeated.count)?;
+            if let (Some(first), Some(last)) = (&repeated.first, &repeated.last) {
+                write!(self.w, " ({} to {})", first, last)?;
+            }
+            writeln!(self.w)?;
+
+            return Ok(());
+        }
+
+        if self.show_raw {
+            write!(self.w, "{}", render::side_by_side(&record.payload, &line))?;
+        } else {
+            writeln!(self.w, "{}", line)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.w.flush()?)
+    }
+}
+
+/// How the messages are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// Lines for people, see `Terminal`
+    Text,
+    /// One JSON object per line with the details of each frame, see `Json::with_details`
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = Error;
+
+    /// `text` or `json`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(anyhow!("Unknown output {:?}, expected text or json", s)),
+        }
+    }
+}
+
+/// One JSON object per line
+pub struct Json<W: Write> {
+    w: W,
+    details: bool,
+}
+
+impl<W: Write> Json<W> {
+    pub fn new(w: W) -> Self {
+        Json { w, details: false }
+    }
+
+    /// Add the format string, the decoded values, the payload as hex and the seconds since boot
+    /// to each object, for tools that consume the frames rather than the messages
+    pub fn with_details(mut self, details: bool) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// A record with the details of its frame, see `Json::with_details`
+#[derive(Serialize)]
+struct Details<'a> {
+    #[serde(flatten)]
+    record: &'a Record,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a str>,
+    args: &'a [String],
+    raw: String,
+}
+
+impl<W: Write> Sink for Json<W> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        if self.details {
+            let details = Details {
+                record,
+                seconds: record.seconds,
+                format: record.format.as_deref(),
+                args: &record.args,
+                raw: channel::hex(&record.payload).replace(' ', ""),
+            };
+            serde_json::to_writer(&mut self.w, &details)?;
+        } else {
+            serde_json::to_writer(&mut self.w, record)?;
+        }
+        writeln!(self.w)?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.w.flush()?)
+    }
+}
+
+/// Passes every record on to several sinks, e.g. the terminal and a broker
+///
+/// An error of any of them ends the session, so the sinks that publish over the network deal
+/// with losing the other end themselves.
+pub struct Fanout {
+    sinks: Vec<Box<dyn Sink + Send>>,
+}
+
+impl Fanout {
+    pub fn new(sinks: Vec<Box<dyn Sink + Send>>) -> Self {
+        Fanout { sinks }
+    }
+}
+
+impl Sink for Fanout {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        self.sinks
+            .iter_mut()
+            .try_for_each(|sink| sink.write(record))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.flush())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.sinks.iter_mut().try_for_each(|sink| sink.finish())
+    }
+}
+
+/// Collapses bursts of identical messages, the first one is passed on as is and the rest are
+/// counted and passed on as one record with `repeated` set once a different message arrives
+pub struct Collapse<S: Sink> {
+    inner: S,
+    last: Option<Record>,
+    repeated: Option<Repeated>,
+}
+
+impl<S: Sink> Collapse<S> {
+    pub fn new(inner: S) -> Self {
+        Collapse {
+            inner,
+            last: None,
+            repeated: None,
+        }
+    }
+
+    /// Pass on the marker for the current burst, if there were duplicates
+    fn end_burst(&mut self) -> Result<()> {
+        if let (Some(last), Some(repeated)) = (&self.last, self.repeated.take()) {
+            let marker = Record {
+                timestamp: repeated.last.clone(),
+                event: None,
+                span: None,
+                channel: None,
+                repeated: Some(repeated),
+                ..last.clone()
+            };
+            self.inner.write(&marker)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: Sink> Sink for Collapse<S> {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let duplicate = match &self.last {
+            Some(last) => last.id == record.id && last.message == record.message,
+            None => false,
+        };
+
+        if duplicate {
+            let repeated = self.repeated.get_or_insert(Repeated {
+                count: 0,
+                first: record.timestamp.clone(),
+                last: None,
+            });
+            repeated.count += 1;
+            repeated.last = record.timestamp.clone();
+
+            return Ok(());
+        }
+
+        self.end_burst()?;
+        self.last = Some(record.clone());
+        self.inner.write(record)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.end_burst()?;
+        self.inner.finish()
+    }
+}
@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Context, Result};
+use probe_rs::{Core, MemoryInterface};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+/// Start of a snapshot file, the last byte is the version of the format
+pub const MAGIC: &[u8; 8] = b"FHDUMP\0\x01";
+
+/// Cortex-M core registers, by register number
+pub const REGISTERS: &[&str] = &[
+    "R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "R8", "R9", "R10", "R11", "R12", "SP", "LR",
+    "PC", "XPSR",
+];
+
+/// What the snapshot was taken of, the contents of the regions follow it in this order
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    chip: String,
+    elf: Option<String>,
+    taken: String,
+    registers: Vec<(String, u32)>,
+    /// Start address and length of each region
+    regions: Vec<(u64, u64)>,
+}
+
+/// Registers and RAM of a halted target, to decode its statics afterwards without a debugger
+///
+/// The file is `MAGIC`, the length of a JSON header as a little endian `u32`, the header, and
+/// the contents of the memory regions.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub chip: String,
+    /// ELF the target was running, if it was given when taking the snapshot
+    pub elf: Option<String>,
+    /// When the snapshot was taken, in RFC 3339
+    pub taken: String,
+    pub registers: Vec<(String, u32)>,
+    pub memory: elf_test::Snapshot,
+}
+
+impl Snapshot {
+    /// Halt the core and read its registers and the `ram` regions, the core is left halted
+    pub fn capture(
+        core: &mut Core,
+        chip: &str,
+        ram: &[Range<u32>],
+        elf: Option<String>,
+    ) -> Result<Self> {
+        core.halt(Duration::from_millis(100))?;
+        let taken = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+
+        let mut registers = Vec::new();
+        for (number, name) in REGISTERS.iter().enumerate() {
+            registers.push((name.to_string(), core.read_core_reg(number as u16)?));
+        }
+
+        let mut regions = Vec::new();
+        for range in ram {
+            let mut data = vec![0; range.len()];
+            core.read_8(range.start, &mut data)
+                .with_context(|| format!("Failed to read RAM at {:#010x}", range.start))?;
+            regions.push((range.start as u64, data));
+        }
+
+        Ok(Snapshot {
+            chip: chip.to_string(),
+            elf,
+            taken,
+            registers,
+            memory: elf_test::Snapshot { regions },
+        })
+    }
+
+    pub fn write_to(&self, w: &mut impl Write) -> Result<()> {
+        let header = serde_json::to_vec(&Header {
+            chip: self.chip.clone(),
+            elf: self.elf.clone(),
+            taken: self.taken.clone(),
+            registers: self.registers.clone(),
+            regions: self
+                .memory
+                .regions
+                .iter()
+                .map(|(start, data)| (*start, data.len() as u64))
+                .collect(),
+        })?;
+
+        w.write_all(MAGIC)?;
+        w.write_all(&(header.len() as u32).to_le_bytes())?;
+        w.write_all(&header)?;
+        for (_, data) in &self.memory.regions {
+            w.write_all(data)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(r: &mut impl Read) -> Result<Self> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("Not a snapshot, or one from another version"));
+        }
+
+        let mut len = [0; 4];
+        r.read_exact(&mut len)?;
+        let mut header = vec![0; u32::from_le_bytes(len) as usize];
+        r.read_exact(&mut header)?;
+        let header: Header = serde_json::from_slice(&header)?;
+
+        let mut regions = Vec::new();
+        for (start, len) in header.regions {
+            let mut data = vec![0; len as usize];
+            r.read_exact(&mut data)
+                .with_context(|| format!("Snapshot truncated in region {:#010x}", start))?;
+            regions.push((start, data));
+        }
+
+        Ok(Snapshot {
+            chip: header.chip,
+            elf: header.elf,
+            taken: header.taken,
+            registers: header.registers,
+            memory: elf_test::Snapshot { regions },
+        })
+    }
+
+    /// Print what the snapshot was taken of, the registers and the captured regions
+    pub fn write_summary(&self, w: &mut impl Write) -> Result<()> {
+        writeln!(w, "Snapshot of {} taken {}", self.chip, self.taken)?;
+        if let Some(elf) = &self.elf {
+            writeln!(w, "ELF: {}", elf)?;
+        }
+
+        writeln!(w, "Registers:")?;
+        for (name, value) in &self.registers {
+            writeln!(w, "  {:>4}: {:#010x}", name, value)?;
+        }
+
+        writeln!(w, "RAM:")?;
+        for (start, data) in &self.memory.regions {
+            writeln!(
+                w,
+                "  {:#010x}..{:#010x} ({} bytes)",
+                start,
+                start + data.len() as u64,
+                data.len()
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    record::{Level, Record},
+    sink::Sink,
+};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Tables of the archive, one row in `sessions` per run of the host
+///
+/// `level` is NULL for messages of `log!`, `args` has the numeric fields of the value as a JSON
+/// object.
+pub const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    started TEXT NOT NULL,
+    elf TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    session INTEGER NOT NULL REFERENCES sessions(id),
+    received TEXT NOT NULL,
+    timestamp TEXT,
+    level TEXT,
+    module TEXT,
+    message_id INTEGER,
+    type TEXT,
+    message TEXT NOT NULL,
+    args TEXT,
+    payload BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS messages_session ON messages(session);
+CREATE INDEX IF NOT EXISTS messages_module ON messages(module);
+CREATE INDEX IF NOT EXISTS messages_message_id ON messages(message_id);
+";
+
+/// Stores a record of the session `?1`
+const INSERT: &str = "\
+INSERT INTO messages (session, received, timestamp, level, module, message_id, type, message, \
+args, payload) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
+
+/// Stores every record in an SQLite file, to query long sessions with SQL afterwards
+///
+/// Each batch of records between flushes is one transaction.
+///
+/// ```sh
+/// sqlite3 session.db "SELECT received, message FROM messages WHERE module LIKE 'app::radio%'"
+/// ```
+pub struct Sqlite {
+    connection: Connection,
+    /// Row of this run in `sessions`
+    session: i64,
+    /// A transaction was started by the first record since the last flush
+    pending: bool,
+}
+
+impl Sqlite {
+    pub fn open(path: &Path, elf: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open the SQLite archive {}", path.display()))?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute(
+            "INSERT INTO sessions (started, elf) VALUES (?1, ?2)",
+            params![
+                humantime::format_rfc3339_micros(SystemTime::now()).to_string(),
+                elf.display().to_string()
+            ],
+        )?;
+        let session = connection.last_insert_rowid();
+
+        Ok(Sqlite {
+            connection,
+            session,
+            pending: false,
+        })
+    }
+
+    /// Store a record that arrived at `received`
+    pub fn insert(&mut self, record: &Record, received: SystemTime) -> Result<()> {
+        let args = if record.values.is_empty() {
+            None
+        } else {
+            let args: serde_json::Map<_, _> = record
+                .values
+                .iter()
+                .map(|(name, value)| (name.clone(), serde_json::json!(value)))
+                .collect();
+            Some(serde_json::Value::Object(args).to_string())
+        };
+
+        self.connection.prepare_cached(INSERT)?.execute(params![
+            self.session,
+            humantime::format_rfc3339_micros(received).to_string(),
+            record.timestamp,
+            record.level.map(Level::as_str),
+            record.module,
+            record.id,
+            record.type_name,
+            record.message,
+            args,
+            record.payload,
+        ])?;
+
+        Ok(())
+    }
+}
+
+impl Sink for Sqlite {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        if !self.pending {
+            self.connection.execute_batch("BEGIN")?;
+            self.pending = true;
+        }
+
+        self.insert(record, SystemTime::now())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.pending {
+            self.connection.execute_batch("COMMIT")?;
+            self.pending = false;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{parser::Resources, record::Record, watchdog::Health};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Messages and payload bytes for one module or task
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Count {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+impl Count {
+    fn add(&mut self, bytes: u64) {
+        self.messages += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// What was received during a session, to spot unexpectedly chatty parts of the firmware
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    total: Count,
+    received: u64,
+    /// Frames the target dropped or overwrote, as far as it reports them
+    lost: u64,
+    /// Frames dropped because they did not match their CRC
+    corrupted: u64,
+    /// Frames missing from the sequence numbers, if the target sends them
+    missed: u64,
+    /// Only counted if the target sends heartbeats
+    heartbeats: u64,
+    /// The most bytes that were in the ring at once and its capacity, if the target tracks it
+    high_water: Option<(usize, usize)>,
+    /// The stack and heap usage the target last reported, if it does
+    resources: Option<Resources>,
+    /// The most heap the target reported in use
+    heap_peak: u32,
+    #[serde(skip)]
+    health: Option<Health>,
+    by_module: HashMap<String, Count>,
+    /// Only filled if the target sends task IDs
+    by_task: HashMap<String, Count>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count bytes read from the ring buffer, framing included
+    pub fn received(&mut self, bytes: usize) {
+        self.received += bytes as u64;
+    }
+
+    /// Count frames the target lost because the ring buffer was full
+    pub fn lost(&mut self, frames: u32) {
+        self.lost += u64::from(frames);
+    }
+
+    /// Count frames dropped because they did not match their CRC
+    pub fn corrupted(&mut self, frames: usize) {
+        self.corrupted += frames as u64;
+    }
+
+    /// Count frames missing from the sequence numbers
+    pub fn missed(&mut self, frames: usize) {
+        self.missed += frames as u64;
+    }
+
+    /// Count a heartbeat that arrived at `now`
+    pub fn heartbeat(&mut self, now: Instant) {
+        self.heartbeats += 1;
+        match &mut self.health {
+            Some(health) => health.heartbeat(now),
+            None => self.health = Some(Health::new(now)),
+        }
+    }
+
+    /// Record the most bytes that were in the ring of `capacity` bytes at once, the highest
+    /// one is kept over reconnects
+    pub fn high_water(&mut self, bytes: usize, capacity: usize) {
+        match &mut self.high_water {
+            Some((peak, _)) if *peak >= bytes => (),
+            _ => self.high_water = Some((bytes, capacity)),
+        }
+    }
+
+    /// Keep the stack and heap usage the target reported
+    pub fn resources(&mut self, resources: Resources) {
+        if let Some(heap) = resources.heap {
+            self.heap_peak = self.heap_peak.max(heap.used);
+        }
+        self.resources = Some(resources);
+    }
+
+    /// Write the stack and heap usage the target last reported
+    pub fn write_resources(&self, w: &mut impl Write) -> Result<()> {
+        writeln!(w, "Resources")?;
+        if self.resources.is_none() {
+            writeln!(w, "  none reported, see `report_resources` on the target")?;
+        }
+        self.write_resource_lines(w)
+    }
+
+    /// The stack and heap lines of the summary, nothing if the target does not report them
+    fn write_resource_lines(&self, w: &mut impl Write) -> Result<()> {
+        let resources = match self.resources {
+            Some(resources) => resources,
+            None => return Ok(()),
+        };
+
+        writeln!(
+            w,
+            "  stack:    {} of {} bytes ({:.0}%)",
+            resources.stack_used,
+            resources.stack_size,
+            percent(resources.stack_used as usize, resources.stack_size as usize)
+        )?;
+        if let Some(heap) = resources.heap {
+            let size = (heap.used + heap.free) as usize;
+            writeln!(
+                w,
+                "  heap:     {} of {} bytes ({:.0}%), peak {} bytes",
+                heap.used,
+                size,
+                percent(heap.used as usize, size),
+                self.heap_peak
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// How the target is doing at `now`, if it sends heartbeats, see `Health::status`
+    pub fn health(&self, now: Instant) -> Option<String> {
+        self.health.as_ref().map(|health| health.status(now))
+    }
+
+    /// Count a decoded message
+    pub fn record(&mut self, record: &Record) {
+        if let Some(health) = &mut self.health {
+            health.message(Instant::now());
+        }
+
+        let bytes = record.payload.len() as u64;
+        let module = record.module.as_deref().unwrap_or("<unknown>");
+
+        self.total.add(bytes);
+        self.by_module.entry(module.into()).or_default().add(bytes);
+        if let Some(task) = &record.task {
+            self.by_task.entry(task.clone()).or_default().add(bytes);
+        }
+    }
+
+    pub fn total(&self) -> Count {
+        self.total
+    }
+
+    /// Counts per module, the chattiest first
+    pub fn by_module(&self) -> Vec<(&str, Count)> {
+        chattiest_first(&self.by_module)
+    }
+
+    /// Counts per task, the chattiest first
+    pub fn by_task(&self) -> Vec<(&str, Count)> {
+        chattiest_first(&self.by_task)
+    }
+
+    /// Write the summary table, `drops` is the number of times data was skipped to resync
+    pub fn write_summary(
+        &self,
+        w: &mut impl Write,
+        duration: Duration,
+        drops: usize,
+    ) -> Result<()> {
+        writeln!(w, "Session summary")?;
+        writeln!(w, "  duration: {:.3} s", duration.as_secs_f64())?;
+        writeln!(w, "  messages: {}", self.total.messages)?;
+        writeln!(w, "  payload:  {} bytes", self.total.bytes)?;
+        writeln!(w, "  received: {} bytes", self.received)?;
+        writeln!(w, "  drops:    {}", drops)?;
+        if self.lost > 0 {
+            writeln!(w, "  lost:     {} frames", self.lost)?;
+        }
+        if self.corrupted > 0 {
+            writeln!(w, "  corrupt:  {} frames", self.corrupted)?;
+        }
+        if self.missed > 0 {
+            writeln!(w, "  missed:   {} frames", self.missed)?;
+        }
+        if let Some(health) = self.health(Instant::now()) {
+            writeln!(w, "  target:   {}", health)?;
+        }
+        if let Some((peak, capacity)) = self.high_water {
+            writeln!(
+                w,
+                "  peak:     {} of {} bytes ({:.0}%)",
+                peak,
+                capacity,
+                percent(peak, capacity)
+            )?;
+        }
+        self.write_resource_lines(w)?;
+
+        write_table(w, "module", &self.by_module())?;
+        write_table(w, "task", &self.by_task())
+    }
+}
+
+fn percent(part: usize, whole: usize) -> f64 {
+    100.0 * part as f64 / whole.max(1) as f64
+}
+
+fn chattiest_first(counts: &HashMap<String, Count>) -> Vec<(&str, Count)> {
+    let mut counts: Vec<_> = counts
+        .iter()
+        .map(|(name, count)| (name.as_str(), *count))
+        .collect();
+    counts.sort_by(|a, b| b.1.messages.cmp(&a.1.messages).then(a.0.cmp(b.0)));
+    counts
+}
+
+/// Write the counts with `heading` over the names, nothing if there are none
+fn write_table(w: &mut impl Write, heading: &str, counts: &[(&str, Count)]) -> Result<()> {
+    if counts.is_empty() {
+        return Ok(());
+    }
+
+    let width = counts
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain(Some(heading.len()))
+        .max()
+        .unwrap_or(0);
+
+    writeln!(w)?;
+    writeln!(
+        w,
+        "  {:<width$}  {:>10}  {:>10}",
+        heading,
+        "messages",
+        "bytes",
+        width = width
+    )?;
+    for (name, count) in counts {
+        writeln!(
+            w,
+            "  {:<width$}  {:>10}  {:>10}",
+            name,
+            count.messages,
+            count.bytes,
+            width = width
+        )?;
+    }
+
+    Ok(())
+}
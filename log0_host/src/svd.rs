@@ -0,0 +1,402 @@
+use crate::{fetch::ReadMemory, xml::Element};
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// The peripherals of a chip, from its CMSIS-SVD file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    pub peripherals: Vec<Peripheral>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peripheral {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_address: u32,
+    pub registers: Vec<Register>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Register {
+    /// Name in the peripheral, `CLUSTER.REGISTER` for registers in a cluster
+    pub name: String,
+    pub description: Option<String>,
+    /// From the base address of the peripheral
+    pub offset: u32,
+    /// In bits
+    pub size: u32,
+    pub readable: bool,
+    /// Reading changes the state of the peripheral, e.g. clears a flag or pops a FIFO
+    pub read_action: bool,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub description: Option<String>,
+    /// Least significant bit
+    pub offset: u32,
+    pub width: u32,
+    /// Names of the values, as read
+    pub values: Vec<(String, u64)>,
+}
+
+/// Register properties inherited from the device, peripheral or cluster
+#[derive(Debug, Clone, Copy)]
+struct Properties {
+    size: u32,
+    readable: bool,
+}
+
+impl Properties {
+    fn inherit(self, element: &Element) -> Result<Self> {
+        Ok(Properties {
+            size: match element.child_text("size") {
+                Some(size) => number(size)? as u32,
+                None => self.size,
+            },
+            readable: match element.child_text("access") {
+                Some(access) => access != "write-only" && access != "writeOnce",
+                None => self.readable,
+            },
+        })
+    }
+}
+
+impl Device {
+    pub fn load(path: &Path) -> Result<Self> {
+        let svd = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Self::parse(&svd).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn parse(svd: &str) -> Result<Self> {
+        let device = Element::parse(svd)?;
+        if device.name != "device" {
+            return Err(anyhow!("Not an SVD file, the root is `{}`", device.name));
+        }
+
+        let properties = Properties {
+            size: 32,
+            readable: true,
+        }
+        .inherit(&device)?;
+
+        let mut peripherals = Vec::new();
+        let mut derived = Vec::new();
+        for element in device
+            .child("peripherals")
+            .into_iter()
+            .flat_map(|p| p.children("peripheral"))
+        {
+            let name = required(element, "name")?;
+            let peripheral = Peripheral {
+                name: name.to_string(),
+                description: description(element),
+                base_address: number(required(element, "baseAddress")?)? as u32,
+                registers: match element.child("registers") {
+                    Some(registers) => {
+                        let properties = properties.inherit(element)?;
+                        parse_registers(registers, properties, "", 0)
+                            .with_context(|| format!("In peripheral {}", name))?
+                    }
+                    None => vec![],
+                },
+            };
+            if let Some(parent) = element.attribute("derivedFrom") {
+                derived.push((peripherals.len(), parent.to_string()));
+            }
+            peripherals.push(peripheral);
+        }
+
+        // Derived peripherals have the registers of their parent, unless they list their own
+        for (i, parent) in derived {
+            if !peripherals[i].registers.is_empty() {
+                continue;
+            }
+            let registers = peripherals
+                .iter()
+                .find(|p| p.name == parent)
+                .map(|p| p.registers.clone())
+                .ok_or_else(|| {
+                    anyhow!("{} is derived from unknown {}", peripherals[i].name, parent)
+                })?;
+            peripherals[i].registers = registers;
+        }
+
+        Ok(Device {
+            name: device.child_text("name").unwrap_or_default().to_string(),
+            peripherals,
+        })
+    }
+
+    /// The peripheral called `name`, ignoring case
+    pub fn peripheral(&self, name: &str) -> Option<&Peripheral> {
+        self.peripherals
+            .iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// The registers and clusters in `parent`, with `prefix` and `offset` of the clusters around
+fn parse_registers(
+    parent: &Element,
+    properties: Properties,
+    prefix: &str,
+    offset: u32,
+) -> Result<Vec<Register>> {
+    let mut registers = Vec::new();
+
+    for element in &parent.children {
+        match element.name.as_str() {
+            "register" => {
+                let properties = properties.inherit(element)?;
+                let fields = match element.child("fields") {
+                    Some(fields) => fields
+                        .children("field")
+                        .map(parse_field)
+                        .collect::<Result<_>>()?,
+                    None => vec![],
+                };
+
+                for (name, dim_offset) in dim(element)? {
+                    registers.push(Register {
+                        name: format!("{}{}", prefix, name),
+                        description: description(element),
+                        offset: offset
+                            + number(required(element, "addressOffset")?)? as u32
+                            + dim_offset,
+                        size: properties.size,
+                        readable: properties.readable,
+                        read_action: element.child("readAction").is_some(),
+                        fields: fields.clone(),
+                    });
+                }
+            }
+            "cluster" => {
+                let properties = properties.inherit(element)?;
+                for (name, dim_offset) in dim(element)? {
+                    registers.extend(parse_registers(
+                        element,
+                        properties,
+                        &format!("{}{}.", prefix, name),
+                        offset + number(required(element, "addressOffset")?)? as u32 + dim_offset,
+                    )?);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    registers.sort_by_key(|register| register.offset);
+
+    Ok(registers)
+}
+
+fn parse_field(element: &Element) -> Result<Field> {
+    let name = required(element, "name")?;
+
+    let (offset, width) = if let Some(range) = element.child_text("bitRange") {
+        // `[msb:lsb]`
+        let (msb, lsb) = range
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid bit range {} of {}", range, name))?;
+        let (msb, lsb) = (number(msb)? as u32, number(lsb)? as u32);
+        (lsb, (msb + 1).saturating_sub(lsb))
+    } else if let (Some(lsb), Some(msb)) = (element.child_text("lsb"), element.child_text("msb")) {
+        let (msb, lsb) = (number(msb)? as u32, number(lsb)? as u32);
+        (lsb, (msb + 1).saturating_sub(lsb))
+    } else {
+        (
+            number(required(element, "bitOffset")?)? as u32,
+            match element.child_text("bitWidth") {
+                Some(width) => number(width)? as u32,
+                None => 1,
+            },
+        )
+    };
+
+    // Names of the values written have nothing to do with what is read
+    let values = element
+        .children("enumeratedValues")
+        .filter(|values| values.child_text("usage") != Some("write"))
+        .flat_map(|values| values.children("enumeratedValue"))
+        .filter_map(|value| {
+            let name = value.child_text("name")?;
+            let value = value.child_text("value")?;
+            Some(number(value).map(|value| (name.to_string(), value)))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Field {
+        name: name.to_string(),
+        description: description(element),
+        offset,
+        width,
+        values,
+    })
+}
+
+/// The names and offsets of the instances of an array, or the name alone if it is not one
+///
+/// `CH%s` or `CH[%s]` with a `dim` of 2 gives `CH0` and `CH1`, or `CH[0]` and `CH[1]`.
+fn dim(element: &Element) -> Result<Vec<(String, u32)>> {
+    let name = required(element, "name")?;
+    let count = match element.child_text("dim") {
+        Some(count) => number(count)? as u32,
+        None => return Ok(vec![(name.to_string(), 0)]),
+    };
+    let increment = number(required(element, "dimIncrement")?)? as u32;
+
+    let indices: Vec<String> = match element.child_text("dimIndex") {
+        Some(index) => match index.split_once('-') {
+            Some((first, last)) if !index.contains(',') => (number(first)?..=number(last)?)
+                .map(|i| i.to_string())
+                .collect(),
+            _ => index.split(',').map(|i| i.trim().to_string()).collect(),
+        },
+        None => (0..count).map(|i| i.to_string()).collect(),
+    };
+
+    Ok(indices
+        .iter()
+        .take(count as usize)
+        .enumerate()
+        .map(|(i, index)| (name.replace("%s", index), i as u32 * increment))
+        .collect())
+}
+
+fn required<'a>(element: &'a Element, name: &str) -> Result<&'a str> {
+    element
+        .child_text(name)
+        .ok_or_else(|| anyhow!("`{}` without `{}`", element.name, name))
+}
+
+/// Description with the line breaks and indentation of the SVD file removed
+fn description(element: &Element) -> Option<String> {
+    element
+        .child_text("description")
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// A number in SVD notation, decimal, `0x` hexadecimal or `#` binary
+fn number(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let parsed = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix('#') {
+        u64::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+
+    parsed.map_err(|_| anyhow!("Invalid number `{}`", text))
+}
+
+impl Register {
+    /// Value of each field in the register value, with its name if it has one
+    pub fn decode(&self, value: u64) -> Vec<(&Field, u64, Option<&str>)> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let mask = if field.width >= 64 {
+                    u64::MAX
+                } else {
+                    (1 << field.width) - 1
+                };
+                let v = (value >> field.offset) & mask;
+                let name = field
+                    .values
+                    .iter()
+                    .find(|(_, value)| *value == v)
+                    .map(|(name, _)| name.as_str());
+
+                (field, v, name)
+            })
+            .collect()
+    }
+
+    fn read(&self, memory: &mut impl ReadMemory, address: u64) -> Result<u64> {
+        let mut bytes = vec![0; (self.size as usize).div_ceil(8)];
+        memory.read_8(address, &mut bytes)?;
+
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+}
+
+impl Peripheral {
+    /// Read the registers and print them with the value of each field, only the ones whose name
+    /// contains `filter` if given
+    ///
+    /// Write-only registers and ones where reading has side effects are not read.
+    pub fn write_registers(
+        &self,
+        memory: &mut impl ReadMemory,
+        filter: Option<&str>,
+        w: &mut impl Write,
+    ) -> Result<()> {
+        write!(w, "{} @ {:#010x}", self.name, self.base_address)?;
+        match &self.description {
+            Some(description) => writeln!(w, ": {}", description)?,
+            None => writeln!(w)?,
+        }
+
+        let filter = filter.map(|filter| filter.to_ascii_uppercase());
+        for register in &self.registers {
+            if let Some(filter) = &filter {
+                if !register.name.to_ascii_uppercase().contains(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            let address = self.base_address + register.offset;
+            write!(w, "  {} {:#010x} = ", register.name, address)?;
+            if !register.readable {
+                writeln!(w, "<write-only>")?;
+                continue;
+            }
+            if register.read_action {
+                writeln!(w, "<not read, reading has side effects>")?;
+                continue;
+            }
+            let value = match register.read(memory, u64::from(address)) {
+                Ok(value) => value,
+                Err(e) => {
+                    writeln!(w, "<read failed: {}>", e)?;
+                    continue;
+                }
+            };
+            writeln!(
+                w,
+                "{:#0width$x}",
+                value,
+                width = register.size as usize / 4 + 2
+            )?;
+
+            for (field, value, name) in register.decode(value) {
+                let bits = if field.width == 1 {
+                    format!("[{}]", field.offset)
+                } else {
+                    format!("[{}:{}]", field.offset + field.width - 1, field.offset)
+                };
+                write!(w, "    {}{} = {}", field.name, bits, value)?;
+                match name {
+                    Some(name) => writeln!(w, " ({})", name)?,
+                    None => writeln!(w)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use std::iter::FromIterator;
+use std::ops::Range;
+
+/// Address ranges sorted by start, to find the one an address falls in
+///
+/// Ranges may overlap, e.g. a symbol for the tail of a merged string constant inside the symbol
+/// for the whole of it. The innermost range, the one starting closest before the address, wins.
+#[derive(Debug, Default, Clone)]
+pub struct Intervals {
+    ranges: Vec<Range<usize>>,
+    /// Largest end of the ranges up to and including each index, to know when to stop looking
+    max_end: Vec<usize>,
+}
+
+impl Intervals {
+    /// `ranges` have to be sorted by start
+    pub fn new(ranges: Vec<Range<usize>>) -> Self {
+        debug_assert!(ranges.windows(2).all(|w| w[0].start <= w[1].start));
+
+        let max_end = ranges
+            .iter()
+            .scan(0, |max, range| {
+                *max = range.end.max(*max);
+                Some(*max)
+            })
+            .collect();
+
+        Intervals { ranges, max_end }
+    }
+
+    /// Index of the range containing `address`, and the offset into it
+    ///
+    /// A range starting exactly at the address matches even if it is empty.
+    pub fn find(&self, address: usize) -> Option<(usize, usize)> {
+        let after = self.ranges.partition_point(|range| range.start <= address);
+
+        (0..after)
+            .rev()
+            .take_while(|&i| self.max_end[i] > address || self.ranges[i].start == address)
+            .find(|&i| self.ranges[i].contains(&address) || self.ranges[i].start == address)
+            .map(|i| (i, address - self.ranges[i].start))
+    }
+}
+
+/// Strings in the ELF by the address range of their symbol
+///
+/// Looking up an address inside a symbol gives the rest of the string from there, as a pointer
+/// into a merged string constant refers to its tail.
+#[derive(Debug, Default, Clone)]
+pub struct Symbols<'a> {
+    intervals: Intervals,
+    strings: Vec<(usize, &'a str)>,
+}
+
+impl<'a> Symbols<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The string at `address`
+    pub fn get(&self, address: usize) -> Option<&'a str> {
+        let (i, offset) = self.intervals.find(address)?;
+
+        self.strings[i].1.get(offset..)
+    }
+
+    /// The strings by the start address of their symbol, in address order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &'a str)> + '_ {
+        self.strings.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl<'a> FromIterator<(usize, &'a str)> for Symbols<'a> {
+    /// If several symbols start at the same address, the last one is kept
+    fn from_iter<I: IntoIterator<Item = (usize, &'a str)>>(iter: I) -> Self {
+        let mut strings: Vec<_> = iter.into_iter().collect();
+        // Stable, so the last of the duplicates is still last
+        strings.sort_by_key(|&(address, _)| address);
+        strings.reverse();
+        strings.dedup_by_key(|&mut (address, _)| address);
+        strings.reverse();
+
+        let intervals = Intervals::new(
+            strings
+                .iter()
+                .map(|&(address, s)| address..address + s.len())
+                .collect(),
+        );
+
+        Symbols { intervals, strings }
+    }
+}
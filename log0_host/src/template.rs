@@ -0,0 +1,147 @@
+use crate::{
+    format_string::parse_spec,
+    record::{Level, Record, World},
+};
+use anyhow::{anyhow, Result};
+use elf_test::FormatOptions;
+use std::str::FromStr;
+
+/// A field of a record that can be put in a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Time,
+    /// `warn` or `error` for messages of `warn!` and `error!`, empty for `log!`
+    Level,
+    Task,
+    /// `secure` or `non-secure`, on targets with a ring in each security world
+    World,
+    /// Index of the core, on targets with a ring for each core
+    Core,
+    Module,
+    Id,
+    Type,
+    Message,
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "time" => Field::Time,
+            "level" => Field::Level,
+            "task" => Field::Task,
+            "world" => Field::World,
+            "core" => Field::Core,
+            "module" => Field::Module,
+            "id" => Field::Id,
+            "type" => Field::Type,
+            "message" => Field::Message,
+            _ => {
+                return Err(anyhow!(
+                "Unknown field `{}`, expected time, level, task, world, core, module, id, type or \
+                     message",
+                s
+            ))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field, FormatOptions),
+}
+
+/// Layout of the lines printed for each message, e.g. `[{time}] {level:>5} {module}: {message}`
+///
+/// Fields take the width, alignment and precision options of `core::fmt`, and fields a
+/// message does not have are empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Template::parse(s)
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+
+        while let Some(pos) = rest.find(&['{', '}'][..]) {
+            literal.push_str(&rest[..pos]);
+            let c = &rest[pos..pos + 1];
+            rest = &rest[pos + 1..];
+
+            if let Some(r) = rest.strip_prefix(c) {
+                literal.push_str(c);
+                rest = r;
+                continue;
+            }
+            if c == "}" {
+                return Err(anyhow!("Unmatched `}}` in {:?}", template));
+            }
+
+            let end = rest
+                .find('}')
+                .ok_or_else(|| anyhow!("Unterminated `{{` in {:?}", template))?;
+            let placeholder = &rest[..end];
+            rest = &rest[end + 1..];
+
+            let (name, spec) = match placeholder.find(':') {
+                Some(colon) => (&placeholder[..colon], &placeholder[colon + 1..]),
+                None => (placeholder, ""),
+            };
+            let options = parse_spec(spec).map_err(|e| anyhow!("{} in {:?}", e, template))?;
+
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(Part::Field(name.trim().parse()?, options));
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template { parts })
+    }
+
+    /// The line for `record`
+    pub fn render(&self, record: &Record) -> String {
+        let mut out = Vec::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => out.extend_from_slice(s.as_bytes()),
+                Part::Field(field, options) => {
+                    let id = record.id.map(|id| id.to_string());
+                    let core = record.core.map(|core| core.to_string());
+                    let value = match field {
+                        Field::Time => record.timestamp.as_deref(),
+                        Field::Level => record.level.map(Level::as_str),
+                        Field::Task => record.task.as_deref(),
+                        Field::World => record.world.map(World::as_str),
+                        Field::Core => core.as_deref(),
+                        Field::Module => record.module.as_deref(),
+                        Field::Id => id.as_deref(),
+                        Field::Type => record.type_name.as_deref(),
+                        Field::Message => Some(record.message.as_str()),
+                    };
+                    options.write_str(&mut out, value.unwrap_or("")).ok();
+                }
+            }
+        }
+
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
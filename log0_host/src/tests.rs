@@ -1,18 +1,9 @@
-fn leb128_write(v: &mut Vec<u8>, mut word: u32) {
-    for _ in 0..5 {
-        let mut byte = (word & 0x7f) as u8;
-        word >>= 7;
-
-        if word != 0 {
-            byte |= crate::leb128::CONTINUE;
-        }
-        v.push(byte);
-
-        if word == 0 {
-            return;
-        }
-    }
-}
+use crate::leb128::encode_u32 as leb128_write;
+use crate::parser::{Packet, Parser};
+use crate::probe;
+use crate::reader::{Poll, Reader};
+use crate::sim::{MockTransport, SimTarget};
+use crate::transport::Transport;
 
 #[test]
 fn encode_and_parse() {
@@ -44,13 +35,4445 @@ fn encode_and_parse() {
         Some(crate::parser::Packet {
             string_loc: 0xcafe,
             type_loc: 0xdeafbeef,
+            timestamp: None,
+            task: None,
             buffer: vec![1, 2, 3, 4, 5]
         })
     );
 }
 
+#[test]
+fn leb128_overlong() {
+    // u32::MAX needs 5 bytes, anything longer is malformed
+    assert_eq!(
+        crate::leb128::decode_u32([0xff, 0xff, 0xff, 0xff, 0x0f].iter()),
+        Ok((u32::MAX, 5))
+    );
+    assert_eq!(
+        crate::leb128::decode_u32([0x80, 0x80, 0x80, 0x80, 0x80, 0x00].iter()),
+        Err(())
+    );
+
+    // The parser must skip past malformed data instead of getting stuck on it
+    let mut parser = Parser::new();
+    parser.push(&[0x80; 5]);
+    assert_eq!(parser.try_parse(), None);
+
+    let mut buf = Vec::new();
+    leb128_write(&mut buf, 1);
+    leb128_write(&mut buf, 2);
+    leb128_write(&mut buf, 3);
+    buf.push(4);
+    parser.push(&buf);
+    assert_eq!(
+        parser.try_parse(),
+        Some(Packet {
+            string_loc: 2,
+            type_loc: 3,
+            timestamp: None,
+            task: None,
+            buffer: vec![4]
+        })
+    );
+}
+
 #[test]
 fn data_to_read() {
     let buf_size = 1024;
     assert_eq!(crate::bytes_to_read(1022, 8, buf_size), 10);
 }
+
+#[test]
+fn read_plan_wraps() {
+    let plan = crate::plan_read(1022, 8, 1024);
+    assert_eq!(plan.first, 1022..1024);
+    assert_eq!(plan.second, 0..8);
+    assert_eq!(plan.new_host_idx, 8);
+    assert_eq!(plan.len(), 10);
+
+    let plan = crate::plan_read(1000, 0, 1024);
+    assert_eq!(plan.first, 1000..1024);
+    assert!(plan.second.is_empty());
+    assert_eq!(plan.new_host_idx, 0);
+
+    let mut plan = crate::plan_read(1022, 8, 1024);
+    plan.limit(5, 1024);
+    assert_eq!(
+        (plan.first, plan.second, plan.new_host_idx),
+        (1022..1024, 0..3, 3)
+    );
+
+    let mut plan = crate::plan_read(1020, 8, 1024);
+    plan.limit(4, 1024);
+    assert_eq!(
+        (plan.first, plan.second, plan.new_host_idx),
+        (1020..1024, 0..0, 0)
+    );
+
+    let mut plan = crate::plan_read(10, 20, 1024);
+    plan.limit(4, 1024);
+    assert_eq!((plan.first, plan.new_host_idx), (10..14, 14));
+}
+
+/// Drain the simulated ring the same way the host does against a real target
+fn drain(reader: &mut Reader, transport: &mut impl Transport, parser: &mut Parser) -> Vec<Packet> {
+    match reader.poll(transport).unwrap() {
+        Poll::Data(read) => parser.push(read),
+        Poll::Resync | Poll::Overrun { .. } => parser.reset(),
+        Poll::Dropped(_) | Poll::Idle => {}
+    }
+
+    std::iter::from_fn(|| parser.try_parse()).collect()
+}
+
+#[test]
+fn simulated_ring_end_to_end() {
+    use xmas_elf::ElfFile;
+
+    // The ring and the call sites of the fixture, with the frames its calls send and what the
+    // host decodes from them
+    let fixture = &log_fixtures()[0];
+    let elf = std::fs::read(fixture).unwrap();
+    let file = ElfFile::new(&elf).unwrap();
+    let res = crate::fmt::extract_format_and_type_strings(&file).unwrap();
+    let mut parser = Parser::new();
+    parser.push(&std::fs::read(fixture.with_extension("frames")).unwrap());
+    let frames: Vec<_> = std::iter::from_fn(|| parser.try_parse()).collect();
+    let lines: Vec<_> = include_str!("../../elf_test/tests/fixtures/log/log.jsonl")
+        .lines()
+        .collect();
+    assert_eq!(frames.len(), lines.len());
+
+    // A small buffer so frames regularly straddle the end of the ring
+    let mut transport = MockTransport::new(SimTarget::new(
+        res.cursor_address as u32,
+        res.buffer_address as u32,
+        res.buffer_size,
+    ));
+    let mut reader = Reader::new(res.buffer_size);
+    let mut parser = Parser::new();
+
+    let mut sent = Vec::new();
+    let mut expected = String::new();
+    let mut received = Vec::new();
+
+    for i in 0..500 {
+        let frame = &frames[i % frames.len()];
+        if transport.target.log(
+            frame.string_loc as u32,
+            frame.type_loc as u32,
+            &frame.buffer,
+        ) {
+            sent.push(frame);
+            expected += lines[i % lines.len()];
+            expected += "\n";
+        }
+
+        // The host polls slower than the target logs
+        if i % 8 == 0 {
+            received.extend(drain(&mut reader, &mut transport, &mut parser));
+        }
+    }
+    received.extend(drain(&mut reader, &mut transport, &mut parser));
+
+    assert!(
+        transport.target.dropped() > 0,
+        "test should exercise a full buffer"
+    );
+    assert_eq!(sent.len() + transport.target.dropped(), 500);
+    assert_eq!(received.iter().collect::<Vec<_>>(), sent);
+    assert_eq!(decode_fixture(&elf, &received), expected);
+}
+
+#[test]
+fn target_writer_end_to_end() {
+    use crate::parser::BOOT_FRAME;
+    use crate::sim::TargetTransport;
+
+    // The frames of the fixture written into the ring of `log0_target` by its own writer, the
+    // host polls slower than it logs
+    let fixture = &log_fixtures()[0];
+    let elf = std::fs::read(fixture).unwrap();
+    let mut parser = Parser::new();
+    parser.push(&std::fs::read(fixture.with_extension("frames")).unwrap());
+    let frames: Vec<_> = std::iter::from_fn(|| parser.try_parse()).collect();
+    let lines: Vec<_> = include_str!("../../elf_test/tests/fixtures/log/log.jsonl")
+        .lines()
+        .collect();
+
+    // The only test using the ring of `log0_target`
+    let cursors = unsafe { log0_target::cursors() };
+    let mut transport = TargetTransport(cursors);
+    let mut reader = Reader::new(log0_target::LOG0_CAPACITY);
+    let mut parser = Parser::new();
+
+    let mut sent = Vec::new();
+    let mut expected = String::new();
+    let mut received = Vec::new();
+
+    for i in 0..500 {
+        let frame = &frames[i % frames.len()];
+        let dropped = cursors.dropped();
+        cursors.write_frame(
+            frame.string_loc as *const u8,
+            frame.type_loc as *const u8,
+            &frame.buffer,
+        );
+        if cursors.dropped() == dropped {
+            sent.push(frame);
+            expected += lines[i % lines.len()];
+            expected += "\n";
+        }
+
+        if i % 128 == 0 {
+            received.extend(drain(&mut reader, &mut transport, &mut parser));
+        }
+    }
+    received.extend(drain(&mut reader, &mut transport, &mut parser));
+
+    // Written before the first frame
+    assert_eq!(received.remove(0).string_loc, BOOT_FRAME);
+    assert!(cursors.dropped() > 0, "test should exercise a full buffer");
+    assert_eq!(sent.len() + cursors.dropped(), 500);
+    assert_eq!(received.iter().collect::<Vec<_>>(), sent);
+    assert_eq!(decode_fixture(&elf, &received), expected);
+}
+
+#[test]
+fn reader_reports_lost_frames() {
+    let packet = |i: u32| Packet {
+        string_loc: 0x1000,
+        type_loc: 0x8000_0000 + i as usize,
+        timestamp: None,
+        task: None,
+        buffer: vec![i as u8; 5],
+    };
+
+    // New frames are dropped, what is in the buffer is intact
+    let mut transport = MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 32));
+    let mut reader = Reader::new(32).with_dropped_count(false);
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Idle);
+    for i in 0..5 {
+        transport.target.log(0x1000, 0x8000_0000 + i, &[i as u8; 5]);
+    }
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Dropped(3));
+    let mut parser = Parser::new();
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        (0..2).map(packet).collect::<Vec<_>>()
+    );
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Idle);
+
+    // The oldest frames are overwritten, and the host skips to the target cursor
+    let mut transport =
+        MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 32).with_overwrite());
+    let mut reader = Reader::new(32).with_dropped_count(true);
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Idle);
+    for i in 0..5 {
+        assert!(transport.target.log(0x1000, 0x8000_0000 + i, &[i as u8; 5]));
+    }
+    assert_eq!(
+        reader.poll(&mut transport).unwrap(),
+        Poll::Overrun {
+            frames: 3,
+            skipped: 26
+        }
+    );
+    let [target, host] = transport.read_cursors().unwrap();
+    assert_eq!(host, target);
+
+    assert!(transport.target.log(0x1000, 0x8000_0005, &[5; 5]));
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        vec![packet(5)]
+    );
+
+    // A host that is far behind only ever gets whole frames that were sent, chunks are not
+    // used as the target needs the host cursor at the start of a frame
+    let mut transport =
+        MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 61).with_overwrite());
+    let mut reader = Reader::new(61).with_dropped_count(true);
+    reader.set_chunk(7);
+    let mut parser = Parser::new();
+    let mut sent = Vec::new();
+    let mut received = Vec::new();
+    let mut lost = 0;
+    for i in 0..300u32 {
+        let data: Vec<u8> = (0..i % 11).map(|b| b as u8 ^ i as u8).collect();
+        assert!(transport.target.log(0x1000 + i % 3, 0x8000_0000 + i, &data));
+        sent.push(Packet {
+            string_loc: 0x1000 + (i % 3) as usize,
+            type_loc: 0x8000_0000 + i as usize,
+            timestamp: None,
+            task: None,
+            buffer: data,
+        });
+
+        if i % 7 == 0 || i == 299 {
+            match reader.poll(&mut transport).unwrap() {
+                Poll::Data(read) => parser.push(read),
+                Poll::Overrun { frames, .. } => {
+                    lost += frames as usize;
+                    parser.reset();
+                }
+                poll => panic!("unexpected {:?}", poll),
+            }
+            received.extend(std::iter::from_fn(|| parser.try_parse()));
+        }
+    }
+
+    assert!(lost > 0, "test should exercise overwrites");
+    assert_eq!(lost, transport.target.dropped());
+    let mut rest = sent.iter();
+    for packet in &received {
+        assert!(rest.any(|sent| sent == packet), "{:?} was not sent", packet);
+    }
+}
+
+#[test]
+fn reader_drains_banks() {
+    let packet = |i: u32| Packet {
+        string_loc: 0x1000,
+        type_loc: 0x8000_0000 + i as usize,
+        timestamp: None,
+        task: None,
+        buffer: vec![i as u8; 5],
+    };
+    let log = |transport: &mut MockTransport, i: u32| {
+        transport.target.log(0x1000, 0x8000_0000 + i, &[i as u8; 5])
+    };
+
+    // Two 13 byte frames fit in a bank. The first one is handed over right away, the next ones
+    // fill the other bank and the one after that is dropped.
+    let mut transport =
+        MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 64).with_banks());
+    let mut reader = Reader::new(64).with_dropped_count(false).with_banks();
+    let mut parser = Parser::new();
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Idle);
+    for i in 0..3 {
+        assert!(log(&mut transport, i));
+    }
+    assert!(!log(&mut transport, 3));
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Dropped(1));
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        vec![packet(0)]
+    );
+
+    // The full bank is only handed over when the target writes again
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Idle);
+    assert!(log(&mut transport, 4));
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        vec![packet(1), packet(2)]
+    );
+    assert!(log(&mut transport, 5));
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        vec![packet(4), packet(5)]
+    );
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Idle);
+
+    // A target that was reset starts over at sequence number 0
+    transport.target = SimTarget::new(0x2000_0000, 0x2000_0100, 64).with_banks();
+    transport.write_host_cursor(3).unwrap();
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Resync);
+    assert_eq!(reader.resyncs(), 1);
+    assert!(log(&mut transport, 6));
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        vec![packet(6)]
+    );
+
+    // Whatever the host polls, it gets the frames that were not dropped in order
+    let mut transport =
+        MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 61).with_banks());
+    let mut reader = Reader::new(61).with_banks();
+    let mut sent = Vec::new();
+    let mut received = Vec::new();
+    for i in 0..300u32 {
+        let data: Vec<u8> = (0..i % 11).map(|b| b as u8 ^ i as u8).collect();
+        if transport.target.log(0x1000 + i % 3, 0x8000_0000 + i, &data) {
+            sent.push(Packet {
+                string_loc: 0x1000 + (i % 3) as usize,
+                type_loc: 0x8000_0000 + i as usize,
+                timestamp: None,
+                task: None,
+                buffer: data,
+            });
+        }
+
+        if i % 7 == 0 {
+            received.extend(drain(&mut reader, &mut transport, &mut parser));
+        }
+    }
+    received.extend(drain(&mut reader, &mut transport, &mut parser));
+
+    assert!(
+        transport.target.dropped() > 0,
+        "test should exercise full banks"
+    );
+    assert_eq!(received[..], sent[..received.len()]);
+    // The last bank is handed over by the next write
+    assert!(sent.len() - received.len() <= 5);
+}
+
+#[test]
+fn reader_reads_in_chunks() {
+    let mut transport = MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 61));
+    let mut reader = Reader::new(61);
+    reader.set_chunk(5);
+    let mut parser = Parser::new();
+
+    let mut sent = Vec::new();
+    for i in 0..40u32 {
+        let data = vec![i as u8; (i % 9) as usize];
+        if transport.target.log(0x1000, 0x8000_0000 + i, &data) {
+            sent.push(Packet {
+                string_loc: 0x1000,
+                type_loc: 0x8000_0000 + i as usize,
+                timestamp: None,
+                task: None,
+                buffer: data,
+            });
+        }
+
+        // Each poll frees at most one chunk
+        let before = transport.read_cursors().unwrap()[1] as usize;
+        let received = drain(&mut reader, &mut transport, &mut parser);
+        let after = transport.read_cursors().unwrap()[1] as usize;
+        assert!(crate::bytes_to_read(before, after, 61) <= 5);
+        assert!(sent.len() >= received.len());
+        sent.retain(|packet| !received.contains(packet));
+    }
+
+    while let Poll::Data(read) = reader.poll(&mut transport).unwrap() {
+        parser.push(read);
+    }
+    let rest: Vec<_> = std::iter::from_fn(|| parser.try_parse()).collect();
+    assert_eq!(rest, sent);
+    assert_eq!(reader.chunk(), 5);
+}
+
+#[test]
+fn read_chunk_tuning() {
+    use crate::tune;
+    use std::time::Duration;
+
+    let us = Duration::from_micros;
+    // 1 ms per transfer and 1 µs per byte, plus some noise
+    let samples = vec![
+        (4, us(1004)),
+        (16, us(1017)),
+        (64, us(1063)),
+        (256, us(1256)),
+    ];
+    let (overhead, per_byte) = tune::fit(&samples);
+    assert!((overhead.as_secs_f64() - 1e-3).abs() < 1e-5);
+    assert!((per_byte.as_secs_f64() - 1e-6).abs() < 1e-8);
+
+    assert_eq!(tune::pick_chunk(us(1000), us(1), 1 << 16), 32768);
+    assert_eq!(tune::pick_chunk(us(1000), us(1), 1024), 1024);
+    assert_eq!(tune::pick_chunk(Duration::from_nanos(10), us(1), 1024), 64);
+    assert_eq!(
+        tune::pick_chunk(us(1000), Duration::from_secs(0), 1024),
+        1024
+    );
+    assert_eq!(tune::pick_chunk(us(0), us(1), 32), 32);
+
+    let mut transport = MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 100));
+    let sizes: Vec<_> = tune::measure(&mut transport, 100)
+        .unwrap()
+        .into_iter()
+        .map(|(size, _)| size)
+        .collect();
+    assert_eq!(sizes, vec![4, 16, 64, 100]);
+}
+
+#[test]
+fn reader_recovers_from_transfer_errors() {
+    let mut transport = MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 32));
+    let mut reader = Reader::new(32);
+    let mut parser = Parser::new();
+
+    assert!(transport.target.log(1, 2, &[1, 2, 3]));
+
+    // Fail reading the cursors, the buffer and writing the host cursor in turn, the data must
+    // still be there afterwards
+    for ok in 0..3 {
+        transport.fail_after(ok);
+        assert!(reader.poll(&mut transport).is_err());
+        assert_eq!(transport.read_cursors().unwrap()[1], 0);
+    }
+
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        vec![Packet {
+            string_loc: 1,
+            type_loc: 2,
+            timestamp: None,
+            task: None,
+            buffer: vec![1, 2, 3]
+        }]
+    );
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Idle);
+}
+
+#[test]
+fn reader_resyncs_on_corrupt_cursors() {
+    let mut transport = MockTransport::new(SimTarget::new(0x2000_0000, 0x2000_0100, 32));
+    let mut reader = Reader::new(32);
+    let mut parser = Parser::new();
+
+    assert!(transport.target.log(1, 2, &[1, 2, 3]));
+
+    // Host cursor out of range, everything unread is skipped
+    transport.target.write_word_32(0x2000_0004, 1000);
+    assert_eq!(reader.poll(&mut transport).unwrap(), Poll::Resync);
+    assert_eq!(reader.resyncs(), 1);
+    assert_eq!(
+        transport.read_cursors().unwrap(),
+        [6, 6],
+        "host cursor should be moved to the target cursor"
+    );
+
+    assert!(transport.target.log(3, 4, &[5]));
+    assert_eq!(
+        drain(&mut reader, &mut transport, &mut parser),
+        vec![Packet {
+            string_loc: 3,
+            type_loc: 4,
+            timestamp: None,
+            task: None,
+            buffer: vec![5]
+        }]
+    );
+}
+
+#[test]
+fn format_string_escapes() {
+    use crate::format_string::{parse_spec, FormatString, Segment};
+
+    let f = FormatString::parse("{{literal}} {} and {:08x}, }}{{").unwrap();
+    assert_eq!(
+        f.segments(),
+        &[
+            Segment::Literal("{literal} ".into()),
+            Segment::Argument {
+                index: 0,
+                options: parse_spec("").unwrap(),
+            },
+            Segment::Literal(" and ".into()),
+            Segment::Argument {
+                index: 1,
+                options: parse_spec("08x").unwrap(),
+            },
+            Segment::Literal(", }{".into()),
+        ]
+    );
+    assert_eq!(f.arguments(), 2);
+    assert_eq!(
+        f.render(|i, _| format!("<{}>", i)),
+        "{literal} <0> and <1>, }{"
+    );
+
+    // Arbitrary UTF-8 passes through untouched
+    let f = FormatString::parse("temp → {0} °C, again {0:.1} ✓").unwrap();
+    assert_eq!(f.arguments(), 1);
+    assert_eq!(f.render(|_, _| "21".into()), "temp → 21 °C, again 21 ✓");
+
+    assert_eq!(FormatString::parse("").unwrap().segments(), &[]);
+}
+
+#[test]
+fn format_string_unbalanced() {
+    use crate::format_string::FormatString;
+
+    for s in &[
+        "{", "}", "a } b", "{:x", "{{}", "{ {} }", "{name}", "{:1$}", "{:.*}", "{:y}",
+    ] {
+        assert!(FormatString::parse(s).is_err(), "{:?} should not parse", s);
+    }
+}
+
+#[test]
+fn format_options_match_core_fmt() {
+    use crate::format_string::parse_spec;
+    use elf_test::BaseType;
+
+    macro_rules! check {
+        ($spec:literal, $typ:expr, $v:expr) => {{
+            let options = parse_spec($spec).unwrap();
+            let mut out = Vec::new();
+            $typ.write_with(&mut out, &$v.to_le_bytes(), &options)
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                format!(concat!("{:", $spec, "}"), $v),
+                $spec
+            );
+        }};
+    }
+
+    check!(">8", BaseType::Unsigned(4), 42u32);
+    check!("<8", BaseType::Unsigned(4), 42u32);
+    check!("*^9", BaseType::Signed(2), -42i16);
+    check!("08x", BaseType::Unsigned(4), 0xbeefu32);
+    check!("#010x", BaseType::Unsigned(4), 0xbeefu32);
+    check!("#X", BaseType::Unsigned(8), u64::MAX);
+    check!("x", BaseType::Signed(1), -1i8);
+    check!("#o", BaseType::Unsigned(2), 0o755u16);
+    check!("#b", BaseType::Unsigned(1), 5u8);
+    check!("+", BaseType::Signed(4), 7i32);
+    check!("+05", BaseType::Signed(4), -7i32);
+    check!("", BaseType::Signed(16), i128::MIN);
+    check!("e", BaseType::Unsigned(4), 1234u32);
+    check!(".3", BaseType::F32, 1.23456f32);
+    check!(".3", BaseType::F64, -2.0f64);
+    check!("10.2", BaseType::F32, 0.125f32);
+    check!("010.2", BaseType::F64, -0.125f64);
+    check!("<10.2", BaseType::F64, 1.5f64);
+    check!("+", BaseType::F32, 0.1f32);
+    check!(".2e", BaseType::F64, 123456.0f64);
+    check!("E", BaseType::F32, 0.00025f32);
+}
+
+#[test]
+fn line_template() {
+    use crate::config::Config;
+    use crate::record::Record;
+    use crate::sink::{Sink, Terminal};
+    use crate::template::Template;
+
+    let record = Record {
+        id: Some(4),
+        timestamp: Some("1.500000".into()),
+        task: Some("uart".into()),
+        message: "rx {{ 3 }}".into(),
+        module: Some("app::serial".into()),
+        type_name: Some("u8".into()),
+        payload: vec![3],
+        ..Record::default()
+    };
+
+    let template: Template = "[{time}] {level:>5} {module}: {message}".parse().unwrap();
+    assert_eq!(
+        template.render(&record),
+        "[1.500000]       app::serial: rx {{ 3 }}"
+    );
+
+    let template = Template::parse("{{{id:03}}} {task:-^8}|{type:.1}|{module:.3}").unwrap();
+    assert_eq!(template.render(&record), "{4  } --uart--|u|app");
+
+    // Missing fields are empty
+    let bare = Record {
+        id: None,
+        timestamp: None,
+        task: None,
+        world: None,
+        core: None,
+        level: None,
+        module: None,
+        type_name: None,
+        ..record.clone()
+    };
+    let template = Template::parse("<{time}><{id}><{task}> {message}").unwrap();
+    assert_eq!(template.render(&bare), "<><><> rx {{ 3 }}");
+
+    for invalid in &["{time", "time}", "{when}", "{time:1$}"] {
+        assert!(Template::parse(invalid).is_err(), "{}", invalid);
+    }
+
+    let mut out = Vec::new();
+    Terminal::new(&mut out, false)
+        .with_template(Some(Template::parse("{task}> {message}").unwrap()))
+        .write(&record)
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "uart> rx {{ 3 }}\n");
+
+    let config = Config::parse(r#"format = "{time} {message}""#).unwrap();
+    assert_eq!(
+        config.template().unwrap().unwrap().render(&record),
+        "1.500000 rx {{ 3 }}"
+    );
+    assert!(Config::parse(r#"format = "{message""#).is_err());
+}
+
+#[test]
+fn config_field_annotations() {
+    use crate::config::Config;
+
+    let config = Config::parse(
+        r#"
+        [fields.vbat_mv]
+        unit = "V"
+        scale = 0.001
+        precision = 3
+
+        [fields."Telemetry.temp"]
+        unit = "°C"
+
+        [fields.current]
+        q = "Q7.8"
+
+        [types.Q15]
+        q = "Q15"
+
+        [types.Events]
+        flags = { RX_DONE = 0x01, CRC_ERR = 0x04, ERR = 0x04 }
+
+        [fields.code]
+        enum = "ErrorCode"
+        symbols = { ERR_TIMEOUT = 0x23, TIMEOUT = 0x23, ERR_NACK = 36 }
+
+        [time]
+        cpu_hz = 64_000_000
+        "#,
+    )
+    .unwrap();
+
+    let annotations = config.annotations().unwrap();
+    let fields = &annotations.fields;
+    assert_eq!(fields.len(), 4);
+    assert_eq!(fields["vbat_mv"].scale, Some(0.001));
+    assert_eq!(fields["vbat_mv"].precision, Some(3));
+    assert_eq!(fields["vbat_mv"].fractional_bits, None);
+    assert_eq!(fields["Telemetry.temp"].unit.as_deref(), Some("°C"));
+    assert_eq!(fields["Telemetry.temp"].scale, None);
+    assert_eq!(fields["current"].fractional_bits, Some(8));
+    assert_eq!(annotations.types["Q15"].fractional_bits, Some(15));
+    assert_eq!(fields["code"].enumeration.as_deref(), Some("ErrorCode"));
+    assert_eq!(
+        fields["code"].constants.iter().collect::<Vec<_>>(),
+        [
+            (&0x23, &"ERR_TIMEOUT".to_string()),
+            (&0x24, &"ERR_NACK".to_string())
+        ]
+    );
+    assert!(fields["vbat_mv"].constants.is_empty());
+    assert_eq!(
+        annotations.types["Events"]
+            .flags
+            .values()
+            .collect::<Vec<_>>(),
+        ["RX_DONE", "CRC_ERR"]
+    );
+    assert_eq!(config.time.cpu_hz, Some(64_000_000));
+    assert_eq!(config.time.timestamp_hz, None);
+
+    assert!(Config::parse("[fields.x]\nscael = 2.0").is_err());
+    assert!(Config::parse("[fields.x]\nq = \"1.15\"").is_err());
+    assert!(Config::parse("[types.x]\nq = \"Q1.\"").is_err());
+    assert!(Config::parse("[types.x]\nsymbols = { A = \"1\" }").is_err());
+    assert!(Config::parse("[types.x]\nflags = { A = -1 }").is_err());
+    assert!(Config::parse("").unwrap().fields.is_empty());
+}
+
+#[test]
+fn raw_side_by_side() {
+    use crate::render::side_by_side;
+
+    let raw: Vec<u8> = (0..20).collect();
+    assert_eq!(
+        side_by_side(&raw, "Foo {\n    a: 1,\n    b: 2,\n}"),
+        "00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f | Foo {\n\
+         10 11 12 13                                     |     a: 1,\n\
+         \x20                                               |     b: 2,\n\
+         \x20                                               | }\n"
+    );
+    assert_eq!(
+        side_by_side(&[0xab], "171"),
+        "ab                                              | 171\n"
+    );
+    assert_eq!(side_by_side(&[], ""), "");
+}
+
+#[test]
+fn parse_timestamps() {
+    let mut v = Vec::new();
+    for (ts, data) in &[(100u32, 7u8), (u32::MAX, 8)] {
+        leb128_write(&mut v, 1);
+        leb128_write(&mut v, 0x10);
+        leb128_write(&mut v, 0x20);
+        leb128_write(&mut v, *ts);
+        v.push(*data);
+    }
+
+    let mut parser = Parser::with_timestamps();
+    for b in &v {
+        parser.push(&[*b]);
+    }
+    let first = parser.try_parse().unwrap();
+    assert_eq!((first.timestamp, first.buffer), (Some(100), vec![7]));
+    let second = parser.try_parse().unwrap();
+    assert_eq!((second.timestamp, second.buffer), (Some(u32::MAX), vec![8]));
+    assert_eq!(parser.try_parse(), None);
+}
+
+#[test]
+fn task_ids() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::sink::{Json, Sink, Terminal};
+    use crate::stats::{Count, Stats};
+    use crate::symbols::Symbols;
+    use elf_test::TypePrinters;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    // The task ID follows the timestamp
+    let mut v = Vec::new();
+    for (ts, task) in &[(100u32, 1u32), (101, 0), (102, 7)] {
+        leb128_write(&mut v, 1);
+        leb128_write(&mut v, 0x10);
+        leb128_write(&mut v, 0x20);
+        leb128_write(&mut v, *ts);
+        leb128_write(&mut v, *task);
+        v.push(0);
+    }
+
+    let mut parser = Parser::with_timestamps().with_tasks();
+    for b in &v {
+        parser.push(&[*b]);
+    }
+    let mut packets = Vec::new();
+    while let Some(packet) = parser.try_parse() {
+        packets.push(packet);
+    }
+    let tasks: Vec<_> = packets.iter().map(|p| (p.timestamp, p.task)).collect();
+    assert_eq!(
+        tasks,
+        [
+            (Some(100), Some(1)),
+            (Some(101), Some(0)),
+            (Some(102), Some(7))
+        ]
+    );
+
+    let mut parser = Parser::new().with_tasks();
+    parser.push(&[1, 0x10, 0x20, 3, 9]);
+    let packet = parser.try_parse().unwrap();
+    assert_eq!((packet.timestamp, packet.task), (None, Some(3)));
+
+    // IDs without a name are shown as they are
+    let strings: Symbols = vec![(0x10, "tick")].into_iter().collect();
+    let mut decoder = Decoder::new(
+        Catalog::new(&strings),
+        Symbols::new(),
+        TypePrinters(HashMap::new()),
+    )
+    .with_task_names(vec!["idle".into(), "uart".into()]);
+    let records: Vec<_> = packets
+        .iter()
+        .map(|packet| decoder.decode(packet, UNIX_EPOCH))
+        .collect();
+    let names: Vec<_> = records.iter().map(|r| r.task.as_deref()).collect();
+    assert_eq!(names, [Some("uart"), Some("idle"), Some("7")]);
+
+    let mut out = Vec::new();
+    let mut terminal = Terminal::new(&mut out, false);
+    for record in &records {
+        terminal.write(record).unwrap();
+    }
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "[100] [uart] tick\n[101] [idle] tick\n[102] [7] tick\n"
+    );
+
+    // A task keeps its color
+    let mut out = Vec::new();
+    let mut terminal = Terminal::new(&mut out, false).with_colors(true);
+    terminal.write(&records[0]).unwrap();
+    terminal.write(&records[0]).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], lines[1]);
+    assert!(lines[0].starts_with("[100] \x1b["));
+    assert!(lines[0].ends_with("[uart]\x1b[0m tick"));
+
+    let mut out = Vec::new();
+    Json::new(&mut out).write(&records[0]).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"id\":0,\"timestamp\":\"100\",\"task\":\"uart\",\"message\":\"tick\"}\n"
+    );
+
+    let mut stats = Stats::new();
+    for record in records.iter().chain(&records[..1]) {
+        stats.record(record);
+    }
+    let one = Count {
+        messages: 1,
+        bytes: 1,
+    };
+    assert_eq!(
+        stats.by_task(),
+        [
+            (
+                "uart",
+                Count {
+                    messages: 2,
+                    bytes: 2
+                }
+            ),
+            ("7", one),
+            ("idle", one),
+        ]
+    );
+}
+
+#[test]
+fn delta_frames() {
+    // Frame sizes carry the delta bit, changes are (skip, length, bytes) runs
+    fn frame(v: &mut Vec<u8>, sym: u32, delta: bool, data: &[u8]) {
+        leb128_write(v, (data.len() as u32) << 1 | delta as u32);
+        leb128_write(v, sym);
+        leb128_write(v, 0x20);
+        v.extend_from_slice(data);
+    }
+
+    let mut v = Vec::new();
+    frame(&mut v, 0x10, false, &[1, 2, 3, 4, 5, 6]);
+    frame(&mut v, 0x11, false, &[9]);
+    frame(&mut v, 0x10, true, &[1, 1, 7, 2, 2, 8, 9]);
+    frame(&mut v, 0x10, true, &[]);
+    // Another call site is not affected
+    frame(&mut v, 0x11, true, &[0, 1, 8]);
+
+    let mut parser = Parser::new().with_deltas();
+    for b in &v {
+        parser.push(&[*b]);
+    }
+    let mut packets = Vec::new();
+    while let Some(packet) = parser.try_parse() {
+        packets.push((packet.string_loc, packet.buffer));
+    }
+    assert_eq!(
+        packets,
+        [
+            (0x10, vec![1, 2, 3, 4, 5, 6]),
+            (0x11, vec![9]),
+            (0x10, vec![1, 7, 3, 4, 8, 9]),
+            (0x10, vec![1, 7, 3, 4, 8, 9]),
+            (0x11, vec![8]),
+        ]
+    );
+
+    // Changes are dropped until a whole value arrives after a resync, or after changes that
+    // did not fit the value
+    parser.reset();
+    let mut v = Vec::new();
+    frame(&mut v, 0x10, true, &[0, 1, 1]);
+    frame(&mut v, 0x10, false, &[0, 0]);
+    frame(&mut v, 0x10, true, &[1, 2, 5, 5]);
+    frame(&mut v, 0x10, true, &[0, 1, 1]);
+    frame(&mut v, 0x10, false, &[3, 3]);
+    frame(&mut v, 0x10, true, &[1, 1, 4]);
+    parser.push(&v);
+    let mut packets = Vec::new();
+    while let Some(packet) = parser.try_parse() {
+        packets.push(packet.buffer);
+    }
+    assert_eq!(packets, [vec![0, 0], vec![3, 3], vec![3, 4]]);
+
+    // Without the feature the size is taken as it is
+    let mut parser = Parser::new();
+    parser.push(&[2, 0x10, 0x20, 1, 2]);
+    assert_eq!(parser.try_parse().unwrap().buffer, [1, 2]);
+}
+
+#[test]
+fn frame_crcs() {
+    use crate::crc::crc16;
+
+    assert_eq!(crc16(b"123456789".iter().copied()), 0x29b1);
+
+    // As the target writes them, the CRC of the whole frame follows it little endian
+    fn frame(v: &mut Vec<u8>, sym: u32, data: &[u8]) {
+        let start = v.len();
+        leb128_write(v, data.len() as u32);
+        leb128_write(v, sym);
+        leb128_write(v, 0x20);
+        v.extend_from_slice(data);
+        let crc = crc16(v[start..].iter().copied());
+        v.extend_from_slice(&crc.to_le_bytes());
+    }
+
+    let mut v = Vec::new();
+    frame(&mut v, 0x10, &[1, 2, 3, 4]);
+    frame(&mut v, 0x11, &[5]);
+    let corrupt = v.len() + 4;
+    frame(&mut v, 0x12, &[6, 7, 8, 9, 10, 11, 12]);
+    for sym in 0x13..0x20 {
+        frame(&mut v, sym, &[sym as u8]);
+    }
+    v[corrupt] ^= 0x40;
+
+    let mut parser = Parser::new().with_crcs();
+    let mut packets = Vec::new();
+    for chunk in [&v[..5], &v[5..]].iter() {
+        parser.push(chunk);
+        while let Some(packet) = parser.try_parse() {
+            packets.push((packet.string_loc, packet.buffer));
+        }
+    }
+    // The frame is dropped, and the parser finds the next one
+    assert_eq!(
+        packets[..3],
+        [
+            (0x10, vec![1, 2, 3, 4]),
+            (0x11, vec![5]),
+            (0x13, vec![0x13])
+        ]
+    );
+    assert_eq!(packets.last(), Some(&(0x1f, vec![0x1f])));
+    assert_eq!(parser.take_corrupted(), 1);
+    assert_eq!(parser.take_corrupted(), 0);
+
+    // A size larger than any ring buffer is not waited for
+    parser.push(&[0xff, 0xff, 0xff, 0x7f]);
+    assert!(parser.try_parse().is_none());
+    assert_eq!(parser.take_corrupted(), 1);
+}
+
+#[test]
+fn frame_sequences() {
+    use crate::parser::BOOT_FRAME;
+
+    // The sequence number follows the task ID
+    fn frame(v: &mut Vec<u8>, sym: u32, sequence: u8) {
+        leb128_write(v, 1);
+        leb128_write(v, sym);
+        leb128_write(v, 0);
+        leb128_write(v, 2);
+        leb128_write(v, u32::from(sequence));
+        v.push(sequence);
+    }
+    let parse = |parser: &mut Parser, v: &[u8]| {
+        parser.push(v);
+        std::iter::from_fn(|| parser.try_parse())
+            .map(|packet| packet.buffer[0])
+            .collect::<Vec<_>>()
+    };
+
+    let mut v = Vec::new();
+    for sequence in &[254, 255, 0, 3] {
+        frame(&mut v, 0x10, *sequence);
+    }
+    let mut parser = Parser::new().with_tasks().with_sequences();
+    assert_eq!(parse(&mut parser, &v), [254, 255, 0, 3]);
+    assert_eq!(parser.take_missed(), 2);
+    assert_eq!(parser.take_missed(), 0);
+
+    // A target that was reset starts over with the boot frame
+    let mut v = Vec::new();
+    frame(&mut v, BOOT_FRAME as u32, 0);
+    frame(&mut v, 0x10, 1);
+    assert_eq!(parse(&mut parser, &v), [0, 1]);
+    assert_eq!(parser.take_missed(), 0);
+
+    // So does the parser after skipping data
+    parser.reset();
+    let mut v = Vec::new();
+    frame(&mut v, 0x10, 40);
+    frame(&mut v, 0x10, 41);
+    assert_eq!(parse(&mut parser, &v), [40, 41]);
+    assert_eq!(parser.take_missed(), 0);
+
+    // Looking for the next frame after a corrupted one starts without its sequence number
+    let mut v = Vec::new();
+    for sequence in 0..4 {
+        let start = v.len();
+        frame(&mut v, 0x10, sequence);
+        let crc = crate::crc::crc16(v[start..].iter().copied());
+        v.extend_from_slice(&crc.to_le_bytes());
+    }
+    // The payload of the second frame
+    v[13] ^= 0x40;
+    let mut parser = Parser::new().with_tasks().with_sequences().with_crcs();
+    let mut sequences = Vec::new();
+    for chunk in v.chunks(4) {
+        sequences.extend(parse(&mut parser, chunk));
+    }
+    assert_eq!(sequences, [0, 2, 3]);
+    assert_eq!(parser.take_corrupted(), 1);
+}
+
+#[test]
+fn batch_values() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::symbols::Symbols;
+    use elf_test::generate_printers;
+    use std::time::UNIX_EPOCH;
+    use xmas_elf::{sections::SectionData, symbol_table::Entry, ElfFile};
+
+    // `TEST2` is `(1.5f32, 2u32)`, as a `log!` of two values would send it
+    let elf = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../elf_test/tests/fixtures/types-rustc-1.95.0.elf"
+    ))
+    .unwrap();
+    let printers = generate_printers(&elf).unwrap();
+    let file = ElfFile::new(&elf).unwrap();
+    let mut buffer = vec![];
+    for section in file.section_iter() {
+        if let Ok(SectionData::SymbolTable64(entries)) = section.get_data(&file) {
+            for entry in entries {
+                if entry.get_name(&file) == Ok("TEST2") {
+                    let section = file.section_header(entry.shndx()).unwrap();
+                    let offset = (entry.value() - section.address()) as usize;
+                    buffer = section.raw_data(&file)[offset..offset + 8].to_vec();
+                }
+            }
+        }
+    }
+    assert_eq!(buffer.len(), 8);
+
+    let strings: Symbols = vec![
+        (0x10, "a: {:.2}, b: {:#x}"),
+        (0x40, "b: {1}, a: {0}, b: {1}"),
+        (0x80, "both {}"),
+    ]
+    .into_iter()
+    .collect();
+    let types: Symbols = vec![(0x300, "(f32, u32)")].into_iter().collect();
+    let mut decoder = Decoder::new(Catalog::new(&strings), types, printers);
+    let packet = |string_loc| Packet {
+        string_loc,
+        type_loc: 0x300,
+        timestamp: None,
+        task: None,
+        buffer: buffer.clone(),
+    };
+
+    let record = decoder.decode(&packet(0x10), UNIX_EPOCH);
+    assert_eq!(record.message, "a: 1.50, b: 0x2");
+    assert_eq!(
+        record.values,
+        [("0".to_string(), 1.5), ("1".to_string(), 2.0)]
+    );
+    let record = decoder.decode(&packet(0x40), UNIX_EPOCH);
+    assert_eq!(record.message, "b: 2, a: 1.5, b: 2");
+
+    // A tuple logged as one value is printed whole
+    let record = decoder.decode(&packet(0x80), UNIX_EPOCH);
+    assert!(record.message.starts_with("both (f32, u32) ("));
+}
+
+#[test]
+fn key_value_events() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::sink::{Json, Sink};
+    use crate::symbols::Symbols;
+    use elf_test::{generate_printers, LogSite};
+    use std::time::UNIX_EPOCH;
+    use xmas_elf::{sections::SectionData, symbol_table::Entry, ElfFile};
+
+    // `TEST2` is `(1.5f32, 2u32)`, as `log_kv!` with two fields would send it
+    let elf = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../elf_test/tests/fixtures/types-rustc-1.95.0.elf"
+    ))
+    .unwrap();
+    let printers = generate_printers(&elf).unwrap();
+    let file = ElfFile::new(&elf).unwrap();
+    let mut buffer = vec![];
+    for section in file.section_iter() {
+        if let Ok(SectionData::SymbolTable64(entries)) = section.get_data(&file) {
+            for entry in entries {
+                if entry.get_name(&file) == Ok("TEST2") {
+                    let section = file.section_header(entry.shndx()).unwrap();
+                    let offset = (entry.value() - section.address()) as usize;
+                    buffer = section.raw_data(&file)[offset..offset + 8].to_vec();
+                }
+            }
+        }
+    }
+
+    let strings: Symbols = vec![
+        (0x10, "adc_sample voltage={} channel={}"),
+        (0x40, "not_an_event voltage={} channel={}"),
+    ]
+    .into_iter()
+    .collect();
+    let site = |address, kv| LogSite {
+        address,
+        file: None,
+        line: None,
+        module: None,
+        type_name: None,
+        type_address: None,
+        level: None,
+        kv,
+        formatted: false,
+    };
+    let catalog = Catalog::new(&strings).with_sites(&[site(0x10, true), site(0x40, false)]);
+    assert_eq!(catalog.get(0x10).unwrap().event(), Some("adc_sample"));
+    assert_eq!(catalog.get(0x10).unwrap().fields, ["voltage", "channel"]);
+    assert_eq!(catalog.get(0x40).unwrap().event(), None);
+
+    let types: Symbols = vec![(0x300, "(f32, u32)")].into_iter().collect();
+    let mut decoder = Decoder::new(catalog, types, printers);
+    let packet = |string_loc| Packet {
+        string_loc,
+        type_loc: 0x300,
+        timestamp: None,
+        task: None,
+        buffer: buffer.clone(),
+    };
+
+    // Shown as key=value text, and with the fields by name in JSON
+    let record = decoder.decode(&packet(0x10), UNIX_EPOCH);
+    assert_eq!(record.message, "adc_sample voltage=1.5 channel=2");
+    assert_eq!(
+        record.values,
+        [("voltage".to_string(), 1.5), ("channel".to_string(), 2.0)]
+    );
+    let json = serde_json::to_value(&record).unwrap();
+    assert_eq!(
+        json["event"],
+        serde_json::json!({
+            "name": "adc_sample",
+            "fields": { "voltage": 1.5, "channel": 2 },
+        })
+    );
+
+    // The same format string from `log!` is no event
+    let record = decoder.decode(&packet(0x40), UNIX_EPOCH);
+    assert_eq!(record.message, "not_an_event voltage=1.5 channel=2");
+    assert_eq!(record.event, None);
+    assert!(serde_json::to_value(&record)
+        .unwrap()
+        .get("event")
+        .is_none());
+
+    // `--output json` adds what the message was made of
+    assert_eq!(
+        record.format.as_deref(),
+        Some("not_an_event voltage={} channel={}")
+    );
+    assert_eq!(record.args, ["1.5", "2"]);
+    let mut out = Vec::new();
+    Json::new(&mut out)
+        .with_details(true)
+        .write(&record)
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(json["message"], "not_an_event voltage=1.5 channel=2");
+    assert_eq!(json["format"], "not_an_event voltage={} channel={}");
+    assert_eq!(json["args"], serde_json::json!(["1.5", "2"]));
+    let raw: String = buffer.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(json["raw"], raw);
+    assert!(json.get("seconds").is_none());
+}
+
+#[test]
+fn output_modes() {
+    use crate::sink::Output;
+
+    assert_eq!("text".parse::<Output>().unwrap(), Output::Text);
+    assert_eq!("json".parse::<Output>().unwrap(), Output::Json);
+    assert!("ndjson".parse::<Output>().is_err());
+}
+
+#[test]
+fn clock_extends_and_formats_ticks() {
+    use crate::time::Clock;
+
+    let mut clock = Clock::new(Some(1_000_000));
+    assert_eq!(clock.extend(10), 10);
+    assert_eq!(clock.extend(u32::MAX), u64::from(u32::MAX));
+    assert_eq!(clock.extend(5), (1 << 32) + 5);
+    assert_eq!(clock.extend(6), (1 << 32) + 6);
+    assert_eq!(clock.format(1_500_000), "1.500000");
+    assert_eq!(clock.format(42), "0.000042");
+
+    let clock = Clock::new(Some(32_768));
+    assert_eq!(clock.format(3 * 32_768 + 16_384), "3.500000");
+
+    let clock = Clock::new(None);
+    assert_eq!(clock.format(1234), "1234");
+}
+
+#[test]
+fn wall_clock_corrects_drift() {
+    use crate::time::WallClock;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let boot = 1_600_000_000.0;
+    let mut wall = WallClock::new();
+
+    // The target clock runs 0.1 % fast, and frames arrive with 0-9 ms of latency
+    for i in 0..1000u32 {
+        let host = boot + f64::from(i) * 0.1;
+        let target = f64::from(i) * 0.1 * 1.001;
+        let latency = f64::from(i * 7 % 10) * 0.001;
+        let arrival = UNIX_EPOCH + Duration::from_secs_f64(host + latency);
+
+        let written = wall
+            .map(target, arrival)
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+
+        assert!(
+            written <= host + latency + 1e-6,
+            "frame {} mapped after it arrived",
+            i
+        );
+        if i > 300 {
+            assert!(
+                (written - host).abs() < 0.01,
+                "frame {} off by {}",
+                i,
+                written - host
+            );
+        }
+    }
+
+    assert!(
+        (wall.rate() - 1.0 / 1.001).abs() < 2e-4,
+        "rate {}",
+        wall.rate()
+    );
+}
+
+#[test]
+fn catalog_ids_and_json_records() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::sink::{Json, Sink};
+    use crate::symbols::Symbols;
+    use elf_test::TypePrinters;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    let strings: Symbols = vec![(0x30, "third {}"), (0x10, "first {:x}"), (0x20, "bad }")]
+        .into_iter()
+        .collect();
+    let catalog = Catalog::new(&strings);
+
+    let ids: Vec<_> = catalog
+        .messages()
+        .iter()
+        .map(|m| (m.id, m.address, m.format.is_ok()))
+        .collect();
+    assert_eq!(
+        ids,
+        vec![(0, 0x10, true), (1, 0x20, false), (2, 0x30, true)]
+    );
+    assert_eq!(catalog.get(0x30).unwrap().text, "third {}");
+    assert!(catalog.get(0x40).is_none());
+
+    let mut decoder = Decoder::new(catalog, Symbols::new(), TypePrinters(HashMap::new()));
+    let packet = Packet {
+        string_loc: 0x10,
+        type_loc: 0,
+        timestamp: None,
+        task: None,
+        buffer: vec![1],
+    };
+    let record = decoder.decode(&packet, UNIX_EPOCH);
+    assert_eq!(record.id, Some(0));
+
+    let mut out = Vec::new();
+    Json::new(&mut out).write(&record).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"id\":0,\"message\":\"first \"}\n"
+    );
+}
+
+#[test]
+fn catalog_export() {
+    use crate::catalog::Catalog;
+    use crate::symbols::Symbols;
+    use elf_test::LogSite;
+
+    let strings: Symbols = vec![(0x10, "speed {}"), (0x20, "state {:?}")]
+        .into_iter()
+        .collect();
+    let catalog = Catalog::new(&strings).with_sites(&[
+        LogSite {
+            address: 0x10,
+            file: Some("src/main.rs".into()),
+            line: Some(12),
+            module: Some("app::main".into()),
+            type_name: Some("f32".into()),
+            type_address: None,
+            level: None,
+            kv: false,
+            formatted: false,
+        },
+        LogSite {
+            address: 0x40,
+            file: None,
+            line: None,
+            module: None,
+            type_name: Some("u8".into()),
+            type_address: None,
+            level: None,
+            kv: false,
+            formatted: false,
+        },
+    ]);
+
+    let mut text = Vec::new();
+    catalog.write_text(&mut text).unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "   0  src/main.rs:12  f32  \"speed {}\"\n   1  <unknown>  <unknown>  \"state {:?}\"\n"
+    );
+
+    let mut json = Vec::new();
+    catalog.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {"id": 0, "address": 16, "format": "speed {}", "type": "f32", "file": "src/main.rs", "line": 12},
+            {"id": 1, "address": 32, "format": "state {:?}", "type": null, "file": null, "line": null},
+        ])
+    );
+}
+
+#[test]
+fn catalog_types_by_address() {
+    use crate::catalog::Catalog;
+    use crate::symbols::Symbols;
+    use elf_test::LogSite;
+
+    // The type statics follow the format strings in the address space of the sections
+    let strings: Symbols = vec![(0x10, "speed {}")].into_iter().collect();
+    let catalog = Catalog::new(&strings).with_sites(&[
+        LogSite {
+            address: 0x10,
+            file: None,
+            line: None,
+            module: None,
+            type_name: Some("f32".into()),
+            type_address: Some(0x18),
+            level: None,
+            kv: false,
+            formatted: false,
+        },
+        // A call site of a target from before `.fasthosting.types`
+        LogSite {
+            address: 0x20,
+            file: None,
+            line: None,
+            module: None,
+            type_name: Some("u8".into()),
+            type_address: None,
+            level: None,
+            kv: false,
+            formatted: false,
+        },
+    ]);
+
+    assert_eq!(catalog.type_name(0x18), Some("f32"));
+    assert_eq!(catalog.type_name(0x10), None);
+    assert_eq!(catalog.type_name(0x20), None);
+}
+
+#[test]
+fn session_stats() {
+    use crate::record::Record;
+    use crate::stats::{Count, Stats};
+    use std::time::Duration;
+
+    let record = |module: Option<&str>, bytes| Record {
+        id: Some(0),
+        message: String::new(),
+        module: module.map(Into::into),
+        payload: vec![0; bytes],
+        ..Record::default()
+    };
+
+    let mut stats = Stats::new();
+    stats.received(40);
+    stats.record(&record(Some("app::imu"), 4));
+    stats.record(&record(Some("app::radio"), 8));
+    stats.record(&record(Some("app::imu"), 4));
+    stats.record(&record(None, 1));
+
+    assert_eq!(
+        stats.total(),
+        Count {
+            messages: 4,
+            bytes: 17
+        }
+    );
+
+    let mut out = Vec::new();
+    stats
+        .write_summary(&mut out, Duration::from_millis(1500), 2)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Session summary
+  duration: 1.500 s
+  messages: 4
+  payload:  17 bytes
+  received: 40 bytes
+  drops:    2
+
+  module        messages       bytes
+  app::imu             2           8
+  <unknown>            1           1
+  app::radio           1           8
+"
+    );
+}
+
+#[test]
+fn live_controls() {
+    use crate::live::{Action, Live};
+    use crate::record::Record;
+    use crate::sink::Json;
+
+    let record = |message: &str| Record {
+        message: message.into(),
+        ..Record::default()
+    };
+    let lines = |out: &[u8]| {
+        String::from_utf8(out.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].clone())
+            .collect::<Vec<_>>()
+    };
+
+    let mut shown = Vec::new();
+    let mut sink = Json::new(&mut shown);
+    let mut prompt = Vec::new();
+    let mut live = Live::new();
+
+    live.show(record("a"), &mut sink).unwrap();
+    assert_eq!(
+        live.key(b' ', &mut sink, &mut prompt).unwrap(),
+        Some(Action::TogglePause)
+    );
+    assert!(live.paused());
+    live.show(record("b"), &mut sink).unwrap();
+
+    // Typing a filter, records keep being held back
+    live.key(b'f', &mut sink, &mut prompt).unwrap();
+    for &key in b"qx\x7fc" {
+        assert_eq!(live.key(key, &mut sink, &mut prompt).unwrap(), None);
+    }
+    live.show(record("c"), &mut sink).unwrap();
+    live.key(b'\n', &mut sink, &mut prompt).unwrap();
+    assert_eq!(live.filter(), Some("qc"));
+
+    live.show(record("qc 1"), &mut sink).unwrap();
+    live.key(b' ', &mut sink, &mut prompt).unwrap();
+    live.show(record("qc 2"), &mut sink).unwrap();
+    live.show(record("d"), &mut sink).unwrap();
+    assert_eq!(
+        live.key(b'q', &mut sink, &mut prompt).unwrap(),
+        Some(Action::Quit)
+    );
+
+    assert_eq!(lines(&shown), vec!["a", "qc 1", "qc 2"]);
+    assert!(String::from_utf8(prompt).unwrap().contains("filter"));
+}
+
+#[test]
+fn live_level_filter() {
+    use crate::live::Live;
+    use crate::record::{Level, Record};
+    use crate::sink::Json;
+
+    let record = |level, message: &str| Record {
+        level,
+        message: message.into(),
+        ..Record::default()
+    };
+
+    assert_eq!("debug".parse::<Level>().unwrap(), Level::Debug);
+    assert!("verbose".parse::<Level>().is_err());
+    assert!(Level::Trace < Level::Info && Level::Warn < Level::Error);
+
+    let mut shown = Vec::new();
+    let mut sink = Json::new(&mut shown);
+    let mut live = Live::new().with_level(Some(Level::Info));
+    live.show(record(Some(Level::Trace), "trace"), &mut sink)
+        .unwrap();
+    live.show(record(Some(Level::Debug), "debug"), &mut sink)
+        .unwrap();
+    live.show(record(None, "log"), &mut sink).unwrap();
+    live.show(record(Some(Level::Info), "info"), &mut sink)
+        .unwrap();
+    live.show(record(Some(Level::Error), "error"), &mut sink)
+        .unwrap();
+
+    let shown: Vec<_> = String::from_utf8(shown)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .map(|record| (record["level"].clone(), record["message"].clone()))
+        .collect();
+    assert_eq!(
+        shown,
+        vec![
+            (serde_json::Value::Null, "log".into()),
+            ("info".into(), "info".into()),
+            ("error".into(), "error".into()),
+        ]
+    );
+}
+
+#[test]
+fn collapse_duplicates() {
+    use crate::record::Record;
+    use crate::sink::{Collapse, Json, Sink, Terminal};
+
+    let record = |id, message: &str, timestamp: &str| Record {
+        id: Some(id),
+        timestamp: Some(timestamp.into()),
+        message: message.into(),
+        ..Record::default()
+    };
+    let records = [
+        record(0, "tick", "1"),
+        record(0, "tick", "2"),
+        record(0, "tick", "3"),
+        record(1, "tock", "4"),
+        record(1, "tock", "5"),
+    ];
+
+    let mut json = Vec::new();
+    let mut sink = Collapse::new(Json::new(&mut json));
+    for record in &records {
+        sink.write(record).unwrap();
+    }
+    sink.finish().unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        r#"{"id":0,"timestamp":"1","message":"tick"}
+{"id":0,"timestamp":"3","message":"tick","repeated":{"count":2,"first":"2","last":"3"}}
+{"id":1,"timestamp":"4","message":"tock"}
+{"id":1,"timestamp":"5","message":"tock","repeated":{"count":1,"first":"5","last":"5"}}
+"#
+    );
+
+    let mut text = Vec::new();
+    let mut sink = Collapse::new(Terminal::new(&mut text, false));
+    for record in &records[..3] {
+        sink.write(record).unwrap();
+    }
+    sink.finish().unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "[1] tick\n... repeated 2 more times (2 to 3)\n"
+    );
+}
+
+#[test]
+fn reconnect_backoff() {
+    use crate::reconnect::Backoff;
+    use std::time::Duration;
+
+    let mut backoff = Backoff::new(3, Duration::from_millis(500));
+    assert_eq!(backoff.next_delay(), Some(Duration::from_millis(500)));
+    assert_eq!(backoff.next_delay(), Some(Duration::from_secs(1)));
+    assert_eq!(backoff.next_delay(), Some(Duration::from_secs(2)));
+    assert_eq!(backoff.next_delay(), None);
+
+    backoff.reset();
+    assert_eq!(backoff.attempt(), 0);
+    assert_eq!(backoff.next_delay(), Some(Duration::from_millis(500)));
+
+    let mut backoff = Backoff::new(40, Duration::from_secs(3));
+    let delays: Vec<_> = std::iter::from_fn(|| backoff.next_delay()).collect();
+    assert_eq!(delays.len(), 40);
+    assert_eq!(delays[2], Duration::from_secs(10));
+    assert_eq!(delays[39], Duration::from_secs(10));
+
+    assert_eq!(Backoff::new(0, Duration::from_secs(1)).next_delay(), None);
+}
+
+#[test]
+fn trustzone_aliases() {
+    use crate::transport::Security;
+
+    assert_eq!(Security::Secure.alias(0x2000_0100), 0x3000_0100);
+    assert_eq!(Security::Secure.alias(0x0800_0000), 0x1800_0000);
+    assert_eq!(Security::NonSecure.alias(0x3000_0100), 0x2000_0100);
+    assert_eq!(Security::NonSecure.alias(0x5000_3000), 0x4000_3000);
+    assert_eq!(Security::Flat.alias(0x3000_0100), 0x3000_0100);
+    // System and external regions have no aliases
+    assert_eq!(Security::Secure.alias(0xe000_ed00), 0xe000_ed00);
+    assert_eq!(Security::NonSecure.alias(0x1_3000_0000), 0x1_3000_0000);
+
+    // No TrustZone, secure debug allowed, secure debug not allowed
+    assert_eq!(Security::from_dauthstatus(0x0000_000f), Security::Flat);
+    assert_eq!(Security::from_dauthstatus(0x0000_00ff), Security::Flat);
+    assert_eq!(Security::from_dauthstatus(0x0000_00af), Security::NonSecure);
+
+    assert_eq!(
+        "non-secure".parse::<Security>().unwrap(),
+        Security::NonSecure
+    );
+    assert_eq!("auto".parse::<Security>().unwrap(), Security::Auto);
+    assert!("nonsecure".parse::<Security>().is_err());
+}
+
+#[test]
+fn error_policies() {
+    use crate::reconnect::Policy;
+    use std::time::Duration;
+
+    assert_eq!("abort".parse::<Policy>().unwrap(), Policy::Abort);
+    assert_eq!("warn".parse::<Policy>().unwrap(), Policy::Warn);
+    assert_eq!("retry:3".parse::<Policy>().unwrap(), Policy::Retry(3));
+    assert!("retry:".parse::<Policy>().is_err());
+    assert!("retry:-1".parse::<Policy>().is_err());
+    assert!("ignore".parse::<Policy>().is_err());
+
+    let delay = Duration::from_millis(100);
+    assert_eq!(Policy::Abort.backoff(delay).next_delay(), None);
+
+    let mut backoff = Policy::Retry(2).backoff(delay);
+    assert_eq!(backoff.retries(), Some(2));
+    assert_eq!(std::iter::from_fn(|| backoff.next_delay()).count(), 2);
+
+    let mut backoff = Policy::Warn.backoff(delay);
+    assert_eq!(backoff.retries(), None);
+    let delays: Vec<_> = (0..100).map(|_| backoff.next_delay()).collect();
+    assert_eq!(delays[99], Some(Duration::from_secs(10)));
+    assert_eq!(backoff.attempt(), 100);
+}
+
+#[test]
+fn stall_watchdog() {
+    use crate::watchdog::{StallAction, Watchdog};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+
+    let mut watchdog = Watchdog::new(Duration::from_secs(1), start);
+    assert_eq!(watchdog.check(at(999)), None);
+    assert_eq!(watchdog.check(at(1200)), Some(Duration::from_millis(1200)));
+    // Only once per stall
+    assert_eq!(watchdog.check(at(5000)), None);
+
+    watchdog.feed(at(5000));
+    assert_eq!(watchdog.check(at(5500)), None);
+    assert_eq!(watchdog.check(at(6000)), Some(Duration::from_secs(1)));
+
+    assert_eq!("halt".parse::<StallAction>().unwrap(), StallAction::Halt);
+    assert_eq!(
+        "exec:notify-send stalled".parse::<StallAction>().unwrap(),
+        StallAction::Exec("notify-send stalled".into())
+    );
+    assert!("exec:".parse::<StallAction>().is_err());
+    assert!("explode".parse::<StallAction>().is_err());
+}
+
+#[test]
+fn target_health() {
+    use crate::parser::HEARTBEAT_FRAME;
+    use crate::watchdog::Health;
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+
+    let mut health = Health::new(start);
+    assert_eq!(health.status(at(10_000)), "alive, no messages");
+
+    health.heartbeat(at(1000));
+    health.message(at(1500));
+    assert_eq!(health.status(at(1600)), "alive");
+    // Only heartbeats since the last message
+    health.heartbeat(at(2000));
+    health.heartbeat(at(3000));
+    assert_eq!(health.status(at(3500)), "alive, silent for 2.000 s");
+    assert!(!health.hung(at(6000)));
+    assert_eq!(health.status(at(6001)), "hung, no heartbeat for 3.001 s");
+
+    // An empty frame, with whatever timestamp and task the target sends
+    let mut v = Vec::new();
+    leb128_write(&mut v, 0);
+    leb128_write(&mut v, HEARTBEAT_FRAME as u32);
+    leb128_write(&mut v, 0);
+    let mut parser = Parser::new();
+    parser.push(&v);
+    let packet = parser.try_parse().unwrap();
+    assert!(packet.is_heartbeat());
+}
+
+#[test]
+fn run_until_pattern() {
+    use crate::until::{Outcome, Until};
+    use regex::Regex;
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+    let until = Until::new(
+        Some(Regex::new("TEST (PASSED|FAILED)").unwrap()),
+        Some(Regex::new("FAILED|panicked").unwrap()),
+        Some(Duration::from_secs(60)),
+        start,
+    );
+
+    assert_eq!(until.check("running test 3"), None);
+    assert_eq!(until.check("TEST PASSED"), Some(Outcome::Passed));
+    assert_eq!(until.check("TEST FAILED"), Some(Outcome::Failed));
+    assert_eq!(
+        until.check("panicked at src/main.rs"),
+        Some(Outcome::Failed)
+    );
+
+    assert_eq!(until.expired(start + Duration::from_secs(59)), None);
+    assert_eq!(
+        until.expired(start + Duration::from_secs(60)),
+        Some(Outcome::TimedOut)
+    );
+    assert_eq!(Outcome::TimedOut.exit_code(), 124);
+
+    let forever = Until::new(None, None, None, start);
+    assert_eq!(forever.check("TEST PASSED"), None);
+    assert_eq!(forever.expired(start + Duration::from_secs(3600)), None);
+}
+
+#[test]
+fn expectation_script() {
+    use crate::expect::{Runner, Script, Status};
+    use std::time::Duration;
+
+    let script = Script::parse(
+        r#"
+        timeout = "30s"
+
+        [[expect]]
+        name = "boot"
+        message = "booted"
+
+        [[expect]]
+        name = "battery"
+        message = "vbat: (?P<value>[0-9.]+)"
+        value = ">= 3.3"
+
+        [[expect]]
+        message = "TEST PASSED"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(script.timeout, Some(Duration::from_secs(30)));
+
+    let ms = Duration::from_millis;
+    let mut runner = Runner::new(script);
+    // Out of order, does not count
+    runner.observe("vbat: 3.7", ms(1));
+    runner.observe("booted", ms(2));
+    runner.observe("vbat: 3.1", ms(3));
+    runner.observe("TEST PASSED", ms(4));
+
+    assert_eq!(runner.status()[0], Status::Met(ms(2)));
+    assert_eq!(
+        runner.status()[1],
+        Status::Pending {
+            mismatch: Some("value 3.1 is not >= 3.3".into())
+        }
+    );
+    assert!(!runner.passed());
+
+    let mut junit = Vec::new();
+    runner.write_junit(&mut junit, "app", ms(4000)).unwrap();
+    assert_eq!(
+        String::from_utf8(junit).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="app" tests="3" failures="2" time="4.000">
+  <testcase name="boot" time="0.002"/>
+  <testcase name="battery">
+    <failure message="matching message seen, but value 3.1 is not &gt;= 3.3"/>
+  </testcase>
+  <testcase name="expect 3">
+    <failure message="no message matching &quot;TEST PASSED&quot;"/>
+  </testcase>
+</testsuite>
+"#
+    );
+
+    runner.observe("vbat: 3.4", ms(5));
+    runner.observe("TEST PASSED", ms(6));
+    assert!(runner.passed());
+
+    let unordered = Script::parse(
+        r#"
+        ordered = false
+        [[expect]]
+        message = "a"
+        [[expect]]
+        message = "b"
+        "#,
+    )
+    .unwrap();
+    let mut runner = Runner::new(unordered);
+    runner.observe("b", ms(1));
+    runner.observe("a", ms(2));
+    assert!(runner.passed());
+
+    assert!(Script::parse("[[expect]]\nmessage = \"x\"\nvalue = \"> 1\"").is_err());
+    assert!(Script::parse("[[expect]]\nmessage = \"(?P<value>.*)\"\nvalue = \"~ 1\"").is_err());
+}
+
+#[test]
+fn command_channel() {
+    use crate::command::{CommandChannel, CommandMemory};
+    use anyhow::Result;
+
+    /// Target memory with the command cursors at 0x100 and a 16 byte buffer at 0x200
+    struct Memory {
+        cursors: [u32; 2],
+        buffer: [u8; 16],
+    }
+
+    impl CommandMemory for Memory {
+        fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<()> {
+            assert_eq!(address, 0x100);
+            data.copy_from_slice(&self.cursors);
+            Ok(())
+        }
+
+        fn write_8(&mut self, address: u64, data: &[u8]) -> Result<()> {
+            let offset = (address - 0x200) as usize;
+            self.buffer[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn write_word_32(&mut self, address: u64, value: u32) -> Result<()> {
+            assert_eq!(address, 0x104);
+            self.cursors[1] = value;
+            Ok(())
+        }
+    }
+
+    let channel = CommandChannel {
+        cursor_address: 0x100,
+        buffer_address: 0x200,
+        buffer_size: 16,
+    };
+    let mut memory = Memory {
+        cursors: [12, 12],
+        buffer: [0; 16],
+    };
+
+    // Wraps around the end of the buffer
+    let frame = channel.frame(b"led on").unwrap();
+    assert!(channel.send(&mut memory, &frame).unwrap());
+    assert_eq!(memory.cursors, [12, 3]);
+    assert_eq!(&memory.buffer[12..], &[6, b'l', b'e', b'd']);
+    assert_eq!(&memory.buffer[..3], b" on");
+
+    // Fills the buffer, no room for more until the target reads
+    let frame = channel.frame(b"led off").unwrap();
+    assert!(channel.send(&mut memory, &frame).unwrap());
+    assert_eq!(memory.cursors, [12, 11]);
+    assert!(!channel.send(&mut memory, &frame).unwrap());
+    memory.cursors[0] = 11;
+    assert!(channel.send(&mut memory, &frame).unwrap());
+
+    assert!(channel.frame(&[0; 15]).is_err());
+}
+
+#[test]
+fn hook_filters_and_transforms() {
+    use crate::hook::Hook;
+    use crate::record::{Level, Record};
+    use crate::sink::{Json, Sink};
+
+    let record = |message: &str| Record {
+        id: Some(1),
+        level: Some(Level::Warn),
+        message: message.into(),
+        payload: vec![1, 2],
+        ..Record::default()
+    };
+
+    let mut out = Vec::new();
+    let mut hook = Hook::new(
+        r#"
+        fn hook(record) {
+            this.seen = (this.seen ?? 0) + 1;
+            if record.message.contains("noise") {
+                return ();
+            }
+            record.message.replace("temp", "temperature");
+            record.message += ` (${this.seen})`;
+            record
+        }
+        "#,
+        Json::new(&mut out),
+    )
+    .unwrap();
+
+    hook.write(&record("temp 21")).unwrap();
+    hook.write(&record("noise")).unwrap();
+    hook.write(&record("done")).unwrap();
+    hook.finish().unwrap();
+
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"id\":1,\"level\":\"warn\",\"message\":\"temperature 21 (1)\"}\n\
+         {\"id\":1,\"level\":\"warn\",\"message\":\"done (3)\"}\n"
+    );
+
+    assert!(Hook::new("fn filter(record) { record }", Json::new(Vec::new())).is_err());
+    assert!(Hook::new("fn hook(record) {", Json::new(Vec::new())).is_err());
+}
+
+#[test]
+fn mqtt_publish() {
+    use crate::config::Config;
+    use crate::mqtt::Mqtt;
+    use crate::record::Record;
+    use crate::sink::Sink;
+    use std::io::{Cursor, Read, Write};
+
+    struct Broker {
+        rx: Cursor<Vec<u8>>,
+        tx: Vec<u8>,
+        closed: bool,
+    }
+
+    impl Read for Broker {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.rx.read(buf)
+        }
+    }
+
+    impl Write for Broker {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.closed {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            self.tx.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let config = Config::parse(
+        r#"
+        [mqtt]
+        broker = "localhost:1883"
+        client_id = "ci"
+        topic = "lab/{module}/{id}"
+        "#,
+    )
+    .unwrap();
+    let config = config.mqtt.unwrap();
+
+    let broker = Broker {
+        rx: Cursor::new(vec![0x20, 2, 0, 0]),
+        tx: vec![],
+        closed: false,
+    };
+    let mut mqtt = Mqtt::handshake(broker, &config).unwrap();
+    assert_eq!(
+        mqtt.stream_mut().tx,
+        b"\x10\x0e\x00\x04MQTT\x04\x02\x00\x00\x00\x02ci".to_vec()
+    );
+    mqtt.stream_mut().tx.clear();
+
+    let record = Record {
+        id: Some(3),
+        message: "hi".into(),
+        module: Some("app::radio".into()),
+        ..Record::default()
+    };
+    mqtt.write(&record).unwrap();
+    let topic = b"lab/app/radio/3";
+    let json = br#"{"id":3,"message":"hi","module":"app::radio"}"#;
+    let mut expected = vec![
+        0x30,
+        (2 + topic.len() + json.len()) as u8,
+        0,
+        topic.len() as u8,
+    ];
+    expected.extend_from_slice(topic);
+    expected.extend_from_slice(json);
+    assert_eq!(mqtt.stream_mut().tx, expected);
+
+    // Refused, bad credentials
+    let broker = Broker {
+        rx: Cursor::new(vec![0x20, 2, 0, 4]),
+        tx: vec![],
+        closed: false,
+    };
+    assert!(Mqtt::handshake(broker, &config).is_err());
+
+    // A password is only sent along with a username
+    let password = |username: Option<&str>| {
+        let config = Config::parse(&format!(
+            "[mqtt]\nbroker = \"localhost:1883\"\nclient_id = \"ci\"\npassword = \"pw\"\n{}",
+            username.map_or(String::new(), |name| format!("username = \"{}\"", name))
+        ))
+        .unwrap();
+        let broker = Broker {
+            rx: Cursor::new(vec![0x20, 2, 0, 0]),
+            tx: vec![],
+            closed: false,
+        };
+        let mut mqtt = Mqtt::handshake(broker, &config.mqtt.unwrap()).unwrap();
+        mqtt.stream_mut().tx[9]
+    };
+    assert_eq!(password(None), 0x02);
+    assert_eq!(password(Some("bench")), 0xc2);
+
+    // Losing the broker drops the records instead of ending the session
+    mqtt.stream_mut().closed = true;
+    mqtt.write(&record).unwrap();
+    mqtt.write(&record).unwrap();
+    mqtt.finish().unwrap();
+}
+
+#[test]
+fn influx_line_protocol() {
+    use crate::config::Config;
+    use crate::influx::Influx;
+    use crate::record::Record;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let config = Config::parse(
+        r#"
+        [influx]
+        file = "telemetry.lp"
+        tags = { board = "rev b" }
+
+        [influx.measurements.Telemetry]
+        name = "power"
+        fields = ["vbat_mv", "temp.0"]
+        "#,
+    )
+    .unwrap();
+    let config = config.influx.unwrap();
+
+    let record = |type_name: &str, values: &[(&str, f64)]| Record {
+        id: Some(1),
+        message: "".into(),
+        module: Some("app::power".into()),
+        type_name: Some(type_name.into()),
+        values: values.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        ..Record::default()
+    };
+    let time = UNIX_EPOCH + Duration::from_millis(1500);
+
+    assert_eq!(
+        Influx::line(
+            &config,
+            &record(
+                "app::Telemetry",
+                &[("vbat_mv", 3300.0), ("current", 1.5), ("temp.0", 21.5)]
+            ),
+            time
+        )
+        .unwrap(),
+        "power,board=rev\\ b,module=app::power vbat_mv=3300,temp.0=21.5 1500000000\n"
+    );
+    assert_eq!(
+        Influx::line(&config, &record("u32", &[("value", 7.0)]), time).unwrap(),
+        "u32,board=rev\\ b,module=app::power value=7 1500000000\n"
+    );
+
+    // Nothing numeric
+    assert_eq!(Influx::line(&config, &record("Status", &[]), time), None);
+}
+
+#[test]
+fn influx_keeps_unposted_points() {
+    use crate::influx::{Influx, InfluxConfig};
+    use crate::record::Record;
+    use crate::sink::Sink;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Answers each write request with the next status, and hands back the bodies
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/write", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let statuses = ["500 Internal Server Error", "400 Bad Request"];
+        let mut bodies = Vec::new();
+        for status in &statuses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            let body = loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).into_owned();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    if body.len() == length {
+                        break body.to_string();
+                    }
+                }
+            };
+            bodies.push(body);
+            write!(stream, "HTTP/1.1 {}\r\n\r\n", status).unwrap();
+        }
+        bodies
+    });
+
+    let config = InfluxConfig {
+        url: Some(url),
+        ..InfluxConfig::default()
+    };
+    let mut influx = Influx::open(&config).unwrap();
+    let record = |value| Record {
+        type_name: Some("u32".into()),
+        values: vec![("value".into(), value)],
+        ..Record::default()
+    };
+
+    // Kept after a server error, dropped once rejected
+    influx.write(&record(1.0)).unwrap();
+    influx.finish().unwrap();
+    influx.write(&record(2.0)).unwrap();
+    influx.finish().unwrap();
+    influx.finish().unwrap();
+
+    let bodies = server.join().unwrap();
+    assert_eq!(bodies.len(), 2);
+    assert!(bodies[0].starts_with("u32 value=1 "));
+    assert_eq!(bodies[1].lines().count(), 2);
+    assert!(bodies[1].starts_with(&bodies[0]));
+    assert!(bodies[1]
+        .lines()
+        .nth(1)
+        .unwrap()
+        .starts_with("u32 value=2 "));
+
+    // Nobody listening any more
+    influx.write(&record(3.0)).unwrap();
+    influx.finish().unwrap();
+}
+
+#[test]
+fn histograms_of_numeric_fields() {
+    use crate::config::Config;
+    use crate::histogram::{Buckets, Histogram, Histograms};
+    use crate::record::Record;
+    use crate::sink::Sink;
+    use std::time::Duration;
+
+    let config = Config::parse(
+        r#"
+        [histograms.latency_us]
+        bounds = [10, 20, 50]
+
+        [histograms."Timing.jitter"]
+        min = -2
+        max = 2
+        count = 2
+        "#,
+    )
+    .unwrap();
+    assert!(Config::parse("[histograms.x]\nbounds = [2, 1]").is_err());
+    assert!(Config::parse("[histograms.x]\nmin = 0\ncount = 4").is_err());
+
+    let mut histogram = Histogram::new(Buckets::PowersOfTwo);
+    for value in &[0.5, 1.0, 3.0, 3.5, 9.0] {
+        histogram.add(*value);
+    }
+    let mut out = Vec::new();
+    histogram.write(&mut out, "wait").unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "wait: 5 samples, min 0.5, mean 3.40, max 9
+      < 1         1  ####################
+   [1, 2)         1  ####################
+   [2, 4)         2  ########################################
+   [4, 8)         0
+  [8, 16)         1  ####################
+"
+    );
+
+    let record = |type_name: &str, values: &[(&str, f64)]| Record {
+        id: Some(1),
+        message: "".into(),
+        type_name: Some(type_name.into()),
+        values: values.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        ..Record::default()
+    };
+
+    let mut out = Vec::new();
+    let mut histograms = Histograms::new(
+        &mut out,
+        &["Sample".to_string(), "latency_us".to_string()],
+        &config.histograms,
+        Duration::from_secs(3600),
+    )
+    .unwrap();
+    let records = [
+        record("app::Timing", &[("latency_us", 12.0), ("jitter", -1.5)]),
+        record("app::Timing", &[("latency_us", 70.0), ("jitter", 0.5)]),
+        record("app::Other", &[("latency_us", 5.0), ("jitter", 9.0)]),
+        record("app::Sample", &[("value", 2.0)]),
+        record("u32", &[("value", f64::NAN)]),
+    ];
+    for record in &records {
+        histograms.write(record).unwrap();
+    }
+    // Not due yet
+    histograms.flush().unwrap();
+    histograms.finish().unwrap();
+    drop(histograms);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "
+Sample: 1 samples, min 2, mean 2.00, max 2
+  [2, 4)         1  ########################################
+
+Timing.jitter: 2 samples, min -1.5, mean -0.50, max 0.5
+  [-2, 0)         1  ########################################
+   [0, 2)         1  ########################################
+
+latency_us: 3 samples, min 5, mean 29.00, max 70
+      < 10         1  ########################################
+  [10, 20)         1  ########################################
+  [20, 50)         0
+     >= 50         1  ########################################
+"
+    );
+}
+
+#[test]
+fn triggers_on_messages() {
+    use crate::config::Config;
+    use crate::trigger::{Action, Triggers};
+
+    let config = Config::parse(
+        r#"
+        [[triggers]]
+        on = "ERROR.*radio"
+        run = "./capture_regs.sh"
+
+        [[triggers]]
+        on = "FAULT|ERROR"
+        action = "halt-and-dump"
+        "#,
+    )
+    .unwrap();
+    let triggers = Triggers::new(&config.triggers).unwrap();
+    assert!(triggers.needs_target());
+
+    assert_eq!(
+        triggers.check("ERROR: radio timed out"),
+        [
+            &Action::Run("./capture_regs.sh".into()),
+            &Action::HaltAndDump
+        ]
+    );
+    assert_eq!(triggers.check("HARD FAULT"), [&Action::HaltAndDump]);
+    assert!(triggers.check("radio ready").is_empty());
+
+    for invalid in &[
+        "[[triggers]]\non = \"(\"\naction = \"reset\"",
+        "[[triggers]]\non = \"x\"",
+        "[[triggers]]\non = \"x\"\nrun = \"true\"\naction = \"reset\"",
+        "[[triggers]]\non = \"x\"\naction = \"explode\"",
+        "[[triggers]]\non = \"x\"\naction = \"run\"",
+    ] {
+        assert!(Config::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn capture_around_triggers() {
+    use crate::capture::Capture;
+    use crate::record::Record;
+    use crate::sink::Sink;
+    use regex::Regex;
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    let dir = std::env::temp_dir().join(format!("fasthosting-capture-{}", std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    let mut capture = Capture::new(
+        Regex::new("FAULT").unwrap(),
+        Duration::from_secs(2),
+        Duration::from_secs(3),
+        &dir,
+    )
+    .unwrap();
+
+    let record = |message: &str| Record {
+        message: message.into(),
+        ..Record::default()
+    };
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+
+    for (secs, message) in &[
+        (0, "too early"),
+        (1, "before"),
+        (3, "FAULT 1"),
+        (5, "after"),
+        // Extends the first capture
+        (6, "FAULT 2"),
+        (9, "last"),
+        (10, "too late"),
+        (20, "FAULT 3"),
+    ] {
+        capture.push(&record(message), at(*secs)).unwrap();
+    }
+    capture.close_expired(at(22)).unwrap();
+    assert_eq!(capture.written().len(), 1);
+    capture.finish().unwrap();
+
+    assert_eq!(
+        capture.written(),
+        [dir.join("capture-1.log"), dir.join("capture-2.log")]
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("capture-1.log")).unwrap(),
+        "before\nFAULT 1\nafter\nFAULT 2\nlast\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("capture-2.log")).unwrap(),
+        "FAULT 3\n"
+    );
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn sqlite_archive() {
+    use crate::record::{Level, Record};
+    use crate::sink::Sink;
+    use crate::sqlite::Sqlite;
+    use rusqlite::Connection;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let record = Record {
+        id: Some(2),
+        timestamp: Some("1.500000".into()),
+        level: Some(Level::Warn),
+        message: "it's 21.5 °C".into(),
+        module: Some("app::sensors".into()),
+        type_name: Some("app::Reading".into()),
+        values: vec![("temp".into(), 21.5)],
+        payload: vec![0x00, 0xac, 0x41],
+        ..Record::default()
+    };
+    let path = std::env::temp_dir().join(format!("fasthosting-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // Two runs, each with a session of its own
+    for _ in 0..2 {
+        let mut sqlite = Sqlite::open(&path, Path::new("app.elf")).unwrap();
+        sqlite
+            .insert(&record, UNIX_EPOCH + Duration::from_secs(60))
+            .unwrap();
+        sqlite
+            .write(&Record {
+                level: None,
+                values: vec![],
+                ..record.clone()
+            })
+            .unwrap();
+        sqlite.finish().unwrap();
+    }
+
+    let connection = Connection::open(&path).unwrap();
+    let sessions: i64 = connection
+        .query_row(
+            "SELECT COUNT(*) FROM sessions",
+            rusqlite::NO_PARAMS,
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(sessions, 2);
+    let mut statement = connection
+        .prepare(
+            "SELECT session, received, timestamp, level, module, message_id, type, message, \
+             args, payload FROM messages WHERE session = 2",
+        )
+        .unwrap();
+    let rows: Vec<_> = statement
+        .query_map(rusqlite::NO_PARAMS, |row| {
+            Ok((
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, Vec<u8>>(9)?,
+            ))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        rows,
+        [
+            (
+                Some("warn".to_string()),
+                Some("{\"temp\":21.5}".to_string()),
+                vec![0x00, 0xac, 0x41]
+            ),
+            (None, None, vec![0x00, 0xac, 0x41]),
+        ]
+    );
+    let first: (String, String, String, u32, String, String) = connection
+        .query_row(
+            "SELECT received, timestamp, module, message_id, type, message FROM messages \
+             WHERE id = 1",
+            rusqlite::NO_PARAMS,
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        first,
+        (
+            "1970-01-01T00:01:00.000000Z".to_string(),
+            "1.500000".to_string(),
+            "app::sensors".to_string(),
+            2,
+            "app::Reading".to_string(),
+            "it's 21.5 °C".to_string()
+        )
+    );
+
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn archive_split_and_index() {
+    use crate::archive::{Archive, IndexEntry, Split, INDEX};
+    use crate::record::Record;
+    use crate::sink::Sink;
+    use flate2::read::GzDecoder;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let dir = std::env::temp_dir().join(format!("fasthosting-archive-{}", std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    let mut archive = Archive::new(&dir, Split::Hourly, true).unwrap();
+    // Left by an earlier session
+    fs::write(dir.join("1970-01-02T02.jsonl"), "").unwrap();
+
+    let record = |message: &str| Record {
+        message: message.into(),
+        ..Record::default()
+    };
+    let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(24 * 3600 + secs);
+
+    archive.push(&record("one"), at(3500)).unwrap();
+    archive.push(&record("two"), at(3599)).unwrap();
+    archive.push(&record("three"), at(7200)).unwrap();
+    // In the way of the compression, the file is kept as it is
+    fs::create_dir(dir.join("1970-01-02T02-1.jsonl.gz")).unwrap();
+    archive.finish().unwrap();
+
+    let index: Vec<IndexEntry> = fs::read_to_string(dir.join(INDEX))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let entry = |file: &str, first: &str, last: &str, records| IndexEntry {
+        file: file.into(),
+        first: first.into(),
+        last: last.into(),
+        records,
+    };
+    assert_eq!(
+        index,
+        vec![
+            entry(
+                "1970-01-02T00.jsonl.gz",
+                "1970-01-02T00:58:20.000000Z",
+                "1970-01-02T00:59:59.000000Z",
+                2
+            ),
+            entry(
+                "1970-01-02T02-1.jsonl",
+                "1970-01-02T02:00:00.000000Z",
+                "1970-01-02T02:00:00.000000Z",
+                1
+            ),
+        ]
+    );
+
+    assert!(!dir.join("1970-01-02T00.jsonl").exists());
+    assert!(dir.join("1970-01-02T02-1.jsonl").exists());
+    let mut unzipped = String::new();
+    GzDecoder::new(File::open(dir.join("1970-01-02T00.jsonl.gz")).unwrap())
+        .read_to_string(&mut unzipped)
+        .unwrap();
+    let messages: Vec<_> = unzipped
+        .lines()
+        .map(|line| serde_json::from_str::<Record>(line).unwrap().message)
+        .collect();
+    assert_eq!(messages, vec!["one", "two"]);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+/// Collects what a pipeline writes to its sink
+#[derive(Default)]
+struct Collect(Vec<crate::record::Record>);
+
+impl Collect {
+    fn messages(&self) -> Vec<&str> {
+        self.0.iter().map(|record| &*record.message).collect()
+    }
+}
+
+impl crate::sink::Sink for Collect {
+    fn write(&mut self, record: &crate::record::Record) -> anyhow::Result<()> {
+        self.0.push(record.clone());
+        Ok(())
+    }
+}
+
+/// A frame of a message without arguments
+fn frame(string_loc: u32) -> Vec<u8> {
+    let mut buf = Vec::new();
+    leb128_write(&mut buf, 1);
+    leb128_write(&mut buf, string_loc);
+    leb128_write(&mut buf, 0);
+    buf.push(0);
+    buf
+}
+
+/// A decoder of messages without arguments, from their strings only
+fn decoder(strings: &crate::symbols::Symbols) -> crate::decoder::Decoder<'static> {
+    crate::decoder::Decoder::new(
+        crate::catalog::Catalog::new(strings),
+        crate::symbols::Symbols::new(),
+        elf_test::TypePrinters(std::collections::HashMap::new()),
+    )
+}
+
+#[test]
+fn pipeline_decodes_on_its_own_thread() {
+    use crate::pipeline::{Chunk, Pipeline, CAPACITY};
+    use crate::reconnect::Policy;
+    use crate::symbols::Symbols;
+    use crate::trigger::{Action, TriggerConfig, Triggers};
+    use crate::until::{Outcome, Until};
+    use regex::Regex;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::time::{Instant, UNIX_EPOCH};
+
+    let strings: Symbols = vec![(0x10, "booting"), (0x20, "ready")]
+        .into_iter()
+        .collect();
+    let running = Arc::new(AtomicBool::new(true));
+    let (target_actions, triggered) = mpsc::channel();
+    let pipeline = Pipeline {
+        until: Until::new(Regex::new("ready").ok(), None, None, Instant::now()),
+        running: running.clone(),
+        triggers: Triggers::new(&[TriggerConfig {
+            on: "^boot".into(),
+            run: None,
+            action: Some(Action::Backtrace),
+        }])
+        .unwrap(),
+        target_actions: Some(target_actions),
+        ..Pipeline::new(Parser::new(), decoder(&strings), Collect::default())
+    };
+
+    let (chunks, received) = mpsc::sync_channel(CAPACITY);
+    let decoding = std::thread::spawn(move || pipeline.run(received, None, None));
+
+    // A frame split over two reads, then one cut off by a resync
+    let booting = frame(0x10);
+    chunks
+        .send(Chunk::Data(booting[..2].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    chunks
+        .send(Chunk::Data(booting[2..].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    chunks
+        .send(Chunk::Data(frame(0x20)[..2].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    chunks.send(Chunk::Resync).unwrap();
+    chunks.send(Chunk::Data(frame(0x20), UNIX_EPOCH)).unwrap();
+    drop(chunks);
+
+    let mut pipeline = decoding.join().unwrap().unwrap();
+    assert_eq!(pipeline.sink.messages(), vec!["booting", "ready"]);
+    assert_eq!(pipeline.resyncs, 1);
+    assert_eq!(pipeline.outcome, Some(Outcome::Passed));
+    assert!(!running.load(Ordering::SeqCst));
+    assert_eq!(
+        triggered.try_iter().collect::<Vec<_>>(),
+        [Action::Backtrace]
+    );
+
+    // Out of resync budget
+    pipeline.on_parse_error = Policy::Retry(1);
+    assert!(pipeline.chunk(Chunk::Resync).is_err());
+}
+
+#[test]
+fn secure_and_non_secure_rings() {
+    use crate::pipeline::{Chunk, Pipeline, Secure};
+    use crate::record::World;
+    use crate::symbols::Symbols;
+    use crate::template::Template;
+    use std::time::UNIX_EPOCH;
+
+    // Both images put their strings at the same addresses
+    let strings: Symbols = vec![(0x10, "app started")].into_iter().collect();
+    let secure_strings: Symbols = vec![(0x10, "attestation ok")].into_iter().collect();
+    let mut pipeline = Pipeline {
+        secure: Some(Secure {
+            parser: Parser::new(),
+            decoder: decoder(&secure_strings).with_world(World::Secure),
+        }),
+        ..Pipeline::new(
+            Parser::new(),
+            decoder(&strings).with_world(World::NonSecure),
+            Collect::default(),
+        )
+    };
+
+    // The rings are parsed apart, so frames can interleave mid-frame
+    let app = frame(0x10);
+    let secure = |chunk| Chunk::Secure(Box::new(chunk));
+    pipeline
+        .chunk(Chunk::Data(app[..2].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    pipeline
+        .chunk(secure(Chunk::Data(frame(0x10), UNIX_EPOCH)))
+        .unwrap();
+    pipeline
+        .chunk(Chunk::Data(app[2..].to_vec(), UNIX_EPOCH))
+        .unwrap();
+
+    // A resync of the secure ring leaves the other one alone
+    pipeline
+        .chunk(secure(Chunk::Data(frame(0x10)[..2].to_vec(), UNIX_EPOCH)))
+        .unwrap();
+    pipeline
+        .chunk(Chunk::Data(app[..2].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    pipeline.chunk(secure(Chunk::Resync)).unwrap();
+    pipeline
+        .chunk(Chunk::Data(app[2..].to_vec(), UNIX_EPOCH))
+        .unwrap();
+
+    let template: Template = "{world:>10}: {message}".parse().unwrap();
+    let lines: Vec<_> = pipeline.sink.0.iter().map(|r| template.render(r)).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "    secure: attestation ok",
+            "non-secure: app started",
+            "non-secure: app started",
+        ]
+    );
+    assert_eq!(pipeline.resyncs, 1);
+    assert!(secure(Chunk::Data(vec![1], UNIX_EPOCH)).has_data());
+    assert!(!secure(Chunk::Resync).has_data());
+}
+
+#[test]
+fn priority_ring_for_warnings_and_errors() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::parser::{HEARTBEAT_FRAME, PANIC_FRAME};
+    use crate::pipeline::{Chunk, Pipeline};
+    use crate::record::Level;
+    use crate::sink::{Sink, Terminal};
+    use crate::symbols::Symbols;
+    use crate::template::Template;
+    use crate::until::Outcome;
+    use elf_test::{LogSite, TypePrinters};
+    use std::collections::HashMap;
+    use std::time::{Instant, UNIX_EPOCH};
+
+    let strings: Symbols = vec![
+        (0x10, "sampling"),
+        (0x20, "battery low"),
+        (0x30, "radio timeout"),
+    ]
+    .into_iter()
+    .collect();
+    let site = |address, level| LogSite {
+        address,
+        file: None,
+        line: None,
+        module: None,
+        type_name: None,
+        type_address: None,
+        level,
+        kv: false,
+        formatted: false,
+    };
+    let catalog = Catalog::new(&strings).with_sites(&[
+        site(0x10, None),
+        site(0x20, Some("warn")),
+        site(0x30, Some("error")),
+    ]);
+    let decoder = Decoder::new(catalog, Symbols::new(), TypePrinters(HashMap::new()));
+    let mut pipeline = Pipeline {
+        priority: Some(Parser::new()),
+        ..Pipeline::new(Parser::new(), decoder, Collect::default())
+    };
+
+    // The rings are parsed apart, and share the strings
+    let sampling = frame(0x10);
+    let priority = |chunk| Chunk::Priority(Box::new(chunk));
+    pipeline
+        .chunk(Chunk::Data(sampling[..2].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    let mut critical = frame(0x20);
+    critical.extend(frame(0x30));
+    pipeline
+        .chunk(priority(Chunk::Data(critical, UNIX_EPOCH)))
+        .unwrap();
+    pipeline
+        .chunk(Chunk::Data(sampling[2..].to_vec(), UNIX_EPOCH))
+        .unwrap();
+
+    // Losing frames in the main ring leaves the other one alone
+    pipeline
+        .chunk(priority(Chunk::Data(frame(0x30)[..2].to_vec(), UNIX_EPOCH)))
+        .unwrap();
+    pipeline
+        .chunk(Chunk::Overrun {
+            frames: 4,
+            skipped: 20,
+        })
+        .unwrap();
+    pipeline
+        .chunk(priority(Chunk::Data(frame(0x30)[2..].to_vec(), UNIX_EPOCH)))
+        .unwrap();
+
+    let levels: Vec<_> = pipeline
+        .sink
+        .0
+        .iter()
+        .map(|record| (record.level, record.message.as_str()))
+        .collect();
+    assert_eq!(
+        levels,
+        vec![
+            (Some(Level::Warn), "battery low"),
+            (Some(Level::Error), "radio timeout"),
+            (None, "sampling"),
+            (Some(Level::Error), "radio timeout"),
+        ]
+    );
+    assert!(priority(Chunk::Data(vec![1], UNIX_EPOCH)).has_data());
+
+    let template: Template = "{level:>5} {message}".parse().unwrap();
+    assert_eq!(template.render(&pipeline.sink.0[0]), " warn battery low");
+    assert_eq!(template.render(&pipeline.sink.0[2]), "      sampling");
+
+    let mut out = Vec::new();
+    let mut terminal = Terminal::new(&mut out, false);
+    for record in &pipeline.sink.0[1..3] {
+        terminal.write(record).unwrap();
+    }
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "ERROR radio timeout\nsampling\n"
+    );
+
+    // Heartbeats and panics are handled on the priority ring like on the main one
+    let mut read = Vec::new();
+    leb128_write(&mut read, 0);
+    leb128_write(&mut read, HEARTBEAT_FRAME as u32);
+    leb128_write(&mut read, 0);
+    let mut data = 7u32.to_le_bytes().to_vec();
+    data.extend_from_slice(b"src/isr.rs\0stack overflow");
+    leb128_write(&mut read, data.len() as u32);
+    leb128_write(&mut read, PANIC_FRAME as u32);
+    leb128_write(&mut read, 0);
+    read.extend_from_slice(&data);
+    pipeline
+        .chunk(priority(Chunk::Data(read, UNIX_EPOCH)))
+        .unwrap();
+    assert!(pipeline.stats.health(Instant::now()).is_some());
+    assert_eq!(
+        pipeline.sink.0.last().unwrap().message,
+        "Target panicked at src/isr.rs:7: stack overflow"
+    );
+    assert_eq!(pipeline.outcome, Some(Outcome::Panicked));
+}
+
+#[test]
+fn ring_per_core() {
+    use crate::pipeline::{Chunk, Pipeline};
+    use crate::sink::{Sink, Terminal};
+    use crate::symbols::Symbols;
+    use crate::template::Template;
+    use std::time::UNIX_EPOCH;
+
+    let strings: Symbols = vec![(0x10, "sampling"), (0x20, "radio up")]
+        .into_iter()
+        .collect();
+    let mut pipeline = Pipeline {
+        cores: vec![Parser::new()],
+        ..Pipeline::new(Parser::new(), decoder(&strings), Collect::default())
+    };
+
+    // The rings are parsed apart, and share the strings
+    let core = |chunk| Chunk::Core(1, Box::new(chunk));
+    let sampling = frame(0x10);
+    pipeline
+        .chunk(Chunk::Data(sampling[..2].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    pipeline
+        .chunk(core(Chunk::Data(frame(0x20), UNIX_EPOCH)))
+        .unwrap();
+    pipeline
+        .chunk(Chunk::Data(sampling[2..].to_vec(), UNIX_EPOCH))
+        .unwrap();
+    // A core the target does not have
+    pipeline
+        .chunk(Chunk::Core(
+            2,
+            Box::new(Chunk::Data(frame(0x20), UNIX_EPOCH)),
+        ))
+        .unwrap();
+
+    let lines: Vec<_> = pipeline
+        .sink
+        .0
+        .iter()
+        .map(|record| (record.core, record.message.as_str()))
+        .collect();
+    assert_eq!(lines, [(Some(1), "radio up"), (Some(0), "sampling")]);
+    assert!(core(Chunk::Data(vec![1], UNIX_EPOCH)).has_data());
+
+    let template: Template = "{core}: {message}".parse().unwrap();
+    assert_eq!(template.render(&pipeline.sink.0[0]), "1: radio up");
+
+    let mut out = Vec::new();
+    let mut terminal = Terminal::new(&mut out, false);
+    for record in &pipeline.sink.0 {
+        terminal.write(record).unwrap();
+    }
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "[core 1] radio up\n[core 0] sampling\n"
+    );
+}
+
+#[test]
+fn wire_version_handshake() {
+    use crate::parser::{check_wire_version, BOOT_FRAME, WIRE_VERSION};
+    use crate::pipeline::{Chunk, Pipeline};
+    use crate::symbols::Symbols;
+    use std::time::UNIX_EPOCH;
+
+    let boot = |type_loc, version| {
+        let mut buf = Vec::new();
+        leb128_write(&mut buf, 1);
+        leb128_write(&mut buf, BOOT_FRAME as u32);
+        leb128_write(&mut buf, type_loc);
+        buf.push(version);
+        buf
+    };
+
+    let strings: Symbols = vec![(0x10, "ready")].into_iter().collect();
+    let mut pipeline = Pipeline::new(Parser::new(), decoder(&strings), Collect::default());
+
+    // The boot frame is not shown
+    let mut read = boot(0, WIRE_VERSION);
+    read.extend(frame(0x10));
+    pipeline.chunk(Chunk::Data(read, UNIX_EPOCH)).unwrap();
+    assert_eq!(pipeline.sink.messages(), vec!["ready"]);
+
+    // A frame that only looks like one is shown as it is
+    pipeline
+        .chunk(Chunk::Data(boot(0x20, 1), UNIX_EPOCH))
+        .unwrap();
+    assert_eq!(pipeline.sink.0.len(), 2);
+
+    let e = pipeline
+        .chunk(Chunk::Data(boot(0, WIRE_VERSION + 1), UNIX_EPOCH))
+        .unwrap_err();
+    assert!(
+        e.to_string().contains("wire format version 2"),
+        "unexpected error: {}",
+        e
+    );
+
+    // The version in the ELF is checked the same way before flashing
+    assert!(check_wire_version(WIRE_VERSION).is_ok());
+    assert!(check_wire_version(WIRE_VERSION + 1).is_err());
+}
+
+#[test]
+fn symbols_by_interval() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::symbols::Symbols;
+    use elf_test::TypePrinters;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    // `app::Foo` merged into the tail of `app::sub::Foo`, with its own symbol as well
+    let types: Symbols = vec![
+        (0x100, "app::sub::Foo"),
+        (0x105, "sub::Foo"),
+        (0x200, ""),
+        (0x300, "u32"),
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(types.get(0x100), Some("app::sub::Foo"));
+    assert_eq!(types.get(0x105), Some("sub::Foo"));
+    assert_eq!(types.get(0x107), Some("b::Foo"));
+    assert_eq!(types.get(0x10c), Some("o"));
+    assert_eq!(types.get(0x10d), None);
+    assert_eq!(types.get(0x200), Some(""));
+    assert_eq!(types.get(0x302), Some("2"));
+    assert_eq!(types.get(0x303), None);
+    assert_eq!(types.get(0xff), None);
+
+    // Later duplicates win, as with a map
+    let dup: Symbols = vec![(0x10, "old"), (0x10, "new")].into_iter().collect();
+    assert_eq!(dup.len(), 1);
+    assert_eq!(dup.get(0x10), Some("new"));
+
+    // A frame pointing into a format string gets the rest of it, without an ID
+    let strings: Symbols = vec![(0x10, "error: disk full")].into_iter().collect();
+    let mut decoder = Decoder::new(Catalog::new(&strings), types, TypePrinters(HashMap::new()));
+    let packet = |string_loc| Packet {
+        string_loc,
+        type_loc: 0x300,
+        timestamp: None,
+        task: None,
+        buffer: vec![],
+    };
+    let record = decoder.decode(&packet(0x17), UNIX_EPOCH);
+    assert_eq!((record.id, record.message.as_str()), (None, "disk full"));
+    assert_eq!(record.type_name.as_deref(), Some("u32"));
+    let record = decoder.decode(&packet(0x10), UNIX_EPOCH);
+    assert_eq!(
+        (record.id, record.message.as_str()),
+        (Some(0), "error: disk full")
+    );
+}
+
+#[test]
+fn address_normalization() {
+    use crate::fmt::{AddressMap, Segment};
+    use std::ops::Range;
+
+    let addresses = AddressMap {
+        code: vec![Range {
+            start: 0x100,
+            end: 0x2000,
+        }],
+        // `.data` linked at 0x2000_0000 and loaded from flash right after the code
+        segments: vec![Segment {
+            virtual_address: 0x2000_0000,
+            physical_address: 0x3000,
+            size: 0x100,
+        }],
+    };
+
+    // Thumb bit on a code address
+    assert_eq!(addresses.normalize(0x1235), 0x1234);
+    // Odd data addresses are left alone
+    assert_eq!(addresses.normalize(0x2001), 0x2001);
+    // Load address of initialized data
+    assert_eq!(addresses.normalize(0x3011), 0x2000_0011);
+    assert_eq!(addresses.normalize(0x3100), 0x3100);
+    // Already a link address
+    assert_eq!(addresses.normalize(0x2000_0011), 0x2000_0011);
+}
+
+#[test]
+fn fetch_unknown_strings() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::fetch::{read_string, Fetcher, ReadMemory, Request, MAX_LEN};
+    use crate::symbols::Symbols;
+    use anyhow::{anyhow, Result};
+    use elf_test::TypePrinters;
+    use std::collections::HashMap;
+    use std::sync::mpsc;
+    use std::time::UNIX_EPOCH;
+
+    struct Ram(Vec<u8>);
+
+    impl ReadMemory for Ram {
+        fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<()> {
+            let address = address as usize;
+            let bytes = self
+                .0
+                .get(address..address + data.len())
+                .ok_or_else(|| anyhow!("Bus fault"))?;
+            data.copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    let mut ram = Ram(vec![b'x'; 1024]);
+    ram.0[0x10..0x1d].copy_from_slice(b"made at boot\0");
+    assert_eq!(read_string(&mut ram, 0x10).unwrap(), "made at boot");
+    assert_eq!(read_string(&mut ram, 0x100).unwrap().len(), MAX_LEN);
+    assert!(read_string(&mut ram, 0x3f0).is_err());
+
+    // The probe thread serves the requests of the decode thread
+    let (requests, requested) = mpsc::sync_channel::<Request>(16);
+    let probe = std::thread::spawn(move || {
+        for request in requested {
+            request.serve(&mut ram);
+        }
+    });
+
+    let strings: Symbols = vec![(0x1000, "in the elf")].into_iter().collect();
+    let mut decoder = Decoder::new(
+        Catalog::new(&strings),
+        Symbols::new(),
+        TypePrinters(HashMap::new()),
+    )
+    .with_fetcher(Fetcher::new(requests));
+    let packet = |string_loc| Packet {
+        string_loc,
+        type_loc: 0x3f0,
+        timestamp: None,
+        task: None,
+        buffer: vec![],
+    };
+
+    let record = decoder.decode(&packet(0x10), UNIX_EPOCH);
+    assert_eq!((record.id, record.message.as_str()), (None, "made at boot"));
+    assert_eq!(record.type_name, None);
+    let record = decoder.decode(&packet(0x1000), UNIX_EPOCH);
+    assert_eq!(record.message, "in the elf");
+
+    drop(decoder);
+    probe.join().unwrap();
+}
+
+#[test]
+fn snapshot_round_trip() {
+    use crate::snapshot::{Snapshot, MAGIC};
+
+    let snapshot = Snapshot {
+        chip: "nrf52840".into(),
+        elf: Some("/build/app.elf".into()),
+        taken: "2020-11-02T10:00:00.000Z".into(),
+        registers: vec![("PC".into(), 0x1234), ("SP".into(), 0x2003_fff0)],
+        memory: elf_test::Snapshot {
+            regions: vec![(0x2000_0000, vec![1, 2, 3, 4]), (0x2000_8000, vec![5; 16])],
+        },
+    };
+
+    let mut file = Vec::new();
+    snapshot.write_to(&mut file).unwrap();
+    assert!(file.starts_with(MAGIC));
+    assert!(file.ends_with(&[&[1, 2, 3, 4][..], &[5; 16]].concat()));
+
+    let read = Snapshot::read_from(&mut &file[..]).unwrap();
+    assert_eq!(read.elf, snapshot.elf);
+    assert_eq!(read.registers, snapshot.registers);
+    assert_eq!(read.memory.regions, snapshot.memory.regions);
+    assert_eq!(read.memory.read(0x2000_0001, 2), Some(&[2, 3][..]));
+    assert_eq!(read.memory.read(0x2000_0002, 4), None);
+
+    let mut summary = Vec::new();
+    read.write_summary(&mut summary).unwrap();
+    assert_eq!(
+        String::from_utf8(summary).unwrap(),
+        "Snapshot of nrf52840 taken 2020-11-02T10:00:00.000Z
+ELF: /build/app.elf
+Registers:
+    PC: 0x00001234
+    SP: 0x2003fff0
+RAM:
+  0x20000000..0x20000004 (4 bytes)
+  0x20008000..0x20008010 (16 bytes)
+"
+    );
+
+    // Truncated files and other files are refused
+    assert!(Snapshot::read_from(&mut &file[..file.len() - 1]).is_err());
+    assert!(Snapshot::read_from(&mut &b"\x7fELF\x01\x01\x01\0\0\0\0\0"[..]).is_err());
+}
+
+#[test]
+fn profile_flat_and_folded() {
+    use crate::profile::Profile;
+
+    let mut profile = Profile::new();
+    for pc in &[0x101, 0x102, 0x104, 0x200, 0x202, 0x300] {
+        profile.add(Some(*pc));
+    }
+    profile.add(None);
+    assert_eq!(profile.total(), 7);
+
+    let key = |pc: u32| match pc {
+        0x100..=0x1ff => Some("app::busy".to_string()),
+        0x200..=0x2ff => Some("app::idle;src/main.rs:12".to_string()),
+        _ => None,
+    };
+    let groups = profile.group(key);
+    assert_eq!(
+        groups,
+        [
+            ("app::busy".to_string(), 3),
+            ("app::idle;src/main.rs:12".to_string(), 2),
+            ("<halted>".to_string(), 1),
+            ("<unknown>".to_string(), 1),
+        ]
+    );
+
+    let mut flat = Vec::new();
+    profile.write_flat(&mut flat, &groups, 2).unwrap();
+    assert_eq!(
+        String::from_utf8(flat).unwrap(),
+        "      %   samples  function
+ 42.86%         3  app::busy
+ 28.57%         2  app::idle;src/main.rs:12
+ 28.57%         2  (2 more)
+"
+    );
+
+    let mut folded = Vec::new();
+    Profile::write_folded(&mut folded, &groups[..2]).unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "app::busy 3\napp::idle;src/main.rs:12 2\n"
+    );
+}
+
+#[test]
+fn svd_inspect() {
+    use crate::fetch::ReadMemory;
+    use crate::svd::Device;
+    use anyhow::{anyhow, Result};
+    use std::collections::HashMap;
+
+    let svd = r#"<?xml version="1.0" encoding="utf-8"?>
+<!-- A made up chip -->
+<device schemaVersion="1.1" xmlns:xs="http://www.w3.org/2001/XMLSchema-instance">
+  <name>CHIP</name>
+  <size>32</size>
+  <peripherals>
+    <peripheral>
+      <name>USART1</name>
+      <description>Universal synchronous &amp; asynchronous
+        receiver transmitter</description>
+      <baseAddress>0x40011000</baseAddress>
+      <registers>
+        <register>
+          <name>DR</name>
+          <addressOffset>0x4</addressOffset>
+          <readAction>clear</readAction>
+        </register>
+        <register>
+          <name>SR</name>
+          <addressOffset>0x0</addressOffset>
+          <fields>
+            <field>
+              <name>RXNE</name>
+              <bitOffset>5</bitOffset>
+              <bitWidth>1</bitWidth>
+              <enumeratedValues>
+                <enumeratedValue><name>Empty</name><value>0</value></enumeratedValue>
+                <enumeratedValue><name>NotEmpty</name><value>1</value></enumeratedValue>
+              </enumeratedValues>
+            </field>
+            <field>
+              <name>STATE</name>
+              <bitRange>[11:8]</bitRange>
+              <enumeratedValues>
+                <usage>write</usage>
+                <enumeratedValue><name>Reset</name><value>#0001</value></enumeratedValue>
+              </enumeratedValues>
+            </field>
+          </fields>
+        </register>
+        <register>
+          <name>TDR</name>
+          <addressOffset>0x8</addressOffset>
+          <access>write-only</access>
+        </register>
+        <register>
+          <dim>2</dim>
+          <dimIncrement>4</dimIncrement>
+          <name>GT%s</name>
+          <addressOffset>0x10</addressOffset>
+          <size>16</size>
+        </register>
+        <cluster>
+          <name>DMA</name>
+          <addressOffset>0x20</addressOffset>
+          <register>
+            <name>CR</name>
+            <addressOffset>0x0</addressOffset>
+          </register>
+        </cluster>
+      </registers>
+    </peripheral>
+    <peripheral derivedFrom="USART1">
+      <name>USART2</name>
+      <baseAddress>0x40004400</baseAddress>
+    </peripheral>
+  </peripherals>
+</device>"#;
+
+    let device = Device::parse(svd).unwrap();
+    assert_eq!(device.name, "CHIP");
+    let usart1 = device.peripheral("usart1").unwrap();
+    assert_eq!(
+        usart1.description.as_deref(),
+        Some("Universal synchronous & asynchronous receiver transmitter")
+    );
+    let names: Vec<_> = usart1.registers.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, ["SR", "DR", "TDR", "GT0", "GT1", "DMA.CR"]);
+    let usart2 = device.peripheral("USART2").unwrap();
+    assert_eq!(usart2.base_address, 0x4000_4400);
+    assert_eq!(usart2.registers, usart1.registers);
+    assert!(device.peripheral("USART3").is_none());
+
+    struct Registers(HashMap<u64, u32>);
+
+    impl ReadMemory for Registers {
+        fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<()> {
+            let word = self
+                .0
+                .get(&(address & !3))
+                .ok_or_else(|| anyhow!("Bus fault"))?;
+            let bytes = word.to_le_bytes();
+            let start = (address & 3) as usize;
+            data.copy_from_slice(&bytes[start..start + data.len()]);
+            Ok(())
+        }
+    }
+
+    let mut memory = Registers(
+        vec![
+            (0x4001_1000, 0x0000_0320),
+            (0x4001_1010, 0x0000_00ab),
+            (0x4001_1014, 0x0000_1234),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    let mut out = Vec::new();
+    usart1.write_registers(&mut memory, None, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "USART1 @ 0x40011000: Universal synchronous & asynchronous receiver transmitter
+  SR 0x40011000 = 0x00000320
+    RXNE[5] = 1 (NotEmpty)
+    STATE[11:8] = 3
+  DR 0x40011004 = <not read, reading has side effects>
+  TDR 0x40011008 = <write-only>
+  GT0 0x40011010 = 0x00ab
+  GT1 0x40011014 = 0x1234
+  DMA.CR 0x40011020 = <read failed: Bus fault>
+"
+    );
+
+    let mut out = Vec::new();
+    usart1
+        .write_registers(&mut memory, Some("gt1"), &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "USART1 @ 0x40011000: Universal synchronous & asynchronous receiver transmitter
+  GT1 0x40011014 = 0x1234
+"
+    );
+}
+
+#[test]
+fn itm_merged_with_messages() {
+    use crate::itm::{Event, ExceptionAction, ItmDecoder, PacketParser};
+    use crate::record::Record;
+    use crate::timeline::{Source, Timeline, HOLD};
+    use std::time::Instant;
+
+    #[rustfmt::skip]
+    let swo = [
+        // Synchronization, then a timestamp of 1000 that starts the count
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0xc0, 0xe8, 0x07,
+        // "hi\n" on port 0, at 2000
+        0x01, b'h', 0x01, b'i', 0x01, b'\n', 0xc0, 0xe8, 0x07,
+        // IRQ3 entered, at 2006
+        0x0e, 0x13, 0x10, 0x60,
+        // A word on port 1 and a global timestamp, at 3006
+        0x0b, 0xef, 0xbe, 0xad, 0xde, 0x94, 0x85, 0x01, 0xc0, 0xe8, 0x07,
+        // Overflow, at 3007
+        0x70, 0x10,
+    ];
+
+    // Events are held until their timestamp has arrived
+    let mut parser = PacketParser::new();
+    let timed = parser.push(&swo[..18]);
+    assert_eq!(timed.len(), 3);
+    assert!(timed.iter().all(|timed| timed.cycles == Some(2000)));
+    assert!(parser.push(&swo[18..21]).is_empty());
+    let timed = parser.push(&swo[21..22]);
+    assert_eq!(timed.len(), 1);
+    assert_eq!(timed[0].cycles, Some(2006));
+    assert_eq!(
+        timed[0].event,
+        Event::Exception {
+            number: 19,
+            action: ExceptionAction::Entered
+        }
+    );
+
+    // Split at every byte, like reads from a FIFO can be
+    let mut decoder = ItmDecoder::new(Some(1000));
+    let mut itm = Vec::new();
+    for b in &swo {
+        itm.extend(decoder.push(&[*b]));
+    }
+    let decoded: Vec<_> = itm
+        .iter()
+        .map(|r| (r.timestamp.as_deref().unwrap(), r.message.as_str()))
+        .collect();
+    assert_eq!(
+        decoded,
+        [
+            ("2.000000", "ITM port 0: hi"),
+            ("2.006000", "ITM exception: entered IRQ3"),
+            ("3.006000", "ITM port 1: 0xdeadbeef"),
+            ("3.007000", "ITM overflow, events were lost"),
+        ]
+    );
+    assert_eq!(itm[1].seconds, Some(2.006));
+
+    let log = |message: &str, seconds| Record {
+        seconds,
+        message: message.into(),
+        ..Record::default()
+    };
+    let messages = |records: Vec<Record>| -> Vec<String> {
+        records.into_iter().map(|record| record.message).collect()
+    };
+
+    // Records are held until the other stream has caught up
+    let now = Instant::now();
+    let mut timeline = Timeline::new();
+    for record in itm {
+        assert!(timeline.push(Source::Itm, record, now).is_empty());
+    }
+    assert_eq!(
+        messages(timeline.push(Source::Log, log("a", Some(1.5)), now)),
+        ["a"]
+    );
+    assert_eq!(
+        messages(timeline.push(Source::Log, log("b", Some(2.5)), now)),
+        ["ITM port 0: hi", "ITM exception: entered IRQ3", "b"]
+    );
+    assert_eq!(
+        messages(timeline.push(Source::Log, log("untimed", None), now)),
+        ["untimed"]
+    );
+    assert!(timeline.release(now).is_empty());
+
+    // or until they have waited long enough
+    assert_eq!(
+        messages(timeline.release(now + HOLD)),
+        ["ITM port 1: 0xdeadbeef", "ITM overflow, events were lost"]
+    );
+    assert!(timeline
+        .push(Source::Log, log("c", Some(4.0)), now + HOLD)
+        .is_empty());
+    assert_eq!(messages(timeline.drain()), ["c"]);
+}
+#[test]
+fn decode_coverage() {
+    use crate::catalog::Catalog;
+    use crate::symbols::Symbols;
+    use elf_test::{generate_printers, LogSite};
+
+    let elf = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../elf_test/tests/fixtures/types-rustc-1.95.0.elf"
+    ))
+    .unwrap();
+    let printers = generate_printers(&elf).unwrap();
+
+    let strings: Symbols = vec![
+        (0x10, "wrapper {}"),
+        (0x20, "name {}"),
+        (0x30, "{} {} {}"),
+        (0x40, "missing {}"),
+        (0x50, "no site {}"),
+        (0x60, "broken {"),
+    ]
+    .into_iter()
+    .collect();
+    let site = |address, type_name: &str| LogSite {
+        address,
+        file: Some("src/main.rs".into()),
+        line: Some(address),
+        module: None,
+        type_name: Some(type_name.into()),
+        type_address: None,
+        level: None,
+        kv: false,
+        formatted: false,
+    };
+    let catalog = Catalog::new(&strings).with_sites(&[
+        site(0x10, "app::Wrapper"),
+        site(0x20, "*const str"),
+        site(0x30, "(f32, u32)"),
+        site(0x40, "app::Missing"),
+        site(0x60, "app::Wrapper"),
+    ]);
+
+    let problems: Vec<_> = catalog
+        .coverage(&printers)
+        .into_iter()
+        .map(|problem| (problem.message.id, problem.reason))
+        .collect();
+    assert_eq!(problems.len(), 5, "{:?}", problems);
+    assert_eq!(
+        problems[0],
+        (
+            1,
+            "data_ptr of *const str: type not supported by the decoder".into()
+        )
+    );
+    assert_eq!(problems[1], (2, "3 placeholders for 2 values".into()));
+    assert_eq!(
+        problems[2],
+        (3, "type app::Missing is not in the debug info".into())
+    );
+    assert_eq!(problems[3].0, 4);
+    assert_eq!(problems[4].0, 5);
+    assert!(problems[4].1.starts_with("invalid format string"));
+    assert_eq!(catalog.messages()[0].location(), "src/main.rs:16");
+}
+
+#[cfg(unix)]
+#[test]
+fn control_socket() {
+    use crate::control::{self, Method};
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let path =
+        std::env::temp_dir().join(format!("fasthosting-control-{}.sock", std::process::id()));
+    let (socket, calls) = control::listen(&path).unwrap();
+
+    // The decode thread of the daemon
+    let decode = std::thread::spawn(move || {
+        let mut methods = Vec::new();
+        for call in calls.decode.iter().take(2) {
+            methods.push(call.method.clone());
+            match call.method {
+                Method::Stats => call.reply(Ok(json!({ "messages": 3 }))),
+                _ => call.reply(Ok(Value::Null)),
+            }
+        }
+        methods
+    });
+
+    let stream = UnixStream::connect(&path).unwrap();
+    let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+    let mut request = |request: &str| {
+        writeln!(&stream, "{}", request).unwrap();
+        serde_json::from_str::<Value>(&lines.next().unwrap().unwrap()).unwrap()
+    };
+
+    assert_eq!(
+        request(r#"{"jsonrpc": "2.0", "id": 1, "method": "filter", "params": {"text": "radio"}}"#),
+        json!({ "jsonrpc": "2.0", "id": 1, "result": null })
+    );
+    assert_eq!(
+        request(r#"{"jsonrpc": "2.0", "id": "a", "method": "stats"}"#),
+        json!({ "jsonrpc": "2.0", "id": "a", "result": { "messages": 3 } })
+    );
+    assert_eq!(
+        request(r#"{"jsonrpc": "2.0", "id": 2, "method": "reboot"}"#)["error"]["code"],
+        -32601
+    );
+    assert_eq!(
+        request(r#"{"jsonrpc": "2.0", "id": 3, "method": "dump"}"#)["error"]["code"],
+        -32602
+    );
+    assert_eq!(request("{")["error"]["code"], -32700);
+
+    assert_eq!(
+        decode.join().unwrap(),
+        vec![Method::Filter(Some("radio".into())), Method::Stats]
+    );
+
+    // Nobody handles the calls anymore
+    assert_eq!(
+        request(r#"{"jsonrpc": "2.0", "id": 4, "method": "stop"}"#)["error"]["code"],
+        -32000
+    );
+
+    drop(socket);
+    assert!(!path.exists());
+}
+
+#[test]
+fn rtt_discovery() {
+    use crate::fetch::ReadMemory;
+    use crate::rtt::{self, UpChannel};
+    use anyhow::{anyhow, Result};
+
+    struct Ram(Vec<u8>);
+
+    impl ReadMemory for Ram {
+        fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<()> {
+            let address = address as usize;
+            let bytes = self
+                .0
+                .get(address..address + data.len())
+                .ok_or_else(|| anyhow!("Bus fault"))?;
+            data.copy_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    // The control block straddles two of the blocks RAM is searched in
+    let block = 0x5fc;
+    let regions = [0..0x200, 0x200..0x1000];
+    let mut ram = Ram(vec![0; 0x1000]);
+    let mut control = rtt::ID.to_vec();
+    for word in &[
+        // Up and down channel counts
+        2u32, 0, // A terminal channel, then the one of log0_target
+        0x800, 0x900, 0x100, 0, 0, 0, //
+        0x810, 0xa00, 0x200, 0x20, 0x10, 0,
+    ] {
+        control.extend_from_slice(&word.to_le_bytes());
+    }
+    ram.0[block..block + control.len()].copy_from_slice(&control);
+    ram.0[0x800..0x809].copy_from_slice(b"Terminal\0");
+    ram.0[0x810..0x815].copy_from_slice(b"log0\0");
+
+    let log0 = UpChannel {
+        name: "log0".into(),
+        cursor_address: block as u64 + 60,
+        buffer_address: 0xa00,
+        buffer_size: 0x200,
+    };
+    assert_eq!(
+        rtt::find(&mut ram, None, &regions).unwrap(),
+        Some(block as u64)
+    );
+    assert_eq!(rtt::channel(&mut ram, None, &regions).unwrap(), log0);
+    // The address in the ELF is only a hint
+    assert_eq!(rtt::channel(&mut ram, Some(0x100), &regions).unwrap(), log0);
+    assert_eq!(rtt::cursor_address(block as u64, 1), log0.cursor_address);
+    assert_eq!(
+        rtt::find(&mut ram, None, &[0..0x200, 0x200..0x5fc]).unwrap(),
+        None
+    );
+    assert_eq!(
+        rtt::up_channels(&mut ram, block as u64).unwrap()[0].name,
+        "Terminal"
+    );
+
+    ram.0[0x810..0x815].copy_from_slice(b"dfmt\0");
+    assert!(rtt::channel(&mut ram, Some(block as u64), &regions).is_err());
+}
+
+#[test]
+fn itm_stimulus_frames() {
+    use crate::itm::{Stimulus, Written};
+
+    let mut frames = Vec::new();
+    for (sym, data) in [(0x10, &[1, 2, 3, 4, 5][..]), (0x11, &[6][..])].iter() {
+        leb128_write(&mut frames, data.len() as u32);
+        leb128_write(&mut frames, *sym);
+        leb128_write(&mut frames, 0x20);
+        frames.extend_from_slice(data);
+    }
+
+    // As the target sends them on port 1, a word at a time and the rest as bytes
+    let mut swo = vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x80];
+    let words = frames.len() / 4 * 4;
+    for word in frames[..words].chunks(4) {
+        swo.push(0x0b);
+        swo.extend_from_slice(word);
+        // Text on port 0 and an exception between them
+        swo.extend_from_slice(&[0x01, b'x', 0x0e, 0x13, 0x10]);
+    }
+    for byte in &frames[words..] {
+        swo.extend_from_slice(&[0x09, *byte]);
+    }
+
+    // Split at every byte, like reads from a FIFO can be
+    let mut stimulus = Stimulus::new(1);
+    let mut bytes = Vec::new();
+    for b in &swo {
+        for written in stimulus.push(&[*b]) {
+            match written {
+                Written::Bytes(written) => bytes.extend(written),
+                Written::Overflow => panic!("No overflow in the capture"),
+            }
+        }
+    }
+    assert_eq!(bytes, frames);
+
+    let mut parser = Parser::new();
+    parser.push(&bytes);
+    let packets: Vec<_> = std::iter::from_fn(|| parser.try_parse())
+        .map(|packet| (packet.string_loc, packet.buffer))
+        .collect();
+    assert_eq!(packets, [(0x10, vec![1, 2, 3, 4, 5]), (0x11, vec![6])]);
+
+    // Lost packets split the bytes, the frame they were part of is gone
+    assert_eq!(
+        stimulus.push(&[0x09, 1, 0x70, 0x09, 2, 0x0b, 3, 4, 5, 6]),
+        [
+            Written::Bytes(vec![1]),
+            Written::Overflow,
+            Written::Bytes(vec![2, 3, 4, 5, 6])
+        ]
+    );
+}
+
+#[test]
+fn panic_frame() {
+    use crate::parser::{Panic, PANIC_FRAME};
+    use crate::pipeline::{Chunk, Pipeline};
+    use crate::record::Level;
+    use crate::symbols::Symbols;
+    use crate::until::Outcome;
+    use std::sync::atomic::Ordering;
+    use std::time::UNIX_EPOCH;
+
+    // As `log0_panic` writes it, the line then the file and the message
+    let mut data = 42u32.to_le_bytes().to_vec();
+    data.extend_from_slice(b"src/main.rs\0index out of bounds");
+    let mut read = Vec::new();
+    leb128_write(&mut read, data.len() as u32);
+    leb128_write(&mut read, PANIC_FRAME as u32);
+    leb128_write(&mut read, 0);
+    read.extend_from_slice(&data);
+
+    let mut parser = Parser::new();
+    parser.push(&read);
+    let panic = parser.try_parse().unwrap().panic().unwrap();
+    assert_eq!(
+        panic,
+        Panic {
+            file: "src/main.rs".into(),
+            line: 42,
+            message: "index out of bounds".into(),
+        }
+    );
+
+    let strings = Symbols::new();
+    let mut pipeline = Pipeline::new(Parser::new(), decoder(&strings), Collect::default());
+
+    // The session ends, and the mark on the ring seen after the frame is not shown again
+    pipeline.chunk(Chunk::Data(read, UNIX_EPOCH)).unwrap();
+    pipeline.chunk(Chunk::Panicked).unwrap();
+    let record = &pipeline.sink.0[0];
+    assert_eq!(pipeline.sink.0.len(), 1);
+    assert_eq!(record.level, Some(Level::Error));
+    assert_eq!(
+        record.message,
+        "Target panicked at src/main.rs:42: index out of bounds"
+    );
+    assert_eq!(pipeline.outcome, Some(Outcome::Panicked));
+    assert_eq!(Outcome::Panicked.exit_code(), 101);
+    assert!(!pipeline.running.load(Ordering::SeqCst));
+
+    // Only the mark, the frame did not fit
+    pipeline.outcome = None;
+    pipeline.chunk(Chunk::Panicked).unwrap();
+    assert_eq!(pipeline.sink.0.len(), 2);
+    assert_eq!(pipeline.outcome, Some(Outcome::Panicked));
+}
+
+#[test]
+fn fault_frame() {
+    use crate::fault::Fault;
+    use crate::parser::FAULT_FRAME;
+    use crate::pipeline::{Chunk, Pipeline};
+    use crate::record::Level;
+    use crate::symbols::Symbols;
+    use crate::until::Outcome;
+    use std::sync::atomic::Ordering;
+    use std::time::UNIX_EPOCH;
+
+    // As `report_fault` writes it, the stacked registers then CFSR, HFSR, MMFAR and BFAR, here a
+    // forced precise bus fault
+    let words = [
+        1,
+        2,
+        3,
+        4,
+        12,
+        0x0000_0457,
+        0x0000_0abc,
+        0x6100_0000,
+        0x0000_8200,
+        0x4000_0000,
+        0,
+        0x2001_0000u32,
+    ];
+    let data: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let mut read = Vec::new();
+    leb128_write(&mut read, data.len() as u32);
+    leb128_write(&mut read, FAULT_FRAME as u32);
+    leb128_write(&mut read, 0);
+    read.extend_from_slice(&data);
+
+    let mut parser = Parser::new();
+    parser.push(&read);
+    let fault = parser.try_parse().unwrap().fault().unwrap();
+    assert_eq!(fault.pc, 0xabc);
+    assert_eq!(fault.lr, 0x457);
+    assert_eq!(fault.address(), Some(0x2001_0000));
+    assert_eq!(
+        fault.causes(),
+        [
+            "precise data bus error",
+            "escalated from a configurable fault"
+        ]
+    );
+    assert_eq!(
+        fault.report(None)[1..],
+        [
+            "pc   0x00000abc",
+            "lr   0x00000456",
+            "r0   0x00000001  r1   0x00000002  r2   0x00000003  r3   0x00000004",
+            "r12  0x0000000c  xpsr 0x61000000",
+            "cfsr 0x00008200  PRECISERR",
+            "hfsr 0x40000000  FORCED",
+            "bfar 0x20010000",
+        ]
+    );
+    // Too short to be one
+    assert_eq!(Fault::parse(&data[..44]), None);
+
+    let strings = Symbols::new();
+    let mut pipeline = Pipeline::new(Parser::new(), decoder(&strings), Collect::default());
+
+    // Without an ELF the addresses are not symbolized
+    pipeline.chunk(Chunk::Data(read, UNIX_EPOCH)).unwrap();
+    let record = &pipeline.sink.0[0];
+    assert_eq!(pipeline.sink.0.len(), 1);
+    assert_eq!(record.level, Some(Level::Error));
+    assert_eq!(
+        record.message,
+        "Target HardFault at 0x00000abc: precise data bus error, escalated from a configurable \
+         fault at 0x20010000"
+    );
+    assert_eq!(pipeline.outcome, Some(Outcome::Faulted));
+    assert_eq!(Outcome::Faulted.exit_code(), 139);
+    assert!(!pipeline.running.load(Ordering::SeqCst));
+}
+
+#[test]
+fn log_crate_records() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::parser::{Facade, FacadeMessage, LOG_FRAME};
+    use crate::record::Level;
+    use crate::symbols::Symbols;
+    use elf_test::TypePrinters;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+    use xmas_elf::ElfFile;
+
+    // As `facade::Logger` writes it, the level then the target and the message
+    let packet = |data: &[u8]| Packet {
+        string_loc: LOG_FRAME,
+        type_loc: 0,
+        timestamp: None,
+        task: None,
+        buffer: data.to_vec(),
+    };
+
+    let formatted = packet(b"\x03app::radio\0sent 5 bytes");
+    assert_eq!(
+        formatted.facade(),
+        Some(Facade {
+            level: Some(Level::Info),
+            target: "app::radio".into(),
+            message: FacadeMessage::Text("sent 5 bytes".into()),
+        })
+    );
+
+    let elf = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../elf_test/tests/fixtures/types-rustc-1.95.0.elf"
+    ))
+    .unwrap();
+    let file = ElfFile::new(&elf).unwrap();
+    let section = file.find_section_by_name(".rodata").unwrap();
+    let rodata = section.address() as u32;
+    let expected = String::from_utf8_lossy(&section.raw_data(&file)[..4]).into_owned();
+
+    // A message without arguments is where it is in the ELF, `DEFERRED` is set in the level
+    let mut data = b"\x82hal\0".to_vec();
+    data.extend_from_slice(&rodata.to_le_bytes());
+    data.extend_from_slice(&4u32.to_le_bytes());
+    let deferred = packet(&data);
+    assert_eq!(
+        deferred.facade().unwrap().message,
+        FacadeMessage::Static {
+            address: rodata,
+            len: 4
+        }
+    );
+
+    let strings = Symbols::new();
+    let decoder = || {
+        Decoder::new(
+            Catalog::new(&strings),
+            Symbols::new(),
+            TypePrinters(HashMap::new()),
+        )
+    };
+    let record = decoder().decode(&formatted, UNIX_EPOCH);
+    assert_eq!(
+        (
+            record.level,
+            record.module.as_deref(),
+            record.message.as_str()
+        ),
+        (Some(Level::Info), Some("app::radio"), "sent 5 bytes")
+    );
+
+    let record = decoder().with_elf(&elf).decode(&deferred, UNIX_EPOCH);
+    assert_eq!(
+        (record.level, record.module.as_deref(), record.message),
+        (Some(Level::Warn), Some("hal"), expected)
+    );
+
+    // Without the ELF there is only the address
+    let record = decoder().decode(&deferred, UNIX_EPOCH);
+    assert_eq!(
+        record.message,
+        format!("<message at {:#010x} not in the ELF>", rodata)
+    );
+}
+
+#[test]
+fn spans() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::parser::{SpanFrame, SPAN_FRAME};
+    use crate::record::SpanKind;
+    use crate::symbols::Symbols;
+    use crate::time::Clock;
+    use elf_test::TypePrinters;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+    use xmas_elf::ElfFile;
+
+    let elf = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../elf_test/tests/fixtures/types-rustc-1.95.0.elf"
+    ))
+    .unwrap();
+    let file = ElfFile::new(&elf).unwrap();
+    let section = file.find_section_by_name(".rodata").unwrap();
+    let rodata = section.address() as u32;
+    let name = String::from_utf8_lossy(&section.raw_data(&file)[..4]).into_owned();
+
+    // As `Span` writes them, enter or exit then where the name is
+    let packet = |enter: u8, timestamp, task| {
+        let mut buffer = vec![enter];
+        buffer.extend_from_slice(&rodata.to_le_bytes());
+        buffer.extend_from_slice(&4u32.to_le_bytes());
+        Packet {
+            string_loc: SPAN_FRAME,
+            type_loc: 0,
+            timestamp: Some(timestamp),
+            task: Some(task),
+            buffer,
+        }
+    };
+    assert_eq!(
+        packet(1, 0, 0).span(),
+        Some(SpanFrame {
+            enter: true,
+            address: rodata,
+            len: 4
+        })
+    );
+    let mut other = packet(2, 0, 0);
+    assert_eq!(other.span(), None);
+    other.buffer = vec![0];
+    assert_eq!(other.span(), None);
+
+    let strings = Symbols::new();
+    let mut decoder = Decoder::new(
+        Catalog::new(&strings),
+        Symbols::new(),
+        TypePrinters(HashMap::new()),
+    )
+    .with_clock(Clock::new(Some(1000)), false)
+    .with_elf(&elf);
+
+    // Nested in task 0, and the same span preempting it in task 1
+    let spans: Vec<_> = [
+        packet(1, 10, 0),
+        packet(1, 12, 0),
+        packet(1, 13, 1),
+        packet(0, 14, 1),
+        packet(0, 15, 0),
+        packet(0, 30, 0),
+        packet(0, 31, 0),
+    ]
+    .iter()
+    .map(|packet| decoder.decode(packet, UNIX_EPOCH))
+    .collect();
+
+    let exits: Vec<_> = spans
+        .iter()
+        .map(|record| record.span.as_ref().unwrap())
+        .filter(|span| span.kind == SpanKind::Exit)
+        .map(|span| {
+            span.duration
+                .map(|duration| (duration * 1000.).round() as u32)
+        })
+        .collect();
+    assert_eq!(exits, [Some(1), Some(3), Some(20), None]);
+
+    let span = spans[4].span.as_ref().unwrap();
+    assert_eq!(
+        (span.name.as_str(), span.kind),
+        (name.as_str(), SpanKind::Exit)
+    );
+    assert_eq!(spans[0].message, format!("enter {}", name));
+    assert_eq!(spans[5].message, format!("exit {} after 20ms", name));
+    assert_eq!(spans[6].message, format!("exit {}", name));
+}
+
+#[test]
+fn raw_channels() {
+    use crate::catalog::Catalog;
+    use crate::channel::ChannelArg;
+    use crate::decoder::Decoder;
+    use crate::parser::{RawFrame, RAW_FRAME};
+    use crate::symbols::Symbols;
+    use elf_test::TypePrinters;
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    // As `write_raw` writes them, the channel then the data
+    let packet = |buffer: &[u8]| Packet {
+        string_loc: RAW_FRAME,
+        type_loc: 0,
+        timestamp: None,
+        task: None,
+        buffer: buffer.to_vec(),
+    };
+    assert_eq!(
+        packet(&[3, 0xde, 0xad]).raw(),
+        Some(RawFrame {
+            channel: 3,
+            data: vec![0xde, 0xad]
+        })
+    );
+    assert_eq!(packet(&[]).raw(), None);
+
+    let strings = Symbols::new();
+    let mut decoder = Decoder::new(
+        Catalog::new(&strings),
+        Symbols::new(),
+        TypePrinters(HashMap::new()),
+    )
+    .with_channel(
+        1,
+        Box::new(|data: &[u8]| -> anyhow::Result<String> {
+            let samples: Vec<_> = data
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect();
+            Ok(format!("{:?}", samples))
+        }),
+    );
+
+    let record = decoder.decode(&packet(&[1, 0xff, 0xff, 0x02, 0x00]), UNIX_EPOCH);
+    assert_eq!(record.message, "[-1, 2]");
+    assert_eq!(record.channel, Some(1));
+    assert_eq!(record.payload, [0xff, 0xff, 0x02, 0x00]);
+
+    // Channels without a decoder are shown as hex
+    let record = decoder.decode(&packet(&[2, 0x0a, 0xff]), UNIX_EPOCH);
+    assert_eq!(record.message, "0a ff");
+    assert_eq!(record.channel, Some(2));
+
+    assert_eq!(
+        "7=exec:./decode.py".parse::<ChannelArg>().unwrap(),
+        ChannelArg {
+            channel: 7,
+            command: Some("./decode.py".into())
+        }
+    );
+    assert_eq!("0=hex".parse::<ChannelArg>().unwrap().command, None);
+    assert!("256=hex".parse::<ChannelArg>().is_err());
+    assert!("1=exec:".parse::<ChannelArg>().is_err());
+    assert!("1".parse::<ChannelArg>().is_err());
+}
+
+#[test]
+fn high_water() {
+    use crate::stats::Stats;
+    use std::time::Duration;
+
+    let mut stats = Stats::new();
+    stats.high_water(300, 1024);
+    stats.high_water(800, 1024);
+    // After a reconnect to a target that was reset
+    stats.high_water(100, 1024);
+
+    let mut out = Vec::new();
+    stats.write_summary(&mut out, Duration::ZERO, 0).unwrap();
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .contains("\n  peak:     800 of 1024 bytes (78%)\n"));
+}
+
+#[test]
+fn resources() {
+    use crate::parser::{HeapUsage, Resources, RESOURCES_FRAME};
+    use crate::stats::Stats;
+
+    // As `report_resources` writes them, the stack then the heap if there is one
+    let packet = |words: &[u32]| Packet {
+        string_loc: RESOURCES_FRAME,
+        type_loc: 0,
+        timestamp: None,
+        task: None,
+        buffer: words.iter().flat_map(|word| word.to_le_bytes()).collect(),
+    };
+    assert_eq!(
+        packet(&[512, 4096]).resources(),
+        Some(Resources {
+            stack_used: 512,
+            stack_size: 4096,
+            heap: None,
+        })
+    );
+    assert_eq!(packet(&[512, 4096, 7]).resources(), None);
+
+    let mut stats = Stats::new();
+    let mut out = Vec::new();
+    stats.write_resources(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Resources\n  none reported, see `report_resources` on the target\n"
+    );
+
+    stats.resources(packet(&[1024, 4096, 3072, 5120]).resources().unwrap());
+    stats.resources(Resources {
+        stack_used: 1024,
+        stack_size: 4096,
+        heap: Some(HeapUsage {
+            used: 2048,
+            free: 6144,
+        }),
+    });
+    let mut out = Vec::new();
+    stats.write_resources(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "Resources
+  stack:    1024 of 4096 bytes (25%)
+  heap:     2048 of 8192 bytes (25%), peak 3072 bytes
+"
+    );
+}
+
+#[test]
+fn formatted_on_target() {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::parser::FORMATTED_TYPE;
+    use crate::symbols::Symbols;
+    use elf_test::{LogSite, TypePrinters};
+    use std::collections::HashMap;
+    use std::time::UNIX_EPOCH;
+
+    // The format string only finds the call site, the text is in the frame
+    let strings: Symbols = vec![(0x10, "request: {:?}")].into_iter().collect();
+    let catalog = Catalog::new(&strings).with_sites(&[LogSite {
+        address: 0x10,
+        file: Some("src/main.rs".into()),
+        line: Some(7),
+        module: Some("app::net".into()),
+        type_name: None,
+        type_address: None,
+        level: None,
+        kv: false,
+        formatted: true,
+    }]);
+    // Nothing to decode on the host
+    assert!(catalog.coverage(&TypePrinters(HashMap::new())).is_empty());
+    let mut decoder = Decoder::new(catalog, Symbols::new(), TypePrinters(HashMap::new()));
+
+    let packet = Packet {
+        string_loc: 0x10,
+        type_loc: FORMATTED_TYPE,
+        timestamp: None,
+        task: None,
+        buffer: b"request: Req { id: 3 }".to_vec(),
+    };
+    let record = decoder.decode(&packet, UNIX_EPOCH);
+    assert_eq!(record.message, "request: Req { id: 3 }");
+    assert_eq!(record.id, Some(0));
+    assert_eq!(record.module.as_deref(), Some("app::net"));
+    assert_eq!(record.type_name, None);
+}
+
+#[test]
+fn chip_names() {
+    let names: Vec<String> = vec![
+        "nRF52832_xxAA",
+        "nRF52840_xxAA",
+        "STM32L412CBUx",
+        "STM32L432KCUx",
+    ]
+    .into_iter()
+    .map(Into::into)
+    .collect();
+
+    // probe-rs takes the start of a name, in any case
+    assert!(probe::check_chip("nrf52840", &names).is_ok());
+    assert!(probe::check_chip("stm32l412cbux", &names).is_ok());
+    assert!(probe::check_chip("Auto", &names).is_ok());
+
+    assert_eq!(
+        probe::close_matches("nrf52804", &names),
+        ["nRF52832_xxAA", "nRF52840_xxAA"]
+    );
+    assert_eq!(
+        probe::close_matches("STM32L421", &names),
+        ["STM32L412CBUx", "STM32L432KCUx"]
+    );
+    assert!(probe::close_matches("esp32", &names).is_empty());
+
+    let error = probe::check_chip("nrf52804", &names).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Unknown chip \"nrf52804\", did you mean nRF52832_xxAA, nRF52840_xxAA?"
+    );
+    assert_eq!(
+        probe::check_chip("esp32", &names).unwrap_err().to_string(),
+        "Unknown chip \"esp32\", probe-rs has targets for 4 chips"
+    );
+}
+
+#[test]
+fn probe_selection() {
+    use probe_rs::{DebugProbeInfo, DebugProbeSelector, DebugProbeType};
+
+    let jlink = |product_id, serial: &str| DebugProbeInfo {
+        identifier: "J-Link".into(),
+        vendor_id: 0x1366,
+        product_id,
+        serial_number: Some(serial.into()),
+        probe_type: DebugProbeType::JLink,
+    };
+    let probes = [
+        jlink(0x1015, "000683"),
+        jlink(0x1015, "000771"),
+        jlink(0x0105, "000912"),
+    ];
+    let selector = |s: &str| s.parse::<DebugProbeSelector>().unwrap();
+    let serial = |selection| match selection {
+        probe::Selection::One(info) => info.serial_number.as_deref().unwrap(),
+        probe::Selection::Ambiguous(_) => panic!("Ambiguous selection"),
+    };
+
+    assert_eq!(
+        serial(probe::select(&probes, Some(&selector("1366:1015:000771")), None).unwrap()),
+        "000771"
+    );
+    assert_eq!(
+        serial(probe::select(&probes, Some(&selector("1366:0105")), None).unwrap()),
+        "000912"
+    );
+    assert_eq!(
+        serial(probe::select(&probes, None, Some(2)).unwrap()),
+        "000912"
+    );
+    // The index counts the probes matching the selector
+    assert_eq!(
+        serial(probe::select(&probes, Some(&selector("1366:1015")), Some(1)).unwrap()),
+        "000771"
+    );
+
+    match probe::select(&probes, Some(&selector("1366:1015")), None).unwrap() {
+        probe::Selection::Ambiguous(matching) => assert_eq!(matching.len(), 2),
+        selection => panic!("Expected an ambiguous selection, got {:?}", selection),
+    }
+
+    assert!(probe::select(&probes, None, Some(3)).is_err());
+    assert!(probe::select(&[], None, None).is_err());
+    let error = probe::select(&probes, Some(&selector("0483:374b")), None).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "No probe matches 0483:374b, found\n  [0] 1366:1015:000683 J-Link\n  \
+         [1] 1366:1015:000771 J-Link\n  [2] 1366:0105:000912 J-Link"
+    );
+}
+
+#[test]
+fn probe_speeds() {
+    assert_eq!(
+        probe::speeds(24_000).collect::<Vec<_>>(),
+        [24_000, 12_000, 4_000, 1_000, 100]
+    );
+    assert_eq!(
+        probe::speeds(4_000).collect::<Vec<_>>(),
+        [4_000, 1_000, 100]
+    );
+    assert_eq!(probe::speeds(50).collect::<Vec<_>>(), [50]);
+}
+
+#[test]
+fn chip_unique_ids() {
+    assert_eq!(probe::unique_id("nRF52840_xxAA"), Some((0x1000_0060, 8)));
+    assert_eq!(probe::unique_id("STM32L412CBUx"), Some((0x1fff_7590, 12)));
+    assert_eq!(probe::unique_id("STM32F411CEUx"), Some((0x1fff_7a10, 12)));
+    assert_eq!(probe::unique_id("LPC55S69JBD100"), None);
+}
+
+#[test]
+fn record_replay() {
+    use crate::pipeline::Chunk;
+    use crate::replay::{Recorder, Replay, MAGIC};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let read = UNIX_EPOCH + Duration::from_micros(1_600_000_000_123_456);
+    let secure = Chunk::Secure(Box::new(Chunk::Data(vec![9], read)));
+    let priority = Chunk::Priority(Box::new(Chunk::Resync));
+    let core = Chunk::Core(1, Box::new(Chunk::Data(vec![7, 8], read)));
+    let mut out = Vec::new();
+    let mut recorder = Recorder::new(&mut out).unwrap();
+    recorder.write(&Chunk::Data(vec![1, 2, 3], read)).unwrap();
+    recorder.write(&secure).unwrap();
+    recorder.write(&Chunk::Dropped(2)).unwrap();
+    recorder.write(&Chunk::Resync).unwrap();
+    recorder.write(&priority).unwrap();
+    recorder.write(&Chunk::Data(vec![], read)).unwrap();
+    recorder.write(&core).unwrap();
+    recorder.write(&Chunk::Data(vec![4], read)).unwrap();
+    recorder.flush().unwrap();
+    assert!(out.starts_with(MAGIC));
+
+    // Each chunk goes back to its ring, the parsers start over instead of skipping data
+    let mut replay = Replay::new(&out[..]).unwrap();
+    let chunks: Vec<_> = std::iter::from_fn(|| replay.next_chunk().unwrap()).collect();
+    assert_eq!(
+        chunks,
+        [
+            Chunk::Data(vec![1, 2, 3], read),
+            secure,
+            Chunk::Reset,
+            Chunk::Priority(Box::new(Chunk::Reset)),
+            Chunk::Data(vec![], read),
+            core,
+            Chunk::Data(vec![4], read),
+        ]
+    );
+
+    // A recording cut off in an entry ends with the last whole one
+    let mut replay = Replay::new(&out[..out.len() - 1]).unwrap();
+    for _ in 0..6 {
+        assert!(replay.next_chunk().unwrap().is_some());
+    }
+    assert_eq!(replay.next_chunk().unwrap(), None);
+
+    assert!(Replay::new(&b"LOG0"[..]).is_err());
+    assert!(Replay::new(&b"not a recording"[..]).is_err());
+}
+
+/// The `log` fixtures of `elf_test`, one for each compiler version
+fn log_fixtures() -> Vec<std::path::PathBuf> {
+    let dir = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../elf_test/tests/fixtures/log"
+    );
+    let mut fixtures: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "elf"))
+        .collect();
+    fixtures.sort();
+
+    assert!(!fixtures.is_empty(), "no fixtures found");
+
+    fixtures
+}
+
+/// Decode `packets` with the format strings and types of `elf`, the way the host does for a
+/// target, to the lines of `--output json`
+fn decode_fixture(elf: &[u8], packets: &[Packet]) -> String {
+    use crate::catalog::Catalog;
+    use crate::decoder::Decoder;
+    use crate::fmt;
+    use crate::sink::{Json, Sink};
+    use elf_test::{generate_printers, log_sites};
+    use std::time::UNIX_EPOCH;
+
+    let file = xmas_elf::ElfFile::new(elf).unwrap();
+    let res = fmt::extract_format_and_type_strings(&file).unwrap();
+    let catalog = Catalog::new(&res.map_strings).with_sites(&log_sites(elf).unwrap());
+    let mut decoder = Decoder::new(catalog, res.map_types, generate_printers(elf).unwrap())
+        .with_addresses(res.addresses);
+
+    let mut out = Vec::new();
+    let mut json = Json::new(&mut out);
+    for packet in packets {
+        json.write(&decoder.decode(packet, UNIX_EPOCH)).unwrap();
+    }
+
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn golden_frames() {
+    let expected = include_str!("../../elf_test/tests/fixtures/log/log.jsonl");
+
+    for fixture in log_fixtures() {
+        let elf = std::fs::read(&fixture).unwrap();
+        let frames = std::fs::read(fixture.with_extension("frames")).unwrap();
+
+        let mut parser = Parser::new();
+        parser.push(&frames);
+        let packets: Vec<_> = std::iter::from_fn(|| parser.try_parse()).collect();
+
+        assert_eq!(
+            decode_fixture(&elf, &packets),
+            expected,
+            "{}",
+            fixture.display()
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Converts target timestamps to time since boot
+///
+/// The target sends a 32-bit tick counter, which is extended to 64 bits here by counting the
+/// wraps, assuming there is at least one frame per wrap.
+#[derive(Debug)]
+pub struct Clock {
+    hz: Option<u32>,
+    last: Option<u32>,
+    wraps: u64,
+}
+
+impl Clock {
+    /// Create a clock for ticks at `hz`, timestamps are printed as raw ticks if it is unknown
+    pub fn new(hz: Option<u32>) -> Self {
+        Clock {
+            hz,
+            last: None,
+            wraps: 0,
+        }
+    }
+
+    pub fn hz(&self) -> Option<u32> {
+        self.hz
+    }
+
+    /// Ticks since boot for a timestamp from the target
+    pub fn extend(&mut self, ticks: u32) -> u64 {
+        if let Some(last) = self.last {
+            if ticks < last {
+                self.wraps += 1;
+            }
+        }
+        self.last = Some(ticks);
+
+        (self.wraps << 32) | u64::from(ticks)
+    }
+
+    /// Seconds since boot, if the frequency is known
+    pub fn seconds(&self, ticks: u64) -> Option<f64> {
+        self.hz.map(|hz| ticks as f64 / f64::from(hz))
+    }
+
+    /// Seconds with microsecond resolution, or the raw ticks if the frequency is unknown
+    pub fn format(&self, ticks: u64) -> String {
+        match self.hz {
+            Some(hz) => {
+                let hz = u64::from(hz);
+                let micros = (ticks % hz) * 1_000_000 / hz;
+                format!("{}.{:06}", ticks / hz, micros)
+            }
+            None => ticks.to_string(),
+        }
+    }
+}
+
+/// Length of the windows, in target seconds, used to estimate the drift between the clocks
+const DRIFT_WINDOW: f64 = 10.0;
+
+/// Maps target time to wall-clock time, using the host arrival time of the frames
+///
+/// Frames arrive some time after they were written, so in each window the frame with the least
+/// latency (the smallest host - target difference) is used as a reference point. The first one
+/// anchors the mapping, and the rate between the clocks is the slope from the anchor to the
+/// reference point of the latest window.
+#[derive(Debug)]
+pub struct WallClock {
+    /// Target seconds of the first frame
+    first: Option<f64>,
+    /// (target seconds, host seconds since the UNIX epoch)
+    anchor: Option<(f64, f64)>,
+    window: Option<(f64, f64)>,
+    window_start: Option<f64>,
+    /// Host seconds per target second
+    rate: f64,
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        WallClock {
+            first: None,
+            anchor: None,
+            window: None,
+            window_start: None,
+            rate: 1.0,
+        }
+    }
+}
+
+impl WallClock {
+    pub fn new() -> Self {
+        WallClock::default()
+    }
+
+    /// Host seconds per target second, as currently estimated
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Record a frame written at `target` seconds since boot that arrived at `arrival`, and
+    /// return the wall-clock time it was written
+    pub fn map(&mut self, target: f64, arrival: SystemTime) -> SystemTime {
+        let host = arrival
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let best = |point: Option<(f64, f64)>| match point {
+            Some((t, h)) if h - t <= host - target => point,
+            _ => Some((target, host)),
+        };
+
+        let first = *self.first.get_or_insert(target);
+
+        match self.anchor {
+            _ if target - first < DRIFT_WINDOW => self.anchor = best(self.anchor),
+            None => unreachable!(),
+            Some((t0, h0)) => {
+                let start = *self.window_start.get_or_insert(target);
+                self.window = best(self.window);
+
+                if target - start >= DRIFT_WINDOW {
+                    let (t, h) = self.window.take().unwrap();
+                    self.rate = (h - h0) / (t - t0);
+                    self.window_start = None;
+                }
+            }
+        }
+
+        // A frame can not have been written after it arrived
+        let (t0, h0) = self.anchor.unwrap();
+        let written = (h0 + (target - t0) * self.rate).min(host);
+        UNIX_EPOCH + Duration::from_secs_f64(written.max(0.0))
+    }
+}
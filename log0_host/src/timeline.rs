@@ -0,0 +1,83 @@
+use crate::record::Record;
+use std::time::{Duration, Instant};
+
+/// How long a record is held back waiting for earlier ones from the other stream
+pub const HOLD: Duration = Duration::from_millis(200);
+
+/// Where a record came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The ring buffer
+    Log,
+    /// The SWO capture
+    Itm,
+}
+
+/// Merges the records from the ring buffer and the ITM into one stream ordered by target time
+///
+/// The streams arrive separately and each one is in order, so a record can be passed on once
+/// both streams have reached its time, or once it has been held for `HOLD` in case one of them
+/// went quiet. Records without a time in seconds are passed on right away.
+#[derive(Debug, Default)]
+pub struct Timeline {
+    /// Ordered by time
+    queue: Vec<(f64, Instant, Record)>,
+    /// Time of the latest record from each stream
+    log: Option<f64>,
+    itm: Option<f64>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a record that arrived at `now`, and return the records that are ready in order
+    pub fn push(&mut self, source: Source, record: Record, now: Instant) -> Vec<Record> {
+        let seconds = match record.seconds {
+            Some(seconds) => seconds,
+            None => return vec![record],
+        };
+
+        let latest = match source {
+            Source::Log => &mut self.log,
+            Source::Itm => &mut self.itm,
+        };
+        *latest = Some(latest.map_or(seconds, |latest| latest.max(seconds)));
+
+        // After the records with the same time, to keep the order they arrived in
+        let pos = self
+            .queue
+            .partition_point(|(queued, _, _)| *queued <= seconds);
+        self.queue.insert(pos, (seconds, now, record));
+
+        self.release(now)
+    }
+
+    /// Return the records that are ready at `now`, in order
+    pub fn release(&mut self, now: Instant) -> Vec<Record> {
+        let reached = match (self.log, self.itm) {
+            (Some(log), Some(itm)) => Some(log.min(itm)),
+            _ => None,
+        };
+
+        // Everything up to the last ready record, so the ones before it are not overtaken
+        let ready = self.queue.iter().rposition(|(seconds, queued, _)| {
+            matches!(reached, Some(reached) if *seconds <= reached)
+                || now.duration_since(*queued) >= HOLD
+        });
+        match ready {
+            Some(last) => self
+                .queue
+                .drain(..=last)
+                .map(|(_, _, record)| record)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Return all records, at the end of the session
+    pub fn drain(&mut self) -> Vec<Record> {
+        self.queue.drain(..).map(|(_, _, record)| record).collect()
+    }
+}
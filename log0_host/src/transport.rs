@@ -0,0 +1,260 @@
+use crate::{command::CommandMemory, fetch::ReadMemory};
+use anyhow::{anyhow, Error, Result};
+use probe_rs::{Core, MemoryInterface};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Debug Authentication Status Register, tells whether the debugger may access secure memory
+const DAUTHSTATUS: u32 = 0xe000_efb8;
+
+/// Addresses below this, the code, SRAM and peripheral regions, have a secure and a non-secure
+/// alias on TrustZone parts
+const ALIASED: u64 = 0x6000_0000;
+
+/// Set in the secure alias of an address, e.g. `0x3000_0000` for SRAM at `0x2000_0000`
+const SECURE_ALIAS: u64 = 1 << 28;
+
+/// Which alias memory is accessed through on Armv8-M targets with TrustZone
+///
+/// The addresses in the ELF, and the pointers the target logs, are in the alias the firmware
+/// runs in, which need not be one the debugger may access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    /// `Flat`, or `NonSecure` if the target does not allow secure debug, see `resolve`
+    Auto,
+    /// Use the addresses as they are, for targets without TrustZone
+    Flat,
+    Secure,
+    NonSecure,
+}
+
+impl Security {
+    /// The address to access for `address`
+    pub fn alias(self, address: u64) -> u64 {
+        if address >= ALIASED {
+            return address;
+        }
+
+        match self {
+            Security::Auto | Security::Flat => address,
+            Security::Secure => address | SECURE_ALIAS,
+            Security::NonSecure => address & !SECURE_ALIAS,
+        }
+    }
+
+    /// Pick the alias for `Auto` from what the target allows, the others are kept
+    pub fn resolve(self, core: &mut Core) -> Security {
+        if self != Security::Auto {
+            return self;
+        }
+
+        match core.read_word_32(DAUTHSTATUS) {
+            Ok(status) => Security::from_dauthstatus(status),
+            // Not an Armv8-M part
+            Err(_) => Security::Flat,
+        }
+    }
+
+    /// `NonSecure` if secure invasive debug is implemented but not allowed, otherwise `Flat`
+    pub fn from_dauthstatus(status: u32) -> Security {
+        match (status >> 4) & 0b11 {
+            0b10 => Security::NonSecure,
+            _ => Security::Flat,
+        }
+    }
+}
+
+impl FromStr for Security {
+    type Err = Error;
+
+    /// `auto`, `flat`, `secure` or `non-secure`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Security::Auto),
+            "flat" => Ok(Security::Flat),
+            "secure" => Ok(Security::Secure),
+            "non-secure" => Ok(Security::NonSecure),
+            _ => Err(anyhow!(
+                "Unknown security {:?}, expected auto, flat, secure or non-secure",
+                s
+            )),
+        }
+    }
+}
+
+/// probe-rs only takes 32-bit addresses
+fn narrow(address: u64) -> Result<u32> {
+    u32::try_from(address)
+        .map_err(|_| anyhow!("Address {:#x} is out of the probe's 32-bit range", address))
+}
+
+/// Access to the `LOG0_CURSORS` and `LOG0_BUFFER` statics of a target
+pub trait Transport {
+    /// Read the `[target, host]` cursor pair
+    fn read_cursors(&mut self) -> Result<[u32; 2]>;
+
+    /// Read from the ring buffer, `offset` is relative to the start of the buffer
+    fn read_buffer(&mut self, offset: u32, data: &mut [u8]) -> Result<()>;
+
+    /// Update the host cursor, releasing the space up to `idx` for the target
+    fn write_host_cursor(&mut self, idx: u32) -> Result<()>;
+
+    /// Read the count of frames the target lost because the buffer was full, after the
+    /// cursors and the buffer pointer
+    fn read_dropped(&mut self) -> Result<u32>;
+}
+
+/// Transport over a probe-rs debug probe connection
+pub struct ProbeTransport<'a> {
+    core: Core<'a>,
+    cursor_address: u64,
+    buffer_address: u64,
+    security: Security,
+}
+
+impl<'a> ProbeTransport<'a> {
+    /// Access the cursors and buffer at `cursor_address` and `buffer_address`, and any other
+    /// address, through the alias picked by `security`
+    pub fn new(
+        mut core: Core<'a>,
+        cursor_address: u64,
+        buffer_address: u64,
+        security: Security,
+    ) -> Self {
+        let security = security.resolve(&mut core);
+        ProbeTransport {
+            core,
+            cursor_address,
+            buffer_address,
+            security,
+        }
+    }
+
+    /// The alias addresses are accessed through, never `Security::Auto`
+    pub fn security(&self) -> Security {
+        self.security
+    }
+
+    /// Another ring buffer over the same connection, e.g. the one of the secure image
+    pub fn ring(&mut self, cursor_address: u64, buffer_address: u64) -> Ring<'_, 'a> {
+        Ring {
+            transport: self,
+            cursor_address,
+            buffer_address,
+        }
+    }
+
+    fn address(&self, address: u64) -> Result<u32> {
+        narrow(self.security.alias(address))
+    }
+
+    /// Whether the target marked the ring as panicked, in the byte after the count of dropped
+    /// frames, only targets with `log0_panic` have the mark
+    pub fn read_panicked(&mut self) -> Result<bool> {
+        let address = self.address(self.cursor_address + 16)?;
+        Ok(self.core.read_word_32(address)? & 0xff != 0)
+    }
+
+    /// The most bytes that were in the ring at once, at `offset` from the target cursor, only
+    /// targets with the `high-water` feature track it
+    pub fn read_high_water(&mut self, offset: u64) -> Result<u32> {
+        let address = self.address(self.cursor_address + offset)?;
+        Ok(self.core.read_word_32(address)?)
+    }
+
+    /// Access the underlying core, e.g. to halt or resume it
+    pub fn core(&mut self) -> &mut Core<'a> {
+        &mut self.core
+    }
+}
+
+impl<'a> Transport for ProbeTransport<'a> {
+    fn read_cursors(&mut self) -> Result<[u32; 2]> {
+        let mut buff = [0u32; 2];
+        let address = self.address(self.cursor_address)?;
+        self.core.read_32(address, &mut buff)?;
+
+        Ok(buff)
+    }
+
+    fn read_buffer(&mut self, offset: u32, data: &mut [u8]) -> Result<()> {
+        let address = self.address(self.buffer_address + u64::from(offset))?;
+        MemoryInterface::read_8(&mut self.core, address, data)?;
+
+        Ok(())
+    }
+
+    fn write_host_cursor(&mut self, idx: u32) -> Result<()> {
+        let address = self.address(self.cursor_address + 4)?;
+        self.core.write_word_32(address, idx)?;
+
+        Ok(())
+    }
+
+    fn read_dropped(&mut self) -> Result<u32> {
+        let address = self.address(self.cursor_address + 12)?;
+        Ok(self.core.read_word_32(address)?)
+    }
+}
+
+/// A ring buffer other than the one a `ProbeTransport` was made for, see `ProbeTransport::ring`
+pub struct Ring<'t, 'a> {
+    transport: &'t mut ProbeTransport<'a>,
+    cursor_address: u64,
+    buffer_address: u64,
+}
+
+impl<'t, 'a> Transport for Ring<'t, 'a> {
+    fn read_cursors(&mut self) -> Result<[u32; 2]> {
+        let mut buff = [0u32; 2];
+        CommandMemory::read_32(self.transport, self.cursor_address, &mut buff)?;
+
+        Ok(buff)
+    }
+
+    fn read_buffer(&mut self, offset: u32, data: &mut [u8]) -> Result<()> {
+        let address = self.buffer_address + u64::from(offset);
+        ReadMemory::read_8(self.transport, address, data)
+    }
+
+    fn write_host_cursor(&mut self, idx: u32) -> Result<()> {
+        CommandMemory::write_word_32(self.transport, self.cursor_address + 4, idx)
+    }
+
+    fn read_dropped(&mut self) -> Result<u32> {
+        let mut buff = [0u32; 1];
+        CommandMemory::read_32(self.transport, self.cursor_address + 12, &mut buff)?;
+
+        Ok(buff[0])
+    }
+}
+
+impl<'a> CommandMemory for ProbeTransport<'a> {
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<()> {
+        let address = self.address(address)?;
+        Ok(self.core.read_32(address, data)?)
+    }
+
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<()> {
+        let address = self.address(address)?;
+        Ok(self.core.write_8(address, data)?)
+    }
+
+    fn write_word_32(&mut self, address: u64, value: u32) -> Result<()> {
+        let address = self.address(address)?;
+        Ok(self.core.write_word_32(address, value)?)
+    }
+}
+
+impl<'a> ReadMemory for ProbeTransport<'a> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<()> {
+        let address = self.address(address)?;
+        Ok(MemoryInterface::read_8(&mut self.core, address, data)?)
+    }
+}
+
+impl<'a> ReadMemory for Core<'a> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<()> {
+        Ok(MemoryInterface::read_8(self, narrow(address)?, data)?)
+    }
+}
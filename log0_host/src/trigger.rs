@@ -0,0 +1,132 @@
+use crate::record::Record;
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::process::Command;
+use std::thread;
+
+/// Something to do when a message matches, an entry of `[[triggers]]` in the config
+///
+/// ```toml
+/// # Run a shell command, with the message in `$FASTHOSTING_MESSAGE`
+/// [[triggers]]
+/// on = "ERROR.*radio"
+/// run = "./capture_regs.sh"
+///
+/// # Or one of the built-in actions, see `Action`
+/// [[triggers]]
+/// on = "FAULT"
+/// action = "halt-and-dump"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerConfig {
+    /// Regex matched against the decoded message
+    pub on: String,
+    pub run: Option<String>,
+    pub action: Option<Action>,
+}
+
+/// What a trigger does
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Halt the core and print its registers and a backtrace, it stays halted
+    HaltAndDump,
+    /// Print a backtrace, the core keeps running
+    Backtrace,
+    Reset,
+    /// Run a shell command, see `run`
+    #[serde(skip)]
+    Run(String),
+}
+
+impl Action {
+    /// Done by the probe thread, as opposed to on the decode thread
+    pub fn needs_target(&self) -> bool {
+        !matches!(self, Action::Run(_))
+    }
+}
+
+#[derive(Debug)]
+struct Trigger {
+    pattern: Regex,
+    action: Action,
+}
+
+/// The triggers of the config, checked against every message
+#[derive(Debug, Default)]
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+}
+
+impl Triggers {
+    pub fn new(configs: &[TriggerConfig]) -> Result<Self> {
+        let triggers = configs
+            .iter()
+            .map(|config| {
+                let pattern = Regex::new(&config.on)
+                    .with_context(|| format!("Invalid trigger pattern {:?}", config.on))?;
+                let action = match (&config.run, &config.action) {
+                    (Some(command), None) => Action::Run(command.clone()),
+                    (None, Some(action)) => action.clone(),
+                    _ => {
+                        return Err(anyhow!(
+                            "Trigger {:?} needs one of `run` or `action`",
+                            config.on
+                        ))
+                    }
+                };
+
+                Ok(Trigger { pattern, action })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Triggers { triggers })
+    }
+
+    pub fn needs_target(&self) -> bool {
+        self.triggers.iter().any(|t| t.action.needs_target())
+    }
+
+    /// Actions of the triggers that match `message`, in the order of the config
+    pub fn check(&self, message: &str) -> Vec<&Action> {
+        self.triggers
+            .iter()
+            .filter(|t| t.pattern.is_match(message))
+            .map(|t| &t.action)
+            .collect()
+    }
+}
+
+/// Start `command` with `sh -c` without waiting for it, with the message, module and timestamp
+/// of `record` in `FASTHOSTING_MESSAGE`, `FASTHOSTING_MODULE` and `FASTHOSTING_TIMESTAMP`
+///
+/// The exit status is reported if the command fails.
+pub fn run(command: &str, record: &Record) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("FASTHOSTING_MESSAGE", &record.message)
+        .env(
+            "FASTHOSTING_MODULE",
+            record.module.as_deref().unwrap_or_default(),
+        )
+        .env(
+            "FASTHOSTING_TIMESTAMP",
+            record.timestamp.as_deref().unwrap_or_default(),
+        )
+        .spawn()
+        .with_context(|| format!("Failed to start trigger {:?}", command))?;
+
+    let command = command.to_string();
+    thread::spawn(move || match child.wait() {
+        Ok(status) if !status.success() => {
+            eprintln!("Trigger {:?} exited with {}", command, status)
+        }
+        Ok(_) => (),
+        Err(e) => eprintln!("Trigger {:?} failed: {}", command, e),
+    });
+
+    Ok(())
+}
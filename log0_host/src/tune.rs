@@ -0,0 +1,82 @@
+use crate::transport::Transport;
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+/// Reads timed per transfer size, the fastest one counts
+const ROUNDS: usize = 4;
+
+/// Smallest chunk picked, below this the cursor traffic is never worth it
+const MIN_CHUNK: usize = 64;
+
+/// Time per transfer for increasing sizes, read from the start of the ring buffer
+///
+/// Only reads, so it is safe to run while the target is logging.
+pub fn measure<T: Transport>(
+    transport: &mut T,
+    buffer_size: usize,
+) -> Result<Vec<(usize, Duration)>> {
+    let mut buf = vec![0; buffer_size];
+    let mut samples = Vec::new();
+
+    let mut size = 4;
+    loop {
+        let size_now = size.min(buffer_size);
+        let mut fastest = Duration::from_secs(u64::MAX);
+        for _ in 0..ROUNDS {
+            let start = Instant::now();
+            transport.read_buffer(0, &mut buf[..size_now])?;
+            fastest = fastest.min(start.elapsed());
+        }
+        samples.push((size_now, fastest));
+
+        if size_now == buffer_size {
+            break;
+        }
+        size *= 4;
+    }
+
+    Ok(samples)
+}
+
+/// Fixed cost per transfer and cost per byte, fitted to `samples` by least squares
+pub fn fit(samples: &[(usize, Duration)]) -> (Duration, Duration) {
+    let n = samples.len() as f64;
+    let (sx, sy) = samples.iter().fold((0.0, 0.0), |(sx, sy), (size, time)| {
+        (sx + *size as f64, sy + time.as_secs_f64())
+    });
+    let (mx, my) = (sx / n, sy / n);
+    let (sxx, sxy) = samples.iter().fold((0.0, 0.0), |(sxx, sxy), (size, time)| {
+        let dx = *size as f64 - mx;
+        (sxx + dx * dx, sxy + dx * (time.as_secs_f64() - my))
+    });
+
+    let per_byte = if sxx > 0.0 { (sxy / sxx).max(0.0) } else { 0.0 };
+    let overhead = (my - per_byte * mx).max(0.0);
+
+    (
+        Duration::from_secs_f64(overhead),
+        Duration::from_secs_f64(per_byte),
+    )
+}
+
+/// Chunk size that keeps sustained throughput within 10% of what the probe can do
+///
+/// Each chunk costs three transfers worth of overhead, reading the cursors, the data and writing
+/// the host cursor, so the smallest chunk where the data itself takes 9 times as long is picked.
+/// Smaller chunks free space for the target sooner, the whole buffer is used if the probe has no
+/// measurable per-byte cost.
+pub fn pick_chunk(overhead: Duration, per_byte: Duration, buffer_size: usize) -> usize {
+    let per_byte = per_byte.as_secs_f64();
+    if per_byte <= 0.0 {
+        return buffer_size;
+    }
+
+    let wanted = 27.0 * overhead.as_secs_f64() / per_byte;
+    let chunk = if wanted >= buffer_size as f64 {
+        buffer_size
+    } else {
+        (wanted.ceil() as usize).next_power_of_two()
+    };
+
+    chunk.clamp(MIN_CHUNK.min(buffer_size), buffer_size)
+}
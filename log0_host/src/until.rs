@@ -0,0 +1,76 @@
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+/// How a run with `--until-regex`, `--fail-regex` or `--timeout` ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    TimedOut,
+    /// The target sent a panic, see `log0_panic`
+    Panicked,
+    /// The target sent a HardFault report, see `report_fault` on the target
+    Faulted,
+}
+
+impl Outcome {
+    /// Exit status of the host, timeouts use 124 like `timeout(1)`, panics 101 like a Rust
+    /// program that panicked and faults 139 like a program killed by `SIGSEGV`
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Passed => 0,
+            Outcome::Failed => 1,
+            Outcome::TimedOut => 124,
+            Outcome::Panicked => 101,
+            Outcome::Faulted => 139,
+        }
+    }
+}
+
+/// Ends a run when a message matches a pattern or time runs out, to use the host as a
+/// hardware-in-the-loop test driver
+#[derive(Debug, Clone)]
+pub struct Until {
+    pass: Option<Regex>,
+    fail: Option<Regex>,
+    deadline: Option<Instant>,
+}
+
+impl Until {
+    pub fn new(
+        pass: Option<Regex>,
+        fail: Option<Regex>,
+        timeout: Option<Duration>,
+        now: Instant,
+    ) -> Self {
+        Until {
+            pass,
+            fail,
+            deadline: timeout.map(|timeout| now + timeout),
+        }
+    }
+
+    /// Check a decoded message, failures win if both patterns match
+    pub fn check(&self, message: &str) -> Option<Outcome> {
+        let matches = |regex: &Option<Regex>| match regex {
+            Some(regex) => regex.is_match(message),
+            None => false,
+        };
+
+        if matches(&self.fail) {
+            Some(Outcome::Failed)
+        } else if matches(&self.pass) {
+            Some(Outcome::Passed)
+        } else {
+            None
+        }
+    }
+
+    /// `Some(Outcome::TimedOut)` once the timeout has expired
+    pub fn expired(&self, now: Instant) -> Option<Outcome> {
+        match self.deadline {
+            Some(deadline) if now >= deadline => Some(Outcome::TimedOut),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, Error};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// What to do when the target stops sending frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StallAction {
+    /// Only print the warning
+    Warn,
+    /// Halt the core and print its registers
+    Halt,
+    Reset,
+    /// Run a shell command
+    Exec(String),
+}
+
+impl FromStr for StallAction {
+    type Err = Error;
+
+    /// `warn`, `halt`, `reset` or `exec:<command>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(StallAction::Warn),
+            "halt" => Ok(StallAction::Halt),
+            "reset" => Ok(StallAction::Reset),
+            _ => match s.strip_prefix("exec:") {
+                Some(command) if !command.trim().is_empty() => {
+                    Ok(StallAction::Exec(command.into()))
+                }
+                _ => Err(anyhow!(
+                    "Unknown stall action {:?}, expected warn, halt, reset or exec:<command>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// Notices when no frames have arrived for a while, so a hung target does not go unnoticed
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    timeout: Duration,
+    last: Instant,
+    stalled: bool,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Watchdog {
+            timeout,
+            last: now,
+            stalled: false,
+        }
+    }
+
+    /// A frame arrived
+    pub fn feed(&mut self, now: Instant) {
+        self.last = now;
+        self.stalled = false;
+    }
+
+    /// Returns the time since the last frame once per stall, when it exceeds the timeout
+    pub fn check(&mut self, now: Instant) -> Option<Duration> {
+        let silent = now.saturating_duration_since(self.last);
+
+        if !self.stalled && silent >= self.timeout {
+            self.stalled = true;
+            Some(silent)
+        } else {
+            None
+        }
+    }
+}
+
+/// How a target that sends heartbeats is doing, from when they and its messages arrived
+///
+/// It is taken to be hung once three of its heartbeat intervals went by without one, and to be
+/// silent while only heartbeats arrived since its last message.
+#[derive(Debug, Clone)]
+pub struct Health {
+    last_heartbeat: Instant,
+    /// Between the last two heartbeats
+    interval: Option<Duration>,
+    last_message: Option<Instant>,
+}
+
+impl Health {
+    /// The first heartbeat arrived at `now`
+    pub fn new(now: Instant) -> Self {
+        Health {
+            last_heartbeat: now,
+            interval: None,
+            last_message: None,
+        }
+    }
+
+    pub fn heartbeat(&mut self, now: Instant) {
+        self.interval = Some(now.saturating_duration_since(self.last_heartbeat));
+        self.last_heartbeat = now;
+    }
+
+    pub fn message(&mut self, now: Instant) {
+        self.last_message = Some(now);
+    }
+
+    /// No heartbeat arrived for three intervals
+    pub fn hung(&self, now: Instant) -> bool {
+        let since = now.saturating_duration_since(self.last_heartbeat);
+        self.interval
+            .is_some_and(|interval| !interval.is_zero() && since > 3 * interval)
+    }
+
+    /// `alive`, `alive, silent for 12.000 s`, `alive, no messages` or
+    /// `hung, no heartbeat for 5.000 s`
+    pub fn status(&self, now: Instant) -> String {
+        let since = |then: Instant| now.saturating_duration_since(then).as_secs_f64();
+
+        if self.hung(now) {
+            return format!("hung, no heartbeat for {:.3} s", since(self.last_heartbeat));
+        }
+        match self.last_message {
+            Some(message) if message >= self.last_heartbeat => "alive".into(),
+            Some(message) => format!("alive, silent for {:.3} s", since(message)),
+            None => "alive, no messages".into(),
+        }
+    }
+}
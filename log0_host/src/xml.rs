@@ -0,0 +1,224 @@
+use anyhow::{anyhow, Result};
+
+/// An XML element, with the text directly in it
+///
+/// Only what SVD files use is supported: elements, attributes, comments, CDATA and the
+/// predefined and numeric entities. Namespaces are left in the names.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    /// Parse a document, returning the root element
+    pub fn parse(xml: &str) -> Result<Self> {
+        let mut parser = Parser { xml, pos: 0 };
+        parser.skip_misc()?;
+        let root = parser.element()?;
+        parser.skip_misc()?;
+        if parser.pos < xml.len() {
+            return Err(parser.error("content after the root element"));
+        }
+
+        Ok(root)
+    }
+
+    /// The first child called `name`
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// The children called `name`
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// Trimmed text of the first child called `name`
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|child| child.text.trim())
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Parser<'a> {
+    xml: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.xml[self.pos..]
+    }
+
+    fn error(&self, what: &str) -> anyhow::Error {
+        let line = self.xml[..self.pos].matches('\n').count() + 1;
+        anyhow!("Invalid XML on line {}: {}", line, what)
+    }
+
+    /// Move past `end`, failing if it is not found
+    fn skip_past(&mut self, end: &str) -> Result<&'a str> {
+        let rest = self.rest();
+        let len = rest
+            .find(end)
+            .ok_or_else(|| self.error(&format!("missing `{}`", end)))?;
+        self.pos += len + end.len();
+
+        Ok(&rest[..len])
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skip whitespace, comments, processing instructions and the doctype
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!DOCTYPE") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+
+        Ok(&rest[..len])
+    }
+
+    fn element(&mut self) -> Result<Element> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+        self.pos += 1;
+
+        let mut element = Element {
+            name: self.name()?.to_string(),
+            ..Element::default()
+        };
+
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            } else if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let name = self.name()?.to_string();
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("expected `=` after the attribute name"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => quote,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+            self.pos += 1;
+            let value = self.skip_past(&quote.to_string())?;
+            element.attributes.push((name, unescape(value)));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+                if name != element.name {
+                    return Err(self.error(&format!("`{}` closed by `{}`", element.name, name)));
+                }
+                self.skip_whitespace();
+                self.skip_past(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let text = self.skip_past("]]>")?;
+                element.text.push_str(text);
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                element.children.push(self.element()?);
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("`{}` is not closed", element.name)));
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                element.text.push_str(&unescape(&rest[..len]));
+                self.pos += len;
+            }
+        }
+    }
+}
+
+/// Replace the predefined and numeric entities, unknown ones are kept as they are
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "apos" => Some('\''),
+            "quot" => Some('"'),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                .and_then(|code| code.ok())
+                .and_then(std::char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
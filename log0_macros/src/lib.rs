@@ -0,0 +1,458 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Group, Ident, Literal, Span, TokenTree};
+use quote::quote;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    Data, DeriveInput, Expr, Fields, LitStr, Token,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The format string of a `log!` call and the values it prints
+struct Format {
+    lit: LitStr,
+    args: Vec<Expr>,
+}
+
+impl Parse for Format {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lit = input.parse()?;
+        let mut args = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            args.push(input.parse()?);
+        }
+
+        Ok(Format { lit, args })
+    }
+}
+
+/// Check a format string against the values it prints at compile time, as `format!` would,
+/// expands to the string literal itself
+///
+/// ```ignore
+/// format_str!("x: {}, y: {:x}", X, Y)
+/// ```
+#[proc_macro]
+pub fn format_str(input: TokenStream) -> TokenStream {
+    let Format { lit, args } = parse_macro_input!(input as Format);
+
+    match check_arguments(&lit.value(), args.len()) {
+        Ok(()) => quote!(#lit).into(),
+        Err((Some(unused), e)) => syn::Error::new_spanned(&args[unused], e)
+            .to_compile_error()
+            .into(),
+        Err((None, e)) => syn::Error::new(lit.span(), e).to_compile_error().into(),
+    }
+}
+
+/// The event name of a `log_kv!` call and its fields, each a name and the value logged under it
+struct KeyValues {
+    name: LitStr,
+    fields: Vec<(Ident, Expr)>,
+}
+
+impl Parse for KeyValues {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let mut fields = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key = input.parse()?;
+            input.parse::<Token![=]>()?;
+            fields.push((key, input.parse()?));
+        }
+
+        Ok(KeyValues { name, fields })
+    }
+}
+
+/// Check the event name and field names of a `log_kv!` call at compile time, expands to the
+/// format string the host shows it with, e.g. `adc_sample voltage={} channel={}`
+///
+/// ```ignore
+/// key_values!("adc_sample", voltage = V, channel = CH)
+/// ```
+#[proc_macro]
+pub fn key_values(input: TokenStream) -> TokenStream {
+    let KeyValues { name, fields } = parse_macro_input!(input as KeyValues);
+    let keys: Vec<_> = fields
+        .iter()
+        .map(|(key, _)| key.to_string().trim_start_matches("r#").to_string())
+        .collect();
+
+    match kv_format(&name.value(), &keys) {
+        Ok(format) => {
+            let lit = LitStr::new(&format, name.span());
+            quote!(#lit).into()
+        }
+        Err((Some(duplicate), e)) => syn::Error::new(fields[duplicate].0.span(), e)
+            .to_compile_error()
+            .into(),
+        Err((None, e)) => syn::Error::new(name.span(), e).to_compile_error().into(),
+    }
+}
+
+/// The format string for the event `name` with the fields `keys`, the error has the index of
+/// the key if it is a duplicate
+fn kv_format(name: &str, keys: &[String]) -> Result<String, (Option<usize>, String)> {
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || "{}=".contains(c)) {
+        return Err((
+            None,
+            "the event name has to be a word without `{`, `}` or `=`".into(),
+        ));
+    }
+    if keys.is_empty() {
+        return Err((None, "an event needs at least one field".into()));
+    }
+    if let Some(duplicate) = (1..keys.len()).find(|&i| keys[..i].contains(&keys[i])) {
+        return Err((Some(duplicate), "field is given more than once".into()));
+    }
+
+    let mut format = name.to_string();
+    for key in keys {
+        format.push_str(&format!(" {}={{}}", key));
+    }
+    Ok(format)
+}
+
+/// Copy the values into a tuple with the padding between them zeroed, for `log_batch!` and
+/// `log_kv!`, expands to a `MaybeUninit` of the tuple with all fields set
+///
+/// Each value is an expression that is evaluated once, and checked with `log0_target::loggable`.
+/// The values are copied bitwise and stay where they are, the copy is never dropped.
+///
+/// ```ignore
+/// batch!(X, buf.len(), x + 1)
+/// ```
+#[doc(hidden)]
+#[proc_macro]
+pub fn batch(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as BatchValues).0;
+    let types: Vec<_> = (0..args.len())
+        .map(|i| Ident::new(&format!("T{}", i), proc_macro2::Span::call_site()))
+        .collect();
+    // Not visible to the expressions of the caller
+    let values: Vec<_> = (0..args.len())
+        .map(|i| Ident::new(&format!("value{}", i), Span::mixed_site()))
+        .collect();
+    let indices = (0..args.len()).map(syn::Index::from);
+
+    quote!({
+        fn zeroed<#(#types),*>(#(_: &#types),*) -> core::mem::MaybeUninit<(#(#types,)*)> {
+            core::mem::MaybeUninit::zeroed()
+        }
+
+        // All evaluated before the first is bound, the temporaries live to the end of the block
+        let (#(#values,)*) = (#(&(#args),)*);
+        #(log0_target::loggable(#values);)*
+        let mut batch = zeroed(#(#values),*);
+        let fields = batch.as_mut_ptr();
+        unsafe {
+            #(core::ptr::copy_nonoverlapping(
+                #values,
+                core::ptr::addr_of_mut!((*fields).#indices),
+                1,
+            );)*
+        }
+        batch
+    })
+    .into()
+}
+
+/// The values of `batch!`, separated by commas
+struct BatchValues(Vec<Expr>);
+
+impl Parse for BatchValues {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let args = Punctuated::<Expr, Token![,]>::parse_separated_nonempty(input)?;
+        Ok(BatchValues(args.into_iter().collect()))
+    }
+}
+
+/// Implement `log0_target::Loggable` for a type without padding, whose bytes can all be sent
+///
+/// Structs qualify if all their fields are `Loggable` and add up to the size of the struct, which
+/// is checked at compile time. Enums qualify if they have no fields and an integer `repr`.
+/// Generic types have to implement it by hand, the size cannot be checked for all of them.
+#[proc_macro_derive(Loggable)]
+pub fn derive_loggable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match loggable(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Integer `repr`s that leave no padding in a fieldless enum
+const INT_REPRS: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+];
+
+fn loggable(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "`Loggable` cannot be derived for generic types, implement it by hand",
+        ));
+    }
+
+    match &input.data {
+        Data::Struct(data) => {
+            let types: Vec<_> = data.fields.iter().map(|field| &field.ty).collect();
+            let message = format!("`{}` has padding, it cannot be `Loggable`", name);
+
+            Ok(quote! {
+                unsafe impl log0_target::Loggable for #name
+                where
+                    #(#types: log0_target::Loggable,)*
+                {}
+
+                // Every byte belongs to a field
+                const _: () = assert!(
+                    core::mem::size_of::<#name>() == 0 #(+ core::mem::size_of::<#types>())*,
+                    #message
+                );
+            })
+        }
+        Data::Enum(data) => {
+            let unit = data
+                .variants
+                .iter()
+                .all(|variant| matches!(variant.fields, Fields::Unit));
+            let int_repr = input.attrs.iter().any(|attr| {
+                attr.path.is_ident("repr")
+                    && attr
+                        .parse_args::<Ident>()
+                        .is_ok_and(|repr| INT_REPRS.contains(&repr.to_string().as_str()))
+            });
+            if !unit || !int_repr {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "`Loggable` can only be derived for enums without fields and with an integer \
+                     `repr`, e.g. `#[repr(u8)]`",
+                ));
+            }
+
+            Ok(quote! {
+                unsafe impl log0_target::Loggable for #name {}
+            })
+        }
+        Data::Union(_) => Err(syn::Error::new_spanned(
+            name,
+            "`Loggable` cannot be derived for unions, implement it by hand",
+        )),
+    }
+}
+
+/// Placeholder in `log!` for the tag of the call site
+const PLACEHOLDER: &str = "ABCD";
+
+/// Identifiers and strings in `log!` that get the tag, user tokens are left alone
+const TAGGED: &[&str] = &[
+    "S_ABCD",
+    "S_TRACE_ABCD",
+    "S_DEBUG_ABCD",
+    "S_INFO_ABCD",
+    "S_WARN_ABCD",
+    "S_ERROR_ABCD",
+    "S_KV_ABCD",
+    "S_FMT_ABCD",
+    "TYPE_ABCD",
+    "__dwarffmt_this_is_for_searching_the_dwarf_ABCD",
+    ".fasthosting.ABCD",
+    ".fasthosting.types.ABCD",
+];
+
+/// Tags handed out so far while compiling the current crate
+static TAGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Replace `ABCD` in the identifiers and section name of `log!` with a tag that is unique for each
+/// invocation, so the host can pair the format string static of a call site with the function
+/// that carries the type of its argument in the DWARF
+#[doc(hidden)]
+#[proc_macro]
+pub fn unique_tag(input: TokenStream) -> TokenStream {
+    let tag = format!("T{}", TAGS.fetch_add(1, Ordering::Relaxed));
+
+    replace_tag(input.into(), &tag).into()
+}
+
+fn replace_tag(input: proc_macro2::TokenStream, tag: &str) -> proc_macro2::TokenStream {
+    input
+        .into_iter()
+        .map(|tt| match tt {
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), replace_tag(g.stream(), tag));
+                group.set_span(g.span());
+                TokenTree::Group(group)
+            }
+            TokenTree::Ident(ident) => {
+                let name = ident.to_string();
+                if TAGGED.contains(&name.as_str()) {
+                    TokenTree::Ident(Ident::new(&name.replace(PLACEHOLDER, tag), ident.span()))
+                } else {
+                    TokenTree::Ident(ident)
+                }
+            }
+            TokenTree::Literal(lit) => match syn::parse_str::<LitStr>(&lit.to_string()) {
+                Ok(s) if TAGGED.contains(&s.value().as_str()) => {
+                    let mut replaced = Literal::string(&s.value().replace(PLACEHOLDER, tag));
+                    replaced.set_span(lit.span());
+                    TokenTree::Literal(replaced)
+                }
+                _ => TokenTree::Literal(lit),
+            },
+            tt => tt,
+        })
+        .collect()
+}
+
+/// Same rules as the host, `{{` and `}}` are escapes and every other brace has to be part of a
+/// `{...}` placeholder
+fn check_braces(s: &str) -> Result<(), &'static str> {
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+            }
+            '}' => return Err("unmatched `}` in format string, use `}}` to print a `}`"),
+            '{' => loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some('{') | None => {
+                        return Err("unterminated `{` in format string, use `{{` to print a `{`")
+                    }
+                    Some(_) => (),
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Same rules as the host for the part of a placeholder after the `:`, as in `core::fmt`
+///
+/// `[[fill]align][sign]['#']['0'][width]['.' precision][type]`, the hint is kept in the format
+/// string and the host applies it to the value. Width and precision from values (`1$`, `.*`)
+/// are not supported, as the target only sends the values being printed.
+fn check_spec(spec: &str) -> Result<(), String> {
+    let is_align = |c| "<^>".contains(c);
+    let digits = |s: &str| s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let mut rest = spec;
+
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next()) {
+        (Some(fill), Some(align)) if is_align(align) => rest = &rest[fill.len_utf8() + 1..],
+        (Some(align), _) if is_align(align) => rest = &rest[1..],
+        _ => (),
+    }
+    rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+    rest = rest.strip_prefix('#').unwrap_or(rest);
+    rest = rest.strip_prefix('0').unwrap_or(rest);
+    rest = &rest[rest.len() - digits(rest)..];
+    if rest.starts_with('$') {
+        return Err(
+            "width from a value is not supported in format string, give it as a number".into(),
+        );
+    }
+    if let Some(precision) = rest.strip_prefix('.') {
+        rest = &precision[precision.len() - digits(precision)..];
+        if rest.len() == precision.len() || rest.starts_with('$') {
+            return Err(
+                "precision from a value is not supported in format string, give it as a number"
+                    .into(),
+            );
+        }
+    }
+
+    match rest {
+        "" | "?" | "x" | "x?" | "X" | "X?" | "o" | "b" | "e" | "E" => Ok(()),
+        _ => Err(format!(
+            "unsupported format spec `{}`, the type can be `x`, `X`, `o`, `b`, `e`, `E` or `?`",
+            spec
+        )),
+    }
+}
+
+/// Check that the placeholders of `s` refer to `count` values and use all of them, the error
+/// has the index of the value if it is an unused one
+fn check_arguments(s: &str, count: usize) -> Result<(), (Option<usize>, String)> {
+    check_braces(s).map_err(|e| (None, e.into()))?;
+
+    let mut used = vec![false; count];
+    let mut next = 0;
+    let mut rest = s;
+    while let Some(open) = rest.find(['{', '}']) {
+        let c = rest.as_bytes()[open];
+        rest = &rest[open + 1..];
+        // Escaped braces
+        if rest.as_bytes().first() == Some(&c) {
+            rest = &rest[1..];
+            continue;
+        }
+        if c == b'}' {
+            continue;
+        }
+
+        let close = rest.find('}').unwrap();
+        let placeholder = &rest[..close];
+        rest = &rest[close + 1..];
+        let (position, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+        let position = position.trim();
+        check_spec(spec).map_err(|e| (None, e))?;
+        let index = if position.is_empty() {
+            next += 1;
+            next - 1
+        } else {
+            position.parse().map_err(|_| {
+                (
+                    None,
+                    format!(
+                        "unsupported argument `{}` in format string, only positions are supported",
+                        position
+                    ),
+                )
+            })?
+        };
+
+        match used.get_mut(index) {
+            Some(used) => *used = true,
+            None => {
+                return Err((
+                    None,
+                    format!(
+                        "format string refers to value {} (counting from 0), but {} {} given",
+                        index,
+                        count,
+                        if count == 1 { "is" } else { "are" }
+                    ),
+                ))
+            }
+        }
+    }
+
+    match used.iter().position(|used| !used) {
+        Some(unused) => Err((
+            Some(unused),
+            "value is never used in the format string".into(),
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    check_arguments, check_braces, check_spec, kv_format, loggable, replace_tag, BatchValues,
+    Format,
+};
+
+#[test]
+fn balanced() {
+    for s in &[
+        "",
+        "no args",
+        "{}",
+        "{:08x} and {0}",
+        "{{}}",
+        "{{{}}}",
+        "→ {} °C",
+    ] {
+        assert!(check_braces(s).is_ok(), "{:?} should be accepted", s);
+    }
+}
+
+#[test]
+fn unbalanced() {
+    for s in &["{", "}", "a } b", "{:x", "{{}", "{ {} }", "}{"] {
+        assert!(check_braces(s).is_err(), "{:?} should be rejected", s);
+    }
+}
+
+#[test]
+fn tags() {
+    let input: proc_macro2::TokenStream = r#"
+        #[link_section = ".fasthosting.ABCD"]
+        static S_ABCD: u8 = 0;
+        static S_WARN_ABCD: u8 = 0;
+        static S_DEBUG_ABCD: u8 = 0;
+        static S_FMT_ABCD: u8 = 0;
+        #[link_section = ".fasthosting.types.ABCD"]
+        static TYPE_ABCD: u8 = 0;
+        fn __dwarffmt_this_is_for_searching_the_dwarf_ABCD() {}
+        fn f() { USER_ABCD(&S_ABCD, "ABCD", ".fasthosting.ABCD"); }
+    "#
+    .parse()
+    .unwrap();
+
+    let expected: proc_macro2::TokenStream = r#"
+        #[link_section = ".fasthosting.T7"]
+        static S_T7: u8 = 0;
+        static S_WARN_T7: u8 = 0;
+        static S_DEBUG_T7: u8 = 0;
+        static S_FMT_T7: u8 = 0;
+        #[link_section = ".fasthosting.types.T7"]
+        static TYPE_T7: u8 = 0;
+        fn __dwarffmt_this_is_for_searching_the_dwarf_T7() {}
+        fn f() { USER_ABCD(&S_T7, "ABCD", ".fasthosting.T7"); }
+    "#
+    .parse()
+    .unwrap();
+
+    assert_eq!(replace_tag(input, "T7").to_string(), expected.to_string());
+}
+
+#[test]
+fn arguments() {
+    for (s, count) in &[
+        ("{}", 1),
+        ("{:08x} and {0}", 1),
+        ("{1} {0} {1:x}", 2),
+        ("{{}} {}", 1),
+        ("{} {} {}", 3),
+        ("no values", 0),
+    ] {
+        assert!(
+            check_arguments(s, *count).is_ok(),
+            "{:?} should be accepted with {} values",
+            s,
+            count
+        );
+    }
+
+    assert!(check_arguments("{} {}", 1)
+        .unwrap_err()
+        .1
+        .contains("refers to value 1"));
+    assert!(check_arguments("{name}", 1)
+        .unwrap_err()
+        .1
+        .contains("unsupported"));
+    assert_eq!(check_arguments("{1}", 2).unwrap_err().0, Some(0));
+    assert_eq!(check_arguments("no value", 1).unwrap_err().0, Some(0));
+    assert_eq!(check_arguments("{", 1).unwrap_err().0, None);
+}
+
+#[test]
+fn expressions() {
+    let format: Format = syn::parse_str(r#""{} {} {}", X, buf.len(), x + 1,"#).unwrap();
+    assert_eq!(format.args.len(), 3);
+
+    let values: BatchValues = syn::parse_str("s.a, buf[0], f(x, y)").unwrap();
+    assert_eq!(values.0.len(), 3);
+}
+
+#[test]
+fn key_values() {
+    let keys = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+        kv_format("adc_sample", &keys(&["voltage", "channel"])).unwrap(),
+        "adc_sample voltage={} channel={}"
+    );
+    assert_eq!(kv_format("x", &keys(&["v"])).unwrap(), "x v={}");
+
+    for name in &["", "adc sample", "adc{}", "a=b"] {
+        assert_eq!(
+            kv_format(name, &keys(&["v"])).unwrap_err().0,
+            None,
+            "{:?} should be rejected",
+            name
+        );
+    }
+    assert_eq!(kv_format("x", &[]).unwrap_err().0, None);
+    assert_eq!(
+        kv_format("x", &keys(&["a", "b", "a"])).unwrap_err().0,
+        Some(2)
+    );
+}
+
+#[test]
+fn specs() {
+    for spec in &[
+        "", "?", "x", "#x", "08x", "#010x", "X?", "b", "#b", "o", ".3", "10.2", "+.2e", "E", ">8",
+        "*^9", "-<4", "→>5", "#",
+    ] {
+        assert!(check_spec(spec).is_ok(), "{:?} should be accepted", spec);
+    }
+    for spec in &["y", "1$", ".*", ".1$", ".", "08z", "x8"] {
+        assert!(check_spec(spec).is_err(), "{:?} should be rejected", spec);
+    }
+
+    assert!(check_arguments("{:08x} {:.3}", 2).is_ok());
+    assert_eq!(check_arguments("{} {:y}", 2).unwrap_err().0, None);
+}
+
+#[test]
+fn derive_loggable() {
+    for accepted in &[
+        "struct Sample { voltage: f32, channel: u32 }",
+        "struct Unit;",
+        "#[repr(u8)] enum State { Idle, Running }",
+        "#[derive(Clone)] #[repr(i16)] enum Offset { A = -1, B = 1 }",
+    ] {
+        let input = syn::parse_str(accepted).unwrap();
+        assert!(
+            loggable(&input).is_ok(),
+            "{:?} should be accepted",
+            accepted
+        );
+    }
+
+    for rejected in &[
+        "struct Wrapper<T> { value: T }",
+        "enum State { Idle, Running }",
+        "#[repr(C)] enum State { Idle, Running }",
+        "#[repr(u8)] enum Reading { Volts(f32) }",
+        "union Bits { f: f32, u: u32 }",
+    ] {
+        let input = syn::parse_str(rejected).unwrap();
+        assert!(
+            loggable(&input).is_err(),
+            "{:?} should be rejected",
+            rejected
+        );
+    }
+
+    // The padding is found at compile time by comparing the size to the fields
+    let input = syn::parse_str("struct Sample { voltage: f32, channel: u8 }").unwrap();
+    let output = loggable(&input).unwrap().to_string();
+    assert!(output.contains("size_of :: < Sample > () == 0 + core :: mem :: size_of :: < f32 > ()"));
+    assert!(output.contains("u8 : log0_target :: Loggable"));
+}
@@ -0,0 +1,49 @@
+//! A `#[panic_handler]` that sends the panic to the host through log0
+//!
+//! The message, file and line are written to the main ring as a panic frame, the ring is marked
+//! as panicked and the core spins. The host shows the panic and exits with status 101. Link it
+//! in with
+//!
+//! ```ignore
+//! use log0_panic as _;
+//! ```
+#![no_std]
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+use log0_target::Message;
+
+/// Marks that panics are sent to the host, it then also looks for the mark on the ring in case
+/// the panic frame did not fit
+#[no_mangle]
+#[used]
+static LOG0_PANIC: u8 = 1;
+
+/// A panic while handling a panic only spins
+///
+/// Set with a load and a store, as Armv6-M has no atomic read-modify-write. Only an interrupt
+/// that panics right in between gets to write a second panic frame.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if !PANICKING.load(Ordering::Relaxed) {
+        PANICKING.store(true, Ordering::Relaxed);
+
+        // Cut off on a character boundary, as the messages of `log_fmt!` are
+        let mut message = Message::new();
+        write!(message, "{}", info.message()).ok();
+        let (file, line) = match info.location() {
+            Some(location) => (location.file(), location.line()),
+            None => ("", 0),
+        };
+
+        // The main ring, where the host looks for the mark
+        unsafe { log0_target::cursors() }.write_panic(file, line, message.as_bytes());
+    }
+
+    loop {
+        compiler_fence(Ordering::SeqCst);
+    }
+}
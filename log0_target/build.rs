@@ -0,0 +1,12 @@
+use std::env;
+
+fn main() {
+    // Armv6-M has no fault status registers, see `report_fault`
+    println!("cargo:rustc-check-cfg=cfg(armv6m)");
+    if env::var("TARGET")
+        .unwrap_or_default()
+        .starts_with("thumbv6m-")
+    {
+        println!("cargo:rustc-cfg=armv6m");
+    }
+}
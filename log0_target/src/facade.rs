@@ -0,0 +1,108 @@
+//! A `log::Log` that writes the records of the `log` crate to the ring buffer, so drivers and
+//! libraries that use `log::info!` and friends end up next to the frames of `log0_target`
+//!
+//! ```ignore
+//! log0_target::facade::init(log::LevelFilter::Info).unwrap();
+//! ```
+
+use crate::{cursors, priority_cursors, Message, LOG_FRAME};
+use core::fmt::Write;
+
+/// Set in the level byte when the message is a `&'static str` without arguments, sent as its
+/// address and length for the host to read from the ELF instead of as its bytes
+const DEFERRED: u8 = 0x80;
+
+/// The most verbose level the `max-level-*` features leave in
+const MAX_LEVEL: log::LevelFilter = if cfg!(feature = "max-level-off") {
+    log::LevelFilter::Off
+} else if cfg!(feature = "max-level-error") {
+    log::LevelFilter::Error
+} else if cfg!(feature = "max-level-warn") {
+    log::LevelFilter::Warn
+} else if cfg!(feature = "max-level-info") {
+    log::LevelFilter::Info
+} else if cfg!(feature = "max-level-debug") {
+    log::LevelFilter::Debug
+} else {
+    log::LevelFilter::Trace
+};
+
+/// Writes each record as a frame with the string address `LOG_FRAME`, warnings and errors to
+/// the priority ring like `warn!` and `error!`
+///
+/// The data is the level as in `log::Level`, the target of the record and a NUL, then the
+/// message.
+pub struct Logger;
+
+pub static LOGGER: Logger = Logger;
+
+/// Install `LOGGER` as the logger of the `log` crate, with records above `level` or the
+/// `max-level-*` features left out
+///
+/// Armv6-M has no compare-and-swap, there this has to be called before any interrupt that logs
+/// is enabled. With the `disabled` feature no logger is installed.
+pub fn init(level: log::LevelFilter) -> Result<(), log::SetLoggerError> {
+    if cfg!(feature = "disabled") {
+        return Ok(());
+    }
+
+    #[cfg(not(armv6m))]
+    log::set_logger(&LOGGER)?;
+
+    #[cfg(armv6m)]
+    unsafe {
+        log::set_logger_racy(&LOGGER)?
+    };
+
+    log::set_max_level(level.min(MAX_LEVEL));
+    Ok(())
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let cursors = match record.level() {
+            log::Level::Error | log::Level::Warn => unsafe { priority_cursors() },
+            _ => unsafe { cursors() },
+        };
+        let target = record.target().as_bytes();
+
+        let mut message = Message::new();
+        // A message without arguments is in the ELF, only where it is is sent
+        let level = match record.args().as_str() {
+            Some(text) => {
+                message.buf[..4].copy_from_slice(&(text.as_ptr() as u32).to_le_bytes());
+                message.buf[4..8].copy_from_slice(&(text.len() as u32).to_le_bytes());
+                message.len = 8;
+                record.level() as u8 | DEFERRED
+            }
+            None => {
+                write!(message, "{}", record.args()).ok();
+                record.level() as u8
+            }
+        };
+        let text = &message.buf[..message.len];
+
+        cursors.write(
+            LOG_FRAME as *const u8,
+            core::ptr::null(),
+            1 + target.len() + 1 + text.len(),
+            false,
+            |writer| {
+                writer.push(level);
+                for b in target.iter().chain(&[0]).chain(text) {
+                    writer.push(*b);
+                }
+            },
+        );
+    }
+
+    fn flush(&self) {}
+}
@@ -1,13 +1,90 @@
 #![no_std]
 
+#[doc(hidden)]
+pub use log0_macros::{batch, format_str, key_values, unique_tag};
+
+/// Derive `Loggable` for a struct without padding or a fieldless enum with an integer `repr`
+pub use log0_macros::Loggable;
+
 #[doc(hidden)]
 pub unsafe fn any_to_byte_slice<T>(data: &T) -> &[u8] {
     core::slice::from_raw_parts(data as *const _ as *const _, core::mem::size_of::<T>())
 }
 
+/// A type whose bytes are all initialized, without padding, so sending them leaks nothing from
+/// the stack and the host never decodes garbage
+///
+/// With the `loggable` feature only these can be logged. Derive it with `#[derive(Loggable)]`,
+/// which checks at compile time that the fields fill the whole struct.
+///
+/// # Safety
+///
+/// Every byte of every value of the type has to be initialized.
+pub unsafe trait Loggable {}
+
+macro_rules! loggable {
+    ($($t:ty),*) => {
+        $(unsafe impl Loggable for $t {})*
+    };
+}
+
+loggable!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+loggable!(f32, f64, bool, char, ());
+
+unsafe impl<T: Loggable, const N: usize> Loggable for [T; N] {}
+unsafe impl<T: ?Sized> Loggable for &T {}
+unsafe impl<T: ?Sized> Loggable for &mut T {}
+unsafe impl<T: ?Sized> Loggable for *const T {}
+unsafe impl<T: ?Sized> Loggable for *mut T {}
+
+/// Rejects values that are not `Loggable` with the `loggable` feature
 #[doc(hidden)]
-pub unsafe fn get_type_str<T>(_: &T) -> &'static str {
-    core::any::type_name::<T>()
+#[cfg(feature = "loggable")]
+#[inline(always)]
+pub fn loggable<T: Loggable>(_: &T) {}
+
+#[doc(hidden)]
+#[cfg(not(feature = "loggable"))]
+#[inline(always)]
+pub fn loggable<T>(_: &T) {}
+
+/// The value of a `log!`, sent as the bytes of the value, except `&str` and `&[u8]` which are
+/// sent as the bytes they point to, with the frame length as their length
+///
+/// Which one is picked by method resolution at the call site: `(&Payload(&v)).bytes()` finds
+/// `Contents::bytes` on `Payload<&str>` before it would borrow once more for `Raw::bytes`.
+#[doc(hidden)]
+pub struct Payload<'a, T>(pub &'a T);
+
+#[doc(hidden)]
+pub mod payload {
+    use super::Payload;
+
+    pub trait Contents<'a> {
+        unsafe fn bytes(&self) -> &'a [u8];
+    }
+
+    impl<'a> Contents<'a> for Payload<'a, &'a str> {
+        unsafe fn bytes(&self) -> &'a [u8] {
+            self.0.as_bytes()
+        }
+    }
+
+    impl<'a> Contents<'a> for Payload<'a, &'a [u8]> {
+        unsafe fn bytes(&self) -> &'a [u8] {
+            self.0
+        }
+    }
+
+    pub trait Raw<'a> {
+        unsafe fn bytes(&self) -> &'a [u8];
+    }
+
+    impl<'a, T> Raw<'a> for &Payload<'a, T> {
+        unsafe fn bytes(&self) -> &'a [u8] {
+            super::any_to_byte_slice(self.0)
+        }
+    }
 }
 
 #[doc(hidden)]
@@ -16,37 +93,538 @@ pub union Transmute<T: Copy, U: Copy> {
     pub to: U,
 }
 
-use core::cell::Cell;
+#[cfg(feature = "sequence")]
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Size of the ring buffer, 1 kB unless a `capacity-*` feature picks another size, the largest
+/// one if several do. The host reads it from the size of `LOG0_BUFFER`.
+#[doc(hidden)]
+pub const LOG0_CAPACITY: usize = if cfg!(feature = "capacity-64k") {
+    64 * 1024
+} else if cfg!(feature = "capacity-32k") {
+    32 * 1024
+} else if cfg!(feature = "capacity-16k") {
+    16 * 1024
+} else if cfg!(feature = "capacity-8k") {
+    8 * 1024
+} else if cfg!(feature = "capacity-4k") {
+    4 * 1024
+} else if cfg!(feature = "capacity-2k") {
+    2 * 1024
+} else if cfg!(feature = "capacity-512") {
+    512
+} else if cfg!(feature = "capacity-256") {
+    256
+} else {
+    1024
+};
+
+/// Version of the frame format, sent in the boot frame so the host can tell if it knows it
+pub const WIRE_VERSION: u8 = 1;
+
+/// The wire version in the ELF, so the host can check it before flashing
+#[cfg(not(feature = "disabled"))]
+#[no_mangle]
+#[used]
+static LOG0_WIRE_VERSION: u8 = WIRE_VERSION;
+
+/// String address of the boot frame, never the address of a format string
+#[cfg(not(feature = "disabled"))]
+const BOOT_FRAME: usize = u32::MAX as usize;
+
+/// String address of the panic frame, see `Cursors::write_panic`
+const PANIC_FRAME: usize = u32::MAX as usize - 1;
+
+/// String address of the fault frame, see `report_fault`
+const FAULT_FRAME: usize = u32::MAX as usize - 2;
+
+/// String address of the frames of `facade::Logger`
+#[cfg(feature = "log")]
+const LOG_FRAME: usize = u32::MAX as usize - 3;
+
+/// String address of the frames of `span_enter!` and `span_exit!`, see `Span`
+#[cfg(not(feature = "disabled"))]
+const SPAN_FRAME: usize = u32::MAX as usize - 4;
+
+/// String address of the frames of `heartbeat`
+const HEARTBEAT_FRAME: usize = u32::MAX as usize - 5;
+
+/// String address of the frames of `write_raw`
+const RAW_FRAME: usize = u32::MAX as usize - 6;
 
-const LOG0_CAPACITY: usize = 1024;
+/// Type string address of the frames of `log_fmt!`, the data is the formatted message instead
+/// of a value
+const FORMATTED_TYPE: usize = u32::MAX as usize;
 
+/// String address of the frames of `report_resources`
+#[cfg(feature = "resources")]
+const RESOURCES_FRAME: usize = u32::MAX as usize - 7;
+
+#[cfg(feature = "log")]
+pub mod facade;
+
+#[cfg(not(any(feature = "rtt", feature = "disabled")))]
 #[no_mangle]
 pub static mut LOG0_CURSORS: Cursors = Cursors {
-    target: Cell::new(0),
-    host: Cell::new(0),
-    buf: unsafe { &mut LOG0_BUFFER as *const _ as *mut u8 },
+    target: AtomicUsize::new(0),
+    host: AtomicUsize::new(0),
+    buf: core::ptr::addr_of_mut!(LOG0_BUFFER) as *mut u8,
+    dropped: AtomicUsize::new(0),
+    panicked: AtomicBool::new(false),
+    capacity: LOG0_CAPACITY,
+    booted: AtomicBool::new(false),
+    #[cfg(feature = "high-water")]
+    high_water: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    active: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    fill: AtomicUsize::new(0),
+    #[cfg(feature = "sequence")]
+    sequence: AtomicU8::new(0),
+};
+
+/// The ring of the second core with the `dual-core` feature, the first core writes to
+/// `LOG0_CURSORS`
+#[cfg(all(feature = "dual-core", not(feature = "disabled")))]
+#[no_mangle]
+pub static mut LOG0_CURSORS_1: Cursors = Cursors {
+    target: AtomicUsize::new(0),
+    host: AtomicUsize::new(0),
+    buf: core::ptr::addr_of_mut!(LOG0_BUFFER_1) as *mut u8,
+    dropped: AtomicUsize::new(0),
+    panicked: AtomicBool::new(false),
+    capacity: LOG0_CAPACITY,
+    booted: AtomicBool::new(false),
+    #[cfg(feature = "high-water")]
+    high_water: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    active: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    fill: AtomicUsize::new(0),
+    #[cfg(feature = "sequence")]
+    sequence: AtomicU8::new(0),
+};
+
+#[cfg(all(feature = "dual-core", not(feature = "disabled")))]
+#[no_mangle]
+static mut LOG0_BUFFER_1: [u8; LOG0_CAPACITY] = [0; LOG0_CAPACITY];
+
+#[cfg(all(
+    feature = "dual-core",
+    any(
+        feature = "rtt",
+        feature = "itm",
+        feature = "user-buffer",
+        feature = "priority"
+    )
+))]
+compile_error!(
+    "The `dual-core` feature cannot be used with `rtt`, `itm`, `user-buffer` or `priority`"
+);
+
+#[cfg(all(feature = "rtt", feature = "priority"))]
+compile_error!("The `rtt` feature has one up-channel, it cannot be used with `priority`");
+
+/// Name of the RTT up-channel the frames are written to, the host looks for it by name
+#[cfg(all(feature = "rtt", not(feature = "disabled")))]
+const RTT_CHANNEL: &[u8] = b"log0\0";
+
+/// The SEGGER RTT control block, with the main ring as its only up-channel
+///
+/// The host finds it by its ID in RAM, so any tool that speaks RTT can read the frames.
+#[cfg(feature = "rtt")]
+#[repr(C)]
+pub struct ControlBlock {
+    id: [u8; 16],
+    max_up: usize,
+    max_down: usize,
+    up: Cursors,
+}
+
+#[cfg(all(feature = "rtt", not(feature = "disabled")))]
+#[no_mangle]
+pub static mut _SEGGER_RTT: ControlBlock = ControlBlock {
+    id: *b"SEGGER RTT\0\0\0\0\0\0",
+    max_up: 1,
+    max_down: 0,
+    up: Cursors {
+        name: RTT_CHANNEL.as_ptr(),
+        buf: core::ptr::addr_of_mut!(LOG0_BUFFER) as *mut u8,
+        capacity: LOG0_CAPACITY,
+        target: AtomicUsize::new(0),
+        host: AtomicUsize::new(0),
+        // Skip what does not fit, the whole frame as the target drops it
+        flags: 0,
+        dropped: AtomicUsize::new(0),
+        panicked: AtomicBool::new(false),
+        booted: AtomicBool::new(false),
+        #[cfg(feature = "high-water")]
+        high_water: AtomicUsize::new(0),
+        #[cfg(feature = "double-buffer")]
+        active: AtomicUsize::new(0),
+        #[cfg(feature = "double-buffer")]
+        fill: AtomicUsize::new(0),
+        #[cfg(feature = "sequence")]
+        sequence: AtomicU8::new(0),
+    },
 };
 
+#[cfg(not(any(feature = "user-buffer", feature = "disabled")))]
 #[no_mangle]
 static mut LOG0_BUFFER: [u8; LOG0_CAPACITY] = [0; LOG0_CAPACITY];
 
+// Defined by the application with `buffer!`
+#[cfg(all(feature = "user-buffer", not(feature = "disabled")))]
+extern "C" {
+    static mut LOG0_BUFFER: [u8; LOG0_CAPACITY];
+}
+
+/// Define the ring buffer in the linker section `$section`, e.g. DTCM or non-cacheable SRAM,
+/// with the `user-buffer` feature
+///
+/// Call it once in the application, without it linking fails on the missing `LOG0_BUFFER`. The
+/// buffer need not be zeroed at startup, so a `NOLOAD` section will do.
+///
+/// ```ignore
+/// log0_target::buffer!(".dtcm_bss");
+/// ```
+#[cfg(all(feature = "user-buffer", not(feature = "disabled")))]
+#[macro_export]
+macro_rules! buffer {
+    ($section:literal) => {
+        #[no_mangle]
+        #[link_section = $section]
+        static mut LOG0_BUFFER: [u8; log0_target::LOG0_CAPACITY] = [0; log0_target::LOG0_CAPACITY];
+    };
+}
+
+#[cfg(all(feature = "user-buffer", feature = "disabled"))]
+#[macro_export]
+macro_rules! buffer {
+    ($section:literal) => {};
+}
+
+/// Capacity of the ring for warnings and errors, it only has to hold what is logged between two
+/// polls of the host
+#[cfg(all(feature = "priority", not(feature = "disabled")))]
+const LOG0_PRIORITY_CAPACITY: usize = 256;
+
+/// Ring for the frames of `warn!` and `error!`, the host drains it before the main one so they
+/// are not dropped when the main ring is full of debug output
+#[cfg(all(feature = "priority", not(feature = "disabled")))]
+#[no_mangle]
+pub static mut LOG0_PRIORITY_CURSORS: Cursors = Cursors {
+    target: AtomicUsize::new(0),
+    host: AtomicUsize::new(0),
+    buf: core::ptr::addr_of_mut!(LOG0_PRIORITY_BUFFER) as *mut u8,
+    dropped: AtomicUsize::new(0),
+    panicked: AtomicBool::new(false),
+    capacity: LOG0_PRIORITY_CAPACITY,
+    booted: AtomicBool::new(false),
+    #[cfg(feature = "high-water")]
+    high_water: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    active: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    fill: AtomicUsize::new(0),
+    #[cfg(feature = "sequence")]
+    sequence: AtomicU8::new(0),
+};
+
+#[cfg(all(feature = "priority", not(feature = "disabled")))]
+#[no_mangle]
+static mut LOG0_PRIORITY_BUFFER: [u8; LOG0_PRIORITY_CAPACITY] = [0; LOG0_PRIORITY_CAPACITY];
+
+/// With the `disabled` feature there is no ring, this one only lets the code that writes to a
+/// ring compile and is optimized out with it, as `Cursors::write` does nothing
+#[cfg(feature = "disabled")]
+static mut DISABLED_CURSORS: Cursors = Cursors {
+    #[cfg(feature = "rtt")]
+    name: core::ptr::null(),
+    buf: core::ptr::null_mut(),
+    capacity: 1,
+    target: AtomicUsize::new(0),
+    host: AtomicUsize::new(0),
+    #[cfg(feature = "rtt")]
+    flags: 0,
+    dropped: AtomicUsize::new(0),
+    panicked: AtomicBool::new(false),
+    booted: AtomicBool::new(false),
+    #[cfg(feature = "high-water")]
+    high_water: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    active: AtomicUsize::new(0),
+    #[cfg(feature = "double-buffer")]
+    fill: AtomicUsize::new(0),
+    #[cfg(feature = "sequence")]
+    sequence: AtomicU8::new(0),
+};
+
+/// The main ring, for `trace!`, `debug!` and `info!`
+#[doc(hidden)]
+pub unsafe fn cursors() -> &'static Cursors {
+    #[cfg(not(any(feature = "rtt", feature = "dual-core", feature = "disabled")))]
+    let cursors = &*core::ptr::addr_of!(LOG0_CURSORS);
+
+    // Each core has its own ring, writing to one ring from both would need a lock
+    #[cfg(all(feature = "dual-core", not(feature = "disabled")))]
+    let cursors = match _log0_core() {
+        0 => &*core::ptr::addr_of!(LOG0_CURSORS),
+        _ => &*core::ptr::addr_of!(LOG0_CURSORS_1),
+    };
+
+    #[cfg(all(feature = "rtt", not(feature = "disabled")))]
+    let cursors = &(*core::ptr::addr_of!(_SEGGER_RTT)).up;
+
+    #[cfg(feature = "disabled")]
+    let cursors = &*core::ptr::addr_of!(DISABLED_CURSORS);
+
+    cursors
+}
+
+/// The ring for `warn!` and `error!`, the priority ring with the `priority` feature and the main
+/// one without
+#[doc(hidden)]
+pub unsafe fn priority_cursors() -> &'static Cursors {
+    #[cfg(all(feature = "priority", not(feature = "disabled")))]
+    let cursors = &*core::ptr::addr_of!(LOG0_PRIORITY_CURSORS);
+
+    #[cfg(not(all(feature = "priority", not(feature = "disabled"))))]
+    let cursors = cursors();
+
+    cursors
+}
+
+/// Number of bytes of the CRC after each frame
+#[cfg_attr(feature = "disabled", allow(dead_code))]
+const CRC_LEN: usize = if cfg!(feature = "crc") { 2 } else { 0 };
+
+/// Marks that each frame ends with a CRC, the host looks for it in the ELF
+#[cfg(all(feature = "crc", not(feature = "disabled")))]
+#[no_mangle]
+#[used]
+static LOG0_CRC: u8 = 1;
+
+/// Marks that each frame has a sequence number after the task ID, the host looks for it in the
+/// ELF
+#[cfg(all(feature = "sequence", not(feature = "disabled")))]
+#[no_mangle]
+#[used]
+static LOG0_SEQUENCE: u8 = 1;
+
+/// The ITM stimulus port the frames are written to, the host reads it from the ELF
+#[cfg(all(feature = "itm", not(feature = "disabled")))]
+#[no_mangle]
+#[used]
+static LOG0_ITM_PORT: u8 = itm::PORT as u8;
+
+#[cfg(all(feature = "itm", feature = "rtt"))]
+compile_error!("The `itm` and `rtt` features pick different transports, enable only one");
+
+/// The Instrumentation Trace Macrocell of Cortex-M, for the `itm` feature
+#[cfg(feature = "itm")]
+mod itm {
+    use core::ptr::{read_volatile, write_volatile};
+
+    /// Stimulus port of the frames, port 0 is left for text
+    pub const PORT: usize = 1;
+
+    const STIM: usize = 0xe000_0000;
+    const TER: usize = 0xe000_0e00;
+    const TCR: usize = 0xe000_0e80;
+
+    /// The ITM and the port are enabled, by the debugger or the firmware
+    pub fn enabled() -> bool {
+        unsafe {
+            read_volatile(TCR as *const u32) & 1 != 0
+                && read_volatile(TER as *const u32) & 1 << PORT != 0
+        }
+    }
+
+    fn stim() -> *mut u32 {
+        (STIM + 4 * PORT) as *mut u32
+    }
+
+    /// Write a word once the stimulus FIFO has room, it is sent as one packet
+    pub fn write_u32(word: u32) {
+        unsafe {
+            while read_volatile(stim()) & 1 == 0 {}
+            write_volatile(stim(), word);
+        }
+    }
+
+    /// Write a byte once the stimulus FIFO has room
+    pub fn write_u8(byte: u8) {
+        unsafe {
+            while read_volatile(stim()) & 1 == 0 {}
+            write_volatile(stim() as *mut u8, byte);
+        }
+    }
+}
+
+/// The registers the core stacks on exception entry, as the HardFault trampoline of
+/// `cortex-m-rt` passes them
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionFrame {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+}
+
+/// Write a fault frame with the stacked registers of `frame`, followed by the CFSR, HFSR, MMFAR
+/// and BFAR fault status registers, for the host to print a fault report
+///
+/// All are sent as little endian `u32`s. Armv6-M has no fault status registers, they are sent
+/// as 0.
+pub fn report_fault(frame: &ExceptionFrame) {
+    if cfg!(feature = "disabled") {
+        return;
+    }
+
+    #[cfg(not(armv6m))]
+    let status = unsafe {
+        [0xe000_ed28_usize, 0xe000_ed2c, 0xe000_ed34, 0xe000_ed38]
+            .map(|address| core::ptr::read_volatile(address as *const u32))
+    };
+
+    #[cfg(armv6m)]
+    let status = [0u32; 4];
+
+    let registers = [
+        frame.r0, frame.r1, frame.r2, frame.r3, frame.r12, frame.lr, frame.pc, frame.xpsr,
+    ];
+    unsafe { priority_cursors() }.write(
+        FAULT_FRAME as *const u8,
+        core::ptr::null(),
+        4 * (registers.len() + status.len()),
+        false,
+        |writer| {
+            for word in registers.iter().chain(&status) {
+                for b in &word.to_le_bytes() {
+                    writer.push(*b);
+                }
+            }
+        },
+    );
+}
+
+/// Report the fault with `report_fault` and spin, with the `hardfault` feature this is the
+/// HardFault handler `cortex-m-rt` calls
+#[cfg(all(feature = "hardfault", not(feature = "disabled")))]
+#[export_name = "HardFault"]
+unsafe extern "C" fn hard_fault(frame: &ExceptionFrame) -> ! {
+    report_fault(frame);
+
+    loop {
+        core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// Marks that the buffer is split in two banks, the host looks for it in the ELF
+#[cfg(all(feature = "double-buffer", not(feature = "disabled")))]
+#[no_mangle]
+#[used]
+static LOG0_DOUBLE_BUFFER: u8 = 1;
+
+#[cfg(all(
+    feature = "double-buffer",
+    any(feature = "overwrite", feature = "rtt", feature = "itm")
+))]
+compile_error!("The `double-buffer` feature cannot be used with `overwrite`, `rtt` or `itm`");
+
+/// Marks that the oldest frames are overwritten when the buffer is full, the host looks for it
+/// in the ELF
+#[cfg(all(feature = "overwrite", not(feature = "disabled")))]
+#[no_mangle]
+#[used]
+static LOG0_OVERWRITE: u8 = 1;
+
+/// Marks that the cursors track the most bytes that were in the buffer at once, holds the offset
+/// of that count from the target cursor for the host to read it
+#[cfg(all(feature = "high-water", not(feature = "disabled")))]
+#[no_mangle]
+#[used]
+static LOG0_HIGH_WATER: u8 =
+    (core::mem::offset_of!(Cursors, high_water) - core::mem::offset_of!(Cursors, target)) as u8;
+
+/// The cursors of a ring buffer, read and written by the host while the target runs
+///
+/// The target cursor is only moved once a whole frame is in the buffer, with release ordering
+/// so the frame is visible to the host and other cores before the cursor is. On Cortex-M that
+/// is a DMB before the store. The host cursor is read with acquire ordering, so the target does
+/// not overwrite bytes the host is still reading.
+///
+/// A ring has one writer at a time. On a multi-core part, give each core its own ring or write
+/// in a critical section that covers both cores.
+///
+/// With the `rtt` feature the fields up to `flags` are laid out as an RTT up-channel, with the
+/// target cursor as its write offset and the host cursor as its read offset. The ones after
+/// keep their offset from the target cursor, so the host reads them the same way.
+///
+/// With the `double-buffer` feature the buffer is two banks instead, the target fills one while
+/// the host drains the other, see `Cursors::swap`.
 #[repr(C)]
 pub struct Cursors {
-    target: Cell<usize>,
-    host: Cell<usize>,
+    #[cfg(feature = "rtt")]
+    name: *const u8,
+    #[cfg(feature = "rtt")]
+    buf: *mut u8,
+    #[cfg(feature = "rtt")]
+    capacity: usize,
+    target: AtomicUsize,
+    host: AtomicUsize,
+    #[cfg(not(feature = "rtt"))]
     buf: *mut u8,
+    /// The RTT operating mode, never changed by the target
+    #[cfg(feature = "rtt")]
+    flags: usize,
+    /// Frames lost because the buffer was full, new ones that were dropped or with the
+    /// `overwrite` feature the oldest ones, wraps around
+    dropped: AtomicUsize,
+    /// Set by `write_panic`, as the panic frame may not fit
+    panicked: AtomicBool,
+    /// Size of the buffer `buf` points to
+    #[cfg(not(feature = "rtt"))]
+    capacity: usize,
+    /// The boot frame has been written
+    booted: AtomicBool,
+    /// The most bytes that were in the buffer at once, for sizing it
+    #[cfg(feature = "high-water")]
+    high_water: AtomicUsize,
+    /// The bank being filled, only used by the target
+    #[cfg(feature = "double-buffer")]
+    active: AtomicUsize,
+    /// Bytes in the bank being filled, only used by the target
+    #[cfg(feature = "double-buffer")]
+    fill: AtomicUsize,
+    /// Sequence number of the next frame written, only used by the target
+    #[cfg(feature = "sequence")]
+    sequence: AtomicU8,
 }
 
-impl Cursors {
+/// Writes a frame into the ring from the target cursor, without moving it
+struct Writer<'a> {
+    cursors: &'a Cursors,
+    pos: usize,
+}
+
+#[cfg_attr(feature = "disabled", allow(dead_code))]
+impl Writer<'_> {
     /// NB: Assumes there is space in the buffer for the data
-    fn push(&self, byte: u8) {
-        let target = self.target.get();
-        unsafe { self.buf.add(target).write(byte) }
-        self.target.set(target.wrapping_add(1) % LOG0_CAPACITY);
+    fn push(&mut self, byte: u8) {
+        unsafe { self.cursors.buf.add(self.pos).write(byte) }
+        self.pos = (self.pos + 1) % self.cursors.capacity;
     }
 
     /// NB: Assumes there is space in the buffer for the data
-    fn leb128_write(&self, mut word: u32) {
+    fn leb128_write(&mut self, mut word: u32) {
         const CONTINUE: u8 = 1 << 7;
 
         loop {
@@ -64,81 +642,1841 @@ impl Cursors {
         }
     }
 
+    /// CRC-16/CCITT-FALSE of the bytes from `start` up to where the frame has been written,
+    /// sent little endian after the frame so the host can drop the frames it read while they
+    /// were overwritten or that got corrupted on the way
+    #[cfg(feature = "crc")]
+    fn crc(&self, start: usize) -> u16 {
+        let mut crc = 0xffff_u16;
+        let mut pos = start;
+        while pos != self.pos {
+            crc ^= (unsafe { self.cursors.buf.add(pos).read() } as u16) << 8;
+            for _ in 0..8 {
+                crc = if crc & 0x8000 != 0 {
+                    crc << 1 ^ 0x1021
+                } else {
+                    crc << 1
+                };
+            }
+            pos = (pos + 1) % self.cursors.capacity;
+        }
+
+        crc
+    }
+}
+
+#[cfg_attr(feature = "disabled", allow(dead_code))]
+impl Cursors {
+    #[cfg(feature = "overwrite")]
+    fn leb128_read(&self, mut pos: usize) -> (u32, usize) {
+        let mut word = 0;
+
+        // Bounded, the host may have moved its cursor into the middle of a frame
+        for shift in (0..32).step_by(7) {
+            let byte = unsafe { self.buf.add(pos).read() };
+            pos = (pos + 1) % self.capacity;
+            word |= ((byte & 0x7f) as u32) << shift;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        (word, pos)
+    }
+
+    #[cfg(not(feature = "double-buffer"))]
     fn len(&self) -> usize {
         self.target
-            .get()
-            .wrapping_sub(self.host.get())
-            .wrapping_add(LOG0_CAPACITY)
-            % LOG0_CAPACITY
+            .load(Ordering::Relaxed)
+            .wrapping_sub(self.host.load(Ordering::Acquire))
+            .wrapping_add(self.capacity)
+            % self.capacity
+    }
+
+    /// Raise the high-water mark to the bytes now in the buffer, or in the bank being filled
+    #[cfg(feature = "high-water")]
+    fn raise_high_water(&self) {
+        #[cfg(not(feature = "double-buffer"))]
+        let len = self.len();
+        #[cfg(feature = "double-buffer")]
+        let len = self.fill.load(Ordering::Relaxed);
+
+        if len > self.high_water.load(Ordering::Relaxed) {
+            self.high_water.store(len, Ordering::Relaxed);
+        }
     }
 
+    #[cfg(not(feature = "double-buffer"))]
     fn free(&self) -> usize {
-        LOG0_CAPACITY - 1 - self.len()
+        self.capacity - 1 - self.len()
     }
 
-    #[doc(hidden)]
-    pub fn write_frame(&self, sym: *const u8, type_str: *const u8, data: &[u8]) {
-        let data_len = data.len();
+    /// Free `len` bytes by moving the host cursor past the oldest frames, returns `false` if
+    /// the buffer is too small
+    ///
+    /// The host notices that `dropped` changed and skips to the target cursor, as what it was
+    /// reading may have been overwritten.
+    #[cfg(feature = "overwrite")]
+    fn make_room(&self, len: usize) -> bool {
+        if len > self.capacity - 1 {
+            return false;
+        }
+
+        while self.free() < len {
+            let host = self.host.load(Ordering::Acquire);
+            let (size, mut pos) = self.leb128_read(host);
+            #[cfg(feature = "delta")]
+            let size = size >> 1;
+
+            // The string and type string addresses, then the timestamp, task ID and sequence
+            // number
+            let headers = 2
+                + cfg!(feature = "timestamp") as usize
+                + cfg!(feature = "task") as usize
+                + cfg!(feature = "sequence") as usize;
+            for _ in 0..headers {
+                pos = self.leb128_read(pos).1;
+            }
+            pos = (pos + size as usize + CRC_LEN) % self.capacity;
+
+            let skipped = pos.wrapping_sub(host).wrapping_add(self.capacity) % self.capacity;
+            if skipped == 0 || skipped > self.len() {
+                // Not a frame boundary, drop everything
+                self.host
+                    .store(self.target.load(Ordering::Relaxed), Ordering::Release);
+            } else {
+                self.host.store(pos, Ordering::Release);
+            }
+            self.drop_frame();
+        }
+
+        true
+    }
+
+    #[cfg(not(any(feature = "overwrite", feature = "double-buffer")))]
+    fn make_room(&self, _len: usize) -> bool {
+        false
+    }
+
+    /// Where the bank being filled starts in the buffer
+    #[cfg(feature = "double-buffer")]
+    fn bank(&self) -> usize {
+        self.active.load(Ordering::Relaxed) * (self.capacity / 2)
+    }
+
+    /// Where a frame of at most `len` bytes goes in the bank being filled, after a swap if it
+    /// does not fit, `None` if the host has not drained the other bank yet
+    #[cfg(feature = "double-buffer")]
+    fn bank_room(&self, len: usize) -> Option<usize> {
+        let half = self.capacity / 2;
+        if self.fill.load(Ordering::Relaxed) + len > half && (len > half || !self.swap()) {
+            return None;
+        }
+
+        Some(self.bank() + self.fill.load(Ordering::Relaxed))
+    }
+
+    /// Hand the bank being filled to the host and fill the other one, returns `false` if the
+    /// host has not drained the other one yet
+    ///
+    /// The bank is published with one store of the target cursor: a sequence number in the top
+    /// 7 bits, the bank in bit 24 and its length below. The host writes the sequence number to
+    /// the host cursor once it drained the bank, so each word has one writer and the host never
+    /// reads a bank the target is writing to.
+    #[cfg(feature = "double-buffer")]
+    fn swap(&self) -> bool {
+        let seq = self.target.load(Ordering::Relaxed) >> 25;
+        if self.host.load(Ordering::Acquire) != seq {
+            return false;
+        }
+
+        let fill = self.fill.load(Ordering::Relaxed);
+        if fill > 0 {
+            let active = self.active.load(Ordering::Relaxed);
+            self.target.store(
+                ((seq + 1) % 128) << 25 | active << 24 | fill,
+                Ordering::Release,
+            );
+            self.active.store(active ^ 1, Ordering::Relaxed);
+            self.fill.store(0, Ordering::Relaxed);
+        }
+
+        true
+    }
+
+    /// Send the bytes from `start` to `end` out of the ITM stimulus port, a word at a time
+    /// where it can, and free them as the host would
+    ///
+    /// The frames are not sent while the ITM or the port is disabled, e.g. without a debugger.
+    #[cfg(feature = "itm")]
+    fn send_itm(&self, start: usize, end: usize) {
+        if itm::enabled() {
+            let byte = |pos: usize| unsafe { self.buf.add(pos % self.capacity).read() };
+            let mut pos = start;
+            while pos != end {
+                let left = end.wrapping_sub(pos).wrapping_add(self.capacity) % self.capacity;
+                if left >= 4 {
+                    itm::write_u32(u32::from_le_bytes([
+                        byte(pos),
+                        byte(pos + 1),
+                        byte(pos + 2),
+                        byte(pos + 3),
+                    ]));
+                    pos = (pos + 4) % self.capacity;
+                } else {
+                    itm::write_u8(byte(pos));
+                    pos = (pos + 1) % self.capacity;
+                }
+            }
+        }
+
+        self.host.store(end, Ordering::Release);
+    }
 
-        // Worst case, data length + 3 LEB encoded u32s, never really happens
-        if self.free() >= data_len + 15 {
-            self.leb128_write(data_len as u32);
-            self.leb128_write(sym as u32);
-            self.leb128_write(type_str as u32);
+    /// Count a lost frame for the host
+    fn drop_frame(&self) {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        self.dropped
+            .store(dropped.wrapping_add(1), Ordering::Release);
+    }
 
-            // TODO: Replace with a copy of the buffer + single update of the target cursor
+    #[doc(hidden)]
+    pub fn write_frame(&self, sym: *const u8, type_str: *const u8, data: &[u8]) {
+        self.write(sym, type_str, data.len(), false, |writer| {
             for b in data {
-                self.push(*b);
+                writer.push(*b);
             }
+        });
+    }
+
+    /// Write the panic frame, with the line as a little endian `u32` followed by the file and
+    /// the message separated by a NUL, and mark the ring as panicked
+    ///
+    /// The host looks for the mark when the frame did not fit. Meant for a panic handler, see
+    /// the `log0_panic` crate.
+    #[doc(hidden)]
+    pub fn write_panic(&self, file: &str, line: u32, message: &[u8]) {
+        let len = 4 + file.len() + 1 + message.len();
+        self.write(
+            PANIC_FRAME as *const u8,
+            core::ptr::null(),
+            len,
+            false,
+            |writer| {
+                let bytes = line.to_le_bytes();
+                let bytes = bytes.iter().chain(file.as_bytes()).chain(&[0]);
+                for b in bytes.chain(message) {
+                    writer.push(*b);
+                }
+            },
+        );
+
+        #[cfg(not(feature = "disabled"))]
+        self.panicked.store(true, Ordering::Release);
+    }
+
+    /// Wait until the host has read all frames written so far, see `flush`
+    #[doc(hidden)]
+    pub fn flush(&self) {
+        #[cfg(not(any(feature = "double-buffer", feature = "disabled")))]
+        while self.host.load(Ordering::Acquire) != self.target.load(Ordering::Relaxed) {
+            core::hint::spin_loop();
+        }
+
+        // The bank being filled is handed over once the host drained the other one, and is
+        // read once the host acked it
+        #[cfg(all(feature = "double-buffer", not(feature = "disabled")))]
+        while self.fill.load(Ordering::Relaxed) > 0
+            || self.host.load(Ordering::Acquire) != self.target.load(Ordering::Relaxed) >> 25
+        {
+            self.swap();
+            core::hint::spin_loop();
         }
     }
-}
 
-#[macro_export]
-macro_rules! log {
-    ($str:literal, $var:ident) => {{
-        // log0::info!("Look what I got: {}", &TEST1);
-        //
-        // expands to
-        //
-        // TODO: Move to proc macro to do the string checking
+    /// Write a frame with `data_len` bytes of data pushed by `data`, returns `false` if it did
+    /// not fit and was dropped
+    ///
+    /// The first frame after boot is preceded by the boot frame, with the string address
+    /// `BOOT_FRAME`, no type string and the wire version as data.
+    #[cfg(not(feature = "disabled"))]
+    fn write(
+        &self,
+        sym: *const u8,
+        type_str: *const u8,
+        data_len: usize,
+        delta: bool,
+        data: impl FnOnce(&mut Writer),
+    ) -> bool {
+        if !self.booted.load(Ordering::Relaxed) {
+            self.booted.store(true, Ordering::Relaxed);
+            self.write_frame(BOOT_FRAME as *const u8, core::ptr::null(), &[WIRE_VERSION]);
+        }
 
-        const FMT: &'static str = $str;
+        // Worst case, data length + 5 LEB encoded u32s + the sequence number + the CRC, never
+        // really happens
+        let len = data_len + 25 + 2 * cfg!(feature = "sequence") as usize + CRC_LEN;
 
-        // To find the type in the DWARF we add a tag to the section, the static and a function
-        // which has as a generic parameter the type we want to print. This will allow us to
-        // backtrack from the address to S_xxx, to link_section, to extract ABCD, to search the
-        // DWARF for the `__dwarffmt_this_is_for_searching_the_dwarf_ABCD`
+        #[cfg(not(feature = "double-buffer"))]
+        let start = if self.free() < len && !self.make_room(len) {
+            None
+        } else {
+            Some(self.target.load(Ordering::Relaxed))
+        };
+
+        #[cfg(feature = "double-buffer")]
+        let start = self.bank_room(len);
 
-        #[link_section = ".fasthosting.ABCD"]
-        static S_ABCD: [u8; FMT.as_bytes().len()] = unsafe {
-            *log0_target::Transmute::<*const [u8; FMT.len()], &[u8; FMT.as_bytes().len()]> {
-                from: FMT.as_ptr() as *const [u8; FMT.as_bytes().len()],
+        let start = match start {
+            Some(start) => start,
+            None => {
+                self.drop_frame();
+                return false;
             }
-            .to
+        };
+        let mut writer = Writer {
+            cursors: self,
+            pos: start,
         };
 
-        let s = unsafe { log0_target::get_type_str(&$var) };
-        let v = unsafe { log0_target::any_to_byte_slice(&$var) };
+        // The lowest bit of the size marks the frames with only the changes since the
+        // previous one, reading the marker keeps it in the binary for the host
+        #[cfg(feature = "delta")]
+        {
+            let delta = delta as u32 & unsafe { core::ptr::read_volatile(&LOG0_DELTA) } as u32;
+            writer.leb128_write((data_len as u32) << 1 | delta);
+        }
+
+        #[cfg(not(feature = "delta"))]
+        {
+            let _ = delta;
+            writer.leb128_write(data_len as u32);
+        }
+
+        writer.leb128_write(sym as u32);
+        writer.leb128_write(type_str as u32);
+
+        #[cfg(feature = "timestamp")]
+        writer.leb128_write(unsafe { _log0_timestamp() });
+
+        #[cfg(feature = "task")]
+        writer.leb128_write(unsafe { _log0_task() });
+
+        // Counts the frames written to the ring, the ones dropped instead are in `dropped`
+        #[cfg(feature = "sequence")]
+        {
+            let sequence = self.sequence.load(Ordering::Relaxed);
+            self.sequence
+                .store(sequence.wrapping_add(1), Ordering::Relaxed);
+            writer.leb128_write(u32::from(sequence));
+        }
 
-        // Trick to get the type of T via DWARF
-        unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_ABCD<T>(
-            sym: *const u8,
-            type_str: *const u8,
-            data: &[u8],
-            _t: &T,
-        ) {
-            log0_target::LOG0_CURSORS.write_frame(sym, type_str, data);
+        data(&mut writer);
+
+        #[cfg(feature = "crc")]
+        {
+            let crc = writer.crc(start);
+            writer.push(crc as u8);
+            writer.push((crc >> 8) as u8);
+        }
+
+        // The host only sees the frame once it is all there
+        #[cfg(not(feature = "double-buffer"))]
+        self.target.store(writer.pos, Ordering::Release);
+
+        #[cfg(all(feature = "high-water", not(feature = "double-buffer")))]
+        self.raise_high_water();
+
+        // With the bank, right away if the host drained the other one and otherwise after a
+        // later frame. The bank may end at the end of the buffer, where the writer wraps.
+        #[cfg(feature = "double-buffer")]
+        {
+            let fill = (writer.pos + self.capacity - self.bank()) % self.capacity;
+            self.fill.store(fill, Ordering::Relaxed);
+            #[cfg(feature = "high-water")]
+            self.raise_high_water();
+            self.swap();
         }
 
+        // The ring only holds the frame until it is sent
+        #[cfg(feature = "itm")]
+        self.send_itm(start, writer.pos);
+
+        true
+    }
+
+    /// Drop the frame, there is no ring with the `disabled` feature
+    #[cfg(feature = "disabled")]
+    fn write(
+        &self,
+        _sym: *const u8,
+        _type_str: *const u8,
+        _data_len: usize,
+        _delta: bool,
+        _data: impl FnOnce(&mut Writer),
+    ) -> bool {
+        false
+    }
+}
+
+/// The ring as the host reads it over the probe, for the tests of the host that run the writer
+/// on the host instead of on a target
+#[cfg(not(feature = "disabled"))]
+impl Cursors {
+    /// The target and host cursors
+    #[doc(hidden)]
+    pub fn indices(&self) -> [usize; 2] {
+        [
+            self.target.load(Ordering::Acquire),
+            self.host.load(Ordering::Relaxed),
+        ]
+    }
+
+    /// Copy the bytes of the buffer from `offset` into `data`
+    #[doc(hidden)]
+    pub fn read(&self, offset: usize, data: &mut [u8]) {
+        assert!(offset + data.len() <= self.capacity);
         unsafe {
-            __dwarffmt_this_is_for_searching_the_dwarf_ABCD(
-                &S_ABCD as *const _,
-                s.as_ptr() as *const _,
-                v,
-                &$var,
-            );
+            core::ptr::copy_nonoverlapping(self.buf.add(offset), data.as_mut_ptr(), data.len())
+        };
+    }
+
+    /// Move the host cursor past what the host read
+    #[doc(hidden)]
+    pub fn set_host(&self, idx: usize) {
+        self.host.store(idx, Ordering::Release);
+    }
+
+    #[doc(hidden)]
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Acquire)
+    }
+}
+
+/// Frames of a `log_delta!` call site between the ones with the whole value, so the host can
+/// start decoding in the middle of a stream and recovers from lost frames
+#[cfg(feature = "delta")]
+const KEYFRAME_INTERVAL: u8 = 32;
+
+/// Unchanged bytes between two changed ones that are sent along, as starting a new run costs
+/// at least two bytes
+#[cfg(feature = "delta")]
+const MAX_GAP: usize = 2;
+
+/// Marks that frame sizes carry the delta bit, the host looks for it in the ELF
+#[cfg(all(feature = "delta", not(feature = "disabled")))]
+#[no_mangle]
+#[used]
+static LOG0_DELTA: u8 = 1;
+
+/// The value last sent by a `log_delta!` call site
+#[cfg(feature = "delta")]
+#[doc(hidden)]
+pub struct Delta<T> {
+    prev: core::mem::MaybeUninit<T>,
+    /// Frames sent since the last one with the whole value, 0 when `prev` is not set
+    since_keyframe: u8,
+}
+
+#[cfg(feature = "delta")]
+impl<T> Delta<T> {
+    pub const fn new() -> Self {
+        Delta {
+            prev: core::mem::MaybeUninit::uninit(),
+            since_keyframe: 0,
+        }
+    }
+
+    /// Write `data`, the bytes of a `T`, as the changes since the previous frame
+    ///
+    /// The changes are runs of LEB128 encoded count of unchanged bytes to skip, LEB128 encoded
+    /// length and the new bytes. The whole value is sent instead every `KEYFRAME_INTERVAL`
+    /// frames, when the changes would not be smaller, and after a frame was dropped.
+    pub fn write_frame(
+        &mut self,
+        cursors: &Cursors,
+        sym: *const u8,
+        type_str: *const u8,
+        data: &[u8],
+    ) {
+        let size = core::mem::size_of::<T>();
+        if data.len() != size {
+            cursors.write_frame(sym, type_str, data);
+            return;
         }
+        let prev_ptr = self.prev.as_mut_ptr() as *mut u8;
+
+        let delta_len = if self.since_keyframe == 0 || self.since_keyframe >= KEYFRAME_INTERVAL {
+            None
+        } else {
+            // Set by the previous frame
+            let prev = unsafe { core::slice::from_raw_parts(prev_ptr, size) };
+            Some(
+                Runs::new(prev, data)
+                    .map(|(skip, len)| leb128_len(skip) + leb128_len(len) + len)
+                    .sum::<usize>(),
+            )
+        };
+
+        let written = match delta_len {
+            Some(delta_len) if delta_len < data.len() => {
+                let prev = unsafe { core::slice::from_raw_parts(prev_ptr, size) };
+                let written = cursors.write(sym, type_str, delta_len, true, |writer| {
+                    let mut pos = 0;
+                    for (skip, len) in Runs::new(prev, data) {
+                        writer.leb128_write(skip as u32);
+                        writer.leb128_write(len as u32);
+                        pos += skip;
+                        for b in &data[pos..pos + len] {
+                            writer.push(*b);
+                        }
+                        pos += len;
+                    }
+                });
+                self.since_keyframe += 1;
+                written
+            }
+            _ => {
+                self.since_keyframe = 1;
+                cursors.write(sym, type_str, data.len(), false, |writer| {
+                    for b in data {
+                        writer.push(*b);
+                    }
+                })
+            }
+        };
+
+        if written {
+            unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), prev_ptr, size) };
+        } else {
+            // The host did not get it, so the next frame cannot build on it
+            self.since_keyframe = 0;
+        }
+    }
+}
+
+#[cfg(feature = "delta")]
+impl<T> Default for Delta<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The runs of changed bytes between two images, as the count of unchanged bytes since the
+/// end of the previous run and the length of the run
+#[cfg(feature = "delta")]
+struct Runs<'a> {
+    prev: &'a [u8],
+    data: &'a [u8],
+    pos: usize,
+}
+
+#[cfg(feature = "delta")]
+impl<'a> Runs<'a> {
+    fn new(prev: &'a [u8], data: &'a [u8]) -> Self {
+        Runs { prev, data, pos: 0 }
+    }
+
+    fn changed(&self, i: usize) -> bool {
+        self.prev[i] != self.data[i]
+    }
+}
+
+#[cfg(feature = "delta")]
+impl<'a> Iterator for Runs<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let start = (self.pos..self.data.len()).find(|&i| self.changed(i))?;
+
+        // Extend the run over short gaps of unchanged bytes
+        let mut end = start + 1;
+        while let Some(next) =
+            (end..self.data.len().min(end + MAX_GAP + 1)).find(|&i| self.changed(i))
+        {
+            end = next + 1;
+        }
+
+        let skip = start - self.pos;
+        self.pos = end;
+        Some((skip, end - start))
+    }
+}
+
+#[cfg(feature = "delta")]
+fn leb128_len(value: usize) -> usize {
+    let bits = usize::BITS - (value | 1).leading_zeros();
+    bits.div_ceil(7) as usize
+}
+
+/// Capacity of the command buffer, one byte is always kept free
+#[cfg(all(feature = "commands", not(feature = "disabled")))]
+const LOG0_COMMAND_CAPACITY: usize = 256;
+
+/// Commands from the host, it writes frames and moves `host`, the target reads and moves
+/// `target`
+#[cfg(all(feature = "commands", not(feature = "disabled")))]
+#[no_mangle]
+pub static mut LOG0_COMMAND_CURSORS: CommandCursors = CommandCursors {
+    target: AtomicUsize::new(0),
+    host: AtomicUsize::new(0),
+    buf: core::ptr::addr_of_mut!(LOG0_COMMAND_BUFFER) as *mut u8,
+};
+
+#[cfg(all(feature = "commands", not(feature = "disabled")))]
+#[no_mangle]
+static mut LOG0_COMMAND_BUFFER: [u8; LOG0_COMMAND_CAPACITY] = [0; LOG0_COMMAND_CAPACITY];
+
+#[cfg(feature = "commands")]
+#[repr(C)]
+pub struct CommandCursors {
+    target: AtomicUsize,
+    host: AtomicUsize,
+    buf: *mut u8,
+}
+
+#[cfg(all(feature = "commands", not(feature = "disabled")))]
+impl CommandCursors {
+    fn pop(&self) -> u8 {
+        let target = self.target.load(Ordering::Relaxed);
+        let byte = unsafe { self.buf.add(target).read_volatile() };
+        self.target
+            .store((target + 1) % LOG0_COMMAND_CAPACITY, Ordering::Release);
+        byte
+    }
+
+    /// NB: The host only moves its cursor once a whole frame is in the buffer
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        if self.target.load(Ordering::Relaxed) == self.host.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let mut len = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.pop();
+            len |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        // Too long commands are skipped
+        for i in 0..len {
+            let byte = self.pop();
+            if let Some(b) = buf.get_mut(i) {
+                *b = byte;
+            }
+        }
+
+        if len <= buf.len() {
+            Some(len)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read the next command sent by the host into `buf`, returns its length
+///
+/// Returns `None` if there is no command, or if it did not fit in `buf` in which case it is
+/// dropped.
+#[cfg(feature = "commands")]
+pub fn read_command(buf: &mut [u8]) -> Option<usize> {
+    #[cfg(not(feature = "disabled"))]
+    let command = unsafe { (*core::ptr::addr_of!(LOG0_COMMAND_CURSORS)).read(buf) };
+
+    #[cfg(feature = "disabled")]
+    let command = {
+        let _ = buf;
+        None
+    };
+
+    command
+}
+
+#[cfg(feature = "timestamp")]
+extern "Rust" {
+    fn _log0_timestamp() -> u32;
+}
+
+/// Provide the timestamp added to each frame, and the frequency of its ticks so the host can
+/// convert it to time
+///
+/// ```ignore
+/// log0_target::timestamp!(64_000_000, cortex_m::peripheral::DWT::get_cycle_count());
+/// ```
+#[cfg(all(feature = "timestamp", not(feature = "disabled")))]
+#[macro_export]
+macro_rules! timestamp {
+    ($hz:expr, $ticks:expr) => {
+        #[no_mangle]
+        #[used]
+        static LOG0_TIMESTAMP_HZ: u32 = $hz;
+
+        #[no_mangle]
+        fn _log0_timestamp() -> u32 {
+            $ticks
+        }
+    };
+}
+
+#[cfg(all(feature = "timestamp", feature = "disabled"))]
+#[macro_export]
+macro_rules! timestamp {
+    ($hz:expr, $ticks:expr) => {
+        const _: () = {
+            let _: u32 = $hz;
+            fn _ticks() -> u32 {
+                $ticks
+            }
+        };
+    };
+}
+
+#[cfg(feature = "task")]
+extern "Rust" {
+    fn _log0_task() -> u32;
+}
+
+/// Provide the ID of the task or context running when a frame is written, and the names of the
+/// tasks by ID so the host can show them
+///
+/// This is meant to be called by the integration with the RTOS or RTIC, e.g. with the ID of the
+/// current priority level:
+///
+/// ```ignore
+/// log0_target::task!(current_task(), ["idle", "uart", "blink"]);
+/// ```
+#[cfg(all(feature = "task", not(feature = "disabled")))]
+#[macro_export]
+macro_rules! task {
+    ($id:expr, [$($name:literal),* $(,)?]) => {
+        const LOG0_TASK_NAMES_STR: &'static str = concat!($($name, "\0"),*);
+
+        // The names separated by NUL, the host reads them from the ELF
+        #[no_mangle]
+        #[used]
+        static LOG0_TASK_NAMES: [u8; LOG0_TASK_NAMES_STR.len()] = unsafe {
+            *$crate::Transmute::<
+                *const [u8; LOG0_TASK_NAMES_STR.len()],
+                &[u8; LOG0_TASK_NAMES_STR.len()],
+            > {
+                from: LOG0_TASK_NAMES_STR.as_ptr() as *const [u8; LOG0_TASK_NAMES_STR.len()],
+            }
+            .to
+        };
+
+        #[no_mangle]
+        fn _log0_task() -> u32 {
+            $id
+        }
+    };
+}
+
+#[cfg(all(feature = "task", feature = "disabled"))]
+#[macro_export]
+macro_rules! task {
+    ($id:expr, [$($name:literal),* $(,)?]) => {
+        const _: () = {
+            fn _task() -> u32 {
+                $id
+            }
+        };
+    };
+}
+
+/// Write a heartbeat frame, so the host can tell a target that is alive but has nothing to log
+/// from one that hung
+///
+/// Call it periodically from a tick hook, e.g. once a second from a timer interrupt. The frame
+/// has no data, only the timestamp and task if the target sends them. The host shows how the
+/// target is doing in the session summary once the first one arrived.
+pub fn heartbeat() {
+    unsafe { cursors() }.write_frame(HEARTBEAT_FRAME as *const u8, core::ptr::null(), &[]);
+}
+
+/// Write `data` as a frame on the raw channel `channel`, for binary data the host does not
+/// decode from the DWARF, e.g. audio samples or ADC bursts, returns `false` if it did not fit and
+/// was dropped
+///
+/// The frame holds the channel, then the bytes as they are. The host shows them as hex unless a
+/// decoder is registered for the channel, e.g. with `--channel 1=exec:<command>`.
+///
+/// ```ignore
+/// log0_target::write_raw(1, &adc_burst);
+/// ```
+pub fn write_raw(channel: u8, data: &[u8]) -> bool {
+    unsafe { cursors() }.write(
+        RAW_FRAME as *const u8,
+        core::ptr::null(),
+        1 + data.len(),
+        false,
+        |writer| {
+            writer.push(channel);
+            for b in data {
+                writer.push(*b);
+            }
+        },
+    )
+}
+
+/// Written to the free stack by `paint_stack`, the words that still hold it were never used
+#[cfg(feature = "resources")]
+const STACK_PAINT: u32 = 0xcccc_cccc;
+
+// Placed by the `cortex-m-rt` linker script, the stack grows down from `_stack_start` towards
+// the end of the statics, `__sheap`
+#[cfg(feature = "resources")]
+extern "C" {
+    static _stack_start: u32;
+    static __sheap: u32;
+}
+
+/// The stack, from the end of the statics up to the initial stack pointer
+#[cfg(feature = "resources")]
+fn stack_bounds() -> (*mut u32, *mut u32) {
+    (
+        core::ptr::addr_of!(__sheap) as *mut u32,
+        core::ptr::addr_of!(_stack_start) as *mut u32,
+    )
+}
+
+/// Fill the unused part of the stack with a pattern, so `report_resources` can tell how deep the
+/// stack has been used, with the `resources` feature
+///
+/// Call it once, first thing in `main` and before interrupts are enabled. The stack is taken to
+/// reach from the end of the statics to the initial stack pointer, as with `cortex-m-rt`, so it
+/// must not be used for a heap; an allocator with its own static arena is fine.
+///
+/// # Safety
+///
+/// Writes to all memory below the current stack frame, nothing may live there.
+#[cfg(feature = "resources")]
+pub unsafe fn paint_stack() {
+    let (bottom, _) = stack_bounds();
+    let here = 0u32;
+    // Leave a few words for the frame of this function
+    let end = (core::ptr::addr_of!(here) as *mut u32).wrapping_sub(16);
+
+    let mut word = bottom;
+    while word < end {
+        word.write_volatile(STACK_PAINT);
+        word = word.add(1);
+    }
+}
+
+/// Heap usage as reported by the allocator, e.g. `used()` and `free()` of `embedded-alloc`
+#[cfg(feature = "resources")]
+#[derive(Debug, Clone, Copy)]
+pub struct Heap {
+    pub used: usize,
+    pub free: usize,
+}
+
+/// Write a resources frame, with how deep the stack has been used since `paint_stack` and the
+/// usage of the heap if there is one, with the `resources` feature
+///
+/// Call it periodically, e.g. along with `heartbeat`. The frame holds the bytes of stack used and
+/// the size of the stack, then the used and free bytes of the heap, as little endian `u32`s. The
+/// host shows the latest one when `r` is pressed and in the session summary.
+///
+/// ```ignore
+/// log0_target::report_resources(Some(log0_target::Heap {
+///     used: HEAP.used(),
+///     free: HEAP.free(),
+/// }));
+/// ```
+#[cfg(feature = "resources")]
+pub fn report_resources(heap: Option<Heap>) {
+    let (bottom, top) = stack_bounds();
+
+    let mut word = bottom;
+    while word < top && unsafe { word.read_volatile() } == STACK_PAINT {
+        word = unsafe { word.add(1) };
+    }
+    let used = top as usize - word as usize;
+    let size = top as usize - bottom as usize;
+
+    let mut data = [0; 16];
+    data[..4].copy_from_slice(&(used as u32).to_le_bytes());
+    data[4..8].copy_from_slice(&(size as u32).to_le_bytes());
+    let len = match heap {
+        Some(heap) => {
+            data[8..12].copy_from_slice(&(heap.used as u32).to_le_bytes());
+            data[12..].copy_from_slice(&(heap.free as u32).to_le_bytes());
+            16
+        }
+        None => 8,
+    };
+
+    unsafe { cursors() }.write_frame(
+        RESOURCES_FRAME as *const u8,
+        core::ptr::null(),
+        &data[..len],
+    );
+}
+
+/// Longest message formatted on the target, by `log_fmt!` and `facade::Logger`, the rest is cut
+/// off
+const MAX_MESSAGE: usize = 128;
+
+/// Scratch buffer for a message formatted on the target, cut off at `MAX_MESSAGE` bytes on a
+/// character boundary
+#[doc(hidden)]
+pub struct Message {
+    buf: [u8; MAX_MESSAGE],
+    len: usize,
+}
+
+impl Message {
+    pub const fn new() -> Self {
+        Message {
+            buf: [0; MAX_MESSAGE],
+            len: 0,
+        }
+    }
+
+    /// The message formatted so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Write for Message {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let mut len = s.len().min(MAX_MESSAGE - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+
+        Ok(())
+    }
+}
+
+/// Format `args` into a scratch buffer on the stack and write it as the frame of the `log_fmt!`
+/// call site with the format string `sym`
+#[doc(hidden)]
+pub fn write_formatted(sym: *const u8, args: core::fmt::Arguments) {
+    let mut message = Message::new();
+    core::fmt::Write::write_fmt(&mut message, args).ok();
+
+    unsafe { cursors() }.write_frame(
+        sym,
+        FORMATTED_TYPE as *const u8,
+        &message.buf[..message.len],
+    );
+}
+
+/// Block until the host has read all frames written so far, e.g. before a reset or powering
+/// down, or at the end of a test
+///
+/// Warnings and errors are flushed first. This waits forever if no host is reading, and for as
+/// long as interrupts keep writing frames. With the `disabled` feature it returns right away.
+pub fn flush() {
+    unsafe {
+        priority_cursors().flush();
+        cursors().flush();
+    }
+}
+
+/// Write the panic frame of a failed `assert_log!` or an `unreachable_log!` and halt on a `udf`
+///
+/// The `udf` raises a HardFault, so with the `hardfault` feature the registers follow the
+/// panic frame. Off Arm, e.g. in host tests, this spins instead.
+#[doc(hidden)]
+#[cold]
+pub fn assert_failed(file: &str, line: u32, message: &str) -> ! {
+    // The main ring, where the host looks for the mark as with `log0_panic`
+    unsafe { cursors() }.write_panic(file, line, message.as_bytes());
+
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        core::arch::asm!("udf #0", options(noreturn))
+    }
+
+    #[cfg(not(target_arch = "arm"))]
+    loop {
+        core::sync::atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+/// A task, interrupt handler or other scope that was entered, exited when dropped or with
+/// `span_exit!`, see `span_enter!`
+///
+/// Enter and exit are each a frame with `SPAN_FRAME` as string address. The data is 1 for enter
+/// and 0 for exit, then the address and length of the name as little endian `u32`s, the host
+/// reads the name from the ELF.
+#[must_use = "the span is exited when it is dropped"]
+pub struct Span {
+    #[cfg_attr(feature = "disabled", allow(dead_code))]
+    name: &'static str,
+}
+
+impl Span {
+    #[doc(hidden)]
+    pub fn enter(name: &'static str) -> Self {
+        let span = Span { name };
+        span.write(1);
+        span
+    }
+
+    fn write(&self, enter: u8) {
+        #[cfg(not(feature = "disabled"))]
+        {
+            let mut data = [enter; 9];
+            data[1..5].copy_from_slice(&(self.name.as_ptr() as u32).to_le_bytes());
+            data[5..].copy_from_slice(&(self.name.len() as u32).to_le_bytes());
+            unsafe { cursors() }.write_frame(SPAN_FRAME as *const u8, core::ptr::null(), &data);
+        }
+
+        #[cfg(feature = "disabled")]
+        let _ = enter;
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        self.write(0);
+    }
+}
+
+/// Enter a span, e.g. at the start of a task or interrupt handler, until the returned `Span` is
+/// dropped or given to `span_exit!`
+///
+/// With the `timestamp` feature the host shows how long each span took, and with `task` in
+/// which task, so it can put together when each task and interrupt handler ran.
+///
+/// ```ignore
+/// let span = log0_target::span_enter!("uart_rx");
+/// // ...
+/// log0_target::span_exit!(span);
+/// ```
+#[macro_export]
+macro_rules! span_enter {
+    ($name:literal) => {
+        log0_target::Span::enter($name)
+    };
+}
+
+/// Exit a span of `span_enter!` before the end of its scope
+#[macro_export]
+macro_rules! span_exit {
+    ($span:expr) => {
+        core::mem::drop::<log0_target::Span>($span)
+    };
+}
+
+#[cfg(feature = "dual-core")]
+extern "Rust" {
+    fn _log0_core() -> u32;
+}
+
+/// Provide the index of the core running when a frame is written, with the `dual-core` feature,
+/// so each core writes to its own ring and the host tags the messages with the core
+///
+/// ```ignore
+/// log0_target::core_id!(rp2040_hal::sio::Sio::core() as u32);
+/// ```
+#[cfg(all(feature = "dual-core", not(feature = "disabled")))]
+#[macro_export]
+macro_rules! core_id {
+    ($id:expr) => {
+        #[no_mangle]
+        fn _log0_core() -> u32 {
+            $id
+        }
+    };
+}
+
+#[cfg(all(feature = "dual-core", feature = "disabled"))]
+#[macro_export]
+macro_rules! core_id {
+    ($id:expr) => {
+        const _: () = {
+            fn _core() -> u32 {
+                $id
+            }
+        };
+    };
+}
+
+/// Log values with a format string, the host formats them
+///
+/// Several values are sent in one frame, and each placeholder gets the value at its position.
+/// The values are expressions, each evaluated once, and a temporary lives until the frame is
+/// written.
+///
+/// With the `disabled` feature this and the other logging macros only check the format string
+/// against the values, nothing is sent and nothing ends up in the binary.
+///
+/// ```ignore
+/// log0_target::log!("x: {}, y: {}, state: {:?}", X, Y, STATE);
+/// log0_target::log!("len = {}, next = {}", buf.len(), x + 1);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! log {
+    ($str:literal, $var:expr) => {{
+        // log0::info!("Look what I got: {}", &TEST1);
+        //
+        // expands to
+        //
+        // The string is checked against the value by the proc macro, as `format!` would
+
+        // `unique_tag!` replaces ABCD with a tag that is unique for each call site
+        log0_target::unique_tag! {{
+            const FMT: &'static str = log0_target::format_str!($str, $var);
+
+            // To find the type in the DWARF we add a tag to the section, the static and a function
+            // which has as a generic parameter the type we want to print. This will allow us to
+            // backtrack from the address to S_xxx, to link_section, to extract ABCD, to search the
+            // DWARF for the `__dwarffmt_this_is_for_searching_the_dwarf_ABCD`
+
+            #[link_section = ".fasthosting.ABCD"]
+            static S_ABCD: [u8; FMT.as_bytes().len()] = unsafe {
+                *log0_target::Transmute::<*const [u8; FMT.len()], &[u8; FMT.as_bytes().len()]> {
+                    from: FMT.as_ptr() as *const [u8; FMT.as_bytes().len()],
+                }
+                .to
+            };
+
+            // The address sent as the type string, the type is in its own section so the host
+            // tells it from a format string by the address, and gets its name through the tag
+            // from the DWARF of the function below instead of from `.rodata`
+            #[link_section = ".fasthosting.types.ABCD"]
+            static TYPE_ABCD: u8 = 0;
+
+            // Evaluated once, a temporary lives until the frame is written
+            let value = &($var);
+            log0_target::loggable(value);
+            let v = unsafe {
+                use log0_target::payload::{Contents as _, Raw as _};
+                (&log0_target::Payload(value)).bytes()
+            };
+
+            // Trick to get the type of T via DWARF
+            unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_ABCD<T>(
+                sym: *const u8,
+                type_str: *const u8,
+                data: &[u8],
+                _t: &T,
+            ) {
+                log0_target::cursors().write_frame(sym, type_str, data);
+            }
+
+            unsafe {
+                __dwarffmt_this_is_for_searching_the_dwarf_ABCD(
+                    &S_ABCD as *const _,
+                    &TYPE_ABCD as *const _,
+                    v,
+                    value,
+                );
+            }
+        }}
+    }};
+    ($str:literal, $($var:expr),+ $(,)?) => {
+        // Several values are sent in one frame, each placeholder gets the value at its position
+        log0_target::log_level!(S_ABCD, cursors, $str, $($var),+)
+    };
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! log {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        // Checked as when enabled, but never evaluated
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `log!`, for tracing. The host shows its level and can filter by it.
+///
+/// Levels are compiled out with the `max-level-*` features, `max-level-debug` leaves out
+/// `trace!`, `max-level-info` `debug!` too and so on down to `max-level-off`. The most
+/// restrictive one wins if several are enabled. `log!` has no level and is always compiled in.
+///
+/// ```ignore
+/// log0_target::trace!("Entering state: {}", STATE);
+/// ```
+#[cfg(not(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info",
+    feature = "max-level-debug"
+)))]
+#[macro_export]
+macro_rules! trace {
+    ($str:literal, $($var:expr),+ $(,)?) => {
+        log0_target::log_level!(S_TRACE_ABCD, cursors, $str, $($var),+)
+    };
+}
+
+#[cfg(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info",
+    feature = "max-level-debug"
+))]
+#[macro_export]
+macro_rules! trace {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `log!`, for debugging, see `trace!` for how levels are compiled out
+///
+/// ```ignore
+/// log0_target::debug!("Packet: {}", PACKET);
+/// ```
+#[cfg(not(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info"
+)))]
+#[macro_export]
+macro_rules! debug {
+    ($str:literal, $($var:expr),+ $(,)?) => {
+        log0_target::log_level!(S_DEBUG_ABCD, cursors, $str, $($var),+)
+    };
+}
+
+#[cfg(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn",
+    feature = "max-level-info"
+))]
+#[macro_export]
+macro_rules! debug {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `log!`, for information, see `trace!` for how levels are compiled out
+///
+/// ```ignore
+/// log0_target::info!("Connected: {}", ADDRESS);
+/// ```
+#[cfg(not(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn"
+)))]
+#[macro_export]
+macro_rules! info {
+    ($str:literal, $($var:expr),+ $(,)?) => {
+        log0_target::log_level!(S_INFO_ABCD, cursors, $str, $($var),+)
+    };
+}
+
+#[cfg(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error",
+    feature = "max-level-warn"
+))]
+#[macro_export]
+macro_rules! info {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `log!`, for a warning. The host shows its level, and with the `priority` feature it
+/// goes through a ring of its own that is drained first.
+///
+/// ```ignore
+/// log0_target::warn!("Battery low: {}", VOLTAGE);
+/// ```
+#[cfg(not(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error"
+)))]
+#[macro_export]
+macro_rules! warn {
+    ($str:literal, $($var:expr),+ $(,)?) => {
+        log0_target::log_level!(S_WARN_ABCD, priority_cursors, $str, $($var),+)
+    };
+}
+
+#[cfg(any(
+    feature = "disabled",
+    feature = "max-level-off",
+    feature = "max-level-error"
+))]
+#[macro_export]
+macro_rules! warn {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `log!`, for an error. The host shows its level, and with the `priority` feature it goes
+/// through a ring of its own that is drained first.
+///
+/// ```ignore
+/// log0_target::error!("Radio timeout: {}", STATUS);
+/// ```
+#[cfg(not(any(feature = "disabled", feature = "max-level-off")))]
+#[macro_export]
+macro_rules! error {
+    ($str:literal, $($var:expr),+ $(,)?) => {
+        log0_target::log_level!(S_ERROR_ABCD, priority_cursors, $str, $($var),+)
+    };
+}
+
+#[cfg(any(feature = "disabled", feature = "max-level-off"))]
+#[macro_export]
+macro_rules! error {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! log_level {
+    ($static:ident, $cursors:ident, $str:literal, $($var:expr),+) => {
+        log0_target::log_level!(
+            @fmt $static,
+            $cursors,
+            log0_target::format_str!($str, $($var),+),
+            $($var),+
+        )
+    };
+    // A format string that is put together by the caller, e.g. `assert_log!`, and not checked
+    (@fmt $static:ident, $cursors:ident, $fmt:expr, $var:expr) => {{
+        // As `log!`, the host reads the level from the name of the static
+        log0_target::unique_tag! {{
+            const FMT: &'static str = $fmt;
+
+            #[link_section = ".fasthosting.ABCD"]
+            static $static: [u8; FMT.as_bytes().len()] = unsafe {
+                *log0_target::Transmute::<*const [u8; FMT.len()], &[u8; FMT.as_bytes().len()]> {
+                    from: FMT.as_ptr() as *const [u8; FMT.as_bytes().len()],
+                }
+                .to
+            };
+
+            #[link_section = ".fasthosting.types.ABCD"]
+            static TYPE_ABCD: u8 = 0;
+
+            // Evaluated once, a temporary lives until the frame is written
+            let value = &($var);
+            log0_target::loggable(value);
+            let v = unsafe {
+                use log0_target::payload::{Contents as _, Raw as _};
+                (&log0_target::Payload(value)).bytes()
+            };
+
+            // Trick to get the type of T via DWARF
+            unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_ABCD<T>(
+                sym: *const u8,
+                type_str: *const u8,
+                data: &[u8],
+                _t: &T,
+            ) {
+                log0_target::$cursors().write_frame(sym, type_str, data);
+            }
+
+            unsafe {
+                __dwarffmt_this_is_for_searching_the_dwarf_ABCD(
+                    &$static as *const _,
+                    &TYPE_ABCD as *const _,
+                    v,
+                    value,
+                );
+            }
+        }}
+    }};
+    (@fmt $static:ident, $cursors:ident, $fmt:expr, $($var:expr),+) => {{
+        // The values are copied into a tuple that is sent as one value, the host puts each
+        // element in the placeholder at its position
+        log0_target::unique_tag! {{
+            const FMT: &'static str = $fmt;
+
+            #[link_section = ".fasthosting.ABCD"]
+            static $static: [u8; FMT.as_bytes().len()] = unsafe {
+                *log0_target::Transmute::<*const [u8; FMT.len()], &[u8; FMT.as_bytes().len()]> {
+                    from: FMT.as_ptr() as *const [u8; FMT.as_bytes().len()],
+                }
+                .to
+            };
+
+            #[link_section = ".fasthosting.types.ABCD"]
+            static TYPE_ABCD: u8 = 0;
+
+            // A bitwise copy with the padding between the values zeroed, the values stay where
+            // they are
+            let batch = log0_target::batch!($($var),+);
+            let batch = unsafe { &*batch.as_ptr() };
+            let v = unsafe { log0_target::any_to_byte_slice(batch) };
+
+            // Trick to get the type of T via DWARF
+            unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_ABCD<T>(
+                sym: *const u8,
+                type_str: *const u8,
+                data: &[u8],
+                _t: &T,
+            ) {
+                log0_target::$cursors().write_frame(sym, type_str, data);
+            }
+
+            unsafe {
+                __dwarffmt_this_is_for_searching_the_dwarf_ABCD(
+                    &$static as *const _,
+                    &TYPE_ABCD as *const _,
+                    v,
+                    batch,
+                );
+            }
+        }}
+    }};
+}
+
+/// Log several values in one frame, the same as `log!` with several values
+///
+/// ```ignore
+/// log0_target::log_batch!("setpoint: {}, measured: {}, output: {}", SETPOINT, MEASURED, OUTPUT);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! log_batch {
+    ($str:literal, $($var:expr),+ $(,)?) => {
+        log0_target::log_level!(S_ABCD, cursors, $str, $($var),+)
+    };
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! log_batch {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Log an event with named fields, the host shows it as `adc_sample voltage=3.3 channel=2` and
+/// puts the fields by name in structured output, e.g. with `--json`
+///
+/// The event and field names end up in `.fasthosting` as the format string
+/// `adc_sample voltage={} channel={}`, the values are sent in one frame like with `log_batch!`.
+///
+/// ```ignore
+/// log0_target::log_kv!("adc_sample", voltage = V, channel = CH);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! log_kv {
+    ($name:literal, $($key:ident = $var:expr),+ $(,)?) => {{
+        // As `log_batch!`, the host finds the names of the fields from the name of the static
+        log0_target::unique_tag! {{
+            const FMT: &'static str = log0_target::key_values!($name, $($key = $var),+);
+
+            #[link_section = ".fasthosting.ABCD"]
+            static S_KV_ABCD: [u8; FMT.as_bytes().len()] = unsafe {
+                *log0_target::Transmute::<*const [u8; FMT.len()], &[u8; FMT.as_bytes().len()]> {
+                    from: FMT.as_ptr() as *const [u8; FMT.as_bytes().len()],
+                }
+                .to
+            };
+
+            #[link_section = ".fasthosting.types.ABCD"]
+            static TYPE_ABCD: u8 = 0;
+
+            // A bitwise copy with the padding zeroed, as in `log_batch!`. Also a tuple for one
+            // field, so the host always finds the fields as its elements.
+            let batch = log0_target::batch!($($var),+);
+            let batch = unsafe { &*batch.as_ptr() };
+            let v = unsafe { log0_target::any_to_byte_slice(batch) };
+
+            // Trick to get the type of T via DWARF
+            unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_ABCD<T>(
+                sym: *const u8,
+                type_str: *const u8,
+                data: &[u8],
+                _t: &T,
+            ) {
+                log0_target::cursors().write_frame(sym, type_str, data);
+            }
+
+            unsafe {
+                __dwarffmt_this_is_for_searching_the_dwarf_ABCD(
+                    &S_KV_ABCD as *const _,
+                    &TYPE_ABCD as *const _,
+                    v,
+                    batch,
+                );
+            }
+        }}
+    }};
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! log_kv {
+    ($name:literal, $($key:ident = $var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::key_values!($name, $($key = $var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `assert!`, but a failure is sent to the host before the core halts on a `udf`, instead
+/// of showing up as a bare HardFault
+///
+/// The condition goes in a panic frame with the file and line, so the host shows it as a panic
+/// and exits with status 101. The values given after the condition are sent before it in an
+/// error frame, shown as `assertion failed with X = 7, LIMIT = 5`.
+///
+/// With the `disabled` feature the condition is still checked, but nothing is sent.
+///
+/// ```ignore
+/// log0_target::assert_log!(X < LIMIT, X, LIMIT);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! assert_log {
+    ($cond:expr $(,)?) => {
+        if !$cond {
+            log0_target::assert_failed(
+                file!(),
+                line!(),
+                concat!("assertion failed: ", stringify!($cond)),
+            );
+        }
+    };
+    ($cond:expr, $first:expr $(, $var:expr)* $(,)?) => {
+        if !$cond {
+            log0_target::log_level!(
+                @fmt S_ERROR_ABCD,
+                priority_cursors,
+                concat!(
+                    "assertion failed with ",
+                    stringify!($first),
+                    " = {}"
+                    $(, ", ", stringify!($var), " = {}")*
+                ),
+                $first $(, $var)*
+            );
+            log0_target::assert_failed(
+                file!(),
+                line!(),
+                concat!("assertion failed: ", stringify!($cond)),
+            );
+        }
+    };
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! assert_log {
+    ($cond:expr $(, $var:expr)* $(,)?) => {
+        if !$cond {
+            let _ = ($(&$var,)*);
+            log0_target::assert_failed(file!(), line!(), "");
+        }
+    };
+}
+
+/// Like `assert_log!`, but only checked with debug assertions enabled, as `debug_assert!`
+///
+/// ```ignore
+/// log0_target::debug_assert_log!(LEN <= CAPACITY, LEN);
+/// ```
+#[macro_export]
+macro_rules! debug_assert_log {
+    ($($arg:tt)*) => {
+        if cfg!(debug_assertions) {
+            log0_target::assert_log!($($arg)*);
+        }
+    };
+}
+
+/// Like `unreachable!`, but sent to the host before the core halts on a `udf`, see
+/// `assert_log!`
+///
+/// A message with values is sent in an error frame before the panic frame, as with `error!`.
+///
+/// ```ignore
+/// log0_target::unreachable_log!("state: {}", STATE);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! unreachable_log {
+    () => {
+        log0_target::assert_failed(file!(), line!(), "internal error: entered unreachable code")
+    };
+    ($str:literal $(,)?) => {
+        log0_target::assert_failed(
+            file!(),
+            line!(),
+            concat!("internal error: entered unreachable code: ", $str),
+        )
+    };
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        log0_target::log_level!(S_ERROR_ABCD, priority_cursors, $str, $($var),+);
+        log0_target::assert_failed(file!(), line!(), "internal error: entered unreachable code")
+    }};
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! unreachable_log {
+    () => {
+        log0_target::assert_failed(file!(), line!(), "")
+    };
+    ($str:literal $(, $var:expr)* $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str $(, $var)*), $(&$var,)*);
+        }
+        log0_target::assert_failed(file!(), line!(), "")
+    }};
+}
+
+/// Calls of a `log_once!` or `log_every_n!` call site, to tell which ones to send
+///
+/// Counted with loads and stores, as Armv6-M has no atomic read-modify-write. A call site that
+/// is also reached from an interrupt may send a frame more or less now and then.
+#[doc(hidden)]
+pub struct CallCount(AtomicUsize);
+
+impl CallCount {
+    pub const fn new() -> Self {
+        CallCount(AtomicUsize::new(0))
+    }
+
+    /// Count a call, returns `true` for the first one
+    pub fn once(&self) -> bool {
+        let first = self.0.load(Ordering::Relaxed) == 0;
+        if first {
+            self.0.store(1, Ordering::Relaxed);
+        }
+
+        first
+    }
+
+    /// Count a call, returns `true` for the first one and every `n`th one after it
+    pub fn every(&self, n: usize) -> bool {
+        let count = self.0.load(Ordering::Relaxed);
+        self.0.store(
+            if count + 1 >= n { 0 } else { count + 1 },
+            Ordering::Relaxed,
+        );
+
+        count == 0
+    }
+}
+
+impl Default for CallCount {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Like `log!`, but only the first time the call site is reached, e.g. for a condition in a
+/// loop that only needs to be reported once
+///
+/// ```ignore
+/// log0_target::log_once!("Sensor saturated: {}", SAMPLE);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! log_once {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        static CALLS: log0_target::CallCount = log0_target::CallCount::new();
+
+        if CALLS.once() {
+            log0_target::log!($str, $($var),+);
+        }
+    }};
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! log_once {
+    ($str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `log!`, but only the first time the call site is reached and every `n`th time after,
+/// e.g. for logging a sample in a loop that runs too often to log every one
+///
+/// ```ignore
+/// log0_target::log_every_n!(100, "ADC sample: {}", SAMPLE);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! log_every_n {
+    ($n:expr, $str:literal, $($var:expr),+ $(,)?) => {{
+        static CALLS: log0_target::CallCount = log0_target::CallCount::new();
+
+        if CALLS.every($n) {
+            log0_target::log!($str, $($var),+);
+        }
+    }};
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! log_every_n {
+    ($n:expr, $str:literal, $($var:expr),+ $(,)?) => {{
+        if false {
+            let _: usize = $n;
+            let _ = (log0_target::format_str!($str, $($var),+), $(&$var),+);
+        }
+    }};
+}
+
+/// Like `log!`, for values that cannot be sent as their bytes, e.g. ones with pointers or of
+/// types that are not in the DWARF, formatted with `core::fmt` on the target instead
+///
+/// The arguments are any expressions with `Debug` or `Display` as the format string asks for.
+/// The message is formatted into a scratch buffer on the stack and sent as text, cut off at 128
+/// bytes. This is much slower than `log!` and pulls in the formatting machinery, it is meant as
+/// a way to migrate code that formats its messages, one call site at a time.
+///
+/// ```ignore
+/// log0_target::log_fmt!("request: {:?}", request);
+/// ```
+#[cfg(not(feature = "disabled"))]
+#[macro_export]
+macro_rules! log_fmt {
+    ($str:literal $(, $arg:expr)* $(,)?) => {{
+        log0_target::unique_tag! {{
+            const FMT: &'static str = $str;
+
+            // Only for the host to find the call site, the message is sent as text
+            #[link_section = ".fasthosting.ABCD"]
+            static S_FMT_ABCD: [u8; FMT.as_bytes().len()] = unsafe {
+                *log0_target::Transmute::<*const [u8; FMT.len()], &[u8; FMT.as_bytes().len()]> {
+                    from: FMT.as_ptr() as *const [u8; FMT.as_bytes().len()],
+                }
+                .to
+            };
+
+            log0_target::write_formatted(
+                &S_FMT_ABCD as *const _ as *const u8,
+                format_args!($str $(, $arg)*),
+            );
+        }}
+    }};
+}
+
+#[cfg(feature = "disabled")]
+#[macro_export]
+macro_rules! log_fmt {
+    ($str:literal $(, $arg:expr)* $(,)?) => {{
+        if false {
+            let _ = format_args!($str $(, $arg)*);
+        }
+    }};
+}
+
+/// Like `log!`, but only the bytes that changed since the previous frame of the call site are
+/// sent, for large values that are logged often and change little, e.g. the state of a
+/// controller. The type of the value has to be given.
+///
+/// ```ignore
+/// log0_target::log_delta!("state: {}", STATE: ControllerState);
+/// ```
+#[cfg(all(feature = "delta", not(feature = "disabled")))]
+#[macro_export]
+macro_rules! log_delta {
+    ($str:literal, $var:ident : $ty:ty) => {{
+        // As `log!`, with the previous value kept in a static for this call site
+        log0_target::unique_tag! {{
+            const FMT: &'static str = log0_target::format_str!($str, $var);
+
+            #[link_section = ".fasthosting.ABCD"]
+            static S_ABCD: [u8; FMT.as_bytes().len()] = unsafe {
+                *log0_target::Transmute::<*const [u8; FMT.len()], &[u8; FMT.as_bytes().len()]> {
+                    from: FMT.as_ptr() as *const [u8; FMT.as_bytes().len()],
+                }
+                .to
+            };
+
+            #[link_section = ".fasthosting.types.ABCD"]
+            static TYPE_ABCD: u8 = 0;
+
+            static mut DELTA: log0_target::Delta<$ty> = log0_target::Delta::new();
+
+            let _: &$ty = &$var;
+            log0_target::loggable(&$var);
+            let v = unsafe { log0_target::any_to_byte_slice(&$var) };
+
+            // Trick to get the type of T via DWARF
+            unsafe fn __dwarffmt_this_is_for_searching_the_dwarf_ABCD<T>(
+                sym: *const u8,
+                type_str: *const u8,
+                data: &[u8],
+                _t: &T,
+            ) {
+                (*core::ptr::addr_of_mut!(DELTA)).write_frame(
+                    log0_target::cursors(),
+                    sym,
+                    type_str,
+                    data,
+                );
+            }
+
+            unsafe {
+                __dwarffmt_this_is_for_searching_the_dwarf_ABCD(
+                    &S_ABCD as *const _,
+                    &TYPE_ABCD as *const _,
+                    v,
+                    &$var,
+                );
+            }
+        }}
+    }};
+}
+
+#[cfg(all(feature = "delta", feature = "disabled"))]
+#[macro_export]
+macro_rules! log_delta {
+    ($str:literal, $var:ident : $ty:ty) => {{
+        let _: &$ty = &$var;
+        let _ = log0_target::format_str!($str, $var);
     }};
 }
 
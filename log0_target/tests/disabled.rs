@@ -0,0 +1,39 @@
+//! Macros that are compiled out check their arguments but do not evaluate them, run with
+//! `cargo test --features disabled --test disabled` or with `max-level-off`
+#![cfg(any(feature = "disabled", feature = "max-level-off"))]
+
+use core::cell::Cell;
+
+#[test]
+fn levels_not_evaluated() {
+    let calls = Cell::new(0);
+    let sample = || {
+        calls.set(calls.get() + 1);
+        42u32
+    };
+
+    log0_target::trace!("sample: {}", sample());
+    log0_target::debug!("sample: {}", sample());
+    log0_target::info!("sample: {}", sample());
+    log0_target::warn!("sample: {}", sample());
+    log0_target::error!("sample: {}", sample());
+    assert_eq!(calls.get(), 0);
+}
+
+#[cfg(feature = "disabled")]
+#[test]
+fn not_evaluated() {
+    let calls = Cell::new(0);
+    let sample = || {
+        calls.set(calls.get() + 1);
+        42u32
+    };
+
+    log0_target::log!("sample: {}", sample());
+    log0_target::log_batch!("samples: {} {}", sample(), sample());
+    log0_target::log_kv!("adc_sample", value = sample());
+    log0_target::log_once!("sample: {}", sample());
+    log0_target::log_every_n!(2, "sample: {}", sample());
+    log0_target::log_fmt!("sample: {:?}", sample());
+    assert_eq!(calls.get(), 0);
+}
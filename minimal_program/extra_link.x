@@ -1,6 +1,13 @@
 SECTIONS {
   .fasthosting 0 (INFO) :
   {
-    *(.fasthosting .fasthosting.*);
+    *(.fasthosting .fasthosting.T*);
+  }
+
+  /* The type markers of the call sites, after the format strings so an address is one or the
+     other */
+  .fasthosting.types ADDR(.fasthosting) + SIZEOF(.fasthosting) (INFO) :
+  {
+    *(.fasthosting.types .fasthosting.types.*);
   }
 }